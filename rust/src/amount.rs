@@ -0,0 +1,177 @@
+//! Exact amount formatting and serialization
+//!
+//! Bitcoin amounts are integers (satoshis) under the hood: 1 BTC = 100,000,000 sat.
+//! Doing arithmetic in `f64` BTC drifts (0.1 + 0.2 != 0.3), so every amount in this
+//! crate is a `bitcoin::Amount` (or `SignedAmount` when it can go negative) and is
+//! only turned into text at the very edge, without ever passing through a float.
+
+use std::str::FromStr;
+
+use bitcoincore_rpc::bitcoin::amount::ParseAmountError;
+use bitcoincore_rpc::bitcoin::{Amount, Denomination, SignedAmount};
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+/// Exact decimal BTC string with no trailing zeros, e.g. `29.9999859`
+pub fn format_btc(amount: Amount) -> String {
+    amount.to_string_in(Denomination::Bitcoin)
+}
+
+/// Exact signed decimal BTC string, e.g. `-0.0000141`
+pub fn format_signed_btc(amount: SignedAmount) -> String {
+    amount.to_string_in(Denomination::Bitcoin)
+}
+
+/// Parse a user-written amount exactly: `"20"` and `"0.5"` are BTC,
+/// `"20 BTC"` or `"1500 sat"` name their denomination explicitly
+pub fn parse_amount(s: &str) -> Result<Amount, ParseAmountError> {
+    let s = s.trim();
+    if s.contains(char::is_whitespace) {
+        Amount::from_str(s)
+    } else {
+        Amount::from_str_in(s, Denomination::Bitcoin)
+    }
+}
+
+/// `{"btc": "29.9999859", "sat": 2999998590}`: the BTC value is a string so JSON
+/// consumers never parse it into a float by accident; `sat` is the integer truth
+#[derive(Serialize)]
+struct Exact<T> {
+    btc: String,
+    sat: T,
+}
+
+/// `#[serde(with = "crate::amount::exact")]` for `Amount` fields
+pub mod exact {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(amount: &Amount, serializer: S) -> Result<S::Ok, S::Error> {
+        Exact {
+            btc: format_btc(*amount),
+            sat: amount.to_sat(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// `#[serde(with = "crate::amount::exact_option")]` for `Option<Amount>` fields (`null`
+/// when there's no amount)
+pub mod exact_option {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        amount: &Option<Amount>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match amount {
+            Some(amount) => exact::serialize(amount, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+/// `#[serde(with = "crate::amount::exact_signed")]` for `SignedAmount` fields
+pub mod exact_signed {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        amount: &SignedAmount,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Exact {
+            btc: format_signed_btc(*amount),
+            sat: amount.to_sat(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// `#[serde(deserialize_with = "crate::amount::human::deserialize")]` for amounts in
+/// hand-written files: a string for [`parse_amount`], or an integer number of whole BTC
+///
+/// Fractional numbers (`0.1`) are rejected on purpose: they'd go through a float.
+pub mod human {
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Text(String),
+        WholeBtc(u64),
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+        match Raw::deserialize(deserializer)? {
+            Raw::Text(text) => parse_amount(&text).map_err(de::Error::custom),
+            Raw::WholeBtc(btc) => btc
+                .checked_mul(Amount::ONE_BTC.to_sat())
+                .map(Amount::from_sat)
+                .ok_or_else(|| de::Error::custom(format!("{btc} BTC is out of range"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_without_float_artifacts() {
+        assert_eq!(format_btc(Amount::from_sat(141)), "0.00000141");
+        assert_eq!(format_btc(Amount::from_sat(2_999_999_859)), "29.99999859");
+        assert_eq!(format_btc(Amount::from_sat(20 * 100_000_000)), "20");
+        assert_eq!(
+            format_signed_btc(SignedAmount::from_sat(-141)),
+            "-0.00000141"
+        );
+    }
+
+    #[test]
+    fn parses_bare_btc_and_explicit_denominations() {
+        assert_eq!(parse_amount("0.1").unwrap(), Amount::from_sat(10_000_000));
+        assert_eq!(
+            parse_amount(" 20 BTC ").unwrap(),
+            Amount::from_sat(2_000_000_000)
+        );
+        assert_eq!(parse_amount("1500 sat").unwrap(), Amount::from_sat(1_500));
+        assert!(parse_amount("0.000000001").is_err());
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Payment {
+        #[serde(deserialize_with = "human::deserialize")]
+        amount: Amount,
+    }
+
+    #[test]
+    fn human_amounts_accept_strings_and_whole_btc_but_not_floats() {
+        let parse = |json: &str| serde_json::from_str::<Payment>(json).map(|p| p.amount);
+        assert_eq!(
+            parse(r#"{"amount": "0.5"}"#).unwrap(),
+            Amount::from_sat(50_000_000)
+        );
+        assert_eq!(
+            parse(r#"{"amount": 2}"#).unwrap(),
+            Amount::from_sat(200_000_000)
+        );
+        assert!(parse(r#"{"amount": 0.5}"#).is_err());
+    }
+
+    #[test]
+    fn exact_serialization_keeps_btc_as_a_string() {
+        #[derive(Serialize)]
+        struct Fee {
+            #[serde(with = "exact")]
+            fee: Amount,
+        }
+        let json = serde_json::to_value(Fee {
+            fee: Amount::from_sat(141),
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "fee": { "btc": "0.00000141", "sat": 141 } })
+        );
+    }
+}
@@ -0,0 +1,456 @@
+//! Transaction forensics
+//!
+//! This module demonstrates how to analyze Bitcoin transactions in detail.
+//! Understanding transaction structure is crucial for Bitcoin development.
+//!
+//! ═══════════════════════════════════════════════════════════════
+//! UTXO MODEL ANALYSIS: Understanding Bitcoin's Transaction Structure
+//! ═══════════════════════════════════════════════════════════════
+//!
+//! Key Concept: Bitcoin uses UTXO (Unspent Transaction Output) model
+//!
+//! Unlike bank accounts (balance-based), Bitcoin transactions work like this:
+//! 1. Inputs: Reference specific outputs from previous transactions
+//! 2. Outputs: Create new "coins" that can be spent in future transactions
+//! 3. Rule: Total inputs must equal or exceed total outputs + fees
+//!
+//! Example: If you have a 50 BTC UTXO and want to send 20 BTC:
+//! - Input: Reference your 50 BTC UTXO
+//! - Output 1: 20 BTC to recipient
+//! - Output 2: 29.999 BTC back to you (change)
+//! - Fee: 0.001 BTC (50 - 20 - 29.999 = 0.001)
+//!
+//! Nothing on-chain says which output is the change: both are just outputs. Only the
+//! sender's wallet knows which addresses are its own, so [`analyze`] asks it
+//! (`getaddressinfo`): outputs it owns are change, everything else pays a recipient.
+//! That holds for any number of either, e.g. a batch payment to several recipients.
+//!
+//! What miners care about isn't the fee itself but the fee *rate*: how much it pays
+//! per unit of block space. Since SegWit that space is measured in weight units
+//! (4 per byte of the transaction proper, 1 per byte of witness data, 4,000,000 per
+//! block), or in virtual bytes: a quarter of the weight, rounded up. Everything here
+//! is measured on the decoded transaction itself rather than taken from the wallet,
+//! and [`compare_fee_rate`] puts the result next to what the node currently asks for.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt;
+
+use bitcoincore_rpc::bitcoin::{
+    Address, Amount, Network, OutPoint, SignedAmount, Transaction, Txid,
+};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::amount;
+use crate::error::{Error, Result};
+use crate::script::{ScriptType, SpendPath, TapscriptReveal};
+
+/// One spent output, resolved from the transaction that created it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InputDetail {
+    /// Which output is being spent (previous txid + vout)
+    pub outpoint: OutPoint,
+    /// Value of the spent output
+    #[serde(with = "amount::exact")]
+    pub amount: Amount,
+    /// Address that owned the spent output, if its script decodes to one
+    pub address: Option<String>,
+    /// What kind of script locked the spent output...
+    pub script_type: ScriptType,
+    /// ... and how this input unlocked it (see script.rs)
+    pub spend_path: SpendPath,
+    /// The spent output's script, disassembled, when there's no address to show
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asm: Option<String>,
+    /// For a taproot script path spend: the leaf it revealed and its control block
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tapscript: Option<TapscriptReveal>,
+}
+
+/// What an output is for, from the payer's point of view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputRole {
+    /// Pays a recipient; that can be the sender itself, at one of its receive addresses
+    Payment,
+    /// Returns leftover funds to the sender's change chain
+    Change,
+    /// Couldn't be attributed (e.g. the script doesn't decode to an address)
+    Unknown,
+}
+
+/// What the sender's wallet said about an output's address (`getaddressinfo`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ownership {
+    /// The wallet holds the key: the coins came back to the sender
+    #[serde(rename(deserialize = "ismine"))]
+    pub is_mine: bool,
+    /// ... on its change chain (`getrawchangeaddress`) rather than a receive address
+    #[serde(rename(deserialize = "ischange"), default)]
+    pub is_change: bool,
+    /// Where the key sits in the wallet's HD tree, e.g. m/84h/1h/0h/1/0 for the first
+    /// change key (the second to last step is 0 for receive, 1 for change)
+    #[serde(rename(deserialize = "hdkeypath"), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hd_keypath: Option<String>,
+}
+
+impl Ownership {
+    /// Ask `wallet` about `address`
+    ///
+    /// `getaddressinfo` directly: bitcoincore-rpc's result type predates `ischange`.
+    pub fn of(wallet: &Client, address: &Address) -> Result<Self> {
+        Ok(wallet.call("getaddressinfo", &[json!(address)])?)
+    }
+}
+
+/// One created output
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutputDetail {
+    /// Position in the transaction's output list
+    pub vout: u32,
+    #[serde(with = "amount::exact")]
+    pub amount: Amount,
+    /// Address the output pays to, if its script decodes to one
+    pub address: Option<String>,
+    /// What the sender's wallet knows about that address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ownership: Option<Ownership>,
+    pub script_type: ScriptType,
+    /// The script, disassembled, when there's no address to show
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asm: Option<String>,
+    pub role: OutputRole,
+}
+
+/// Everything we learned about one payment transaction
+///
+/// All values are exact satoshi amounts; nothing here ever goes through `f64`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TransactionAnalysis {
+    pub txid: Txid,
+    /// Every input, in transaction order
+    pub inputs: Vec<InputDetail>,
+    /// Distinct addresses that funded the transaction, in first-seen order
+    pub sender_addresses: Vec<String>,
+    /// Sum of all spent outputs
+    #[serde(with = "amount::exact")]
+    pub total_input_amount: Amount,
+    /// Addresses of every payment output, in output order
+    pub recipient_addresses: Vec<String>,
+    /// Sum of the payment outputs
+    #[serde(with = "amount::exact")]
+    pub recipient_amount: Amount,
+    /// Addresses of every change output, in output order
+    pub change_addresses: Vec<String>,
+    /// Sum of the change outputs
+    #[serde(with = "amount::exact")]
+    pub change_amount: Amount,
+    /// Every output, in transaction order
+    pub outputs: Vec<OutputDetail>,
+    /// Sum of all outputs
+    #[serde(with = "amount::exact")]
+    pub total_output_amount: Amount,
+    /// Serialized size in bytes, witness data included
+    pub size: u64,
+    /// Weight units: 4 per byte outside the witness, 1 per witness byte
+    pub weight: u64,
+    /// Virtual size in vbytes (weight / 4, rounded up) - what fee rates are measured against
+    pub vsize: u64,
+    /// Inputs minus outputs - never negative for a valid transaction
+    #[serde(with = "amount::exact")]
+    pub fee: Amount,
+    /// Fee divided by virtual size; a ratio, not an amount, so a plain number
+    pub fee_rate_sat_per_vb: f64,
+    /// What the transaction did to the sender's funds: whatever came back to its wallet
+    /// (change and payments to itself) minus the inputs, i.e. minus what other people
+    /// were paid and the fee, the way a wallet statement shows it
+    #[serde(with = "amount::exact_signed")]
+    pub sender_net: SignedAmount,
+}
+
+/// Decode `txid` and split it into senders, recipients, change and fee
+///
+/// `rpc` must be able to look up arbitrary transactions (node-level client with
+/// `txindex=1`, or the previous outputs must belong to the wallet). `sender` is the
+/// wallet that paid: the outputs it owns are its change.
+pub fn analyze(
+    rpc: &Client,
+    sender: &Client,
+    txid: &Txid,
+    network: Network,
+) -> Result<TransactionAnalysis> {
+    // get_raw_transaction() gives us the actual transaction data structure
+    let raw_transaction = rpc.get_raw_transaction(txid, None)?;
+
+    // Analyze transaction inputs (where money came from)
+    // A wallet may combine several UTXOs to fund one payment, so every input counts
+    let inputs = resolve_inputs(rpc, &raw_transaction, network)?;
+    let total_input_amount: Amount = inputs.iter().map(|input| input.amount).sum();
+
+    let mut sender_addresses: Vec<String> = Vec::with_capacity(inputs.len());
+    for address in inputs.iter().filter_map(|input| input.address.as_ref()) {
+        if !sender_addresses.contains(address) {
+            sender_addresses.push(address.clone());
+        }
+    }
+
+    // Analyze transaction outputs (where money went)
+    // A Bitcoin transaction typically has 2 outputs:
+    // 1. Payment to recipient (what they requested)
+    // 2. "Change" back to sender (like getting change from a $20 bill)
+    // but a batch payment has one per recipient, and a wallet may split its change
+    let mut outputs = Vec::with_capacity(raw_transaction.output.len());
+
+    for (vout, transaction_output) in (0u32..).zip(&raw_transaction.output) {
+        let script = &transaction_output.script_pubkey;
+
+        // Try to decode the address from this output's script_pubkey
+        let (address, ownership, role) = match Address::from_script(script, network) {
+            Ok(output_address) => {
+                // Only the sender's wallet can tell its own addresses from anyone else's.
+                // Being ours isn't enough to be change: coins sent to one of our receive
+                // addresses are a payment to ourselves (moving savings, consolidating)
+                let ownership = Ownership::of(sender, &output_address)?;
+                let role = if ownership.is_mine && ownership.is_change {
+                    OutputRole::Change
+                } else {
+                    OutputRole::Payment
+                };
+                (Some(output_address.to_string()), Some(ownership), role)
+            },
+            // OP_RETURN, bare multisig and other scripts without an address can't be
+            // payment or change we recognise, but they're still part of the transaction
+            Err(_) => (None, None, OutputRole::Unknown),
+        };
+        outputs.push(OutputDetail {
+            vout,
+            amount: transaction_output.value,
+            ownership,
+            script_type: ScriptType::of(script),
+            asm: address.is_none().then(|| script.to_asm_string()),
+            address,
+            role,
+        });
+    }
+
+    let (recipient_addresses, recipient_amount) = sum_role(&outputs, OutputRole::Payment);
+    let (change_addresses, change_amount) = sum_role(&outputs, OutputRole::Change);
+
+    // Bitcoin transaction fees are calculated as: Total Inputs - Total Outputs
+    // The "missing" money between inputs and outputs becomes the miner's fee
+    // This incentivizes miners to include the transaction in their blocks
+    let total_output_amount: Amount = raw_transaction.output.iter().map(|out| out.value).sum();
+
+    // checked_sub() instead of `-`: outputs exceeding inputs would be an invalid transaction,
+    // so it means we resolved the inputs wrongly - surface that instead of a bogus fee
+    let fee = total_input_amount
+        .checked_sub(total_output_amount)
+        .ok_or_else(|| {
+            Error::Invariant(format!(
+                "outputs ({total_output_amount}) exceed inputs ({total_input_amount}) in {txid}"
+            ))
+        })?;
+    let returned: Amount = outputs
+        .iter()
+        .filter(|output| output.ownership.as_ref().is_some_and(|owner| owner.is_mine))
+        .map(|output| output.amount)
+        .sum();
+    let sender_net = to_signed(returned)? - to_signed(total_input_amount)?;
+
+    // Measured on the transaction we decoded, not on what the wallet told us it paid.
+    // Fee rate is what miners actually sort by: sats paid per unit of block space
+    let vsize = raw_transaction.vsize() as u64;
+    let fee_rate_sat_per_vb = if vsize == 0 {
+        0.0
+    } else {
+        fee.to_sat() as f64 / vsize as f64
+    };
+
+    Ok(TransactionAnalysis {
+        txid: *txid,
+        inputs,
+        sender_addresses,
+        total_input_amount,
+        recipient_addresses,
+        recipient_amount,
+        change_addresses,
+        change_amount,
+        outputs,
+        total_output_amount,
+        size: raw_transaction.total_size() as u64,
+        weight: raw_transaction.weight().to_wu(),
+        vsize,
+        fee,
+        fee_rate_sat_per_vb,
+        sender_net,
+    })
+}
+
+/// Confirmation target to ask `estimatesmartfee` about: about an hour on mainnet,
+/// the "economical" choice most wallets default to
+pub const FEE_ESTIMATE_TARGET_BLOCKS: u16 = 6;
+
+/// A fee rate next to what the node currently asks for
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeeComparison {
+    pub fee_rate_sat_per_vb: f64,
+    /// The lowest rate the node's mempool accepts right now (`getmempoolinfo`); it only
+    /// rises above the relay minimum when the mempool is full
+    pub mempool_min_fee_sat_per_vb: f64,
+    /// Confirmation target `estimatesmartfee` was asked about, in blocks
+    pub estimate_target_blocks: u16,
+    /// The rate the node expects to confirm within that target, if it has enough
+    /// history to say (fresh regtest nodes usually don't)
+    pub estimate_sat_per_vb: Option<f64>,
+    /// Why there is no estimate, as the node put it
+    pub estimate_errors: Vec<String>,
+}
+
+impl FeeComparison {
+    /// Whether the node would accept the transaction into its mempool at this rate
+    pub fn meets_mempool_min(&self) -> bool {
+        self.fee_rate_sat_per_vb >= self.mempool_min_fee_sat_per_vb
+    }
+
+    /// The fee rate as a multiple of the estimate: below 1 means it's likely to wait
+    pub fn estimate_ratio(&self) -> Option<f64> {
+        self.estimate_sat_per_vb
+            .filter(|estimate| *estimate > 0.0)
+            .map(|estimate| self.fee_rate_sat_per_vb / estimate)
+    }
+}
+
+impl fmt::Display for FeeComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.2} sat/vB against a mempool minimum of {:.2} sat/vB ({}); ",
+            self.fee_rate_sat_per_vb,
+            self.mempool_min_fee_sat_per_vb,
+            if self.meets_mempool_min() {
+                "accepted"
+            } else {
+                "too low"
+            }
+        )?;
+        match (self.estimate_sat_per_vb, self.estimate_ratio()) {
+            (Some(estimate), Some(ratio)) => write!(
+                f,
+                "estimate for {} blocks {estimate:.2} sat/vB ({ratio:.2}x)",
+                self.estimate_target_blocks
+            ),
+            _ => write!(
+                f,
+                "no estimate for {} blocks ({})",
+                self.estimate_target_blocks,
+                self.estimate_errors.join("; ")
+            ),
+        }
+    }
+}
+
+/// Compare `fee_rate_sat_per_vb` with the node's mempool minimum and its
+/// `estimatesmartfee` for confirming within `target_blocks`
+pub fn compare_fee_rate(
+    rpc: &Client,
+    fee_rate_sat_per_vb: f64,
+    target_blocks: u16,
+) -> Result<FeeComparison> {
+    // Both come in BTC per 1000 vbytes
+    let per_vb = |btc_per_kvb: Amount| btc_per_kvb.to_sat() as f64 / 1000.0;
+
+    let mempool = rpc.get_mempool_info()?;
+    let estimate = rpc.estimate_smart_fee(target_blocks, None)?;
+    Ok(FeeComparison {
+        fee_rate_sat_per_vb,
+        mempool_min_fee_sat_per_vb: per_vb(mempool.mempool_min_fee),
+        estimate_target_blocks: target_blocks,
+        estimate_sat_per_vb: estimate.fee_rate.map(per_vb),
+        estimate_errors: estimate.errors.unwrap_or_default(),
+    })
+}
+
+/// Addresses and total amount of the outputs with `role`
+fn sum_role(outputs: &[OutputDetail], role: OutputRole) -> (Vec<String>, Amount) {
+    let outputs = outputs.iter().filter(|output| output.role == role);
+    (
+        outputs
+            .clone()
+            .filter_map(|output| output.address.clone())
+            .collect(),
+        outputs.map(|output| output.amount).sum(),
+    )
+}
+
+/// Amounts above 21M BTC can't be signed amounts, nor can they exist on-chain
+fn to_signed(value: Amount) -> Result<SignedAmount> {
+    value
+        .to_signed()
+        .map_err(|e| Error::Invariant(format!("amount {value} out of range: {e}")))
+}
+
+/// Look up the previous output behind every input of `transaction`
+///
+/// Bitcoin transactions don't have "from" addresses directly:
+/// each input only names an outpoint, so we fetch the transaction that created it.
+pub fn resolve_inputs(
+    rpc: &Client,
+    transaction: &Transaction,
+    network: Network,
+) -> Result<Vec<InputDetail>> {
+    // A coinbase input spends nothing - the block subsidy has no previous output to resolve
+    if transaction.is_coinbase() {
+        return Err(Error::Invariant(format!(
+            "{} is a coinbase transaction; it has no previous outputs to analyze",
+            transaction.txid()
+        )));
+    }
+
+    // Several inputs often spend outputs of the same parent; fetch each parent once
+    let mut parents: HashMap<Txid, Transaction> = HashMap::new();
+    let mut inputs = Vec::with_capacity(transaction.input.len());
+
+    for transaction_input in &transaction.input {
+        // previous_output.txid = the transaction ID we're spending from
+        // previous_output.vout = which output index from that transaction
+        let outpoint = transaction_input.previous_output;
+        let parent = match parents.entry(outpoint.txid) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                entry.insert(rpc.get_raw_transaction(&outpoint.txid, None)?)
+            },
+        };
+
+        // A missing output means the node handed us inconsistent data - report it, don't panic
+        let previous_output = parent.output.get(outpoint.vout as usize).ok_or_else(|| {
+            Error::Invariant(format!("input spends {outpoint}, which does not exist"))
+        })?;
+
+        // Decode the address from the script_pubkey (Bitcoin's locking script)
+        // script_pubkey defines the conditions needed to spend this output
+        let script = &previous_output.script_pubkey;
+        let address = Address::from_script(script, network)
+            .ok()
+            .map(|addr| addr.to_string());
+
+        // The input's own scriptSig and witness show how those conditions were met
+        inputs.push(InputDetail {
+            outpoint,
+            amount: previous_output.value,
+            script_type: ScriptType::of(script),
+            spend_path: SpendPath::of(
+                script,
+                &transaction_input.script_sig,
+                &transaction_input.witness,
+            ),
+            asm: address.is_none().then(|| script.to_asm_string()),
+            tapscript: TapscriptReveal::of(script, &transaction_input.witness),
+            address,
+        });
+    }
+
+    Ok(inputs)
+}
@@ -0,0 +1,335 @@
+//! RPC credential discovery
+//!
+//! Bitcoin Core accepts three kinds of RPC authentication:
+//! - `rpcuser`/`rpcpassword` in bitcoin.conf (plain text, what this repo ships)
+//! - `rpcauth=user:salt$hash` (only the *server* can use it: the password is hashed)
+//! - the `.cookie` file bitcoind writes into its datadir when no password is configured
+//!
+//! We try the sources from most to least explicit and remember which one won,
+//! so the user can see why a connection was (or wasn't) authorized.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use bitcoincore_rpc::Auth;
+use bitcoincore_rpc::bitcoin::Network;
+
+use crate::conf::{self, BitcoinConf};
+use crate::error::{Error, Result};
+
+/// Credentials used when nothing else is configured; they match the repo's bitcoin.conf
+const FALLBACK_RPC_USER: &str = "alice";
+const FALLBACK_RPC_PASS: &str = "password";
+
+/// Where the RPC credentials came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialSource {
+    /// `--rpc-user`/`--rpc-password` (or their environment variables)
+    CommandLine,
+    /// `rpcuser`/`rpcpassword` read from a bitcoin.conf file
+    ConfigFile(PathBuf),
+    /// A `.cookie` file written by bitcoind
+    CookieFile(PathBuf),
+    /// Nothing was configured; the repo's regtest defaults were used
+    Fallback,
+}
+
+impl fmt::Display for CredentialSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CommandLine => write!(f, "command-line/environment rpc user and password"),
+            Self::ConfigFile(path) => write!(f, "rpcuser/rpcpassword from {}", path.display()),
+            Self::CookieFile(path) => write!(f, "cookie file {}", path.display()),
+            Self::Fallback => write!(f, "built-in regtest defaults ({FALLBACK_RPC_USER})"),
+        }
+    }
+}
+
+/// Resolved authentication plus its provenance
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub auth: Auth,
+    pub source: CredentialSource,
+    /// Something the user should know about how the source was picked (e.g. an
+    /// `rpcauth=` line that had to be passed over); printing it is up to the caller
+    pub note: Option<String>,
+}
+
+/// Everything the user may have told us about authentication
+#[derive(Debug, Clone, Default)]
+pub struct AuthOptions<'a> {
+    pub rpc_user: Option<&'a str>,
+    pub rpc_password: Option<&'a str>,
+    pub cookie_file: Option<&'a Path>,
+    pub conf: Option<&'a Path>,
+    pub datadir: Option<&'a Path>,
+}
+
+/// Work out which credentials to use for `network`
+///
+/// Order of precedence:
+/// 1. explicit `--rpc-user` + `--rpc-password`
+/// 2. explicit `--rpc-cookie-file`
+/// 3. `rpcuser`/`rpcpassword` in bitcoin.conf (`--conf`, or `<datadir>/bitcoin.conf`)
+/// 4. the `.cookie` file in the network's datadir (or `rpccookiefile=` from the conf)
+/// 5. the built-in regtest defaults
+pub fn discover(options: &AuthOptions<'_>, network: Network) -> Result<Credentials> {
+    match (options.rpc_user, options.rpc_password) {
+        (Some(user), Some(pass)) => {
+            return Ok(Credentials {
+                auth: Auth::UserPass(user.to_owned(), pass.to_owned()),
+                source: CredentialSource::CommandLine,
+                note: None,
+            });
+        },
+        (Some(_), None) | (None, Some(_)) => {
+            return Err(Error::Config(
+                "--rpc-user and --rpc-password must be given together".to_owned(),
+            ));
+        },
+        (None, None) => {},
+    }
+
+    if let Some(cookie) = options.cookie_file {
+        // An explicit cookie path that doesn't exist is a user error, not something to skip
+        if !cookie.is_file() {
+            return Err(Error::Config(format!(
+                "cookie file {} does not exist",
+                cookie.display()
+            )));
+        }
+        return Ok(cookie_credentials(cookie.to_path_buf()));
+    }
+
+    let datadir = options
+        .datadir
+        .map(Path::to_path_buf)
+        .or_else(conf::default_datadir);
+
+    // An explicit --conf must exist; the default location is optional
+    let conf_path = match options.conf {
+        Some(path) => Some(path.to_path_buf()),
+        None => datadir
+            .as_ref()
+            .map(|dir| dir.join("bitcoin.conf"))
+            .filter(|p| p.is_file()),
+    };
+    let conf = match &conf_path {
+        Some(path) => BitcoinConf::from_file(path)?,
+        None => BitcoinConf::default(),
+    };
+
+    if let (Some(path), Some(user), Some(pass)) = (
+        &conf_path,
+        conf.get("rpcuser", network),
+        conf.get("rpcpassword", network),
+    ) {
+        return Ok(Credentials {
+            auth: Auth::UserPass(user.to_owned(), pass.to_owned()),
+            source: CredentialSource::ConfigFile(path.clone()),
+            note: None,
+        });
+    }
+    let note = match (&conf_path, conf.get("rpcauth", network)) {
+        (Some(path), Some(_)) => Some(format!(
+            "{} only has rpcauth= (a salted hash); it cannot be used as a client password",
+            path.display()
+        )),
+        _ => None,
+    };
+
+    // The conf may relocate the datadir or the cookie itself
+    let datadir = conf.get("datadir", network).map(PathBuf::from).or(datadir);
+    let cookie = match conf.get("rpccookiefile", network).map(PathBuf::from) {
+        Some(path) if path.is_absolute() => Some(path),
+        Some(relative) => datadir
+            .as_ref()
+            .map(|dir| network_dir(dir, network).join(relative)),
+        None => datadir
+            .as_ref()
+            .map(|dir| network_dir(dir, network).join(".cookie")),
+    };
+    if let Some(cookie) = cookie.filter(|path| path.is_file()) {
+        return Ok(Credentials {
+            note,
+            ..cookie_credentials(cookie)
+        });
+    }
+
+    Ok(Credentials {
+        auth: Auth::UserPass(FALLBACK_RPC_USER.to_owned(), FALLBACK_RPC_PASS.to_owned()),
+        source: CredentialSource::Fallback,
+        note,
+    })
+}
+
+fn cookie_credentials(path: PathBuf) -> Credentials {
+    Credentials {
+        auth: Auth::CookieFile(path.clone()),
+        source: CredentialSource::CookieFile(path),
+        note: None,
+    }
+}
+
+/// `<datadir>/<chain>` for test networks, the datadir itself for mainnet
+fn network_dir(datadir: &Path, network: Network) -> PathBuf {
+    match conf::chain_subdir(network) {
+        Some(subdir) => datadir.join(subdir),
+        None => datadir.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// A datadir holding `conf` as its bitcoin.conf
+    fn datadir_with(conf: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("bitcoin.conf"), conf).unwrap();
+        dir
+    }
+
+    fn user_pass(credentials: &Credentials) -> (&str, &str) {
+        match &credentials.auth {
+            Auth::UserPass(user, pass) => (user, pass),
+            other => panic!("expected a user and password, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn command_line_wins_over_everything() {
+        let dir = datadir_with("rpcuser=conf\nrpcpassword=conf-secret\n");
+        let options = AuthOptions {
+            rpc_user: Some("bob"),
+            rpc_password: Some("hunter2"),
+            datadir: Some(dir.path()),
+            ..AuthOptions::default()
+        };
+        let credentials = discover(&options, Network::Regtest).unwrap();
+        assert_eq!(credentials.source, CredentialSource::CommandLine);
+        assert_eq!(user_pass(&credentials), ("bob", "hunter2"));
+    }
+
+    #[test]
+    fn user_without_password_is_refused() {
+        let options = AuthOptions {
+            rpc_user: Some("bob"),
+            ..AuthOptions::default()
+        };
+        let error = discover(&options, Network::Regtest).unwrap_err();
+        assert!(matches!(error, Error::Config(_)), "{error}");
+    }
+
+    #[test]
+    fn explicit_cookie_file_wins_over_the_conf() {
+        let dir = datadir_with("rpcuser=conf\nrpcpassword=conf-secret\n");
+        let cookie = dir.path().join("my.cookie");
+        fs::write(&cookie, "__cookie__:abc").unwrap();
+        let options = AuthOptions {
+            cookie_file: Some(&cookie),
+            datadir: Some(dir.path()),
+            ..AuthOptions::default()
+        };
+        let credentials = discover(&options, Network::Regtest).unwrap();
+        assert_eq!(
+            credentials.source,
+            CredentialSource::CookieFile(cookie.clone())
+        );
+
+        // ... and must exist, rather than being skipped
+        let missing = dir.path().join("missing.cookie");
+        let options = AuthOptions {
+            cookie_file: Some(&missing),
+            ..options
+        };
+        let error = discover(&options, Network::Regtest).unwrap_err();
+        assert!(matches!(error, Error::Config(_)), "{error}");
+    }
+
+    #[test]
+    fn conf_credentials_come_from_the_network_section() {
+        let dir = datadir_with(
+            "rpcuser=top\nrpcpassword=top-secret\n[regtest]\nrpcuser=alice\nrpcpassword=password\n",
+        );
+        let options = AuthOptions {
+            datadir: Some(dir.path()),
+            ..AuthOptions::default()
+        };
+        let credentials = discover(&options, Network::Regtest).unwrap();
+        assert_eq!(
+            credentials.source,
+            CredentialSource::ConfigFile(dir.path().join("bitcoin.conf"))
+        );
+        assert_eq!(user_pass(&credentials), ("alice", "password"));
+        // Other networks don't see the [regtest] section
+        let credentials = discover(&options, Network::Testnet).unwrap();
+        assert_eq!(user_pass(&credentials), ("top", "top-secret"));
+    }
+
+    #[test]
+    fn explicit_conf_is_read_instead_of_the_datadir_one() {
+        let dir = datadir_with("rpcuser=datadir\nrpcpassword=datadir-secret\n");
+        let conf = dir.path().join("other.conf");
+        fs::write(&conf, "rpcuser=other\nrpcpassword=other-secret\n").unwrap();
+        let options = AuthOptions {
+            conf: Some(&conf),
+            datadir: Some(dir.path()),
+            ..AuthOptions::default()
+        };
+        let credentials = discover(&options, Network::Regtest).unwrap();
+        assert_eq!(credentials.source, CredentialSource::ConfigFile(conf));
+        assert_eq!(user_pass(&credentials), ("other", "other-secret"));
+    }
+
+    #[test]
+    fn user_without_password_in_the_conf_falls_through_to_the_cookie() {
+        let dir = datadir_with("rpcuser=alice\n");
+        fs::create_dir(dir.path().join("regtest")).unwrap();
+        let cookie = dir.path().join("regtest/.cookie");
+        fs::write(&cookie, "__cookie__:abc").unwrap();
+        let options = AuthOptions {
+            datadir: Some(dir.path()),
+            ..AuthOptions::default()
+        };
+        let credentials = discover(&options, Network::Regtest).unwrap();
+        assert_eq!(credentials.source, CredentialSource::CookieFile(cookie));
+        assert_eq!(credentials.note, None);
+    }
+
+    #[test]
+    fn relative_cookie_file_is_resolved_in_the_network_datadir() {
+        let dir = datadir_with("[regtest]\nrpccookiefile=auth/rpc.cookie\n");
+        fs::create_dir_all(dir.path().join("regtest/auth")).unwrap();
+        let cookie = dir.path().join("regtest/auth/rpc.cookie");
+        fs::write(&cookie, "__cookie__:abc").unwrap();
+        // The default .cookie is passed over for the configured one
+        fs::write(dir.path().join("regtest/.cookie"), "__cookie__:old").unwrap();
+        let options = AuthOptions {
+            datadir: Some(dir.path()),
+            ..AuthOptions::default()
+        };
+        let credentials = discover(&options, Network::Regtest).unwrap();
+        assert_eq!(credentials.source, CredentialSource::CookieFile(cookie));
+    }
+
+    #[test]
+    fn nothing_configured_falls_back_to_the_repo_defaults() {
+        let dir = datadir_with("rpcauth=alice:salt$hash\n");
+        let options = AuthOptions {
+            datadir: Some(dir.path()),
+            ..AuthOptions::default()
+        };
+        let credentials = discover(&options, Network::Regtest).unwrap();
+        assert_eq!(credentials.source, CredentialSource::Fallback);
+        assert_eq!(
+            user_pass(&credentials),
+            (FALLBACK_RPC_USER, FALLBACK_RPC_PASS)
+        );
+        // The rpcauth= line can't be used, and the caller is told why
+        let note = credentials.note.unwrap();
+        assert!(note.contains("only has rpcauth="), "{note}");
+    }
+}
@@ -0,0 +1,425 @@
+//! Managed bitcoind: spawn, wait for RPC, tear down
+//!
+//! Instead of relying on docker-compose or run.sh to have started a node out-of-band,
+//! [`Bitcoind::spawn`] launches a local `bitcoind` binary itself:
+//! 1. a fresh temporary datadir gets a bitcoin.conf generated from the repo's one:
+//!    same settings, but RPC and P2P bound to free localhost ports, so it can't clash
+//!    with a node (or docker container) that is already running
+//! 2. the process is started and RPC is polled until it answers; errors that only
+//!    mean "not yet" (connection refused, -28 "Loading block index…") are retried
+//! 3. on drop, including while unwinding from a panic, the node is asked to `stop`,
+//!    killed if it doesn't exit in time, and the datadir is deleted
+//! 4. a signal (Ctrl-C, `kill`) skips every drop, so [`stop_all_on_signal`] installs a
+//!    handler that kills whatever nodes are still running and deletes their datadirs
+//!
+//! [`wait_for_rpc`] is the same readiness check for nodes started some other way.
+
+use std::fs::{self, File};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bitcoincore_rpc::bitcoin::Network;
+use bitcoincore_rpc::{RpcApi, jsonrpc};
+use tempfile::TempDir;
+
+use crate::auth::{self, AuthOptions};
+use crate::conf::{self, BitcoinConf};
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::progress::{self, Progress};
+
+/// Bitcoin Core's error code for "still starting up" (`Loading block index…`, `Verifying blocks…`)
+pub const RPC_IN_WARMUP: i32 = -28;
+
+/// How long `stop` gets before the process is killed
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Lines of bitcoind's output to include when it dies during startup
+const OUTPUT_TAIL_LINES: usize = 20;
+
+/// How to launch a [`Bitcoind`]
+#[derive(Debug, Clone)]
+pub struct BitcoindOptions {
+    /// The bitcoind executable; a bare name is looked up on `PATH`
+    pub executable: PathBuf,
+    /// bitcoin.conf to base the node's config on (default: [`DEFAULT_CONF_TEMPLATE`])
+    pub conf_template: Option<PathBuf>,
+    pub network: Network,
+    /// Extra command-line arguments, e.g. `-fallbackfee=0.0002`
+    pub extra_args: Vec<String>,
+    /// How long to wait for RPC to come up
+    pub startup_timeout: Duration,
+    /// Where "Waiting for …" messages go while it starts (default: nowhere)
+    pub progress: Progress,
+}
+
+impl Default for BitcoindOptions {
+    fn default() -> Self {
+        Self {
+            executable: PathBuf::from("bitcoind"),
+            conf_template: None,
+            network: Network::Regtest,
+            extra_args: Vec::new(),
+            startup_timeout: Duration::from_secs(60),
+            progress: progress::quiet,
+        }
+    }
+}
+
+/// The repo's bitcoin.conf (the one docker-compose.yaml mounts)
+///
+/// Built in rather than read at run time, so a spawned node doesn't depend on where the
+/// binary runs, yet stays in sync with the file. Its RPC and P2P settings get replaced.
+pub const DEFAULT_CONF_TEMPLATE: &str = include_str!("../../bitcoin.conf");
+
+/// Nodes spawned by this process that haven't been stopped yet, for the signal handler
+static RUNNING: Mutex<Vec<Arc<Process>>> = Mutex::new(Vec::new());
+
+/// A running bitcoind owned by this process; stopped and cleaned up when dropped
+#[derive(Debug)]
+pub struct Bitcoind {
+    process: Arc<Process>,
+    datadir: TempDir,
+    connection: Connection,
+    rpc_port: u16,
+    p2p_port: u16,
+}
+
+impl Bitcoind {
+    /// Start bitcoind in a temporary datadir and wait until its RPC server is ready
+    pub fn spawn(options: &BitcoindOptions) -> Result<Self> {
+        let datadir = tempfile::Builder::new().prefix("bitcoind-").tempdir()?;
+        let rpc_port = free_port()?;
+        let p2p_port = free_port()?;
+
+        let template = match &options.conf_template {
+            Some(path) => BitcoinConf::from_file(path)?,
+            None => BitcoinConf::parse(DEFAULT_CONF_TEMPLATE),
+        };
+        let conf = node_conf(template, options.network, rpc_port, p2p_port);
+        let conf_path = datadir.path().join("bitcoin.conf");
+        fs::write(&conf_path, conf.to_string())?;
+        // Settled before the process exists: once it does, nothing may fail until
+        // `node` owns it, or bitcoind would outlive its deleted datadir
+        let url = format!("http://127.0.0.1:{rpc_port}");
+        let credentials = discover_credentials(datadir.path(), options.network)?;
+
+        // bitcoind logs to debug.log; stdout/stderr only carry startup errors,
+        // which we want to show if it dies before RPC comes up
+        let output = File::create(output_path(datadir.path()))?;
+        let child = Command::new(&options.executable)
+            .arg(format!("-datadir={}", datadir.path().display()))
+            .arg(format!("-conf={}", conf_path.display()))
+            .args(&options.extra_args)
+            .stdin(Stdio::null())
+            .stdout(output.try_clone()?)
+            .stderr(output)
+            .spawn()
+            .map_err(|e| {
+                Error::Node(format!(
+                    "could not start {}: {e}",
+                    options.executable.display()
+                ))
+            })?;
+        let process = Arc::new(Process {
+            child: Mutex::new(child),
+            datadir: datadir.path().to_path_buf(),
+        });
+        lock(&RUNNING).push(Arc::clone(&process));
+        let mut node = Self {
+            process,
+            datadir,
+            connection: Connection::new(&url, credentials, options.network),
+            rpc_port,
+            p2p_port,
+        };
+        // If this fails, dropping `node` kills the process and removes the datadir
+        node.wait_until_ready(options.startup_timeout, options.progress)?;
+        Ok(node)
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn datadir(&self) -> &Path {
+        self.datadir.path()
+    }
+
+    pub fn rpc_port(&self) -> u16 {
+        self.rpc_port
+    }
+
+    /// Port the node accepts peer connections on (localhost only)
+    pub fn p2p_port(&self) -> u16 {
+        self.p2p_port
+    }
+
+    /// Stop the node now and report how that went (dropping it does the same, silently)
+    pub fn stop(mut self) -> Result<()> {
+        self.shutdown()
+    }
+
+    fn wait_until_ready(&mut self, timeout: Duration, progress: Progress) -> Result<()> {
+        let network = self.connection.network();
+        let url = self.connection.url().to_owned();
+        poll_until_ready(&url, timeout, progress, || {
+            if let Some(status) = self.process.try_wait()? {
+                return Err(Error::Node(format!(
+                    "bitcoind exited during startup ({status}):\n{}",
+                    output_tail(self.datadir.path())
+                )));
+            }
+            // Without rpcuser/rpcpassword the .cookie only appears once RPC is up
+            let credentials = discover_credentials(self.datadir.path(), network)?;
+            self.connection = Connection::new(&url, credentials, network);
+            probe(&self.connection)
+        })
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        if self.process.try_wait()?.is_some() {
+            return Ok(());
+        }
+
+        // Ask nicely first, so bitcoind flushes its chainstate and wallets;
+        // a node that can't even take the request gets killed straight away
+        let stopping = self
+            .connection
+            .client(None)
+            .and_then(|rpc| rpc.stop())
+            .is_ok();
+        let deadline = Instant::now()
+            + if stopping {
+                STOP_TIMEOUT
+            } else {
+                Duration::ZERO
+            };
+        while Instant::now() < deadline {
+            if self.process.try_wait()?.is_some() {
+                return Ok(());
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        self.process.kill()?;
+        Ok(())
+    }
+}
+
+impl Drop for Bitcoind {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+        lock(&RUNNING).retain(|process| !Arc::ptr_eq(process, &self.process));
+    }
+}
+
+/// The bitcoind process of a [`Bitcoind`], shared with the signal handler
+#[derive(Debug)]
+struct Process {
+    child: Mutex<Child>,
+    datadir: PathBuf,
+}
+
+impl Process {
+    fn try_wait(&self) -> Result<Option<std::process::ExitStatus>> {
+        Ok(lock(&self.child).try_wait()?)
+    }
+
+    fn kill(&self) -> Result<()> {
+        let mut child = lock(&self.child);
+        child.kill()?;
+        child.wait()?;
+        Ok(())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// On SIGINT or SIGTERM, kill every node spawned so far, delete their datadirs and exit
+///
+/// A signal ends the process without running any `Drop`, which would leave bitcoind
+/// running and its temporary datadir behind. There is no time to ask for a clean `stop`,
+/// nor any need: the datadir is thrown away. The exit status is 130 for both signals.
+/// Only one handler can be installed per process.
+pub fn stop_all_on_signal() -> Result<()> {
+    ctrlc::set_handler(|| {
+        for process in std::mem::take(&mut *lock(&RUNNING)) {
+            let _ = process.kill();
+            let _ = fs::remove_dir_all(&process.datadir);
+        }
+        // 128 + SIGINT, as shells report a command interrupted with Ctrl-C. Deliberately
+        // the same for SIGTERM: ctrlc doesn't say which signal arrived, and either way
+        // the run was interrupted rather than failed
+        std::process::exit(130);
+    })
+    .map_err(|e| Error::Node(format!("could not install the signal handler: {e}")))
+}
+
+/// Wait until the node behind `connection` answers RPC, retrying for up to `timeout`
+///
+/// A zero timeout checks once, which still turns "connection refused" into an error
+/// that says which node couldn't be reached.
+pub fn wait_for_rpc(
+    connection: &Connection,
+    timeout: Duration,
+    progress: Progress,
+) -> Result<()> {
+    poll_until_ready(connection.url(), timeout, progress, || probe(connection))
+}
+
+/// What one readiness check found
+pub(crate) enum Probe {
+    Ready,
+    /// Not answering yet, and why
+    NotYet(String),
+}
+
+fn probe(connection: &Connection) -> Result<Probe> {
+    use bitcoincore_rpc::Error::JsonRpc;
+
+    match connection.client(None)?.get_block_count() {
+        Ok(_) => Ok(Probe::Ready),
+        Err(JsonRpc(jsonrpc::Error::Rpc(e))) if e.code == RPC_IN_WARMUP => {
+            Ok(Probe::NotYet(e.message))
+        },
+        // Refused/reset connections and 401s while the cookie is being written
+        Err(JsonRpc(jsonrpc::Error::Transport(e))) => Ok(Probe::NotYet(e.to_string())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Run `check` until it reports [`Probe::Ready`] or `timeout` has passed
+///
+/// `what` names the thing being waited for in the error, and in the messages sent to
+/// `progress` whenever the reason it isn't ready yet changes.
+pub(crate) fn poll_until_ready(
+    what: &str,
+    timeout: Duration,
+    progress: Progress,
+    mut check: impl FnMut() -> Result<Probe>,
+) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    let mut last_reason = String::new();
+    loop {
+        let reason = match check()? {
+            Probe::Ready => return Ok(()),
+            Probe::NotYet(reason) => reason,
+        };
+        if Instant::now() >= deadline {
+            return Err(Error::Node(format!(
+                "{what} not ready after {}s: {reason}",
+                timeout.as_secs()
+            )));
+        }
+        if reason != last_reason {
+            progress(&format!("Waiting for {what}: {reason}"));
+            last_reason = reason;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// The template's settings, with everything that decides *where* the node lives
+/// replaced: chain, datadir and localhost-only RPC/P2P ports
+fn node_conf(
+    mut conf: BitcoinConf,
+    network: Network,
+    rpc_port: u16,
+    p2p_port: u16,
+) -> BitcoinConf {
+    for key in [
+        "regtest",
+        "testnet",
+        "signet",
+        "chain",
+        "datadir",
+        "daemon",
+        "rpcbind",
+        "rpcallowip",
+        "rpcport",
+        "bind",
+        "port",
+    ] {
+        conf.remove(key);
+    }
+
+    let section = Some(conf::section_name(network));
+    conf.set(None, "chain", network.to_core_arg());
+    conf.set(section, "rpcbind", "127.0.0.1");
+    conf.set(section, "rpcallowip", "127.0.0.1");
+    conf.set(section, "rpcport", &rpc_port.to_string());
+    conf.set(section, "bind", "127.0.0.1");
+    conf.set(section, "port", &p2p_port.to_string());
+    conf
+}
+
+/// Let the OS pick an unused port; it's released again right away for bitcoind to take
+fn free_port() -> Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+fn discover_credentials(datadir: &Path, network: Network) -> Result<auth::Credentials> {
+    let conf = datadir.join("bitcoin.conf");
+    let options = AuthOptions {
+        conf: Some(&conf),
+        datadir: Some(datadir),
+        ..AuthOptions::default()
+    };
+    auth::discover(&options, network)
+}
+
+fn output_path(datadir: &Path) -> PathBuf {
+    datadir.join("bitcoind.out")
+}
+
+fn output_tail(datadir: &Path) -> String {
+    let output = fs::read_to_string(output_path(datadir)).unwrap_or_default();
+    let lines: Vec<&str> = output.lines().collect();
+    lines[lines.len().saturating_sub(OUTPUT_TAIL_LINES)..].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_conf_moves_the_node_to_private_ports() {
+        let template = BitcoinConf::parse(
+            "regtest=1\nserver=1\n[regtest]\nrpcbind=0.0.0.0:18443\nrpcport=18443\n\
+             rpcuser=alice\ntxindex=1\nfallbackfee=0.00001\n",
+        );
+        let conf = node_conf(template, Network::Regtest, 28443, 28444);
+        let conf = BitcoinConf::parse(&conf.to_string());
+
+        assert_eq!(conf.get("chain", Network::Regtest), Some("regtest"));
+        assert_eq!(conf.get("rpcbind", Network::Regtest), Some("127.0.0.1"));
+        assert_eq!(conf.get("rpcport", Network::Regtest), Some("28443"));
+        assert_eq!(conf.get("port", Network::Regtest), Some("28444"));
+        assert_eq!(conf.get("regtest", Network::Regtest), None);
+        // Everything else carries over from the template
+        assert_eq!(conf.get("rpcuser", Network::Regtest), Some("alice"));
+        assert_eq!(conf.get("txindex", Network::Regtest), Some("1"));
+        assert_eq!(conf.get("fallbackfee", Network::Regtest), Some("0.00001"));
+    }
+
+    #[test]
+    fn default_template_keeps_the_repo_settings_on_private_ports() {
+        let template = BitcoinConf::parse(DEFAULT_CONF_TEMPLATE);
+        assert_eq!(template.get("rpcuser", Network::Regtest), Some("alice"));
+        assert_eq!(template.get("txindex", Network::Regtest), Some("1"));
+
+        let conf = node_conf(template, Network::Regtest, 28443, 28444);
+        let conf = BitcoinConf::parse(&conf.to_string());
+        assert_eq!(conf.get("rpcpassword", Network::Regtest), Some("password"));
+        // The docker setup listens on every interface; a spawned node must not
+        assert_eq!(conf.get("rpcbind", Network::Regtest), Some("127.0.0.1"));
+        assert_eq!(conf.get("rpcallowip", Network::Regtest), Some("127.0.0.1"));
+    }
+}
@@ -0,0 +1,332 @@
+//! Several nodes peered into one regtest network
+//!
+//! A single node can't show propagation, relay policy or two miners racing each other.
+//! [`Cluster::spawn`] starts N [`Bitcoind`]s, each on its own ports, and connects them
+//! with `addnode`; [`Cluster::new`] does the connecting for nodes started some other
+//! way (docker, or [`MockNode`](crate::mock::MockNode)s in tests).
+//!
+//! Once connected, blocks and transactions travel between the nodes on their own, some
+//! time after the call that created them returned. Before looking at another node,
+//! wait for the network to catch up:
+//! - [`wait_for_same_tip`]: every node has the same best block
+//! - [`wait_for_mempools`]: a transaction has reached every mempool
+//! - [`wait_for_sync`]: same tip and identical mempools (Core's `sync_all`)
+//!
+//! [`Cluster::partition`] splits the network in two and [`Cluster::heal`] joins it
+//! again; whatever the halves mined in between, the longer branch wins everywhere
+//! (see reorg.rs for what that does to the other one's transactions).
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use bitcoincore_rpc::bitcoin::{BlockHash, Txid};
+use bitcoincore_rpc::{RpcApi, jsonrpc};
+use serde::Deserialize;
+
+use crate::bitcoind::{self, Bitcoind, BitcoindOptions, Probe};
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::progress::Progress;
+
+/// How long [`Cluster::new`]/[`Cluster::spawn`] wait for the P2P handshakes
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Core's error code for `disconnectnode` on a peer it isn't connected to
+const RPC_CLIENT_NODE_NOT_CONNECTED: i32 = -29;
+
+/// One member of a cluster
+#[derive(Debug, Clone)]
+pub struct ClusterNode {
+    pub connection: Connection,
+    /// `host:port` its peers connect to (the P2P port, not RPC)
+    pub p2p_address: String,
+}
+
+/// Which nodes get connected to which
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topology {
+    /// 0–1, 1–2, …: news from one end takes several hops to reach the other
+    #[default]
+    Line,
+    /// Every node connected to every other one
+    Mesh,
+}
+
+impl Topology {
+    /// `(from, to)` pairs of node indices; `from` is the one that runs `addnode`
+    fn links(self, count: usize) -> Vec<(usize, usize)> {
+        match self {
+            Self::Line => (1..count).map(|to| (to - 1, to)).collect(),
+            Self::Mesh => (0..count)
+                .flat_map(|from| (from + 1..count).map(move |to| (from, to)))
+                .collect(),
+        }
+    }
+}
+
+/// A set of connected nodes; the ones it spawned are stopped when it's dropped
+#[derive(Debug)]
+pub struct Cluster {
+    nodes: Vec<ClusterNode>,
+    /// `(from, to)` node indices of every connection the topology calls for
+    links: Vec<(usize, usize)>,
+    /// Nodes this cluster started itself (none for [`Cluster::new`])
+    _spawned: Vec<Bitcoind>,
+    /// Where waiting for (dis)connections is reported
+    progress: Progress,
+}
+
+impl Cluster {
+    /// Start `count` bitcoinds, each with its own datadir and ports, and connect them
+    pub fn spawn(count: usize, options: &BitcoindOptions, topology: Topology) -> Result<Self> {
+        // If one fails to start, dropping the ones already running stops them again
+        let spawned = (0..count)
+            .map(|_| Bitcoind::spawn(options))
+            .collect::<Result<Vec<_>>>()?;
+        let nodes = spawned
+            .iter()
+            .map(|node| ClusterNode {
+                connection: node.connection().clone(),
+                p2p_address: format!("127.0.0.1:{}", node.p2p_port()),
+            })
+            .collect();
+
+        let cluster = Self {
+            links: topology.links(spawned.len()),
+            nodes,
+            _spawned: spawned,
+            progress: options.progress,
+        };
+        cluster.connect()?;
+        Ok(cluster)
+    }
+
+    /// Connect nodes that are already running
+    ///
+    /// `progress` hears about handshakes that take a while, now and in
+    /// [`Cluster::partition`]/[`Cluster::heal`].
+    pub fn new(
+        nodes: Vec<ClusterNode>,
+        topology: Topology,
+        progress: Progress,
+    ) -> Result<Self> {
+        let cluster = Self {
+            links: topology.links(nodes.len()),
+            nodes,
+            _spawned: Vec::new(),
+            progress,
+        };
+        cluster.connect()?;
+        Ok(cluster)
+    }
+
+    pub fn nodes(&self) -> &[ClusterNode] {
+        &self.nodes
+    }
+
+    /// Every node's RPC connection, in node order
+    pub fn connections(&self) -> Vec<Connection> {
+        self.nodes
+            .iter()
+            .map(|node| node.connection.clone())
+            .collect()
+    }
+
+    /// Cut every connection between the nodes in `side` and the others
+    ///
+    /// Each half keeps mining and relaying among itself, unaware of the other, until
+    /// [`Cluster::heal`]. Returns once every cut connection is gone.
+    pub fn partition(&self, side: &[usize]) -> Result<()> {
+        if let Some(node) = side.iter().find(|&&node| node >= self.nodes.len()) {
+            return Err(Error::Config(format!(
+                "can't partition off node {node}: the cluster has {} nodes",
+                self.nodes.len()
+            )));
+        }
+
+        for &(from, to) in &self.links {
+            if side.contains(&from) == side.contains(&to) {
+                continue;
+            }
+            let rpc = self.nodes[from].connection.client(None)?;
+            let address = &self.nodes[to].p2p_address;
+            match rpc.disconnect_node(address) {
+                // Cut by an earlier partition already
+                Err(bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(e)))
+                    if e.code == RPC_CLIENT_NODE_NOT_CONNECTED => {},
+                result => result?,
+            }
+            bitcoind::poll_until_ready(
+                &format!("disconnection of node {from} from node {to}"),
+                CONNECT_TIMEOUT,
+                self.progress,
+                || {
+                    Ok(match outbound_peer(&rpc, address)? {
+                        None => Probe::Ready,
+                        Some(_) => Probe::NotYet(format!("still connected to {address}")),
+                    })
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Restore every connection of the topology, e.g. after [`Cluster::partition`]
+    ///
+    /// The halves then sync up on their own: wait for it with [`wait_for_sync`].
+    pub fn heal(&self) -> Result<()> {
+        self.connect()
+    }
+
+    /// `addnode … onetry` for every link, then wait until every handshake is done
+    ///
+    /// `onetry` connects right away, like Core's own functional tests do; `add` would
+    /// only be picked up by the node's next round of connection attempts. Links that
+    /// are already up are left alone.
+    fn connect(&self) -> Result<()> {
+        for &(from, to) in &self.links {
+            self.nodes[from]
+                .connection
+                .client(None)?
+                .onetry_node(&self.nodes[to].p2p_address)?;
+        }
+
+        for &(from, to) in &self.links {
+            let rpc = self.nodes[from].connection.client(None)?;
+            let address = &self.nodes[to].p2p_address;
+            bitcoind::poll_until_ready(
+                &format!("connection from node {from} to node {to}"),
+                CONNECT_TIMEOUT,
+                self.progress,
+                || {
+                    // Version 0 means the handshake hasn't completed yet
+                    let connected =
+                        outbound_peer(&rpc, address)?.is_some_and(|peer| peer.version > 0);
+                    Ok(if connected {
+                        Probe::Ready
+                    } else {
+                        Probe::NotYet(format!("no handshake with {address} yet"))
+                    })
+                },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The connection `rpc`'s node made to `address`, if there is one
+fn outbound_peer(rpc: &bitcoincore_rpc::Client, address: &str) -> Result<Option<PeerInfo>> {
+    let peers: Vec<PeerInfo> = rpc.call("getpeerinfo", &[])?;
+    Ok(peers
+        .into_iter()
+        .find(|peer| !peer.inbound && peer.addr == address))
+}
+
+/// The parts of a `getpeerinfo` entry we look at
+///
+/// bitcoincore-rpc's own result type insists on fields that newer Cores dropped.
+#[derive(Deserialize)]
+struct PeerInfo {
+    addr: String,
+    inbound: bool,
+    version: u32,
+}
+
+/// Wait until every node has the same best block, and return that block
+pub fn wait_for_same_tip(
+    nodes: &[Connection],
+    timeout: Duration,
+    progress: Progress,
+) -> Result<BlockHash> {
+    if nodes.is_empty() {
+        return Err(Error::Config("no nodes to wait for".to_owned()));
+    }
+    let clients = clients(nodes)?;
+    let mut agreed = None;
+    bitcoind::poll_until_ready("block sync", timeout, progress, || {
+        let mut tips = Vec::new();
+        for rpc in &clients {
+            tips.push((rpc.get_block_count()?, rpc.get_best_block_hash()?));
+        }
+        if tips.windows(2).all(|pair| pair[0].1 == pair[1].1) {
+            agreed = tips.first().map(|(_, hash)| *hash);
+            return Ok(Probe::Ready);
+        }
+        let tips: Vec<String> = tips
+            .iter()
+            .enumerate()
+            .map(|(i, (height, hash))| format!("node {i} at {height} ({})", short(hash)))
+            .collect();
+        Ok(Probe::NotYet(tips.join(", ")))
+    })?;
+    Ok(agreed.expect("set when the tips agreed"))
+}
+
+/// Wait until `txid` is in every node's mempool
+///
+/// A transaction that gets mined before it reached everyone never will be; mine after
+/// this returns.
+pub fn wait_for_mempools(
+    nodes: &[Connection],
+    txid: &Txid,
+    timeout: Duration,
+    progress: Progress,
+) -> Result<()> {
+    let clients = clients(nodes)?;
+    let what = format!("propagation of {txid}");
+    bitcoind::poll_until_ready(&what, timeout, progress, || {
+        let mut missing = Vec::new();
+        for (i, rpc) in clients.iter().enumerate() {
+            if !rpc.get_raw_mempool()?.contains(txid) {
+                missing.push(format!("node {i}"));
+            }
+        }
+        Ok(if missing.is_empty() {
+            Probe::Ready
+        } else {
+            Probe::NotYet(format!("not in the mempool of {}", missing.join(", ")))
+        })
+    })
+}
+
+/// Wait until every node has the same tip and exactly the same mempool; returns the tip
+pub fn wait_for_sync(
+    nodes: &[Connection],
+    timeout: Duration,
+    progress: Progress,
+) -> Result<BlockHash> {
+    let tip = wait_for_same_tip(nodes, timeout, progress)?;
+
+    let clients = clients(nodes)?;
+    bitcoind::poll_until_ready("mempool sync", timeout, progress, || {
+        let mut mempools = Vec::new();
+        for rpc in &clients {
+            mempools.push(rpc.get_raw_mempool()?.into_iter().collect::<BTreeSet<_>>());
+        }
+        if mempools.windows(2).all(|pair| pair[0] == pair[1]) {
+            return Ok(Probe::Ready);
+        }
+        let sizes: Vec<String> = mempools
+            .iter()
+            .enumerate()
+            .map(|(i, mempool)| format!("node {i} has {}", mempool.len()))
+            .collect();
+        Ok(Probe::NotYet(format!(
+            "mempools differ: {}",
+            sizes.join(", ")
+        )))
+    })?;
+    Ok(tip)
+}
+
+fn clients(nodes: &[Connection]) -> Result<Vec<bitcoincore_rpc::Client>> {
+    Ok(nodes
+        .iter()
+        .map(|node| node.client(None))
+        .collect::<bitcoincore_rpc::Result<_>>()?)
+}
+
+/// First 8 hex digits, enough to tell blocks apart in a progress message
+fn short(hash: &BlockHash) -> String {
+    hash.to_string()[..8].to_owned()
+}
@@ -0,0 +1,241 @@
+//! Building a transaction by hand, from coins we pick ourselves
+//!
+//! `sendtoaddress` (and `walletcreatefundedpsbt`) let the wallet's coin selection
+//! decide which UTXOs to spend; the forensics only find out afterwards. With the raw
+//! transaction RPCs every choice is ours:
+//! - the inputs: which of the wallet's coins (`listunspent`), and each input's sequence
+//!   number, which decides whether it signals RBF and enables nLockTime,
+//! - the outputs, and the address the change goes to,
+//! - the nLockTime: the earliest block height (or time) the transaction may be mined at.
+//!
+//! The wallet's only job is signing (`signrawtransactionwithwallet`). Before anything
+//! is broadcast the node is asked whether it would accept the transaction
+//! (`testmempoolaccept`), which names the rule a rejected one breaks.
+
+use std::fmt;
+
+use bitcoincore_rpc::bitcoin::{
+    Address, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+    absolute, transaction,
+};
+use bitcoincore_rpc::json::ListUnspentResultEntry;
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Serialize;
+
+use crate::amount::{self, format_btc};
+use crate::error::{Error, Result};
+use crate::transfer;
+
+/// A coin to spend, and the sequence number of the input spending it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChosenInput {
+    pub outpoint: OutPoint,
+    pub sequence: Sequence,
+}
+
+impl ChosenInput {
+    /// Spend `outpoint` with 0xfffffffd: signals RBF and leaves nLockTime enforced
+    pub fn new(outpoint: OutPoint) -> Self {
+        Self {
+            outpoint,
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+        }
+    }
+}
+
+/// Everything that goes into a hand-built transaction
+#[derive(Debug, Clone)]
+pub struct RawTransactionSpec {
+    /// Coins of the signing wallet, spent in this order
+    pub inputs: Vec<ChosenInput>,
+    /// Paid in this order, before any change
+    pub outputs: Vec<(Address, Amount)>,
+    /// Gets whatever the inputs hold beyond the outputs and the fee. Without one (or
+    /// when it would be dust) all of that goes to the fee.
+    pub change_address: Option<Address>,
+    pub fee_rate_sat_per_vb: u64,
+    pub lock_time: absolute::LockTime,
+}
+
+impl Default for RawTransactionSpec {
+    fn default() -> Self {
+        Self {
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            change_address: None,
+            fee_rate_sat_per_vb: 1,
+            lock_time: absolute::LockTime::ZERO,
+        }
+    }
+}
+
+/// The node's answer to `testmempoolaccept`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MempoolAcceptance {
+    pub allowed: bool,
+    /// Why it isn't allowed, e.g. `non-final` or `min relay fee not met, ...`
+    pub reject_reason: Option<String>,
+}
+
+/// A signed transaction built from a [`RawTransactionSpec`], not broadcast yet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HandBuiltTransaction {
+    #[serde(skip)]
+    pub tx: Transaction,
+    pub txid: Txid,
+    #[serde(with = "amount::exact")]
+    pub fee: Amount,
+    /// Position of the change output, if there is one
+    pub change_output: Option<usize>,
+    pub acceptance: MempoolAcceptance,
+}
+
+impl fmt::Display for HandBuiltTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Built {} spending {} coin(s) into {} output(s), fee {}",
+            self.txid,
+            self.tx.input.len(),
+            self.tx.output.len(),
+            format_btc(self.fee)
+        )?;
+        match &self.acceptance.reject_reason {
+            None => write!(f, "; the mempool would accept it"),
+            Some(reason) => write!(f, "; the mempool would reject it: {reason}"),
+        }
+    }
+}
+
+/// The coins `wallet` can spend right now, largest first
+pub fn list_coins(wallet: &Client) -> Result<Vec<ListUnspentResultEntry>> {
+    let mut coins = wallet.list_unspent(None, None, None, None, None)?;
+    coins.sort_by_key(|coin| std::cmp::Reverse(coin.amount));
+    Ok(coins)
+}
+
+/// Build the transaction `spec` describes and have `wallet` sign it, then ask the node
+/// whether it would accept it
+///
+/// Every input must be one of `wallet`'s spendable coins. The fee is the spec's rate
+/// times the signed size, so a draft is signed first just to weigh it. A rejection by
+/// the mempool isn't an error: it's reported in [`HandBuiltTransaction::acceptance`].
+pub fn build(wallet: &Client, spec: &RawTransactionSpec) -> Result<HandBuiltTransaction> {
+    if spec.inputs.is_empty() {
+        return Err(Error::Config(
+            "a transaction needs at least one input".to_owned(),
+        ));
+    }
+    let coins = list_coins(wallet)?;
+    let mut spent = Amount::ZERO;
+    for input in &spec.inputs {
+        let coin = coins
+            .iter()
+            .find(|coin| OutPoint::new(coin.txid, coin.vout) == input.outpoint)
+            .ok_or_else(|| {
+                Error::Config(format!(
+                    "{} isn't one of the wallet's spendable coins",
+                    input.outpoint
+                ))
+            })?;
+        spent += coin.amount;
+    }
+    let paid: Amount = spec.outputs.iter().map(|(_, amount)| *amount).sum();
+    let available = spent.checked_sub(paid).ok_or_else(|| {
+        Error::Config(format!(
+            "the chosen coins hold {spent}, less than the {paid} paid"
+        ))
+    })?;
+
+    let with_change = |change: Option<Amount>| -> Result<Transaction> {
+        let mut output: Vec<TxOut> = spec
+            .outputs
+            .iter()
+            .map(|(address, value)| TxOut {
+                value: *value,
+                script_pubkey: address.script_pubkey(),
+            })
+            .collect();
+        if let (Some(value), Some(address)) = (change, &spec.change_address) {
+            output.push(TxOut {
+                value,
+                script_pubkey: address.script_pubkey(),
+            });
+        }
+        let unsigned = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: spec.lock_time,
+            input: spec
+                .inputs
+                .iter()
+                .map(|input| TxIn {
+                    previous_output: input.outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: input.sequence,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output,
+        };
+        transfer::sign(wallet, &unsigned)
+    };
+    let fee_for = |tx: &Transaction| {
+        // A vbyte of slack: the final signature can come out a byte longer
+        Amount::from_sat(spec.fee_rate_sat_per_vb * (tx.vsize() as u64 + 1))
+    };
+
+    // Weigh a draft with change, and keep the change only if it's worth having
+    let draft = with_change(spec.change_address.as_ref().map(|_| available))?;
+    // Change worth less than its script's dust limit costs more to spend than it holds
+    let change = available.checked_sub(fee_for(&draft)).filter(|change| {
+        spec.change_address
+            .as_ref()
+            .is_some_and(|address| *change >= address.script_pubkey().dust_value())
+    });
+    let tx = match change {
+        Some(change) => with_change(Some(change))?,
+        None => {
+            let tx = with_change(None)?;
+            if available < fee_for(&tx) {
+                return Err(Error::Config(format!(
+                    "the chosen coins leave {available} for the fee, {} short of {} sat/vB",
+                    fee_for(&tx) - available,
+                    spec.fee_rate_sat_per_vb
+                )));
+            }
+            tx
+        },
+    };
+
+    Ok(HandBuiltTransaction {
+        txid: tx.txid(),
+        fee: spent - tx.output.iter().map(|output| output.value).sum(),
+        change_output: change.map(|_| spec.outputs.len()),
+        acceptance: test_accept(wallet, &tx)?,
+        tx,
+    })
+}
+
+/// Ask the node whether its mempool would take `tx`, without sending it
+pub fn test_accept(rpc: &Client, tx: &Transaction) -> Result<MempoolAcceptance> {
+    let result = rpc
+        .test_mempool_accept(&[tx])?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::Invariant("testmempoolaccept returned no result".to_owned()))?;
+    Ok(MempoolAcceptance {
+        allowed: result.allowed,
+        reject_reason: result.reject_reason,
+    })
+}
+
+/// Broadcast `built`, unless the node already said it would reject it
+pub fn broadcast(rpc: &Client, built: &HandBuiltTransaction) -> Result<Txid> {
+    if let Some(reason) = &built.acceptance.reject_reason {
+        return Err(Error::State(format!(
+            "the mempool would reject {}: {reason}",
+            built.txid
+        )));
+    }
+    Ok(rpc.send_raw_transaction(&built.tx)?)
+}
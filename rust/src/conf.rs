@@ -0,0 +1,210 @@
+//! Minimal `bitcoin.conf` reader
+//!
+//! Bitcoin Core's config format is INI-like:
+//! - `key=value` lines, `#` starts a comment
+//! - `[main]`, `[test]`, `[signet]`, `[regtest]` open a network-specific section
+//! - `regtest.rpcuser=alice` is shorthand for `rpcuser=alice` inside `[regtest]`
+//!
+//! A value set inside the section for the active network wins over the same key
+//! set at the top level, which mirrors how bitcoind itself resolves options.
+
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+use bitcoincore_rpc::bitcoin::Network;
+
+/// One `key=value` line: `(section, key, value)`, where a `None` section means top level
+type Entry = (Option<String>, String, String);
+
+/// Parsed contents of a `bitcoin.conf` file
+#[derive(Debug, Clone, Default)]
+pub struct BitcoinConf {
+    /// Entries in file order
+    entries: Vec<Entry>,
+}
+
+impl BitcoinConf {
+    /// Read and parse the config file at `path`
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parse config text; malformed lines are ignored, just like bitcoind warns and moves on
+    pub fn parse(text: &str) -> Self {
+        let mut section: Option<String> = None;
+        let mut entries = Vec::new();
+
+        for line in text.lines() {
+            // Everything after '#' is a comment (this also disables lines like `#rpcauth=...`)
+            let line = line
+                .split_once('#')
+                .map_or(line, |(before, _)| before)
+                .trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = Some(name.trim().to_owned());
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            // `regtest.rpcuser=...` form: the prefix overrides the current section
+            let (entry_section, key) = match key.split_once('.') {
+                Some((prefix, key)) => (Some(prefix.to_owned()), key),
+                None => (section.clone(), key),
+            };
+            entries.push((entry_section, key.to_owned(), value.to_owned()));
+        }
+
+        Self { entries }
+    }
+
+    /// Look up `key` for `network`: the network's section first, then the top level
+    ///
+    /// When a key appears several times in the same scope the last one wins.
+    pub fn get(&self, key: &str, network: Network) -> Option<&str> {
+        let section = section_name(network);
+        self.lookup(key, Some(section))
+            .or_else(|| self.lookup(key, None))
+    }
+
+    /// Drop `key` from every scope (top level, every section, `net.key=` forms)
+    pub fn remove(&mut self, key: &str) {
+        self.entries.retain(|(_, k, _)| k != key);
+    }
+
+    /// Append `key=value`, inside `[section]` when one is given
+    ///
+    /// Multi-value keys (like `rpcbind`) accumulate; [`remove`](Self::remove) first to replace.
+    pub fn set(&mut self, section: Option<&str>, key: &str, value: &str) {
+        self.entries
+            .push((section.map(str::to_owned), key.to_owned(), value.to_owned()));
+    }
+
+    fn lookup(&self, key: &str, section: Option<&str>) -> Option<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(s, k, _)| k == key && s.as_deref() == section)
+            .map(|(_, _, v)| v.as_str())
+    }
+}
+
+/// Renders back to config text: top-level keys first, then one block per section
+///
+/// Comments and blank lines of the original file are not preserved.
+impl fmt::Display for BitcoinConf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sections: Vec<Option<&str>> = vec![None];
+        for (section, ..) in &self.entries {
+            if !sections.contains(&section.as_deref()) {
+                sections.push(section.as_deref());
+            }
+        }
+
+        for section in sections {
+            if let Some(name) = section {
+                writeln!(f, "\n[{name}]")?;
+            }
+            for (_, key, value) in self
+                .entries
+                .iter()
+                .filter(|(s, ..)| s.as_deref() == section)
+            {
+                writeln!(f, "{key}={value}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Name of the config section bitcoind reads for `network`
+pub fn section_name(network: Network) -> &'static str {
+    match network {
+        Network::Testnet => "test",
+        Network::Signet => "signet",
+        Network::Regtest => "regtest",
+        _ => "main",
+    }
+}
+
+/// Sub-directory of the datadir where `network` keeps its chain data and `.cookie`
+pub fn chain_subdir(network: Network) -> Option<&'static str> {
+    match network {
+        Network::Testnet => Some("testnet3"),
+        Network::Signet => Some("signet"),
+        Network::Regtest => Some("regtest"),
+        _ => None,
+    }
+}
+
+/// Bitcoin Core's platform default data directory, if a home directory is known
+pub fn default_datadir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(|appdata| PathBuf::from(appdata).join("Bitcoin"))
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join("Library/Application Support/Bitcoin"))
+    } else {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".bitcoin"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_section_wins_over_top_level() {
+        let conf = BitcoinConf::parse(
+            "rpcuser=top\nrpcpassword=top-secret\n\n[main]\nrpcuser=mainnet\n\n[regtest]\nrpcuser=alice # the repo's user\n",
+        );
+        assert_eq!(conf.get("rpcuser", Network::Regtest), Some("alice"));
+        assert_eq!(conf.get("rpcuser", Network::Bitcoin), Some("mainnet"));
+        assert_eq!(
+            conf.get("rpcpassword", Network::Regtest),
+            Some("top-secret")
+        );
+    }
+
+    #[test]
+    fn prefixed_keys_and_comments() {
+        let conf = BitcoinConf::parse(
+            "#rpcauth=alice:hash\nregtest.rpcport=18443\n[test]\nmain.rpcport=8332\n",
+        );
+        assert_eq!(conf.get("rpcauth", Network::Regtest), None);
+        assert_eq!(conf.get("rpcport", Network::Regtest), Some("18443"));
+        assert_eq!(conf.get("rpcport", Network::Bitcoin), Some("8332"));
+        assert_eq!(conf.get("rpcport", Network::Testnet), None);
+    }
+
+    #[test]
+    fn edited_conf_renders_and_parses_back() {
+        let mut conf =
+            BitcoinConf::parse("regtest=1\n[regtest]\nrpcbind=0.0.0.0:18443\nrpcuser=alice\n");
+        conf.remove("rpcbind");
+        conf.set(Some("regtest"), "rpcbind", "127.0.0.1");
+        conf.set(Some("regtest"), "rpcport", "28443");
+
+        let text = conf.to_string();
+        assert_eq!(
+            text,
+            "regtest=1\n\n[regtest]\nrpcuser=alice\nrpcbind=127.0.0.1\nrpcport=28443\n"
+        );
+        let reparsed = BitcoinConf::parse(&text);
+        assert_eq!(reparsed.get("rpcbind", Network::Regtest), Some("127.0.0.1"));
+        assert_eq!(reparsed.get("regtest", Network::Regtest), Some("1"));
+    }
+
+    #[test]
+    fn last_value_wins() {
+        let conf = BitcoinConf::parse("rpcuser=first\nrpcuser=second\n");
+        assert_eq!(conf.get("rpcuser", Network::Regtest), Some("second"));
+    }
+}
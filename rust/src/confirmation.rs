@@ -0,0 +1,114 @@
+//! Where a transaction was confirmed, and proof that it's really in that block
+//!
+//! The chain tip only says where the chain is *now*: a block mined after ours (or a
+//! reorg) moves it on, while the transaction stays where it was. So the containing
+//! block is looked up from the transaction itself (`getrawtransaction` with
+//! `txindex=1` reports its `blockhash`), and its height and confirmation count from
+//! that block's header.
+//!
+//! A block header commits to its transactions through the Merkle root: the hashes of
+//! all txids, paired up level by level until one is left. Proving that one transaction
+//! is in the block takes only the hashes along its path to the root - log2(n) of
+//! them instead of the whole block. That's what SPV wallets rely on, and what
+//! `gettxoutproof` hands out (a serialized "merkle block"). [`locate`] has the node
+//! check the proof (`verifytxoutproof`) and checks it again itself.
+
+use bitcoincore_rpc::bitcoin::hex::DisplayHex;
+use bitcoincore_rpc::bitcoin::{BlockHash, MerkleBlock, Txid, consensus};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::error::{Error, Result};
+
+/// The block a transaction is confirmed in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Confirmation {
+    pub block_hash: BlockHash,
+    pub block_height: u64,
+    /// 1 while the block is the tip, one more for every block on top of it
+    pub confirmations: u64,
+    /// Index among the block's transactions (0 is always the coinbase)
+    pub block_position: usize,
+    pub block_tx_count: usize,
+    pub merkle_proof: MerkleProof,
+}
+
+/// A `gettxoutproof` proof of inclusion, and whether it holds up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MerkleProof {
+    /// The serialized merkle block: header, transaction count, hashes and flag bits
+    pub hex: String,
+    /// `verifytxoutproof` returned exactly our txid
+    pub verified_by_node: bool,
+    /// The proof's hashes lead to the header's Merkle root, the header is the
+    /// containing block's, and the proof puts the transaction at its position
+    pub verified_locally: bool,
+}
+
+impl MerkleProof {
+    pub fn is_valid(&self) -> bool {
+        self.verified_by_node && self.verified_locally
+    }
+}
+
+/// Find the block `txid` is confirmed in and prove it's there
+///
+/// `rpc` needs `txindex=1` (see bitcoin.conf) to look up transactions that aren't
+/// the wallet's. An unconfirmed transaction is an error.
+pub fn locate(rpc: &Client, txid: &Txid) -> Result<Confirmation> {
+    let block_hash = rpc
+        .get_raw_transaction_info(txid, None)?
+        .blockhash
+        .ok_or_else(|| Error::State(format!("{txid} isn't confirmed yet")))?;
+
+    // The header knows the height, and how many blocks have been built on top of it
+    let header = rpc.get_block_header_info(&block_hash)?;
+    // -1 confirmations: the block is no longer part of the active chain
+    let confirmations = u64::try_from(header.confirmations).map_err(|_| {
+        Error::Invariant(format!(
+            "{block_hash} holding {txid} isn't in the active chain"
+        ))
+    })?;
+
+    let block = rpc.get_block(&block_hash)?;
+    let block_position = block
+        .txdata
+        .iter()
+        .position(|tx| tx.txid() == *txid)
+        .ok_or_else(|| Error::Invariant(format!("{block_hash} doesn't contain {txid}")))?;
+
+    let proof = rpc.get_tx_out_proof(&[*txid], Some(&block_hash))?;
+    let proven: Vec<Txid> =
+        rpc.call("verifytxoutproof", &[json!(proof.to_lower_hex_string())])?;
+    let merkle_proof = MerkleProof {
+        hex: proof.to_lower_hex_string(),
+        verified_by_node: proven == [*txid],
+        verified_locally: verify(&proof, &block_hash, txid, block_position),
+    };
+
+    Ok(Confirmation {
+        block_hash,
+        block_height: header.height as u64,
+        confirmations,
+        block_position,
+        block_tx_count: block.txdata.len(),
+        merkle_proof,
+    })
+}
+
+/// Whether `proof` shows `txid` at `position` in the block `block_hash`
+fn verify(proof: &[u8], block_hash: &BlockHash, txid: &Txid, position: usize) -> bool {
+    let Ok(merkle_block) = consensus::deserialize::<MerkleBlock>(proof) else {
+        return false;
+    };
+    let (mut matches, mut indexes) = (Vec::new(), Vec::new());
+    // Rebuilds the root from the partial tree, collecting the txids it flags as matched
+    let Ok(root) = merkle_block.txn.extract_matches(&mut matches, &mut indexes) else {
+        return false;
+    };
+    root == merkle_block.header.merkle_root
+        && merkle_block.header.block_hash() == *block_hash
+        && matches == [*txid]
+        && indexes == [position as u32]
+}
@@ -0,0 +1,47 @@
+//! Resolved connection to a Bitcoin Core node
+//!
+//! Holds the base RPC URL, the discovered credentials and the expected network,
+//! and hands out RPC clients for the node itself or for one of its wallets.
+
+use bitcoincore_rpc::Client;
+use bitcoincore_rpc::bitcoin::Network;
+
+use crate::auth::Credentials;
+
+#[derive(Debug, Clone)]
+pub struct Connection {
+    url: String,
+    credentials: Credentials,
+    network: Network,
+}
+
+impl Connection {
+    pub fn new(url: &str, credentials: Credentials, network: Network) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_owned(),
+            credentials,
+            network,
+        }
+    }
+
+    /// Open an RPC client, optionally scoped to one wallet
+    ///
+    /// Bitcoin Core exposes each loaded wallet under `<url>/wallet/<name>`;
+    /// wallet-specific calls (balance, send, new address) must go through that path.
+    pub fn client(&self, wallet: Option<&str>) -> bitcoincore_rpc::Result<Client> {
+        let url = match wallet {
+            Some(name) => format!("{}/wallet/{name}", self.url),
+            None => self.url.clone(),
+        };
+        Client::new(&url, self.credentials.auth.clone())
+    }
+
+    /// Base RPC URL, without any `/wallet/...` suffix
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+}
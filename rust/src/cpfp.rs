@@ -0,0 +1,235 @@
+//! Child-pays-for-parent: rescuing a stuck transaction by spending its output
+//!
+//! Miners fill blocks with the best-paying transactions per vbyte, but they can't
+//! include a child without its parent. So a transaction with unconfirmed ancestors is
+//! judged by its *package*: the fees of it and all its ancestors over their combined
+//! size, the "ancestor fee rate" `getmempoolentry` reports. A parent paying too little
+//! gets mined anyway once a child pays enough for both.
+//!
+//! Unlike replace-by-fee (see replacement.rs) this needs no RBF signal and no help
+//! from the sender: anyone with an output of the stuck transaction can do it, so the
+//! recipient with their payment just as well as the sender with the change.
+
+use std::fmt;
+
+use bitcoincore_rpc::bitcoin::{
+    Amount, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+    absolute, transaction,
+};
+use bitcoincore_rpc::bitcoincore_rpc_json::GetMempoolEntryResult;
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Serialize;
+
+use crate::amount;
+use crate::error::{Error, Result};
+use crate::transfer;
+
+/// One mempool transaction and the relatives it's mined with, from `getmempoolentry`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MempoolPackage {
+    pub vsize: u64,
+    #[serde(with = "amount::exact")]
+    pub fee: Amount,
+    /// The transaction and every unconfirmed one it spends from, recursively
+    pub ancestor_count: u64,
+    pub ancestor_size: u64,
+    #[serde(with = "amount::exact")]
+    pub ancestor_fees: Amount,
+    /// The transaction and every mempool one spending from it, recursively
+    pub descendant_count: u64,
+    pub descendant_size: u64,
+    #[serde(with = "amount::exact")]
+    pub descendant_fees: Amount,
+}
+
+impl MempoolPackage {
+    pub fn of(rpc: &Client, txid: &Txid) -> Result<Self> {
+        Ok(Self::from(rpc.get_mempool_entry(txid)?))
+    }
+
+    /// The transaction on its own, in sat/vB
+    pub fn fee_rate(&self) -> f64 {
+        sat_per_vb(self.fee, self.vsize)
+    }
+
+    /// What miners go by: the transaction together with its ancestors, in sat/vB
+    pub fn ancestor_fee_rate(&self) -> f64 {
+        sat_per_vb(self.ancestor_fees, self.ancestor_size)
+    }
+
+    /// The transaction together with its descendants, in sat/vB
+    pub fn descendant_fee_rate(&self) -> f64 {
+        sat_per_vb(self.descendant_fees, self.descendant_size)
+    }
+}
+
+impl From<GetMempoolEntryResult> for MempoolPackage {
+    fn from(entry: GetMempoolEntryResult) -> Self {
+        Self {
+            vsize: entry.vsize,
+            fee: entry.fees.base,
+            ancestor_count: entry.ancestor_count,
+            ancestor_size: entry.ancestor_size,
+            ancestor_fees: entry.fees.ancestor,
+            descendant_count: entry.descendant_count,
+            descendant_size: entry.descendant_size,
+            descendant_fees: entry.fees.descendant,
+        }
+    }
+}
+
+fn sat_per_vb(fee: Amount, vsize: u64) -> f64 {
+    if vsize == 0 {
+        0.0
+    } else {
+        fee.to_sat() as f64 / vsize as f64
+    }
+}
+
+/// A child broadcast to pull its parent's package up to a target fee rate
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CpfpBump {
+    pub parent: Txid,
+    pub child: Txid,
+    /// The parent's output the child spends
+    pub spent: OutPoint,
+    pub target_sat_per_vb: u64,
+    #[serde(with = "amount::exact")]
+    pub child_fee: Amount,
+    /// The parent before the child existed...
+    pub parent_before: MempoolPackage,
+    /// ... and with it as a descendant
+    pub parent_after: MempoolPackage,
+    /// The child, whose ancestors are the whole package
+    pub child_package: MempoolPackage,
+}
+
+impl fmt::Display for CpfpBump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (before, child) = (&self.parent_before, &self.child_package);
+        writeln!(
+            f,
+            "Child {} spends {} for a fee of {}",
+            self.child, self.spent, self.child_fee
+        )?;
+        writeln!(
+            f,
+            "  parent alone: {} for {} vB ({:.2} sat/vB, {:.2} with its ancestors)",
+            before.fee,
+            before.vsize,
+            before.fee_rate(),
+            before.ancestor_fee_rate()
+        )?;
+        writeln!(
+            f,
+            "  package: {} transaction(s), {} for {} vB ({:.2} sat/vB, target {})",
+            child.ancestor_count,
+            child.ancestor_fees,
+            child.ancestor_size,
+            child.ancestor_fee_rate(),
+            self.target_sat_per_vb
+        )?;
+        write!(
+            f,
+            "  parent's descendants: {} transaction(s), {} for {} vB",
+            self.parent_after.descendant_count,
+            self.parent_after.descendant_fees,
+            self.parent_after.descendant_size
+        )
+    }
+}
+
+/// Spend `wallet`'s output of `parent`, a mempool transaction, with a child paying
+/// enough to lift the package's fee rate to `target_sat_per_vb`
+///
+/// The largest output `wallet` owns is spent, and all of it but the fee goes back to
+/// a change address of `wallet`, which must be on `network`.
+pub fn bump(
+    wallet: &Client,
+    network: Network,
+    parent: &Txid,
+    target_sat_per_vb: u64,
+) -> Result<CpfpBump> {
+    let parent_before = MempoolPackage::of(wallet, parent)?;
+    if parent_before.ancestor_fee_rate() >= target_sat_per_vb as f64 {
+        return Err(Error::State(format!(
+            "{parent} already pays {:.2} sat/vB with its ancestors",
+            parent_before.ancestor_fee_rate()
+        )));
+    }
+
+    // Unconfirmed outputs only: the parent isn't in a block
+    let output = wallet
+        .list_unspent(Some(0), Some(0), None, Some(true), None)?
+        .into_iter()
+        .filter(|entry| entry.txid == *parent)
+        .max_by_key(|entry| entry.amount)
+        .ok_or_else(|| Error::State(format!("the wallet owns no output of {parent}")))?;
+    let spent = OutPoint::new(output.txid, output.vout);
+    let to = wallet
+        .get_raw_change_address(None)?
+        .require_network(network)?;
+    // Outputs below this aren't relayed; it depends on the script (294 sat for P2WPKH,
+    // 330 for P2TR, 546 for P2PKH at Core's default 3 sat/vB)
+    let dust = to.script_pubkey().dust_value();
+
+    // Sign once without a fee to learn the child's size, then again with the real one
+    let child_with_fee = |fee: Amount| -> Result<Transaction> {
+        let value = output
+            .amount
+            .checked_sub(fee)
+            .filter(|value| *value >= dust)
+            .ok_or_else(|| {
+                Error::State(format!(
+                    "{spent} holds {}, too little to pay a fee of {fee}",
+                    output.amount
+                ))
+            })?;
+        let unsigned = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: spent,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey: to.script_pubkey(),
+            }],
+        };
+        transfer::sign(wallet, &unsigned)
+    };
+    // A vbyte of slack: the final signature can come out a byte longer
+    let child_vsize = child_with_fee(Amount::ZERO)?.vsize() as u64 + 1;
+
+    // target = (ancestor fees + child fee) / (ancestor size + child size)
+    let package_fee = (parent_before.ancestor_size + child_vsize)
+        .checked_mul(target_sat_per_vb)
+        .map(Amount::from_sat)
+        .ok_or_else(|| {
+            Error::Config(format!(
+                "a package fee of {target_sat_per_vb} sat/vB doesn't fit in an amount"
+            ))
+        })?;
+    let child_fee = package_fee
+        .checked_sub(parent_before.ancestor_fees)
+        .ok_or_else(|| {
+            Error::Invariant(format!(
+                "{parent}'s ancestors already pay more than {package_fee}"
+            ))
+        })?;
+    let child = wallet.send_raw_transaction(&child_with_fee(child_fee)?)?;
+
+    Ok(CpfpBump {
+        parent: *parent,
+        child,
+        spent,
+        target_sat_per_vb,
+        child_fee,
+        parent_before,
+        parent_after: MempoolPackage::of(wallet, parent)?,
+        child_package: MempoolPackage::of(wallet, &child)?,
+    })
+}
@@ -0,0 +1,46 @@
+//! Crate-wide error type
+//!
+//! Instead of squeezing every failure into `bitcoincore_rpc::Error`, callers can
+//! tell apart "the node said no" from "the disk said no" from "the data makes no sense".
+
+use bitcoincore_rpc::bitcoin::address;
+
+/// Everything that can go wrong while driving the simulator
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The node rejected a call, or we couldn't reach it at all
+    #[error("RPC error: {0}")]
+    Rpc(#[from] bitcoincore_rpc::Error),
+
+    /// Reading config/cookie files or writing the report failed
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// An address didn't decode, or belongs to a different network
+    #[error("address error: {0}")]
+    Address(#[from] address::Error),
+
+    /// The user-supplied configuration is inconsistent (e.g. a user without a password)
+    #[error("configuration error: {0}")]
+    Config(String),
+
+    /// The chain, mempool or a wallet isn't in a state that allows the operation right
+    /// now (e.g. a payment that isn't confirmed yet); nothing to fix in the configuration
+    #[error("not possible in the current state: {0}")]
+    State(String),
+
+    /// Data returned by the node contradicts itself (e.g. an input spending a missing output)
+    #[error("invariant violated: {0}")]
+    Invariant(String),
+
+    /// A node we manage failed to start, died, or never became ready
+    #[error("node error: {0}")]
+    Node(String),
+
+    /// A scenario's balance assertion didn't hold
+    #[error("assertion failed: {0}")]
+    Assertion(String),
+}
+
+/// `Result` alias defaulting to the crate's [`Error`]
+pub type Result<T, E = Error> = std::result::Result<T, E>;
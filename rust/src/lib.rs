@@ -0,0 +1,59 @@
+//! Bitcoin Regtest Transaction Simulator - library
+//!
+//! The building blocks of the simulator, usable on their own by other tools:
+//! - [`connection`]: credential discovery and RPC clients (node- and wallet-scoped)
+//! - [`bitcoind`]: launching a throwaway local node and waiting for its RPC server
+//! - [`cluster`]: several peered nodes, and waiting for blocks/transactions to propagate
+//! - [`wallets`]: creating/loading wallets, and the address types they hand out
+//! - [`mining`]: mining blocks until coinbase rewards mature
+//! - [`transfer`]: sending a payment and confirming it
+//! - [`coin_control`]: building a transaction by hand from chosen coins, checked with
+//!   `testmempoolaccept`
+//! - [`psbt`]: paying through a PSBT, with creator, signer and finalizer as separate steps
+//! - [`multisig`]: M-of-N wallets shared between cosigners, spent by combining their
+//!   signatures
+//! - [`timelock`]: nLockTime, CLTV and CSV locks, and the mempool refusing early spends
+//! - [`taproot`]: a script tree behind one output key, spent through key and script path
+//! - [`confirmation`]: the block a transaction is confirmed in, with a Merkle proof
+//! - [`replacement`]: replace-by-fee, bumping a payment's fee or double-spending it
+//! - [`cpfp`]: child-pays-for-parent, speeding up a transaction by spending its output
+//! - [`reorg`]: forking the chain on purpose, and what that does to confirmed transactions
+//! - [`analysis`]: transaction forensics (inputs, outputs, change, fees)
+//! - [`script`]: classifying output scripts and how inputs spend them
+//! - [`report`]: writing the analysis out
+//! - [`amount`]: exact BTC/satoshi formatting and serialization
+//! - [`scenario`]: multi-party simulations described in TOML/YAML files
+//! - [`progress`]: where long-running calls report what they're doing (they never print)
+//! - `mock`: an in-process fake node for running all of the above under `cargo test`
+//!   (only built for tests, or with the `mock` feature)
+//!
+//! The `rust` binary (src/main.rs) strings these together into the full simulation.
+
+pub mod amount;
+pub mod analysis;
+pub mod auth;
+pub mod bitcoind;
+pub mod cluster;
+pub mod coin_control;
+pub mod conf;
+pub mod confirmation;
+pub mod connection;
+pub mod cpfp;
+pub mod error;
+pub mod mining;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod multisig;
+pub mod progress;
+pub mod psbt;
+pub mod reorg;
+pub mod replacement;
+pub mod report;
+pub mod scenario;
+pub mod script;
+pub mod taproot;
+pub mod timelock;
+pub mod transfer;
+pub mod wallets;
+
+pub use crate::error::{Error, Result};
@@ -0,0 +1,132 @@
+//! Block mining and coinbase maturity
+//!
+//! When you mine a block, you get a "coinbase" reward (50 BTC in regtest)
+//! BUT there's a consensus rule: you CANNOT spend this reward immediately!
+//! It must be buried under 100 more blocks first (see main.rs for the full story).
+//!
+//! Instead of mining one block at a time and polling the balance, we work out
+//! exactly how many blocks are still missing and mine them in a single call.
+
+use std::fmt;
+
+use bitcoincore_rpc::bitcoin::{Address, Amount, BlockHash};
+use bitcoincore_rpc::json::GetTransactionResultDetailCategory;
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Serialize;
+
+use crate::amount;
+use crate::error::{Error, Result};
+
+/// Blocks that must be built on top of a coinbase before consensus lets it be spent
+pub const COINBASE_MATURITY: u64 = 100;
+
+/// How many recent wallet transactions to scan for immature coinbase rewards
+///
+/// Only the last 100 blocks can hold immature coinbases, so this comfortably covers
+/// them unless the wallet also made hundreds of other transactions in that window.
+const IMMATURE_SCAN_LIMIT: usize = 1_000;
+
+/// A wallet's balance split by spendability, as reported by `getbalances`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct WalletBalances {
+    /// Confirmed (or our own unconfirmed change): spendable right now
+    #[serde(with = "amount::exact")]
+    pub trusted: Amount,
+    /// Unconfirmed payments from others: not spendable until they confirm
+    #[serde(with = "amount::exact")]
+    pub untrusted_pending: Amount,
+    /// Coinbase rewards still waiting for maturity
+    #[serde(with = "amount::exact")]
+    pub immature: Amount,
+}
+
+impl fmt::Display for WalletBalances {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "trusted {}, untrusted pending {}, immature {}",
+            self.trusted, self.untrusted_pending, self.immature
+        )
+    }
+}
+
+/// What [`mine_to_maturity`] did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MiningOutcome {
+    pub blocks_mined: u64,
+    pub balances: WalletBalances,
+}
+
+/// Current balances of the wallet behind `rpc`
+pub fn balances(rpc: &Client) -> Result<WalletBalances> {
+    let mine = rpc.get_balances()?.mine;
+    Ok(WalletBalances {
+        trusted: mine.trusted,
+        untrusted_pending: mine.untrusted_pending,
+        immature: mine.immature,
+    })
+}
+
+/// Mine `count` blocks to `address` in one call, returning their hashes
+pub fn mine_blocks(rpc: &Client, address: &Address, count: u64) -> Result<Vec<BlockHash>> {
+    let hashes = rpc.generate_to_address(count, address)?;
+    if hashes.len() as u64 != count {
+        return Err(Error::Invariant(format!(
+            "asked for {count} blocks, generatetoaddress returned {}",
+            hashes.len()
+        )));
+    }
+    Ok(hashes)
+}
+
+/// Blocks still needed before the wallet behind `rpc` has a spendable balance
+///
+/// - already spendable: 0
+/// - immature rewards pending: enough for the oldest one to mature
+/// - nothing at all: a fresh coinbase plus [`COINBASE_MATURITY`] blocks on top of it
+pub fn blocks_until_spendable(rpc: &Client, balances: &WalletBalances) -> Result<u64> {
+    if balances.trusted > Amount::ZERO {
+        return Ok(0);
+    }
+    // Bitcoin Core's wallet counts a coinbase as mature at COINBASE_MATURITY + 1 confirmations
+    let fresh = COINBASE_MATURITY + 1;
+    if balances.immature == Amount::ZERO {
+        return Ok(fresh);
+    }
+
+    let deepest_immature = rpc
+        .list_transactions(None, Some(IMMATURE_SCAN_LIMIT), None, None)?
+        .iter()
+        .filter(|tx| tx.detail.category == GetTransactionResultDetailCategory::Immature)
+        .map(|tx| u64::try_from(tx.info.confirmations).unwrap_or(0))
+        .max()
+        .unwrap_or(0);
+
+    Ok(fresh.saturating_sub(deepest_immature))
+}
+
+/// Mine to `address` exactly as many blocks as the wallet behind `rpc` needs to be able
+/// to spend, in one `generatetoaddress` call
+///
+/// `address` must belong to that wallet. RPC failures are returned, not retried;
+/// if the mined blocks still leave nothing spendable, that is an invariant violation.
+pub fn mine_to_maturity(rpc: &Client, address: &Address) -> Result<MiningOutcome> {
+    let before = balances(rpc)?;
+    let blocks_mined = blocks_until_spendable(rpc, &before)?;
+    if blocks_mined > 0 {
+        // generate_to_address() creates the blocks and assigns every coinbase to our address
+        mine_blocks(rpc, address, blocks_mined)?;
+    }
+
+    let after = balances(rpc)?;
+    if after.trusted == Amount::ZERO {
+        return Err(Error::Invariant(format!(
+            "mined {blocks_mined} blocks to {address} but nothing is spendable ({after})"
+        )));
+    }
+
+    Ok(MiningOutcome {
+        blocks_mined,
+        balances: after,
+    })
+}
@@ -0,0 +1,241 @@
+//! In-process fake Bitcoin Core JSON-RPC server for tests
+//!
+//! [`MockNode::start`] binds a random localhost port and answers the RPC calls the
+//! simulator makes (see mock/node.rs for the list and how faithful each one is),
+//! so the whole flow can run under `cargo test` on a machine without bitcoind:
+//!
+//! ```no_run
+//! let node = rust::mock::MockNode::start()?;
+//! let connection = node.connection();
+//! rust::wallets::ensure_wallets(&connection, &["Miner", "Trader"])?;
+//! # Ok::<(), rust::Error>(())
+//! ```
+//!
+//! The server speaks just enough HTTP/1.1 for `bitcoincore-rpc`: keep-alive
+//! connections, `Content-Length` bodies, and `/wallet/<name>` paths for wallet RPCs.
+//! Credentials are accepted without checking.
+//!
+//! Several mock nodes can be peered with `addnode` [`MockNode::p2p_address`]; they then
+//! relay blocks and transactions to each other (see mock/p2p.rs).
+
+mod chain;
+mod descriptor;
+mod node;
+mod p2p;
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+use bitcoincore_rpc::Auth;
+use bitcoincore_rpc::bitcoin::Network;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use self::node::{Node, RPC_METHOD_NOT_FOUND, RPC_PARSE_ERROR, RpcError};
+use crate::auth::{CredentialSource, Credentials};
+use crate::connection::Connection;
+
+/// A running fake node; shuts down when dropped
+pub struct MockNode {
+    addr: SocketAddr,
+    node: Arc<Mutex<Node>>,
+    shutdown: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl MockNode {
+    /// Start a fresh regtest node with only the genesis block and no wallets
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let node = Arc::new(Mutex::new(Node::new(Network::Regtest, addr)));
+        p2p::register(addr, &node);
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let acceptor = {
+            let node = Arc::clone(&node);
+            let shutdown = Arc::clone(&shutdown);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let Ok(stream) = stream else { continue };
+                    let node = Arc::clone(&node);
+                    // One thread per connection; it ends when the client hangs up
+                    thread::spawn(move || {
+                        let _ = serve(stream, &node, addr);
+                    });
+                }
+            })
+        };
+
+        Ok(Self {
+            addr,
+            node,
+            shutdown,
+            acceptor: Some(acceptor),
+        })
+    }
+
+    /// Base RPC URL, e.g. `http://127.0.0.1:49152`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// What other nodes pass to `addnode` to connect to this one
+    ///
+    /// The same address as the RPC server: mock nodes exchange blocks in-process.
+    pub fn p2p_address(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// A [`Connection`] to this node with the repo's default credentials
+    pub fn connection(&self) -> Connection {
+        let credentials = Credentials {
+            auth: Auth::UserPass("alice".to_owned(), "password".to_owned()),
+            source: CredentialSource::CommandLine,
+            note: None,
+        };
+        Connection::new(&self.url(), credentials, Network::Regtest)
+    }
+
+    /// Answer the next `calls` requests with `-28 Loading block index…`, as a node
+    /// that is still starting up would
+    pub fn warm_up(&self, calls: usize) {
+        lock(&self.node).warmup_calls = calls;
+    }
+
+    /// Every RPC method called so far, in order
+    pub fn calls(&self) -> Vec<String> {
+        lock(&self.node).calls.clone()
+    }
+}
+
+impl Drop for MockNode {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        p2p::unregister(self.addr);
+        // accept() blocks, so wake it up with a throwaway connection
+        let _ = TcpStream::connect(self.addr);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+    }
+}
+
+/// A test that panicked while holding the lock shouldn't take every other call down too
+fn lock(node: &Mutex<Node>) -> MutexGuard<'_, Node> {
+    node.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[derive(Deserialize)]
+struct Request {
+    method: String,
+    #[serde(default)]
+    params: Vec<Value>,
+    #[serde(default)]
+    id: Value,
+}
+
+/// Answer requests on one keep-alive connection until the client closes it
+fn serve(stream: TcpStream, node: &Mutex<Node>, addr: SocketAddr) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    loop {
+        // Request line: `POST /wallet/Miner HTTP/1.1`
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let path = line.split_whitespace().nth(1).unwrap_or("/").to_owned();
+
+        let mut content_length = 0;
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            if line == "\r\n" || line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+
+        let wallet = path.strip_prefix("/wallet/").map(percent_decode);
+        let (status, response) = match serde_json::from_slice::<Request>(&body) {
+            Ok(request) => {
+                let result =
+                    lock(node).handle(wallet.as_deref(), &request.method, &request.params);
+                respond(request.id, result)
+            },
+            Err(e) => respond(
+                Value::Null,
+                Err(RpcError::new(RPC_PARSE_ERROR, format!("Parse error: {e}"))),
+            ),
+        };
+
+        let body = response.to_string();
+        write!(
+            writer,
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )?;
+        writer.flush()?;
+
+        // Like a real node, peers hear about a new block or transaction only after
+        // the call that made it has returned
+        p2p::relay(addr);
+    }
+}
+
+/// JSON-RPC envelope plus the HTTP status Core would use
+fn respond(id: Value, result: Result<Value, RpcError>) -> (&'static str, Value) {
+    match result {
+        Ok(result) => (
+            "200 OK",
+            json!({ "result": result, "error": null, "id": id }),
+        ),
+        Err(error) => {
+            let status = if error.code == RPC_METHOD_NOT_FOUND {
+                "404 Not Found"
+            } else {
+                "500 Internal Server Error"
+            };
+            let error = json!({ "code": error.code, "message": error.message });
+            (status, json!({ "result": null, "error": error, "id": id }))
+        },
+    }
+}
+
+/// Wallet names in the path are URL-encoded (`My%20Wallet`)
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,332 @@
+//! The mock node's block tree: every block it knows, and which of them are active
+//!
+//! Blocks are never forgotten. Ones that lost a race, or were invalidated by hand, stay
+//! in the tree as side branches, exactly like in Core's block index; the active chain
+//! is the longest branch without an invalid block in it. A branch that is merely as
+//! long as the active one doesn't replace it: on a tie, the chain seen first wins.
+//!
+//! Headers are real: each commits to its parent, its transactions' merkle root and a
+//! timestamp, and meets regtest's proof-of-work target, so blocks serialize and hash
+//! the way Core's do.
+
+use std::collections::{HashMap, HashSet};
+
+use bitcoincore_rpc::bitcoin::block::{Header, Version};
+use bitcoincore_rpc::bitcoin::{
+    BlockHash, CompactTarget, Network, TxMerkleNode, Txid, constants,
+};
+
+/// Regtest's proof-of-work limit; about every other nonce meets it
+const REGTEST_BITS: u32 = 0x207f_ffff;
+/// BIP113: lock times compare against the median time of this many blocks
+const MEDIAN_TIME_SPAN: usize = 11;
+
+pub(super) struct Block {
+    pub hash: BlockHash,
+    pub header: Header,
+    pub height: u64,
+    /// Coinbase first, then the transactions it confirmed
+    pub txids: Vec<Txid>,
+    /// How many blocks the node knew before this one; breaks ties between branches
+    arrival: usize,
+}
+
+impl Block {
+    pub fn time(&self) -> u64 {
+        u64::from(self.header.time)
+    }
+}
+
+/// A branch's standing, as `getchaintips` reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TipStatus {
+    Active,
+    /// Fully valid, just not the best chain
+    ValidFork,
+    /// Contains a block `invalidateblock` was called on
+    Invalid,
+}
+
+impl TipStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::ValidFork => "valid-fork",
+            Self::Invalid => "invalid",
+        }
+    }
+}
+
+pub(super) struct Chain {
+    blocks: HashMap<BlockHash, Block>,
+    /// The active chain's hashes, indexed by height
+    active: Vec<BlockHash>,
+    /// Blocks `invalidateblock` was called on; their descendants are invalid too
+    invalid: HashSet<BlockHash>,
+    /// Newest timestamp of any block, so each new block gets a later one
+    latest_time: u64,
+}
+
+impl Chain {
+    /// Only the network's genesis block
+    pub fn new(network: Network) -> Self {
+        let genesis = constants::genesis_block(network);
+        let hash = genesis.block_hash();
+        let block = Block {
+            hash,
+            header: genesis.header,
+            height: 0,
+            txids: genesis.txdata.iter().map(|tx| tx.txid()).collect(),
+            arrival: 0,
+        };
+        Self {
+            latest_time: block.time(),
+            blocks: HashMap::from([(hash, block)]),
+            active: vec![hash],
+            invalid: HashSet::new(),
+        }
+    }
+
+    pub fn tip(&self) -> &Block {
+        let hash = self
+            .active
+            .last()
+            .expect("the genesis block is always there");
+        &self.blocks[hash]
+    }
+
+    pub fn height(&self) -> u64 {
+        self.active.len() as u64 - 1
+    }
+
+    /// The active chain's block at `height`
+    pub fn at(&self, height: u64) -> Option<&Block> {
+        let hash = self.active.get(usize::try_from(height).ok()?)?;
+        Some(&self.blocks[hash])
+    }
+
+    /// Any known block, active or not
+    pub fn get(&self, hash: &BlockHash) -> Option<&Block> {
+        self.blocks.get(hash)
+    }
+
+    pub fn is_active(&self, block: &Block) -> bool {
+        self.active.get(block.height as usize) == Some(&block.hash)
+    }
+
+    /// The active chain, genesis first
+    pub fn active(&self) -> impl Iterator<Item = &Block> {
+        self.active.iter().map(|hash| &self.blocks[hash])
+    }
+
+    pub fn active_hashes(&self) -> &[BlockHash] {
+        &self.active
+    }
+
+    /// Confirmations of a block: 1 for the tip, -1 for one that isn't active
+    pub fn confirmations(&self, block: &Block) -> i64 {
+        if self.is_active(block) {
+            (self.height() - block.height + 1) as i64
+        } else {
+            -1
+        }
+    }
+
+    /// Each regtest block adds 2 units of work
+    pub fn chainwork(block: &Block) -> String {
+        format!("{:064x}", (block.height + 1) * 2)
+    }
+
+    /// Median timestamp of `block` and its ancestors, up to [`MEDIAN_TIME_SPAN`] of them
+    pub fn median_time_past(&self, block: &Block) -> u64 {
+        let mut times = Vec::with_capacity(MEDIAN_TIME_SPAN);
+        let mut next = Some(block);
+        while let Some(block) = next.filter(|_| times.len() < MEDIAN_TIME_SPAN) {
+            times.push(block.time());
+            next = self.blocks.get(&block.header.prev_blockhash);
+        }
+        times.sort_unstable();
+        times[times.len() / 2]
+    }
+
+    /// The block after `block` in the active chain, if it is active and not the tip
+    pub fn next(&self, block: &Block) -> Option<&Block> {
+        self.is_active(block)
+            .then(|| self.at(block.height + 1))
+            .flatten()
+    }
+
+    /// A header for a new block on top of the active tip, proof of work included
+    pub fn next_header(&self, merkle_root: TxMerkleNode) -> Header {
+        let time = self.latest_time + 1;
+        let mut header = Header {
+            version: Version::from_consensus(0x2000_0000),
+            prev_blockhash: self.tip().hash,
+            merkle_root,
+            time: u32::try_from(time).unwrap_or(u32::MAX),
+            bits: CompactTarget::from_consensus(REGTEST_BITS),
+            nonce: 0,
+        };
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    /// Add a block to the tree; `false` if it was known already or its parent isn't
+    ///
+    /// This doesn't change the active chain; call [`Chain::activate_best`] for that.
+    pub fn insert(&mut self, header: Header, txids: Vec<Txid>) -> bool {
+        let hash = header.block_hash();
+        if self.blocks.contains_key(&hash) {
+            return false;
+        }
+        let Some(parent) = self.blocks.get(&header.prev_blockhash) else {
+            return false;
+        };
+        let block = Block {
+            hash,
+            header,
+            height: parent.height + 1,
+            txids,
+            arrival: self.blocks.len(),
+        };
+        self.latest_time = self.latest_time.max(block.time());
+        self.blocks.insert(hash, block);
+        true
+    }
+
+    /// Mark `hash` invalid; `false` if there's no such block
+    pub fn invalidate(&mut self, hash: &BlockHash) -> bool {
+        self.blocks.contains_key(hash) && {
+            self.invalid.insert(*hash);
+            true
+        }
+    }
+
+    /// Undo `invalidate` for `hash`, its ancestors and its descendants
+    pub fn reconsider(&mut self, hash: &BlockHash) -> bool {
+        let Some(block) = self.blocks.get(hash) else {
+            return false;
+        };
+        let cleared: Vec<BlockHash> = self
+            .invalid
+            .iter()
+            .filter(|invalid| {
+                self.is_ancestor(invalid, block)
+                    || self.is_ancestor(hash, &self.blocks[*invalid])
+            })
+            .copied()
+            .collect();
+        for invalid in cleared {
+            self.invalid.remove(&invalid);
+        }
+        true
+    }
+
+    /// Make the best valid branch the active chain
+    ///
+    /// Returns the blocks that left the active chain, tip first, or `None` if the
+    /// active chain didn't change at all.
+    pub fn activate_best(&mut self) -> Option<Vec<BlockHash>> {
+        let valid = self.valid_blocks();
+        let current = self.tip().hash;
+        let best = valid
+            .iter()
+            .map(|hash| &self.blocks[hash])
+            .max_by_key(|block| {
+                (
+                    block.height,
+                    block.hash == current,
+                    std::cmp::Reverse(block.arrival),
+                )
+            })
+            .expect("the genesis block is always valid");
+        if best.hash == current {
+            return None;
+        }
+
+        let mut chain = vec![best.hash];
+        let mut block = best;
+        while let Some(parent) = self.blocks.get(&block.header.prev_blockhash) {
+            chain.push(parent.hash);
+            block = parent;
+        }
+        chain.reverse();
+        let common = self
+            .active
+            .iter()
+            .zip(&chain)
+            .take_while(|(ours, theirs)| ours == theirs)
+            .count();
+        let mut disconnected = self.active.split_off(common);
+        disconnected.reverse();
+        self.active = chain;
+        Some(disconnected)
+    }
+
+    /// Every branch's tip: blocks no other block builds on, highest first
+    pub fn tips(&self) -> Vec<(&Block, TipStatus)> {
+        let parents: HashSet<&BlockHash> = self
+            .blocks
+            .values()
+            .map(|block| &block.header.prev_blockhash)
+            .collect();
+        let valid = self.valid_blocks();
+        let mut tips: Vec<(&Block, TipStatus)> = self
+            .blocks
+            .values()
+            .filter(|block| !parents.contains(&block.hash))
+            .map(|block| {
+                let status = if self.is_active(block) {
+                    TipStatus::Active
+                } else if valid.contains(&block.hash) {
+                    TipStatus::ValidFork
+                } else {
+                    TipStatus::Invalid
+                };
+                (block, status)
+            })
+            .collect();
+        tips.sort_by_key(|(block, _)| (std::cmp::Reverse(block.height), block.arrival));
+        tips
+    }
+
+    /// Blocks `block`'s branch has past the point where it leaves the active chain
+    pub fn branch_length(&self, block: &Block) -> u64 {
+        let mut block = block;
+        let mut length = 0;
+        while !self.is_active(block) {
+            block = &self.blocks[&block.header.prev_blockhash];
+            length += 1;
+        }
+        length
+    }
+
+    /// Blocks with no invalidated block among themselves and their ancestors
+    fn valid_blocks(&self) -> HashSet<BlockHash> {
+        let mut by_height: Vec<&Block> = self.blocks.values().collect();
+        by_height.sort_by_key(|block| block.height);
+        let mut valid = HashSet::new();
+        for block in by_height {
+            let parent_valid =
+                block.height == 0 || valid.contains(&block.header.prev_blockhash);
+            if parent_valid && !self.invalid.contains(&block.hash) {
+                valid.insert(block.hash);
+            }
+        }
+        valid
+    }
+
+    /// Whether `ancestor` is `block` or one of the blocks it builds on
+    fn is_ancestor(&self, ancestor: &BlockHash, block: &Block) -> bool {
+        let mut next = Some(block);
+        while let Some(block) = next {
+            if block.hash == *ancestor {
+                return true;
+            }
+            next = self.blocks.get(&block.header.prev_blockhash);
+        }
+        false
+    }
+}
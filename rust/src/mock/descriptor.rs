@@ -0,0 +1,445 @@
+//! The output descriptors the mock node's wallets speak
+//!
+//! Every wallet with private keys has a BIP32 master key, and `listdescriptors` shows
+//! the same four single-key descriptors Core gives a new wallet (BIP44/49/84/86, each
+//! with a receive and a change chain). Only `wpkh` is ever used to hand out addresses.
+//!
+//! Watch-only wallets can import multisig descriptors built from such keys:
+//! - `wsh(multi(k,KEY,...))` and `wsh(sortedmulti(k,KEY,...))`: P2WSH, CHECKMULTISIG
+//! - `tr(INTERNAL,multi_a(k,KEY,...))` and `tr(INTERNAL,sortedmulti_a(k,KEY,...))`:
+//!   Taproot with a single leaf that counts CHECKSIGADDs; the internal key is a plain
+//!   hex key (usually an unspendable one, so the key path is off)
+//!
+//! A KEY is `[fingerprint/origin/path]xpub/child/path/*` or a hex public key. Anything
+//! else (miniscript, several leaves, private keys) is rejected as unsupported.
+
+use bitcoincore_rpc::bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint, Xpub};
+use bitcoincore_rpc::bitcoin::key::XOnlyPublicKey;
+use bitcoincore_rpc::bitcoin::opcodes::all::{
+    OP_CHECKMULTISIG, OP_CHECKSIG, OP_CHECKSIGADD, OP_NUMEQUAL,
+};
+use bitcoincore_rpc::bitcoin::script::Builder;
+use bitcoincore_rpc::bitcoin::secp256k1::{self, All, Parity, Secp256k1};
+use bitcoincore_rpc::bitcoin::taproot::{LeafVersion, TaprootBuilder, TaprootSpendInfo};
+use bitcoincore_rpc::bitcoin::{PublicKey, ScriptBuf, Witness};
+
+/// BIP380: the characters a descriptor may use, in checksum order
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn polymod(c: u64, value: u64) -> u64 {
+    const GENERATOR: [u64; 5] = [
+        0xf5_dee5_1989,
+        0xa9_fdca_3312,
+        0x1b_ab10_e32d,
+        0x37_06b1_677a,
+        0x64_4d62_6ffd,
+    ];
+    let top = c >> 35;
+    let mut c = ((c & 0x7_ffff_ffff) << 5) ^ value;
+    for (bit, generator) in GENERATOR.iter().enumerate() {
+        if top >> bit & 1 == 1 {
+            c ^= generator;
+        }
+    }
+    c
+}
+
+/// BIP380 checksum of a descriptor (without its `#`); `None` for characters outside
+/// the descriptor alphabet
+pub(super) fn checksum(descriptor: &str) -> Option<String> {
+    let mut c = 1;
+    let mut class = 0;
+    let mut class_count = 0;
+    for ch in descriptor.chars() {
+        let position = INPUT_CHARSET.find(ch)? as u64;
+        c = polymod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+    Some(
+        (0..8)
+            .map(|j| char::from(CHECKSUM_CHARSET[(c >> (5 * (7 - j)) & 31) as usize]))
+            .collect(),
+    )
+}
+
+/// `descriptor#checksum`
+pub(super) fn with_checksum(descriptor: &str) -> String {
+    let checksum = checksum(descriptor).expect("built from descriptor characters");
+    format!("{descriptor}#{checksum}")
+}
+
+/// Split off and verify the checksum, if there is one
+pub(super) fn strip_checksum(text: &str) -> Result<(&str, bool), String> {
+    match text.split_once('#') {
+        None => Ok((text, false)),
+        Some((descriptor, given)) => match checksum(descriptor) {
+            Some(expected) if expected == given => Ok((descriptor, true)),
+            Some(expected) => Err(format!(
+                "Provided checksum '{given}' does not match computed checksum '{expected}'"
+            )),
+            None => Err("Invalid characters in payload".to_owned()),
+        },
+    }
+}
+
+/// A path written the way descriptors do: `/84h/1h/0h` (no leading `m`)
+fn path_suffix(path: &DerivationPath) -> String {
+    path.into_iter()
+        .map(|child| format!("/{child:#}"))
+        .collect()
+}
+
+/// `[fingerprint/origin]xpub/<chain>/*`, a ranged key as `listdescriptors` shows it
+pub(super) fn ranged_key(
+    fingerprint: Fingerprint,
+    origin: &DerivationPath,
+    xpub: &Xpub,
+    chain: u32,
+) -> String {
+    format!("[{fingerprint}{}]{xpub}/{chain}/*", path_suffix(origin))
+}
+
+/// Where a key came from: its master key's fingerprint and the path from there
+pub(super) type KeyOrigin = (Fingerprint, DerivationPath);
+
+/// One KEY of a descriptor
+#[derive(Debug, Clone)]
+enum KeyExpression {
+    Extended {
+        origin: Option<KeyOrigin>,
+        xpub: Xpub,
+        /// Below the xpub, before the wildcard (if any)
+        path: DerivationPath,
+        ranged: bool,
+    },
+    Single {
+        origin: Option<KeyOrigin>,
+        key: secp256k1::PublicKey,
+    },
+}
+
+impl KeyExpression {
+    fn parse(text: &str) -> Result<Self, String> {
+        let invalid = |what: &str| format!("key '{text}': {what}");
+        let (origin, key) = match text.strip_prefix('[') {
+            None => (None, text),
+            Some(rest) => {
+                let (origin, key) = rest
+                    .split_once(']')
+                    .ok_or_else(|| invalid("key origin start '[' without end ']'"))?;
+                let (fingerprint, path) = origin.split_once('/').unwrap_or((origin, ""));
+                let fingerprint: Fingerprint = fingerprint
+                    .parse()
+                    .map_err(|_| invalid("fingerprint is not 4 bytes of hex"))?;
+                let path: DerivationPath = format!("m/{path}")
+                    .trim_end_matches('/')
+                    .parse()
+                    .map_err(|_| invalid("bad origin path"))?;
+                (Some((fingerprint, path)), key)
+            },
+        };
+        if key.len() == 66 || key.len() == 64 {
+            let key = if key.len() == 66 {
+                key.parse::<secp256k1::PublicKey>().ok()
+            } else {
+                key.parse::<XOnlyPublicKey>()
+                    .ok()
+                    .map(|key| key.public_key(Parity::Even))
+            };
+            let key = key.ok_or_else(|| invalid("not a valid public key"))?;
+            return Ok(Self::Single { origin, key });
+        }
+
+        let mut parts = key.split('/');
+        let xpub: Xpub = parts
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .map_err(|_| invalid("not a valid public key or xpub"))?;
+        let mut path = Vec::new();
+        let mut ranged = false;
+        for part in parts {
+            if ranged {
+                return Err(invalid("'*' may only be the last step"));
+            }
+            if part == "*" {
+                ranged = true;
+                continue;
+            }
+            match part.parse::<ChildNumber>() {
+                Ok(child @ ChildNumber::Normal { .. }) => path.push(child),
+                Ok(ChildNumber::Hardened { .. }) => {
+                    return Err(invalid("hardened derivation needs the private key"));
+                },
+                Err(_) => return Err(invalid("bad derivation step")),
+            }
+        }
+        Ok(Self::Extended {
+            origin,
+            xpub,
+            path: path.into(),
+            ranged,
+        })
+    }
+
+    fn is_ranged(&self) -> bool {
+        matches!(self, Self::Extended { ranged: true, .. })
+    }
+
+    /// The public key at `index` (ignored unless ranged), and where it came from
+    fn derive(&self, secp: &Secp256k1<All>, index: u32) -> (secp256k1::PublicKey, KeyOrigin) {
+        match self {
+            Self::Extended {
+                origin,
+                xpub,
+                path,
+                ranged,
+            } => {
+                let mut child = path.clone();
+                if *ranged {
+                    child = child.child(ChildNumber::Normal { index });
+                }
+                let key = xpub
+                    .derive_pub(secp, &child)
+                    .expect("normal derivation from an xpub")
+                    .public_key;
+                let origin = match origin {
+                    Some((fingerprint, origin)) => (*fingerprint, origin.extend(&child)),
+                    None => (xpub.fingerprint(), child),
+                };
+                (key, origin)
+            },
+            Self::Single { origin, key } => {
+                let origin = origin
+                    .clone()
+                    .unwrap_or_else(|| (Fingerprint::default(), DerivationPath::master()));
+                (*key, origin)
+            },
+        }
+    }
+}
+
+/// Which script a multisig descriptor locks coins with
+#[derive(Debug, Clone)]
+enum MultisigScript {
+    /// `wsh(multi(...))`
+    Wsh,
+    /// `tr(internal,multi_a(...))`
+    Tr { internal: XOnlyPublicKey },
+}
+
+/// A parsed multisig descriptor
+#[derive(Debug, Clone)]
+pub(super) struct MultisigDescriptor {
+    script: MultisigScript,
+    threshold: usize,
+    keys: Vec<KeyExpression>,
+    /// `sortedmulti`: keys are put in order per address, not in the order written
+    sorted: bool,
+}
+
+impl MultisigDescriptor {
+    /// Parse a descriptor (checksum already stripped)
+    pub(super) fn parse(descriptor: &str) -> Result<Self, String> {
+        let unsupported =
+            || format!("the mock node only imports multisig descriptors, not '{descriptor}'");
+        let (script, inner) = if let Some(inner) = descriptor.strip_prefix("wsh(") {
+            (MultisigScript::Wsh, inner.strip_suffix(')'))
+        } else if let Some(inner) = descriptor.strip_prefix("tr(") {
+            let (internal, leaf) = inner.split_once(',').ok_or_else(unsupported)?;
+            let internal = match KeyExpression::parse(internal)? {
+                KeyExpression::Single { key, .. } => key.x_only_public_key().0,
+                KeyExpression::Extended { .. } => return Err(unsupported()),
+            };
+            (MultisigScript::Tr { internal }, leaf.strip_suffix(')'))
+        } else {
+            return Err(unsupported());
+        };
+        let inner = inner.ok_or_else(unsupported)?;
+
+        let (function, arguments) = inner
+            .strip_suffix(')')
+            .and_then(|inner| inner.split_once('('))
+            .ok_or_else(unsupported)?;
+        let sorted = match (&script, function) {
+            (MultisigScript::Wsh, "multi") | (MultisigScript::Tr { .. }, "multi_a") => false,
+            (MultisigScript::Wsh, "sortedmulti")
+            | (MultisigScript::Tr { .. }, "sortedmulti_a") => true,
+            _ => return Err(unsupported()),
+        };
+        let mut arguments = arguments.split(',');
+        let threshold: usize = arguments
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .map_err(|_| format!("Multi threshold '{inner}' is not valid"))?;
+        let keys = arguments
+            .map(KeyExpression::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if threshold == 0 || threshold > keys.len() {
+            return Err(format!(
+                "Multisig threshold cannot be {threshold}, must be at least 1 and at most {}",
+                keys.len()
+            ));
+        }
+        Ok(Self {
+            script,
+            threshold,
+            keys,
+            sorted,
+        })
+    }
+
+    pub(super) fn is_ranged(&self) -> bool {
+        self.keys.iter().any(KeyExpression::is_ranged)
+    }
+
+    /// `tr(...)`, which hands out bech32m addresses (`wsh(...)` gives bech32 ones)
+    pub(super) fn is_taproot(&self) -> bool {
+        matches!(self.script, MultisigScript::Tr { .. })
+    }
+
+    /// The output at `index` of the descriptor's range
+    pub(super) fn derive(&self, secp: &Secp256k1<All>, index: u32) -> MultisigOutput {
+        let mut keys: Vec<(secp256k1::PublicKey, KeyOrigin)> = self
+            .keys
+            .iter()
+            .map(|key| key.derive(secp, index))
+            .collect();
+        match self.script {
+            MultisigScript::Wsh => {
+                if self.sorted {
+                    keys.sort_by_key(|(key, _)| key.serialize());
+                }
+                let script = keys
+                    .iter()
+                    .fold(
+                        Builder::new().push_int(self.threshold as i64),
+                        |builder, (key, _)| builder.push_key(&PublicKey::new(*key)),
+                    )
+                    .push_int(keys.len() as i64)
+                    .push_opcode(OP_CHECKMULTISIG)
+                    .into_script();
+                MultisigOutput {
+                    script_pubkey: ScriptBuf::new_p2wsh(&script.wscript_hash()),
+                    script,
+                    threshold: self.threshold,
+                    keys,
+                    taproot: None,
+                }
+            },
+            MultisigScript::Tr { internal } => {
+                if self.sorted {
+                    keys.sort_by_key(|(key, _)| key.x_only_public_key().0.serialize());
+                }
+                let script = keys
+                    .iter()
+                    .enumerate()
+                    .fold(Builder::new(), |builder, (position, (key, _))| {
+                        builder
+                            .push_x_only_key(&key.x_only_public_key().0)
+                            .push_opcode(if position == 0 {
+                                OP_CHECKSIG
+                            } else {
+                                OP_CHECKSIGADD
+                            })
+                    })
+                    .push_int(self.threshold as i64)
+                    .push_opcode(OP_NUMEQUAL)
+                    .into_script();
+                let spend_info = TaprootBuilder::new()
+                    .add_leaf(0, script.clone())
+                    .expect("a single leaf at depth 0")
+                    .finalize(secp, internal)
+                    .expect("a single leaf is a complete tree");
+                MultisigOutput {
+                    script_pubkey: ScriptBuf::new_p2tr_tweaked(spend_info.output_key()),
+                    script,
+                    threshold: self.threshold,
+                    keys,
+                    taproot: Some(spend_info),
+                }
+            },
+        }
+    }
+}
+
+/// One address of a multisig descriptor, and what it takes to spend from it
+#[derive(Debug, Clone)]
+pub(super) struct MultisigOutput {
+    pub script_pubkey: ScriptBuf,
+    /// P2WSH: the witness script; Taproot: the leaf script
+    pub script: ScriptBuf,
+    pub threshold: usize,
+    /// In script order
+    pub keys: Vec<(secp256k1::PublicKey, KeyOrigin)>,
+    pub taproot: Option<TaprootSpendInfo>,
+}
+
+impl MultisigOutput {
+    pub(super) fn leaf(&self) -> (ScriptBuf, LeafVersion) {
+        (self.script.clone(), LeafVersion::TapScript)
+    }
+
+    /// A witness as big as the real one will be, for fee estimates: `threshold`
+    /// worst-case signatures (the rest empty), the script and, for Taproot, the
+    /// control block
+    pub(super) fn dummy_witness(&self) -> Witness {
+        let mut witness = Witness::new();
+        match &self.taproot {
+            None => {
+                // CHECKMULTISIG pops one item too many
+                witness.push([]);
+                for _ in 0..self.threshold {
+                    witness.push([0u8; 72]);
+                }
+                witness.push(self.script.as_bytes());
+            },
+            Some(spend_info) => {
+                for position in 0..self.keys.len() {
+                    if position < self.threshold {
+                        witness.push([0u8; 64]);
+                    } else {
+                        witness.push([]);
+                    }
+                }
+                witness.push(self.script.as_bytes());
+                let control_block = spend_info
+                    .control_block(&self.leaf())
+                    .expect("the leaf is in the tree");
+                witness.push(control_block.serialize());
+            },
+        }
+        witness
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_bip380() {
+        // The test vector from BIP380
+        assert_eq!(checksum("raw(deadbeef)").as_deref(), Some("89f8spxm"));
+        assert_eq!(
+            strip_checksum("raw(deadbeef)#89f8spxm"),
+            Ok(("raw(deadbeef)", true))
+        );
+        assert!(strip_checksum("raw(deadbeef)#89f8spxn").is_err());
+    }
+}
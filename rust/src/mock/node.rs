@@ -0,0 +1,3454 @@
+//! The simulated node behind [`MockNode`](super::MockNode): chain, mempool and wallets
+//!
+//! Just enough of Bitcoin Core's behaviour to drive the simulator:
+//! - blocks pay a halving coinbase subsidy plus the fees of every mempool transaction
+//! - coinbase outputs mature after 100 blocks, exactly like consensus demands
+//! - wallets hold real secp256k1 keys and sign what they send, so the transactions
+//!   decode and weigh the same as the real thing. Each address type (P2PKH,
+//!   P2SH-P2WPKH, P2WPKH, Taproot key path) gets its own BIP44/49/84/86-style receive
+//!   and change chains, as `getaddressinfo` reports
+//! - fees are charged at 1 sat/vB, the `fallbackfee` in this repo's bitcoin.conf
+//! - peers added with `addnode` exchange blocks and transactions (see p2p.rs), and a
+//!   node that learns of a longer chain reorganizes onto it
+//! - `invalidateblock`/`reconsiderblock` and `generateblock` build competing branches
+//!   by hand (see chain.rs for how the active one is picked)
+//! - a transaction spending the same outputs as mempool transactions replaces them
+//!   under BIP125's opt-in rules, like Core before v28 made full RBF the default
+//! - `estimatesmartfee` answers with the median fee rate confirmed in the last blocks
+//!   of the target, a crude stand-in for Core's bucketed history that also has nothing
+//!   to say until some transactions confirm
+//!
+//! Everything is deterministic: the same calls always produce the same addresses and
+//! transactions. The only exception is the key seed, which differs per node so that
+//! peered nodes never derive each other's keys. Block timestamps advance one second
+//! per block mined, on whichever branch.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bitcoincore_rpc::bitcoin::bip32::{ChildNumber, DerivationPath, Xpriv, Xpub};
+use bitcoincore_rpc::bitcoin::block::Header;
+use bitcoincore_rpc::bitcoin::hashes::hex::FromHex;
+use bitcoincore_rpc::bitcoin::hashes::{Hash, sha256};
+use bitcoincore_rpc::bitcoin::hex::DisplayHex;
+use bitcoincore_rpc::bitcoin::key::{Keypair, TapTweak, XOnlyPublicKey};
+use bitcoincore_rpc::bitcoin::psbt::{Input as PsbtInput, Psbt};
+use bitcoincore_rpc::bitcoin::script::{Builder, Instruction, PushBytesBuf};
+use bitcoincore_rpc::bitcoin::secp256k1::{All, Message, Secp256k1, SecretKey};
+use bitcoincore_rpc::bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+use bitcoincore_rpc::bitcoin::taproot::{self, TapLeafHash};
+use bitcoincore_rpc::bitcoin::{
+    Address, Amount, Block, BlockHash, EcdsaSighashType, MerkleBlock, Network, OutPoint,
+    PublicKey, ScriptBuf, Sequence, SignedAmount, Transaction, TxIn, TxOut, Txid, Witness,
+    absolute, consensus, constants, ecdsa, opcodes, relative, transaction,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+use super::chain::{self, Chain};
+use super::descriptor::{self, KeyOrigin};
+use super::p2p;
+use crate::mining::COINBASE_MATURITY;
+use crate::psbt;
+use crate::script::ScriptType;
+use crate::wallets::AddressType;
+
+/// Regtest halves the subsidy every 150 blocks (mainnet: 210,000)
+const HALVING_INTERVAL: u64 = 150;
+/// `fallbackfee=0.00001` BTC/kvB from bitcoin.conf
+const FEE_RATE_SAT_PER_VB: u64 = 1;
+const PROTOCOL_VERSION: u32 = 70016;
+const SUBVERSION: &str = "/Satoshi:28.0.0(mock)/";
+/// BIP141: the coinbase output committing to the block's witness data starts with this
+const WITNESS_COMMITMENT_HEADER: [u8; 4] = [0xaa, 0x21, 0xa9, 0xed];
+
+// Bitcoin Core's RPC error codes (src/rpc/protocol.h)
+pub(super) const RPC_PARSE_ERROR: i32 = -32700;
+pub(super) const RPC_METHOD_NOT_FOUND: i32 = -32601;
+const RPC_MISC_ERROR: i32 = -1;
+const RPC_TYPE_ERROR: i32 = -3;
+const RPC_WALLET_ERROR: i32 = -4;
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+const RPC_WALLET_INSUFFICIENT_FUNDS: i32 = -6;
+const RPC_INVALID_PARAMETER: i32 = -8;
+const RPC_WALLET_UNLOCK_NEEDED: i32 = -13;
+const RPC_WALLET_PASSPHRASE_INCORRECT: i32 = -14;
+const RPC_WALLET_WRONG_ENC_STATE: i32 = -15;
+const RPC_WALLET_NOT_FOUND: i32 = -18;
+const RPC_WALLET_NOT_SPECIFIED: i32 = -19;
+const RPC_DESERIALIZATION_ERROR: i32 = -22;
+const RPC_VERIFY_ERROR: i32 = -25;
+const RPC_VERIFY_REJECTED: i32 = -26;
+const RPC_VERIFY_ALREADY_IN_CHAIN: i32 = -27;
+const RPC_IN_WARMUP: i32 = -28;
+const RPC_WALLET_ALREADY_LOADED: i32 = -35;
+const RPC_CLIENT_NODE_ALREADY_ADDED: i32 = -23;
+const RPC_CLIENT_NODE_NOT_ADDED: i32 = -24;
+const RPC_CLIENT_NODE_NOT_CONNECTED: i32 = -29;
+
+/// A JSON-RPC error, as Bitcoin Core would report it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct RpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl RpcError {
+    pub(super) fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+type RpcResult<T = Value> = Result<T, RpcError>;
+
+/// An unspent output, and where it is
+type Coin = (OutPoint, TxOut);
+
+/// An output script a wallet handed out, and what it takes to spend from it
+struct Key {
+    wallet: String,
+    label: String,
+    change: bool,
+    /// Position on the wallet's receive or change chain, for `hdkeypath`
+    index: u64,
+    spend: Spend,
+}
+
+enum Spend {
+    /// One of the wallet's own keys, in an address of `address_type`
+    Single {
+        secret: SecretKey,
+        public: PublicKey,
+        address_type: AddressType,
+    },
+    /// An address of an imported multisig descriptor: the keys are the cosigners'
+    Multisig(Box<descriptor::MultisigOutput>),
+}
+
+impl Key {
+    /// BIP44/49/84/86 path on regtest's coin type:
+    /// m/<purpose>h/1h/0h/<0 receive, 1 change>/<index>
+    fn hd_keypath(&self) -> String {
+        let purpose = match &self.spend {
+            Spend::Single { address_type, .. } => purpose(*address_type),
+            Spend::Multisig(_) => purpose(AddressType::Bech32),
+        };
+        format!(
+            "m/{purpose}h/1h/0h/{}/{}",
+            u8::from(self.change),
+            self.index
+        )
+    }
+}
+
+/// The BIP44 purpose of the derivation path for each address type's keys
+fn purpose(address_type: AddressType) -> u32 {
+    match address_type {
+        AddressType::Legacy => 44,
+        AddressType::P2shSegwit => 49,
+        AddressType::Bech32 => 84,
+        AddressType::Bech32m => 86,
+    }
+}
+
+/// The address type an imported multisig descriptor hands out
+fn descriptor_type(descriptor: &descriptor::MultisigDescriptor) -> AddressType {
+    if descriptor.is_taproot() {
+        AddressType::Bech32m
+    } else {
+        AddressType::Bech32
+    }
+}
+
+/// A signature by one of a wallet's single keys, of the kind its address takes
+enum SingleSignature {
+    Ecdsa(PublicKey, ecdsa::Signature),
+    Schnorr(taproot::Signature),
+}
+
+/// A descriptor imported with `importdescriptors`
+struct ImportedDescriptor {
+    /// As given, checksum included
+    text: String,
+    descriptor: descriptor::MultisigDescriptor,
+    active: bool,
+    internal: bool,
+    timestamp: u64,
+    /// The next index to hand out an address for
+    next: u32,
+}
+
+struct Wallet {
+    loaded: bool,
+    private_keys_enabled: bool,
+    blank: bool,
+    avoid_reuse: bool,
+    descriptors: bool,
+    passphrase: Option<String>,
+    /// Unix time until which an encrypted wallet may sign
+    unlocked_until: u64,
+    /// BIP32 master key; wallets without private keys (or blank ones) have none
+    master: Option<Xpriv>,
+    imported: Vec<ImportedDescriptor>,
+}
+
+impl Wallet {
+    fn is_locked(&self) -> bool {
+        self.passphrase.is_some() && self.unlocked_until <= unix_now()
+    }
+}
+
+/// A node's blocks and mempool, as announced to peers
+pub(super) struct Inventory {
+    blocks: Vec<BlockHash>,
+    mempool: Vec<Txid>,
+}
+
+/// A block as relayed to peers: its header and full transactions
+type RelayedBlock = (Header, Vec<Transaction>);
+
+/// What a node sends a peer to bring it up to date
+pub(super) struct Relay {
+    /// Our blocks after the last one the peer's chain shares with ours
+    chain: Option<Vec<RelayedBlock>>,
+    /// Mempool transactions the peer doesn't have, in arrival order
+    transactions: Vec<Transaction>,
+}
+
+impl Relay {
+    pub(super) fn is_empty(&self) -> bool {
+        self.chain.is_none() && self.transactions.is_empty()
+    }
+}
+
+/// A wallet's balance split the way `getbalances` reports it
+#[derive(Debug, Clone, Copy, Default)]
+struct Balances {
+    trusted: Amount,
+    untrusted_pending: Amount,
+    immature: Amount,
+}
+
+/// The whole simulated node
+pub(super) struct Node {
+    network: Network,
+    /// Where the node listens; peers reach it (and it names itself) by this address
+    addr: SocketAddr,
+    secp: Secp256k1<All>,
+    chain: Chain,
+    /// Every transaction ever accepted, confirmed or not (like `txindex=1`)
+    transactions: HashMap<Txid, Transaction>,
+    confirmed_at: HashMap<Txid, u64>,
+    /// Unconfirmed transactions in arrival order, with the time and tip height they arrived at
+    mempool: Vec<(Txid, u64, u64)>,
+    /// Unspent outputs of confirmed and mempool transactions
+    utxos: BTreeMap<OutPoint, TxOut>,
+    wallets: BTreeMap<String, Wallet>,
+    keys: HashMap<ScriptBuf, Key>,
+    key_seed: u64,
+    /// Calls still to be answered with "Loading block index…", like a node starting up
+    pub(super) warmup_calls: usize,
+    /// Every method called so far, for tests that care about *how* something was done
+    pub(super) calls: Vec<String>,
+}
+
+impl Node {
+    pub(super) fn new(network: Network, addr: SocketAddr) -> Self {
+        static NEXT_KEY_SEED: AtomicU64 = AtomicU64::new(0);
+
+        // Like Core, the genesis coinbase is never part of the UTXO set; it's only
+        // kept so the genesis block can be served in full
+        let genesis = constants::genesis_block(network);
+        Self {
+            network,
+            addr,
+            secp: Secp256k1::new(),
+            chain: Chain::new(network),
+            transactions: genesis
+                .txdata
+                .into_iter()
+                .map(|tx| (tx.txid(), tx))
+                .collect(),
+            confirmed_at: HashMap::new(),
+            mempool: Vec::new(),
+            utxos: BTreeMap::new(),
+            wallets: BTreeMap::new(),
+            keys: HashMap::new(),
+            key_seed: NEXT_KEY_SEED.fetch_add(1, Ordering::Relaxed),
+            warmup_calls: 0,
+            calls: Vec::new(),
+        }
+    }
+
+    /// Execute one RPC call; `wallet` comes from a `/wallet/<name>` request path
+    pub(super) fn handle(
+        &mut self,
+        wallet: Option<&str>,
+        method: &str,
+        params: &[Value],
+    ) -> RpcResult {
+        self.calls.push(method.to_owned());
+        if self.warmup_calls > 0 {
+            self.warmup_calls -= 1;
+            return Err(RpcError::new(RPC_IN_WARMUP, "Loading block index…"));
+        }
+        match method {
+            // Node
+            "getblockchaininfo" => Ok(self.blockchain_info()),
+            "getnetworkinfo" => Ok(network_info(p2p::peers(self.addr).len())),
+            "getblockcount" => Ok(json!(self.tip_height())),
+            "getbestblockhash" => Ok(json!(self.chain.tip().hash)),
+            "getblockhash" => self.block_hash(params),
+            "getblock" => self.block(params),
+            "getblockheader" => self.block_header(params),
+            "getchaintips" => Ok(self.chain_tips()),
+            "invalidateblock" => self.invalidate_block(params),
+            "reconsiderblock" => self.reconsider_block(params),
+            "getrawtransaction" => self.raw_transaction(params),
+            "gettxoutproof" => self.tx_out_proof(params),
+            "verifytxoutproof" => self.verify_tx_out_proof(params),
+            "getrawmempool" => Ok(json!(self.mempool_txids())),
+            "getmempoolentry" => self.mempool_entry(params),
+            "getmempoolinfo" => Ok(self.mempool_info()),
+            "estimatesmartfee" => self.estimate_smart_fee(params),
+            "generatetoaddress" => self.generate_to_address(params),
+            "generateblock" => self.generate_block(params),
+            "sendrawtransaction" => self.send_raw_transaction(params),
+            "testmempoolaccept" => self.test_mempool_accept(params),
+            "getdescriptorinfo" => self.descriptor_info(params),
+            // Peers
+            "addnode" => self.add_node(params),
+            "disconnectnode" => self.disconnect_node(params),
+            "getpeerinfo" => Ok(self.peer_info()),
+            "getconnectioncount" => Ok(json!(p2p::peers(self.addr).len())),
+            // Wallet management
+            "listwallets" => Ok(json!(self.loaded_wallets())),
+            "listwalletdir" => Ok(self.wallet_dir()),
+            "createwallet" => self.create_wallet(params),
+            "loadwallet" => self.load_wallet(params),
+            "unloadwallet" => self.unload_wallet(wallet, params),
+            // Wallet
+            "getwalletinfo" => self.wallet_info(wallet),
+            "getnewaddress" => self.new_address(wallet, params, false),
+            "getrawchangeaddress" => {
+                // Its only param is the address type, which getnewaddress takes second
+                let address_type = params.first().cloned().unwrap_or(Value::Null);
+                self.new_address(wallet, &[Value::Null, address_type], true)
+            },
+            "getaddressinfo" => self.address_info(wallet, params),
+            "listdescriptors" => self.list_descriptors(wallet, params),
+            "importdescriptors" => self.import_descriptors(wallet, params),
+            "getbalance" => self.balance(wallet, params),
+            "getbalances" => self.balances_json(wallet),
+            "listtransactions" => self.list_transactions(wallet, params),
+            "listunspent" => self.list_unspent(wallet, params),
+            "sendtoaddress" => self.send_to_address(wallet, params),
+            "bumpfee" => self.bump_fee(wallet, params),
+            "signrawtransactionwithwallet" => self.sign_raw_transaction(wallet, params),
+            "walletcreatefundedpsbt" => self.wallet_create_funded_psbt(wallet, params),
+            "walletprocesspsbt" => self.wallet_process_psbt(wallet, params),
+            "finalizepsbt" => self.finalize_psbt(params),
+            "combinepsbt" => self.combine_psbt(params),
+            "walletpassphrase" => self.wallet_passphrase(wallet, params),
+            _ => Err(RpcError::new(RPC_METHOD_NOT_FOUND, "Method not found")),
+        }
+    }
+
+    // ── Chain ─────────────────────────────────────────────────────────
+
+    fn tip_height(&self) -> u64 {
+        self.chain.height()
+    }
+
+    fn confirmations(&self, txid: &Txid) -> u64 {
+        self.confirmed_at
+            .get(txid)
+            .map_or(0, |height| self.tip_height() - height + 1)
+    }
+
+    fn blockchain_info(&self) -> Value {
+        let tip = self.chain.tip();
+        json!({
+            "chain": self.network.to_core_arg(),
+            "blocks": tip.height,
+            "headers": tip.height,
+            "bestblockhash": tip.hash,
+            "difficulty": tip.header.difficulty_float(),
+            "time": tip.time(),
+            "mediantime": self.chain.median_time_past(tip),
+            "verificationprogress": 1,
+            "initialblockdownload": false,
+            "chainwork": Chain::chainwork(tip),
+            "size_on_disk": 0,
+            "pruned": false,
+            "warnings": "",
+        })
+    }
+
+    fn block_hash(&self, params: &[Value]) -> RpcResult {
+        let height: u64 = required(params, 0, "height")?;
+        self.chain
+            .at(height)
+            .map(|block| json!(block.hash))
+            .ok_or_else(|| RpcError::new(RPC_INVALID_PARAMETER, "Block height out of range"))
+    }
+
+    fn full_block(&self, block: &chain::Block) -> Block {
+        Block {
+            header: block.header,
+            txdata: block
+                .txids
+                .iter()
+                .map(|txid| self.transactions[txid].clone())
+                .collect(),
+        }
+    }
+
+    /// The active-chain block `txid` is confirmed in
+    fn containing_block(&self, txid: &Txid) -> Option<&chain::Block> {
+        self.chain.at(*self.confirmed_at.get(txid)?)
+    }
+
+    fn block_param(&self, params: &[Value]) -> RpcResult<&chain::Block> {
+        let hash: BlockHash = required(params, 0, "blockhash")?;
+        self.chain.get(&hash).ok_or_else(block_not_found)
+    }
+
+    /// getblock params: (blockhash, verbosity); verbosity 2 (decoded transactions)
+    /// isn't supported
+    fn block(&self, params: &[Value]) -> RpcResult {
+        let block = self.block_param(params)?;
+        // Older clients pass `verbose` as a bool
+        let verbosity = match params.get(1) {
+            Some(Value::Bool(verbose)) => u64::from(*verbose),
+            _ => optional(params, 1)?.unwrap_or(1),
+        };
+        let full = self.full_block(block);
+        match verbosity {
+            0 => Ok(json!(consensus::encode::serialize_hex(&full))),
+            1 => {
+                let mut info = self.header_json(block);
+                let (size, weight) = (full.total_size(), full.weight().to_wu() as usize);
+                info["size"] = json!(size);
+                // weight = 3 × stripped size + total size
+                info["strippedsize"] = json!((weight - size) / 3);
+                info["weight"] = json!(weight);
+                info["tx"] = json!(block.txids);
+                Ok(info)
+            },
+            _ => Err(RpcError::new(
+                RPC_INVALID_PARAMETER,
+                "getblock verbosity 2 is not supported by the mock node",
+            )),
+        }
+    }
+
+    /// getblockheader params: (blockhash, verbose)
+    fn block_header(&self, params: &[Value]) -> RpcResult {
+        let block = self.block_param(params)?;
+        if optional(params, 1)?.unwrap_or(true) {
+            Ok(self.header_json(block))
+        } else {
+            Ok(json!(consensus::encode::serialize_hex(&block.header)))
+        }
+    }
+
+    /// The fields `getblock` and `getblockheader` share
+    fn header_json(&self, block: &chain::Block) -> Value {
+        let header = &block.header;
+        let mut info = json!({
+            "hash": block.hash,
+            "confirmations": self.chain.confirmations(block),
+            "height": block.height,
+            "version": header.version.to_consensus(),
+            "versionHex": format!("{:08x}", header.version.to_consensus()),
+            "merkleroot": header.merkle_root,
+            "time": block.time(),
+            "mediantime": self.chain.median_time_past(block),
+            "nonce": header.nonce,
+            "bits": format!("{:08x}", header.bits.to_consensus()),
+            "difficulty": header.difficulty_float(),
+            "chainwork": Chain::chainwork(block),
+            "nTx": block.txids.len(),
+        });
+        if block.height > 0 {
+            info["previousblockhash"] = json!(header.prev_blockhash);
+        }
+        if let Some(next) = self.chain.next(block) {
+            info["nextblockhash"] = json!(next.hash);
+        }
+        info
+    }
+
+    fn chain_tips(&self) -> Value {
+        let tips: Vec<Value> = self
+            .chain
+            .tips()
+            .into_iter()
+            .map(|(block, status)| {
+                json!({
+                    "height": block.height,
+                    "hash": block.hash,
+                    "branchlen": self.chain.branch_length(block),
+                    "status": status.as_str(),
+                })
+            })
+            .collect();
+        json!(tips)
+    }
+
+    /// Treat a block and everything built on it as invalid, reorganizing away from it
+    fn invalidate_block(&mut self, params: &[Value]) -> RpcResult {
+        let hash: BlockHash = required(params, 0, "blockhash")?;
+        if !self.chain.invalidate(&hash) {
+            return Err(block_not_found());
+        }
+        self.activate_best_chain();
+        Ok(Value::Null)
+    }
+
+    /// Undo `invalidateblock`; the branch only becomes active again if it is the longest
+    fn reconsider_block(&mut self, params: &[Value]) -> RpcResult {
+        let hash: BlockHash = required(params, 0, "blockhash")?;
+        if !self.chain.reconsider(&hash) {
+            return Err(block_not_found());
+        }
+        self.activate_best_chain();
+        Ok(Value::Null)
+    }
+
+    fn raw_transaction(&self, params: &[Value]) -> RpcResult {
+        let txid: Txid = required(params, 0, "txid")?;
+        let verbose = params
+            .get(1)
+            .is_some_and(|v| v.as_bool().unwrap_or(v.as_u64().unwrap_or(0) > 0));
+        let tx = self.transactions.get(&txid).ok_or_else(|| {
+            RpcError::new(
+                RPC_INVALID_ADDRESS_OR_KEY,
+                "No such mempool or blockchain transaction. Use gettransaction for wallet transactions.",
+            )
+        })?;
+        if !verbose {
+            return Ok(json!(consensus::encode::serialize_hex(tx)));
+        }
+
+        let mut info = self.decoded_tx(tx);
+        if let Some(block) = self.containing_block(&txid) {
+            info["blockhash"] = json!(block.hash);
+            info["confirmations"] = json!(self.chain.confirmations(block));
+            info["time"] = json!(block.time());
+            info["blocktime"] = json!(block.time());
+        }
+        Ok(info)
+    }
+
+    /// A transaction the way getrawtransaction's verbose mode and decoderawtransaction
+    /// show it
+    fn decoded_tx(&self, tx: &Transaction) -> Value {
+        let vin: Vec<Value> = tx
+            .input
+            .iter()
+            .map(|input| {
+                let mut vin = if tx.is_coinbase() {
+                    json!({ "coinbase": input.script_sig.to_hex_string() })
+                } else {
+                    json!({
+                        "txid": input.previous_output.txid,
+                        "vout": input.previous_output.vout,
+                        "scriptSig": {
+                            "asm": input.script_sig.to_asm_string(),
+                            "hex": input.script_sig.to_hex_string(),
+                        },
+                    })
+                };
+                if !input.witness.is_empty() {
+                    let items: Vec<String> = input
+                        .witness
+                        .iter()
+                        .map(|item| item.to_lower_hex_string())
+                        .collect();
+                    vin["txinwitness"] = json!(items);
+                }
+                vin["sequence"] = json!(input.sequence.0);
+                vin
+            })
+            .collect();
+        let vout: Vec<Value> = (0u32..)
+            .zip(&tx.output)
+            .map(|(n, output)| {
+                let script = &output.script_pubkey;
+                let mut script_pubkey = json!({
+                    "asm": script.to_asm_string(),
+                    "hex": script.to_hex_string(),
+                    "type": ScriptType::of(script).core_name(),
+                });
+                if let Ok(address) = Address::from_script(script, self.network) {
+                    script_pubkey["address"] = json!(address);
+                }
+                json!({ "value": output.value.to_btc(), "n": n, "scriptPubKey": script_pubkey })
+            })
+            .collect();
+        json!({
+            "txid": tx.txid(),
+            "hash": tx.wtxid(),
+            "version": tx.version.0,
+            "size": tx.total_size(),
+            "vsize": tx.vsize(),
+            "weight": tx.weight().to_wu(),
+            "locktime": tx.lock_time.to_consensus_u32(),
+            "vin": vin,
+            "vout": vout,
+            "hex": consensus::encode::serialize_hex(tx),
+        })
+    }
+
+    /// gettxoutproof params: (txids, blockhash); without a block hash the transactions
+    /// must be confirmed (this node always has a transaction index)
+    fn tx_out_proof(&self, params: &[Value]) -> RpcResult {
+        let txids: Vec<Txid> = required(params, 0, "txids")?;
+        let first = txids.first().ok_or_else(|| {
+            RpcError::new(RPC_INVALID_PARAMETER, "Parameter 'txids' cannot be empty")
+        })?;
+        let block = match optional::<BlockHash>(params, 1)? {
+            Some(hash) => self.chain.get(&hash).ok_or_else(block_not_found)?,
+            None => self.containing_block(first).ok_or_else(|| {
+                RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "Transaction not yet in block")
+            })?,
+        };
+        if !txids.iter().all(|txid| block.txids.contains(txid)) {
+            return Err(RpcError::new(
+                RPC_INVALID_ADDRESS_OR_KEY,
+                "Not all transactions found in specified or retrieved block",
+            ));
+        }
+        let proof = MerkleBlock::from_block_with_predicate(&self.full_block(block), |txid| {
+            txids.contains(txid)
+        });
+        Ok(json!(consensus::encode::serialize_hex(&proof)))
+    }
+
+    /// verifytxoutproof params: (proof); the txids the proof commits to, or none when
+    /// it doesn't add up to the header's Merkle root
+    fn verify_tx_out_proof(&self, params: &[Value]) -> RpcResult {
+        let hex: String = required(params, 0, "proof")?;
+        let proof: MerkleBlock = Vec::<u8>::from_hex(&hex)
+            .ok()
+            .and_then(|bytes| consensus::deserialize(&bytes).ok())
+            .ok_or_else(|| RpcError::new(RPC_DESERIALIZATION_ERROR, "Proof decode failed"))?;
+
+        let (mut matches, mut indexes) = (Vec::new(), Vec::new());
+        let root = proof.txn.extract_matches(&mut matches, &mut indexes);
+        if root.ok() != Some(proof.header.merkle_root) {
+            return Ok(json!([]));
+        }
+        match self.chain.get(&proof.header.block_hash()) {
+            Some(block) if self.chain.is_active(block) => Ok(json!(matches)),
+            _ => Err(RpcError::new(
+                RPC_INVALID_ADDRESS_OR_KEY,
+                "Block not found in chain",
+            )),
+        }
+    }
+
+    fn mempool_txids(&self) -> Vec<Txid> {
+        self.mempool.iter().map(|(txid, ..)| *txid).collect()
+    }
+
+    fn in_mempool(&self, txid: &Txid) -> bool {
+        self.mempool.iter().any(|(id, ..)| id == txid)
+    }
+
+    /// Value of the output `outpoint` refers to, spent or not
+    fn prevout(&self, outpoint: &OutPoint) -> Option<&TxOut> {
+        self.transactions
+            .get(&outpoint.txid)?
+            .output
+            .get(outpoint.vout as usize)
+    }
+
+    fn fee(&self, tx: &Transaction) -> Amount {
+        if tx.is_coinbase() {
+            return Amount::ZERO;
+        }
+        let inputs: Amount = tx
+            .input
+            .iter()
+            .filter_map(|input| self.prevout(&input.previous_output))
+            .map(|prevout| prevout.value)
+            .sum();
+        let outputs: Amount = tx.output.iter().map(|output| output.value).sum();
+        inputs - outputs
+    }
+
+    /// In-mempool transactions `txid` spends from directly
+    fn mempool_parents(&self, txid: &Txid) -> Vec<Txid> {
+        let mut parents: Vec<Txid> = Vec::new();
+        for input in &self.transactions[txid].input {
+            let parent = input.previous_output.txid;
+            if self.in_mempool(&parent) && !parents.contains(&parent) {
+                parents.push(parent);
+            }
+        }
+        parents
+    }
+
+    /// In-mempool transactions spending `txid`'s outputs directly
+    fn mempool_children(&self, txid: &Txid) -> Vec<Txid> {
+        self.mempool_txids()
+            .into_iter()
+            .filter(|child| self.mempool_parents(child).contains(txid))
+            .collect()
+    }
+
+    /// Transitive closure of `step` from `txid`, excluding `txid` itself
+    fn mempool_family(&self, txid: &Txid, step: impl Fn(&Txid) -> Vec<Txid>) -> Vec<Txid> {
+        let mut seen: Vec<Txid> = Vec::new();
+        let mut queue = step(txid);
+        while let Some(next) = queue.pop() {
+            if !seen.contains(&next) {
+                queue.extend(step(&next));
+                seen.push(next);
+            }
+        }
+        seen
+    }
+
+    /// BIP125: a mempool transaction is replaceable if it, or any unconfirmed ancestor,
+    /// signals
+    fn signals_rbf(&self, txid: &Txid) -> bool {
+        std::iter::once(txid)
+            .chain(&self.mempool_family(txid, |id| self.mempool_parents(id)))
+            .any(|id| self.transactions[id].is_explicitly_rbf())
+    }
+
+    fn mempool_entry(&self, params: &[Value]) -> RpcResult {
+        let txid: Txid = required(params, 0, "txid")?;
+        let &(_, time, height) = self
+            .mempool
+            .iter()
+            .find(|(id, ..)| *id == txid)
+            .ok_or_else(|| {
+                RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "Transaction not in mempool")
+            })?;
+        let tx = &self.transactions[&txid];
+
+        let ancestors = self.mempool_family(&txid, |id| self.mempool_parents(id));
+        let descendants = self.mempool_family(&txid, |id| self.mempool_children(id));
+        let vsize = |id: &Txid| self.transactions[id].vsize() as u64;
+        let fee = |id: &Txid| self.fee(&self.transactions[id]);
+        let base = fee(&txid);
+
+        Ok(json!({
+            "vsize": tx.vsize(),
+            "weight": tx.weight().to_wu(),
+            "time": time,
+            "height": height,
+            "descendantcount": descendants.len() + 1,
+            "descendantsize": vsize(&txid) + descendants.iter().map(vsize).sum::<u64>(),
+            "ancestorcount": ancestors.len() + 1,
+            "ancestorsize": vsize(&txid) + ancestors.iter().map(vsize).sum::<u64>(),
+            "wtxid": tx.wtxid(),
+            "fees": {
+                "base": base.to_btc(),
+                "modified": base.to_btc(),
+                "ancestor": (base + ancestors.iter().map(fee).sum::<Amount>()).to_btc(),
+                "descendant": (base + descendants.iter().map(fee).sum::<Amount>()).to_btc(),
+            },
+            "depends": self.mempool_parents(&txid),
+            "spentby": self.mempool_children(&txid),
+            "bip125-replaceable": self.signals_rbf(&txid),
+            "unbroadcast": false,
+        }))
+    }
+
+    fn mempool_info(&self) -> Value {
+        let txs = || {
+            self.mempool
+                .iter()
+                .map(|(txid, ..)| &self.transactions[txid])
+        };
+        let bytes: usize = txs().map(Transaction::vsize).sum();
+        let total_fee: Amount = txs().map(|tx| self.fee(tx)).sum();
+        // The mempool is never full here, so its minimum is just the relay minimum
+        let min_fee = Amount::from_sat(FEE_RATE_SAT_PER_VB * 1000).to_btc();
+        json!({
+            "loaded": true,
+            "size": self.mempool.len(),
+            "bytes": bytes,
+            "usage": bytes * 4,
+            "total_fee": total_fee.to_btc(),
+            "maxmempool": 300_000_000,
+            "mempoolminfee": min_fee,
+            "minrelaytxfee": min_fee,
+            "incrementalrelayfee": min_fee,
+            "unbroadcastcount": 0,
+            "fullrbf": false,
+        })
+    }
+
+    /// estimatesmartfee params: (conf_target, estimate_mode); the mode is ignored
+    fn estimate_smart_fee(&self, params: &[Value]) -> RpcResult {
+        let target: u64 = required(params, 0, "conf_target")?;
+        if target < 1 {
+            return Err(RpcError::new(
+                RPC_INVALID_PARAMETER,
+                "Invalid conf_target, must be between 1 and 1008",
+            ));
+        }
+        // Like Core, a target of 1 is answered for 2 and anything past 1008 for 1008
+        let target = target.clamp(2, 1008);
+
+        let tip = self.tip_height();
+        let mut rates: Vec<f64> = self
+            .chain
+            .active()
+            .filter(|block| block.height + target > tip)
+            .flat_map(|block| &block.txids)
+            .filter_map(|txid| self.transactions.get(txid))
+            .filter(|tx| !tx.is_coinbase())
+            .map(|tx| self.fee(tx).to_sat() as f64 / tx.vsize() as f64)
+            .collect();
+        if rates.is_empty() {
+            return Ok(json!({
+                "errors": ["Insufficient data or no feerate found"],
+                "blocks": 0,
+            }));
+        }
+        rates.sort_by(f64::total_cmp);
+        let median = rates[rates.len() / 2];
+        // sat/vB to BTC/kvB
+        Ok(json!({
+            "feerate": Amount::from_sat((median * 1000.0).round() as u64).to_btc(),
+            "blocks": target,
+        }))
+    }
+
+    fn generate_to_address(&mut self, params: &[Value]) -> RpcResult {
+        let count: u64 = required(params, 0, "nblocks")?;
+        let address = self.address_param(params, 1)?;
+
+        let hashes: Vec<BlockHash> = (0..count)
+            .map(|_| self.mine_block(&address.script_pubkey(), &self.mempool_txids()))
+            .collect();
+        Ok(json!(hashes))
+    }
+
+    /// generateblock params: (output, transactions)
+    ///
+    /// Mines exactly `transactions`, given as mempool txids or raw transaction hex, in
+    /// that order; the rest of the mempool stays where it is. Raw transactions don't
+    /// have to be in the mempool at all, which is how a block can confirm something
+    /// that conflicts with it. `output` must be an address (Core also takes descriptors).
+    fn generate_block(&mut self, params: &[Value]) -> RpcResult {
+        let address = self.address_param(params, 0)?;
+        let entries: Vec<String> = required(params, 1, "transactions")?;
+
+        let mut transactions = Vec::new();
+        for entry in &entries {
+            let tx = match Txid::from_str(entry) {
+                Ok(txid) if self.in_mempool(&txid) => self.transactions[&txid].clone(),
+                Ok(txid) => {
+                    return Err(RpcError::new(
+                        RPC_INVALID_ADDRESS_OR_KEY,
+                        format!("Transaction {txid} not in mempool."),
+                    ));
+                },
+                Err(_) => decode_tx(entry)?,
+            };
+            transactions.push(tx);
+        }
+
+        // Like TestBlockValidity: inputs must be unspent on the active chain, or be
+        // created earlier in this block
+        let mut utxos = self.chain_utxos();
+        for tx in &transactions {
+            let available = tx
+                .input
+                .iter()
+                .all(|input| utxos.contains_key(&input.previous_output));
+            if tx.is_coinbase() || !available {
+                return Err(RpcError::new(
+                    RPC_VERIFY_ERROR,
+                    "TestBlockValidity failed: bad-txns-inputs-missingorspent",
+                ));
+            }
+            apply_to(&mut utxos, tx);
+        }
+
+        let txids: Vec<Txid> = transactions.iter().map(Transaction::txid).collect();
+        for tx in transactions {
+            self.transactions.insert(tx.txid(), tx);
+        }
+        let hash = self.mine_block(&address.script_pubkey(), &txids);
+        Ok(json!({ "hash": hash }))
+    }
+
+    /// Mine one block on the active tip confirming `txids`, paying subsidy + fees to
+    /// `script`; the transactions must already be known and valid on the tip
+    fn mine_block(&mut self, script: &ScriptBuf, txids: &[Txid]) -> BlockHash {
+        let height = self.tip_height() + 1;
+        let fees: Amount = txids
+            .iter()
+            .map(|txid| self.fee(&self.transactions[txid]))
+            .sum();
+        let subsidy = Amount::from_sat(
+            (50 * Amount::ONE_BTC.to_sat()) >> (height / HALVING_INTERVAL).min(63),
+        );
+
+        let coinbase = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                // BIP34: the height goes first; OP_0 pads heights 1-16 to the 2-byte minimum
+                script_sig: Builder::new()
+                    .push_int(height as i64)
+                    .push_opcode(opcodes::OP_0)
+                    .into_script(),
+                sequence: Sequence::MAX,
+                // BIP141's witness reserved value
+                witness: Witness::from_slice(&[[0u8; 32]]),
+            }],
+            output: vec![TxOut {
+                value: subsidy + fees,
+                script_pubkey: script.clone(),
+            }],
+        };
+        let mut block = Block {
+            header: self.chain.tip().header,
+            txdata: std::iter::once(coinbase)
+                .chain(txids.iter().map(|txid| self.transactions[txid].clone()))
+                .collect(),
+        };
+
+        // BIP141: a coinbase output commits to every transaction's witness data
+        let witness_root = block
+            .witness_root()
+            .expect("a block always has its coinbase");
+        let commitment = Block::compute_witness_commitment(&witness_root, &[0; 32]);
+        let mut data = WITNESS_COMMITMENT_HEADER.to_vec();
+        data.extend(commitment.to_byte_array());
+        let data = PushBytesBuf::try_from(data).expect("36 bytes fit in one push");
+        block.txdata[0].output.push(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::new_op_return(&data),
+        });
+
+        let merkle_root = block
+            .compute_merkle_root()
+            .expect("a block always has its coinbase");
+        let header = self.chain.next_header(merkle_root);
+        let coinbase = block.txdata.swap_remove(0);
+        let coinbase_txid = coinbase.txid();
+        self.transactions.insert(coinbase_txid, coinbase);
+
+        let mut block_txids = vec![coinbase_txid];
+        block_txids.extend(txids);
+        self.chain.insert(header, block_txids);
+        self.activate_best_chain();
+        header.block_hash()
+    }
+
+    /// Switch to the best valid branch if that isn't the active one any more, then bring
+    /// the UTXO set and mempool in line with it; `true` if the active chain changed
+    ///
+    /// Transactions of disconnected blocks go back to the mempool, unless the new chain
+    /// confirms or conflicts with them. Coinbases can't: they only exist in their block.
+    fn activate_best_chain(&mut self) -> bool {
+        let Some(disconnected) = self.chain.activate_best() else {
+            return false;
+        };
+        let resurrected = disconnected
+            .iter()
+            .rev()
+            .flat_map(|hash| {
+                let block = self
+                    .chain
+                    .get(hash)
+                    .expect("disconnected blocks stay known");
+                block.txids.iter().skip(1).copied()
+            })
+            .collect();
+        self.rebuild(resurrected);
+        true
+    }
+
+    /// sendrawtransaction params: (hexstring, maxfeerate)
+    fn send_raw_transaction(&mut self, params: &[Value]) -> RpcResult {
+        let hex: String = required(params, 0, "hexstring")?;
+        let tx = decode_tx(&hex)?;
+        let txid = tx.txid();
+        if self.in_mempool(&txid) {
+            return Ok(json!(txid));
+        }
+        if self.confirmed_at.contains_key(&txid) {
+            return Err(RpcError::new(
+                RPC_VERIFY_ALREADY_IN_CHAIN,
+                "Transaction already in block chain",
+            ));
+        }
+        Ok(json!(self.accept_to_mempool(tx)?))
+    }
+
+    /// Add a signed transaction to the mempool, spending its inputs
+    ///
+    /// If it spends outputs that mempool transactions already spend, it replaces them
+    /// (and everything built on them) when BIP125 allows, and is rejected otherwise.
+    fn accept_to_mempool(&mut self, tx: Transaction) -> RpcResult<Txid> {
+        let evicted = self.check_acceptance(&tx)?;
+        if !evicted.is_empty() {
+            self.mempool.retain(|(txid, ..)| !evicted.contains(txid));
+            // Recomputes the UTXO set without the evicted transactions
+            self.rebuild(Vec::new());
+        }
+
+        let txid = tx.txid();
+        self.transactions.insert(txid, tx);
+        self.apply(txid);
+        self.mempool
+            .push((txid, self.chain.tip().time(), self.tip_height()));
+        Ok(txid)
+    }
+
+    /// Whether the mempool would take `tx`; returns the transactions it would replace
+    ///
+    /// Core's policy and consensus checks, roughly in Core's order, with Core's reject
+    /// reasons. Scripts aren't run: the only signing mistake noticed is a witness
+    /// program spent with no witness at all, and of a witness script only its
+    /// CHECKLOCKTIMEVERIFY and CHECKSEQUENCEVERIFY are checked.
+    fn check_acceptance(&self, tx: &Transaction) -> RpcResult<Vec<Txid>> {
+        let rejected = |reason: String| Err(RpcError::new(RPC_VERIFY_REJECTED, reason));
+        if tx
+            .output
+            .iter()
+            .any(|output| output.value < output.script_pubkey.dust_value())
+        {
+            return rejected("dust".to_owned());
+        }
+        if !self.is_final(tx) {
+            return rejected("non-final".to_owned());
+        }
+
+        let conflicts = self.mempool_conflicts(tx);
+        let available = tx.input.iter().all(|input| {
+            self.utxos.contains_key(&input.previous_output)
+                || conflicts.iter().any(|conflict| {
+                    self.transactions[conflict]
+                        .input
+                        .iter()
+                        .any(|spent| spent.previous_output == input.previous_output)
+                })
+        });
+        if !available {
+            return rejected("bad-txns-inputs-missingorspent".to_owned());
+        }
+        if !self.is_bip68_final(tx) {
+            return rejected("non-BIP68-final".to_owned());
+        }
+        // Consensus counts from the block the spend would go into, one past the tip
+        let premature = tx.input.iter().any(|input| {
+            let txid = &input.previous_output.txid;
+            self.transactions[txid].is_coinbase()
+                && self.confirmations(txid) < COINBASE_MATURITY
+        });
+        if premature {
+            return rejected("bad-txns-premature-spend-of-coinbase".to_owned());
+        }
+        let value_in: Amount = tx
+            .input
+            .iter()
+            .filter_map(|input| self.prevout(&input.previous_output))
+            .map(|prevout| prevout.value)
+            .sum();
+        let value_out: Amount = tx.output.iter().map(|output| output.value).sum();
+        if value_in < value_out {
+            return rejected("bad-txns-in-belowout".to_owned());
+        }
+        let fee = value_in - value_out;
+        let min_fee = Amount::from_sat(tx.vsize() as u64);
+        if fee < min_fee {
+            return rejected(format!(
+                "min relay fee not met, {} < {}",
+                fee.to_sat(),
+                min_fee.to_sat()
+            ));
+        }
+        let evicted = if conflicts.is_empty() {
+            Vec::new()
+        } else {
+            self.check_replacement(tx, &conflicts)?
+        };
+
+        let unwitnessed = tx.input.iter().any(|input| {
+            input.witness.is_empty()
+                && self
+                    .prevout(&input.previous_output)
+                    .is_some_and(|prevout| prevout.script_pubkey.is_witness_program())
+        });
+        if unwitnessed {
+            return rejected(
+                "mandatory-script-verify-flag-failed (Witness program was passed an empty witness)"
+                    .to_owned(),
+            );
+        }
+        let unlocked = tx.input.iter().enumerate().all(|(index, input)| {
+            let Some(script) = input
+                .witness
+                .last()
+                .map(|bytes| ScriptBuf::from_bytes(bytes.to_vec()))
+            else {
+                return true;
+            };
+            let is_witness_script = self
+                .prevout(&input.previous_output)
+                .is_some_and(|prevout| prevout.script_pubkey == script.to_p2wsh());
+            !is_witness_script || timelocks_satisfied(&script, tx, index)
+        });
+        if !unlocked {
+            return rejected(
+                "mandatory-script-verify-flag-failed (Locktime requirement not satisfied)"
+                    .to_owned(),
+            );
+        }
+        Ok(evicted)
+    }
+
+    /// Whether `tx`'s nLockTime lets it into the next block
+    ///
+    /// Lock times are ignored unless an input's sequence number enables them. A height
+    /// must be below the next block's, a time below the tip's median time past (BIP113).
+    fn is_final(&self, tx: &Transaction) -> bool {
+        if !tx.is_lock_time_enabled() {
+            return true;
+        }
+        let tip = self.chain.tip();
+        match tx.lock_time {
+            absolute::LockTime::Blocks(height) => {
+                u64::from(height.to_consensus_u32()) < tip.height + 1
+            },
+            absolute::LockTime::Seconds(time) => {
+                u64::from(time.to_consensus_u32()) < self.chain.median_time_past(tip)
+            },
+        }
+    }
+
+    /// Whether every input's relative lock time (BIP68) has passed by the next block
+    ///
+    /// Only version 2 transactions have them, in the sequence numbers of inputs that
+    /// don't set the disable flag: a number of blocks since the coin confirmed, or of
+    /// 512 second units since the median time past of the block before it. A coin still
+    /// in the mempool counts as confirming in the next block.
+    fn is_bip68_final(&self, tx: &Transaction) -> bool {
+        if tx.version < transaction::Version::TWO {
+            return true;
+        }
+        let tip = self.chain.tip();
+        tx.input.iter().all(|input| {
+            let txid = &input.previous_output.txid;
+            match input.sequence.to_relative_lock_time() {
+                None => true,
+                Some(relative::LockTime::Blocks(blocks)) => {
+                    self.confirmations(txid) >= u64::from(blocks.value())
+                },
+                Some(relative::LockTime::Time(time)) => {
+                    let coin_height = self
+                        .confirmed_at
+                        .get(txid)
+                        .copied()
+                        .unwrap_or(tip.height + 1);
+                    let coin_time = self
+                        .chain
+                        .at(coin_height.saturating_sub(1))
+                        .map_or(0, |block| self.chain.median_time_past(block));
+                    coin_time + u64::from(time.value()) * 512
+                        <= self.chain.median_time_past(tip)
+                },
+            }
+        })
+    }
+
+    /// testmempoolaccept params: (rawtxs, maxfeerate)
+    ///
+    /// The checks sendrawtransaction makes, without adding anything to the mempool.
+    /// Core can also test a package of related transactions; the mock takes one.
+    fn test_mempool_accept(&self, params: &[Value]) -> RpcResult {
+        let rawtxs: Vec<String> = required(params, 0, "rawtxs")?;
+        let [hex] = rawtxs.as_slice() else {
+            return Err(RpcError::new(
+                RPC_INVALID_PARAMETER,
+                "the mock node only tests one transaction at a time",
+            ));
+        };
+        let tx = decode_tx(hex)?;
+        let txid = tx.txid();
+        let verdict = if self.in_mempool(&txid) {
+            Err("txn-already-in-mempool".to_owned())
+        } else if self.confirmed_at.contains_key(&txid) {
+            Err("txn-already-known".to_owned())
+        } else {
+            self.check_acceptance(&tx).map_err(|e| e.message)
+        };
+        let result = match verdict {
+            Ok(_) => json!({
+                "txid": txid,
+                "wtxid": tx.wtxid(),
+                "allowed": true,
+                "vsize": tx.vsize(),
+                "fees": { "base": self.fee(&tx).to_btc() },
+            }),
+            Err(reason) => json!({
+                "txid": txid,
+                "wtxid": tx.wtxid(),
+                "allowed": false,
+                "reject-reason": reason,
+            }),
+        };
+        Ok(json!([result]))
+    }
+
+    /// Mempool transactions spending any of the outputs `tx` spends
+    fn mempool_conflicts(&self, tx: &Transaction) -> Vec<Txid> {
+        self.mempool_txids()
+            .into_iter()
+            .filter(|txid| {
+                self.transactions[txid].input.iter().any(|spent| {
+                    tx.input
+                        .iter()
+                        .any(|input| input.previous_output == spent.previous_output)
+                })
+            })
+            .collect()
+    }
+
+    /// BIP125's rules for `tx` replacing `conflicts`; returns every transaction it
+    /// would evict: the conflicts and their descendants
+    ///
+    /// Every conflict must signal, and the replacement must pay a higher fee rate than
+    /// each of them, at least as much fee as everything it evicts, and on top of that
+    /// its own relay cost at the incremental relay fee (1 sat/vB).
+    fn check_replacement(&self, tx: &Transaction, conflicts: &[Txid]) -> RpcResult<Vec<Txid>> {
+        let txid = tx.txid();
+        let insufficient = |reason: String| {
+            RpcError::new(
+                RPC_VERIFY_REJECTED,
+                format!("insufficient fee, rejecting replacement {txid}, {reason}"),
+            )
+        };
+        if !conflicts.iter().all(|conflict| self.signals_rbf(conflict)) {
+            return Err(RpcError::new(RPC_VERIFY_REJECTED, "txn-mempool-conflict"));
+        }
+
+        let fee = self.fee(tx);
+        let vsize = tx.vsize() as u64;
+        for conflict in conflicts {
+            let original = &self.transactions[conflict];
+            let original_fee = self.fee(original);
+            // fee / vsize <= original_fee / original_vsize, without the division
+            if fee.to_sat() * original.vsize() as u64 <= original_fee.to_sat() * vsize {
+                return Err(insufficient(format!(
+                    "new feerate {:.8} BTC/kvB <= old feerate {:.8} BTC/kvB",
+                    fee.to_btc() * 1000.0 / vsize as f64,
+                    original_fee.to_btc() * 1000.0 / original.vsize() as f64
+                )));
+            }
+        }
+
+        let mut evicted = conflicts.to_vec();
+        for conflict in conflicts {
+            for descendant in self.mempool_family(conflict, |id| self.mempool_children(id)) {
+                if !evicted.contains(&descendant) {
+                    evicted.push(descendant);
+                }
+            }
+        }
+        let evicted_fees: Amount = evicted
+            .iter()
+            .map(|id| self.fee(&self.transactions[id]))
+            .sum();
+        let Some(additional) = fee.checked_sub(evicted_fees) else {
+            return Err(insufficient(format!(
+                "less fees than conflicting txs; {:.8} < {:.8}",
+                fee.to_btc(),
+                evicted_fees.to_btc()
+            )));
+        };
+        let relay_cost = Amount::from_sat(vsize * FEE_RATE_SAT_PER_VB);
+        if additional < relay_cost {
+            return Err(insufficient(format!(
+                "not enough additional fees to relay; {:.8} < {:.8}",
+                additional.to_btc(),
+                relay_cost.to_btc()
+            )));
+        }
+        Ok(evicted)
+    }
+
+    /// Every input spends an output that is still unspent
+    fn inputs_available(&self, tx: &Transaction) -> bool {
+        tx.input
+            .iter()
+            .all(|input| self.utxos.contains_key(&input.previous_output))
+    }
+
+    /// Update the UTXO set for `txid`: its inputs are now spent, its outputs unspent
+    fn apply(&mut self, txid: Txid) {
+        apply_to(&mut self.utxos, &self.transactions[&txid]);
+    }
+
+    /// Outputs the active chain leaves unspent, ignoring the mempool
+    fn chain_utxos(&self) -> BTreeMap<OutPoint, TxOut> {
+        let mut utxos = BTreeMap::new();
+        // The genesis coinbase is unspendable
+        for block in self.chain.active().skip(1) {
+            for txid in &block.txids {
+                apply_to(&mut utxos, &self.transactions[txid]);
+            }
+        }
+        utxos
+    }
+
+    fn address_param(&self, params: &[Value], index: usize) -> RpcResult<Address> {
+        let text: String = required(params, index, "address")?;
+        Address::from_str(&text)
+            .ok()
+            .and_then(|address| address.require_network(self.network).ok())
+            .ok_or_else(|| {
+                RpcError::new(
+                    RPC_INVALID_ADDRESS_OR_KEY,
+                    format!("Invalid Bitcoin address: {text}"),
+                )
+            })
+    }
+
+    // ── Relay ─────────────────────────────────────────────────────────
+
+    /// What this node has, so a peer can work out what to send it
+    pub(super) fn inventory(&self) -> Inventory {
+        Inventory {
+            blocks: self.chain.active_hashes().to_vec(),
+            mempool: self.mempool_txids(),
+        }
+    }
+
+    /// Everything `peer` is missing: our blocks past the fork point if our chain is
+    /// longer, and the mempool transactions it hasn't seen
+    pub(super) fn relay_to(&self, peer: &Inventory) -> Relay {
+        let ours = self.chain.active_hashes();
+        let chain = (ours.len() > peer.blocks.len()).then(|| {
+            // Both chains start at the same genesis block, so the peer always knows
+            // the parent of the first block sent
+            let common = ours
+                .iter()
+                .zip(&peer.blocks)
+                .take_while(|(ours, theirs)| ours == theirs)
+                .count();
+            self.chain
+                .active()
+                .skip(common)
+                .map(|block| {
+                    let transactions = block
+                        .txids
+                        .iter()
+                        .map(|txid| self.transactions[txid].clone())
+                        .collect();
+                    (block.header, transactions)
+                })
+                .collect()
+        });
+        let transactions = self
+            .mempool_txids()
+            .into_iter()
+            .filter(|txid| !peer.mempool.contains(txid))
+            .map(|txid| self.transactions[&txid].clone())
+            .collect();
+        Relay {
+            chain,
+            transactions,
+        }
+    }
+
+    /// Take in what a peer relayed; `true` if it changed anything
+    pub(super) fn receive(&mut self, relay: Relay) -> bool {
+        let mut changed = false;
+        if let Some(blocks) = relay.chain {
+            changed |= self.receive_blocks(blocks);
+        }
+        for tx in relay.transactions {
+            let txid = tx.txid();
+            if !self.in_mempool(&txid) && !self.confirmed_at.contains_key(&txid) {
+                // Conflicts with what we already have are dropped unless they're valid
+                // replacements; without RBF, the first transaction seen wins
+                changed |= self.accept_to_mempool(tx).is_ok();
+            }
+        }
+        changed
+    }
+
+    /// Add a peer's blocks to the tree, and switch to them if they make the best chain
+    ///
+    /// Blocks we invalidated stay invalid, however long the peer's chain is.
+    fn receive_blocks(&mut self, blocks: Vec<RelayedBlock>) -> bool {
+        for (header, transactions) in blocks {
+            let txids = transactions.iter().map(Transaction::txid).collect();
+            for tx in transactions {
+                self.transactions.insert(tx.txid(), tx);
+            }
+            self.chain.insert(header, txids);
+        }
+        self.activate_best_chain()
+    }
+
+    /// Recompute the UTXO set and confirmations from the active chain, then re-admit
+    /// `resurrected` transactions and the old mempool, dropping whatever no longer fits
+    fn rebuild(&mut self, resurrected: Vec<Txid>) {
+        self.utxos = self.chain_utxos();
+        self.confirmed_at = self
+            .chain
+            .active()
+            .flat_map(|block| block.txids.iter().map(|txid| (*txid, block.height)))
+            .collect();
+
+        let (time, height) = (self.chain.tip().time(), self.tip_height());
+        let candidates: Vec<(Txid, u64, u64)> = resurrected
+            .into_iter()
+            .map(|txid| (txid, time, height))
+            .chain(std::mem::take(&mut self.mempool))
+            .collect();
+        for (txid, time, height) in candidates {
+            if self.confirmed_at.contains_key(&txid)
+                || self.in_mempool(&txid)
+                || !self.inputs_available(&self.transactions[&txid])
+            {
+                continue;
+            }
+            self.apply(txid);
+            self.mempool.push((txid, time, height));
+        }
+    }
+
+    // ── Peers ─────────────────────────────────────────────────────────
+
+    /// addnode params: (node, command)
+    ///
+    /// `add` and `onetry` connect straight away (an address without a mock node behind
+    /// it simply never connects); `remove` also drops the connection.
+    fn add_node(&mut self, params: &[Value]) -> RpcResult {
+        let peer: String = required(params, 0, "node")?;
+        let command: String = required(params, 1, "command")?;
+        let connected = p2p::is_connected(self.addr, &peer);
+        match command.as_str() {
+            "add" if connected => Err(RpcError::new(
+                RPC_CLIENT_NODE_ALREADY_ADDED,
+                "Error: Node already added",
+            )),
+            "add" | "onetry" => {
+                p2p::connect(self.addr, &peer);
+                Ok(Value::Null)
+            },
+            "remove" if connected => {
+                p2p::disconnect(self.addr, &peer);
+                Ok(Value::Null)
+            },
+            "remove" => Err(RpcError::new(
+                RPC_CLIENT_NODE_NOT_ADDED,
+                "Error: Node could not be removed. It has not been added previously.",
+            )),
+            _ => Err(RpcError::new(
+                RPC_MISC_ERROR,
+                "addnode \"node\" \"command\": command must be add, remove or onetry",
+            )),
+        }
+    }
+
+    /// disconnectnode params: (address); drops the connection whichever side made it
+    fn disconnect_node(&mut self, params: &[Value]) -> RpcResult {
+        let peer: String = required(params, 0, "address")?;
+        if p2p::disconnect_peer(self.addr, &peer) {
+            Ok(Value::Null)
+        } else {
+            Err(RpcError::new(
+                RPC_CLIENT_NODE_NOT_CONNECTED,
+                "Node not found in connected nodes",
+            ))
+        }
+    }
+
+    fn peer_info(&self) -> Value {
+        let peers: Vec<Value> = p2p::peers(self.addr)
+            .into_iter()
+            .enumerate()
+            .map(|(id, (addr, inbound))| {
+                json!({
+                    "id": id,
+                    "addr": addr.to_string(),
+                    "inbound": inbound,
+                    "connection_type": if inbound { "inbound" } else { "manual" },
+                    "version": PROTOCOL_VERSION,
+                    "subver": SUBVERSION,
+                    "relaytxes": true,
+                })
+            })
+            .collect();
+        json!(peers)
+    }
+
+    // ── Wallet management ─────────────────────────────────────────────
+
+    fn loaded_wallets(&self) -> Vec<&str> {
+        self.wallets
+            .iter()
+            .filter(|(_, wallet)| wallet.loaded)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    fn wallet_dir(&self) -> Value {
+        let wallets: Vec<Value> = self
+            .wallets
+            .keys()
+            .map(|name| json!({ "name": name }))
+            .collect();
+        json!({ "wallets": wallets })
+    }
+
+    /// createwallet params: (name, disable_private_keys, blank, passphrase, avoid_reuse, descriptors)
+    fn create_wallet(&mut self, params: &[Value]) -> RpcResult {
+        let name: String = required(params, 0, "wallet_name")?;
+        if self.wallets.contains_key(&name) {
+            return Err(RpcError::new(
+                RPC_WALLET_ERROR,
+                format!(
+                    "Wallet file verification failed. Failed to create database path '{name}'. Database already exists."
+                ),
+            ));
+        }
+
+        let passphrase: Option<String> = optional(params, 3)?;
+        let private_keys_enabled = !optional(params, 1)?.unwrap_or(false);
+        let blank = optional(params, 2)?.unwrap_or(false);
+        let master = (private_keys_enabled && !blank).then(|| self.master_key(&name));
+        self.wallets.insert(
+            name.clone(),
+            Wallet {
+                loaded: true,
+                private_keys_enabled,
+                blank,
+                avoid_reuse: optional(params, 4)?.unwrap_or(false),
+                descriptors: optional(params, 5)?.unwrap_or(true),
+                passphrase: passphrase.filter(|p| !p.is_empty()),
+                unlocked_until: 0,
+                master,
+                imported: Vec::new(),
+            },
+        );
+        Ok(json!({ "name": name, "warning": "" }))
+    }
+
+    fn load_wallet(&mut self, params: &[Value]) -> RpcResult {
+        let name: String = required(params, 0, "filename")?;
+        let wallet = self.wallets.get_mut(&name).ok_or_else(|| {
+            RpcError::new(
+                RPC_WALLET_NOT_FOUND,
+                format!("Wallet file verification failed. Failed to load database path '{name}'. Path does not exist."),
+            )
+        })?;
+        if wallet.loaded {
+            return Err(RpcError::new(
+                RPC_WALLET_ALREADY_LOADED,
+                format!("Wallet \"{name}\" is already loaded."),
+            ));
+        }
+        wallet.loaded = true;
+        Ok(json!({ "name": name, "warning": "" }))
+    }
+
+    fn unload_wallet(&mut self, wallet: Option<&str>, params: &[Value]) -> RpcResult {
+        let name = match optional::<String>(params, 0)? {
+            Some(name) => name,
+            None => self.wallet_name(wallet)?,
+        };
+        match self.wallets.get_mut(&name) {
+            Some(wallet) if wallet.loaded => {
+                wallet.loaded = false;
+                Ok(json!({ "warning": "" }))
+            },
+            _ => Err(RpcError::new(
+                RPC_WALLET_NOT_FOUND,
+                "Requested wallet does not exist or is not loaded",
+            )),
+        }
+    }
+
+    // ── Wallet ────────────────────────────────────────────────────────
+
+    /// Resolve which wallet a wallet RPC applies to, the way Core does
+    fn wallet_name(&self, wallet: Option<&str>) -> RpcResult<String> {
+        match wallet {
+            Some(name) if self.wallets.get(name).is_some_and(|w| w.loaded) => {
+                Ok(name.to_owned())
+            },
+            Some(_) => Err(RpcError::new(
+                RPC_WALLET_NOT_FOUND,
+                "Requested wallet does not exist or is not loaded",
+            )),
+            None => match self.loaded_wallets().as_slice() {
+                [only] => Ok((*only).to_owned()),
+                [] => Err(RpcError::new(
+                    RPC_WALLET_NOT_FOUND,
+                    "No wallet is loaded. Load a wallet using loadwallet or create a new one with createwallet. (Note: A default wallet is no longer automatically created)",
+                )),
+                _ => Err(RpcError::new(
+                    RPC_WALLET_NOT_SPECIFIED,
+                    "Wallet file not specified (must request wallet RPC through /wallet/<filename> uri-path).",
+                )),
+            },
+        }
+    }
+
+    fn owner(&self, script: &ScriptBuf) -> Option<&str> {
+        self.keys.get(script).map(|key| key.wallet.as_str())
+    }
+
+    fn is_mine(&self, script: &ScriptBuf, wallet: &str) -> bool {
+        self.owner(script) == Some(wallet)
+    }
+
+    /// `wallet` holds the key for `script` all by itself
+    fn can_sign(&self, script: &ScriptBuf, wallet: &str) -> bool {
+        self.keys.get(script).is_some_and(|key| {
+            key.wallet == wallet && matches!(key.spend, Spend::Single { .. })
+        })
+    }
+
+    /// Every input spends one of `wallet`'s outputs (Core trusts such unconfirmed txs)
+    fn is_from_me(&self, tx: &Transaction, wallet: &str) -> bool {
+        !tx.is_coinbase()
+            && tx.input.iter().all(|input| {
+                self.prevout(&input.previous_output)
+                    .is_some_and(|prevout| self.is_mine(&prevout.script_pubkey, wallet))
+            })
+    }
+
+    fn is_immature(&self, txid: &Txid) -> bool {
+        self.transactions[txid].is_coinbase()
+            && self.confirmations(txid) < COINBASE_MATURITY + 1
+    }
+
+    /// `wallet`'s outputs that can be spent right now, largest first
+    fn spendable(&self, wallet: &str) -> Vec<(OutPoint, TxOut)> {
+        let mut coins: Vec<(OutPoint, TxOut)> = self
+            .utxos
+            .iter()
+            .filter(|(outpoint, output)| {
+                self.is_mine(&output.script_pubkey, wallet)
+                    && !self.is_immature(&outpoint.txid)
+                    && (self.confirmations(&outpoint.txid) > 0
+                        || self.is_from_me(&self.transactions[&outpoint.txid], wallet))
+            })
+            .map(|(outpoint, output)| (*outpoint, output.clone()))
+            .collect();
+        coins.sort_by(|(a, a_out), (b, b_out)| b_out.value.cmp(&a_out.value).then(a.cmp(b)));
+        coins
+    }
+
+    fn wallet_balances(&self, wallet: &str) -> Balances {
+        let mut balances = Balances::default();
+        for (outpoint, output) in &self.utxos {
+            if !self.is_mine(&output.script_pubkey, wallet) {
+                continue;
+            }
+            let tx = &self.transactions[&outpoint.txid];
+            let bucket = if self.is_immature(&outpoint.txid) {
+                &mut balances.immature
+            } else if self.confirmations(&outpoint.txid) == 0 && !self.is_from_me(tx, wallet) {
+                &mut balances.untrusted_pending
+            } else {
+                &mut balances.trusted
+            };
+            *bucket += output.value;
+        }
+        balances
+    }
+
+    fn wallet_info(&self, wallet: Option<&str>) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        let wallet = &self.wallets[&name];
+        let balances = self.wallet_balances(&name);
+
+        let mut info = json!({
+            "walletname": name,
+            "walletversion": 169900,
+            "format": if wallet.descriptors { "sqlite" } else { "bdb" },
+            "balance": balances.trusted.to_btc(),
+            "unconfirmed_balance": balances.untrusted_pending.to_btc(),
+            "immature_balance": balances.immature.to_btc(),
+            "txcount": self.wallet_txids(&name).len(),
+            "keypoolsize": 1000,
+            "keypoolsize_hd_internal": 1000,
+            "paytxfee": 0.0,
+            "private_keys_enabled": wallet.private_keys_enabled,
+            "avoid_reuse": wallet.avoid_reuse,
+            "scanning": false,
+            "descriptors": wallet.descriptors,
+            "external_signer": false,
+            "blank": wallet.blank,
+        });
+        if wallet.passphrase.is_some() {
+            info["unlocked_until"] = json!(wallet.unlocked_until);
+        }
+        Ok(info)
+    }
+
+    fn new_address(
+        &mut self,
+        wallet: Option<&str>,
+        params: &[Value],
+        change: bool,
+    ) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        let label: String = optional(params, 0)?.unwrap_or_default();
+        // Core's -addresstype and -changetype both default to bech32
+        let address_type = match optional::<String>(params, 1)? {
+            None => AddressType::Bech32,
+            Some(text) => text.parse().map_err(|_| {
+                RpcError::new(
+                    RPC_INVALID_ADDRESS_OR_KEY,
+                    format!("Unknown address type '{text}'"),
+                )
+            })?,
+        };
+        let script = self.derive_script(&name, &label, change, address_type)?;
+        let address = Address::from_script(&script, self.network)
+            .map_err(|e| RpcError::new(RPC_MISC_ERROR, e.to_string()))?;
+        Ok(json!(address.to_string()))
+    }
+
+    /// A wallet's BIP32 master key, seeded from the node's key seed and its name
+    fn master_key(&self, wallet: &str) -> Xpriv {
+        let mut seed = b"mock node seed ".to_vec();
+        seed.extend(self.key_seed.to_be_bytes());
+        seed.extend(wallet.as_bytes());
+        Xpriv::new_master(self.network, sha256::Hash::hash(&seed).as_byte_array())
+            .expect("a SHA-256 digest is a valid seed with overwhelming probability")
+    }
+
+    /// Next `address_type` address of `wallet` on its receive or change chain; returns
+    /// its script
+    ///
+    /// Wallets with keys derive them on the BIP44/49/84/86 path of the type; watch-only
+    /// wallets use their active imported descriptor of that type for that chain.
+    fn derive_script(
+        &mut self,
+        wallet: &str,
+        label: &str,
+        change: bool,
+        address_type: AddressType,
+    ) -> RpcResult<ScriptBuf> {
+        let mut index = self.next_index(wallet, change, address_type);
+        let state = self
+            .wallets
+            .get_mut(wallet)
+            .expect("resolved by the caller");
+        let spend = if let Some(master) = &state.master {
+            let path = [purpose(address_type), 1, 0]
+                .into_iter()
+                .map(ChildNumber::from_hardened_idx)
+                .chain([u32::from(change), index as u32].map(ChildNumber::from_normal_idx))
+                .collect::<Result<DerivationPath, _>>()
+                .expect("indexes below 2^31");
+            let secret = master
+                .derive_priv(&self.secp, &path)
+                .expect("derivation only fails with negligible probability")
+                .private_key;
+            Spend::Single {
+                secret,
+                public: PublicKey::new(secret.public_key(&self.secp)),
+                address_type,
+            }
+        } else if let Some(imported) = state.imported.iter_mut().find(|imported| {
+            imported.active
+                && imported.internal == change
+                && descriptor_type(&imported.descriptor) == address_type
+        }) {
+            index = u64::from(imported.next);
+            let output = imported.descriptor.derive(&self.secp, imported.next);
+            imported.next += 1;
+            Spend::Multisig(Box::new(output))
+        } else if state.imported.iter().any(|imported| imported.active) {
+            return Err(RpcError::new(
+                RPC_WALLET_ERROR,
+                format!("Error: No {address_type} addresses available."),
+            ));
+        } else {
+            return Err(RpcError::new(
+                RPC_WALLET_ERROR,
+                "Error: This wallet has no available keys",
+            ));
+        };
+
+        let script = match &spend {
+            Spend::Single {
+                public,
+                address_type,
+                ..
+            } => self.single_script(public, *address_type),
+            Spend::Multisig(output) => output.script_pubkey.clone(),
+        };
+        self.keys.insert(
+            script.clone(),
+            Key {
+                wallet: wallet.to_owned(),
+                label: label.to_owned(),
+                change,
+                index,
+                spend,
+            },
+        );
+        Ok(script)
+    }
+
+    /// How many `address_type` keys `wallet` handed out on its receive or change chain
+    fn next_index(&self, wallet: &str, change: bool, address_type: AddressType) -> u64 {
+        self.keys
+            .values()
+            .filter(|key| {
+                key.wallet == wallet
+                    && key.change == change
+                    && matches!(key.spend, Spend::Single { address_type: t, .. } if t == address_type)
+            })
+            .count() as u64
+    }
+
+    /// The output script paying `public` in an address of `address_type`
+    ///
+    /// Taproot outputs commit to the key alone (no script tree), as BIP86 has it.
+    fn single_script(&self, public: &PublicKey, address_type: AddressType) -> ScriptBuf {
+        match address_type {
+            AddressType::Legacy => ScriptBuf::new_p2pkh(&public.pubkey_hash()),
+            AddressType::P2shSegwit => p2wpkh(public).to_p2sh(),
+            AddressType::Bech32 => p2wpkh(public),
+            AddressType::Bech32m => {
+                ScriptBuf::new_p2tr(&self.secp, public.inner.x_only_public_key().0, None)
+            },
+        }
+    }
+
+    /// What `wallet` knows about an address; addresses of other wallets are simply
+    /// not `ismine`
+    fn address_info(&self, wallet: Option<&str>, params: &[Value]) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        let address = self.address_param(params, 0)?;
+        let script = address.script_pubkey();
+        let mut info = json!({
+            "address": address,
+            "scriptPubKey": script.to_hex_string(),
+            "ismine": false,
+            "solvable": false,
+            "iswatchonly": false,
+            "isscript": script.is_p2sh() || script.is_p2wsh(),
+            "iswitness": script.is_witness_program(),
+            "ischange": false,
+            "labels": [],
+        });
+        if let Some(version) = script.witness_version() {
+            info["witness_version"] = json!(version.to_num());
+            info["witness_program"] = json!(script.as_bytes()[2..].to_lower_hex_string());
+        }
+        if let Some(key) = self.keys.get(&script).filter(|key| key.wallet == name) {
+            info["ismine"] = json!(true);
+            info["solvable"] = json!(true);
+            info["ischange"] = json!(key.change);
+            match &key.spend {
+                Spend::Single {
+                    public,
+                    address_type,
+                    ..
+                } => {
+                    if *address_type == AddressType::P2shSegwit {
+                        info["script"] = json!("witness_v0_keyhash");
+                        info["hex"] = json!(p2wpkh(public).to_hex_string());
+                    }
+                    info["pubkey"] = json!(public);
+                    info["iscompressed"] = json!(true);
+                    info["hdkeypath"] = json!(key.hd_keypath());
+                },
+                Spend::Multisig(output) => {
+                    if output.taproot.is_none() {
+                        info["script"] = json!("multisig");
+                        info["hex"] = json!(output.script.to_hex_string());
+                    }
+                    info["sigsrequired"] = json!(output.threshold);
+                    info["pubkeys"] = output
+                        .keys
+                        .iter()
+                        .map(|(public, _)| json!(public.to_string()))
+                        .collect();
+                },
+            }
+            // Like Core, change addresses aren't in the address book, so have no label
+            if !key.change {
+                info["labels"] = json!([key.label]);
+            }
+        }
+        Ok(info)
+    }
+
+    /// listdescriptors params: (private)
+    ///
+    /// A wallet with keys lists Core's default set: pkh, sh(wpkh), wpkh and tr, each
+    /// with a receive and a change chain, then whatever was imported. Private keys are
+    /// never exported.
+    fn list_descriptors(&self, wallet: Option<&str>, params: &[Value]) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        if optional::<bool>(params, 0)?.unwrap_or(false) {
+            return Err(RpcError::new(
+                RPC_INVALID_PARAMETER,
+                "the mock node never exports private keys",
+            ));
+        }
+        let wallet = &self.wallets[&name];
+        let birth = self.chain.at(0).map_or(0, chain::Block::time);
+
+        let mut descriptors = Vec::new();
+        if let Some(master) = &wallet.master {
+            let fingerprint = master.fingerprint(&self.secp);
+            for (address_type, template) in [
+                (AddressType::Legacy, "pkh(KEY)"),
+                (AddressType::P2shSegwit, "sh(wpkh(KEY))"),
+                (AddressType::Bech32, "wpkh(KEY)"),
+                (AddressType::Bech32m, "tr(KEY)"),
+            ] {
+                let origin: DerivationPath = [purpose(address_type), 1, 0]
+                    .into_iter()
+                    .map(ChildNumber::from_hardened_idx)
+                    .collect::<Result<_, _>>()
+                    .expect("indexes below 2^31");
+                let account = master
+                    .derive_priv(&self.secp, &origin)
+                    .expect("derivation only fails with negligible probability");
+                let xpub = Xpub::from_priv(&self.secp, &account);
+                for internal in [false, true] {
+                    let key =
+                        descriptor::ranged_key(fingerprint, &origin, &xpub, internal.into());
+                    let next = self.next_index(&name, internal, address_type);
+                    descriptors.push(json!({
+                        "desc": descriptor::with_checksum(&template.replace("KEY", &key)),
+                        "timestamp": birth,
+                        "active": true,
+                        "internal": internal,
+                        "range": [0, next + 999],
+                        "next": next,
+                        "next_index": next,
+                    }));
+                }
+            }
+        }
+        for imported in &wallet.imported {
+            let mut entry = json!({
+                "desc": imported.text,
+                "timestamp": imported.timestamp,
+                "active": imported.active,
+            });
+            if imported.active {
+                entry["internal"] = json!(imported.internal);
+            }
+            if imported.descriptor.is_ranged() {
+                entry["range"] = json!([0, imported.next + 999]);
+                entry["next"] = json!(imported.next);
+                entry["next_index"] = json!(imported.next);
+            }
+            descriptors.push(entry);
+        }
+        Ok(json!({ "wallet_name": name, "descriptors": descriptors }))
+    }
+
+    /// getdescriptorinfo params: (descriptor)
+    ///
+    /// Checks the checksum if there is one and computes it; unlike Core, the mock
+    /// doesn't parse the descriptor itself (that waits for `importdescriptors`).
+    fn descriptor_info(&self, params: &[Value]) -> RpcResult {
+        let text: String = required(params, 0, "descriptor")?;
+        let (descriptor, _) = descriptor::strip_checksum(&text)
+            .map_err(|message| RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, message))?;
+        let checksum = descriptor::checksum(descriptor).ok_or_else(|| {
+            RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "Invalid characters in payload")
+        })?;
+        let private = ["xprv", "tprv"]
+            .iter()
+            .any(|prefix| descriptor.contains(prefix));
+        Ok(json!({
+            "descriptor": format!("{descriptor}#{checksum}"),
+            "checksum": checksum,
+            "isrange": descriptor.contains('*'),
+            "issolvable": true,
+            "hasprivatekeys": private,
+        }))
+    }
+
+    /// importdescriptors params: (requests)
+    ///
+    /// Only multisig descriptors (see descriptor.rs), and only into wallets without
+    /// private keys. Each request reads `desc`, `active`, `internal`, `next_index` and
+    /// `timestamp`; nothing is rescanned, so only coins received afterwards are seen.
+    fn import_descriptors(&mut self, wallet: Option<&str>, params: &[Value]) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        let requests: Vec<Value> = required(params, 0, "requests")?;
+        let now = self.chain.tip().time();
+        let wallet = self.wallets.get_mut(&name).expect("resolved above");
+        if !wallet.descriptors {
+            return Err(RpcError::new(
+                RPC_WALLET_ERROR,
+                "importdescriptors is not available for non-descriptor wallets",
+            ));
+        }
+
+        let mut results = Vec::new();
+        for request in &requests {
+            let import = || -> RpcResult<ImportedDescriptor> {
+                let invalid =
+                    |message: String| RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, message);
+                let text = request["desc"]
+                    .as_str()
+                    .ok_or_else(|| invalid("Descriptor not found.".to_owned()))?;
+                let (body, checked) = descriptor::strip_checksum(text).map_err(invalid)?;
+                if !checked {
+                    return Err(invalid("Missing checksum".to_owned()));
+                }
+                let descriptor =
+                    descriptor::MultisigDescriptor::parse(body).map_err(invalid)?;
+                if wallet.private_keys_enabled {
+                    return Err(RpcError::new(
+                        RPC_WALLET_ERROR,
+                        "Cannot import descriptor without private keys to a wallet with private keys enabled",
+                    ));
+                }
+                let active = request["active"].as_bool().unwrap_or(false);
+                if active && !descriptor.is_ranged() {
+                    return Err(RpcError::new(
+                        RPC_INVALID_PARAMETER,
+                        "Active descriptors must be ranged",
+                    ));
+                }
+                Ok(ImportedDescriptor {
+                    text: text.to_owned(),
+                    active,
+                    internal: request["internal"].as_bool().unwrap_or(false),
+                    timestamp: request["timestamp"].as_u64().unwrap_or(now),
+                    next: request["next_index"].as_u64().unwrap_or(0) as u32,
+                    descriptor,
+                })
+            };
+            match import() {
+                Ok(imported) => {
+                    if imported.active {
+                        // Takes over the chain from whichever descriptor had it
+                        for other in &mut wallet.imported {
+                            other.active &= other.internal != imported.internal;
+                        }
+                    }
+                    wallet.imported.push(imported);
+                    results.push(json!({ "success": true, "warnings": [] }));
+                },
+                Err(error) => results.push(json!({
+                    "success": false,
+                    "error": { "code": error.code, "message": error.message },
+                })),
+            }
+        }
+        Ok(json!(results))
+    }
+
+    fn balance(&self, wallet: Option<&str>, params: &[Value]) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        let min_conf: u64 = optional(params, 1)?.unwrap_or(0);
+        if min_conf == 0 {
+            return Ok(json!(self.wallet_balances(&name).trusted.to_btc()));
+        }
+        let total: Amount = self
+            .spendable(&name)
+            .iter()
+            .filter(|(outpoint, _)| self.confirmations(&outpoint.txid) >= min_conf)
+            .map(|(_, output)| output.value)
+            .sum();
+        Ok(json!(total.to_btc()))
+    }
+
+    fn balances_json(&self, wallet: Option<&str>) -> RpcResult {
+        let balances = self.wallet_balances(&self.wallet_name(wallet)?);
+        Ok(json!({
+            "mine": {
+                "trusted": balances.trusted.to_btc(),
+                "untrusted_pending": balances.untrusted_pending.to_btc(),
+                "immature": balances.immature.to_btc(),
+            }
+        }))
+    }
+
+    /// Transactions touching `wallet`, oldest first (chain order, then mempool order)
+    fn wallet_txids(&self, wallet: &str) -> Vec<Txid> {
+        self.chain
+            .active()
+            .flat_map(|block| block.txids.iter().copied())
+            .chain(self.mempool_txids())
+            .filter(|txid| {
+                let tx = &self.transactions[txid];
+                tx.output
+                    .iter()
+                    .any(|o| self.is_mine(&o.script_pubkey, wallet))
+                    || tx.input.iter().any(|input| {
+                        self.prevout(&input.previous_output)
+                            .is_some_and(|prevout| self.is_mine(&prevout.script_pubkey, wallet))
+                    })
+            })
+            .collect()
+    }
+
+    /// listtransactions params: (label, count, skip, include_watchonly)
+    fn list_transactions(&self, wallet: Option<&str>, params: &[Value]) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        let count: usize = optional(params, 1)?.unwrap_or(10);
+        let skip: usize = optional(params, 2)?.unwrap_or(0);
+
+        let mut entries = Vec::new();
+        for txid in self.wallet_txids(&name) {
+            let tx = &self.transactions[&txid];
+            let from_me = self.is_from_me(tx, &name);
+            for (vout, output) in tx.output.iter().enumerate() {
+                let key = self
+                    .keys
+                    .get(&output.script_pubkey)
+                    .filter(|key| key.wallet == name);
+                let value = output.value.to_signed().unwrap_or(SignedAmount::MAX);
+                let (category, amount, fee) = match key {
+                    // Change comes back to us, so Core leaves it out of the list
+                    Some(key) if from_me && key.change => continue,
+                    Some(_) if self.is_immature(&txid) => ("immature", value, None),
+                    Some(_) if tx.is_coinbase() => ("generate", value, None),
+                    Some(_) => ("receive", value, None),
+                    None if from_me => ("send", SignedAmount::ZERO - value, Some(self.fee(tx))),
+                    None => continue,
+                };
+                let outpoint = OutPoint::new(txid, vout as u32);
+                entries.push(self.wallet_tx_entry(&name, outpoint, category, amount, fee));
+            }
+        }
+
+        let end = entries.len().saturating_sub(skip);
+        let start = end.saturating_sub(count);
+        Ok(json!(entries[start..end]))
+    }
+
+    /// listunspent params: (minconf, maxconf, addresses, include_unsafe)
+    ///
+    /// Like Core, immature coinbase outputs are left out, and unconfirmed outputs from
+    /// other wallets are listed as unsafe: their sender could still double-spend them.
+    fn list_unspent(&self, wallet: Option<&str>, params: &[Value]) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        let min_conf: u64 = optional(params, 0)?.unwrap_or(1);
+        let max_conf: u64 = optional(params, 1)?.unwrap_or(9_999_999);
+        let addresses: Vec<String> = optional(params, 2)?.unwrap_or_default();
+        let include_unsafe: bool = optional(params, 3)?.unwrap_or(true);
+
+        let mut entries = Vec::new();
+        for (outpoint, output) in &self.utxos {
+            let Some(key) = self
+                .keys
+                .get(&output.script_pubkey)
+                .filter(|key| key.wallet == name)
+            else {
+                continue;
+            };
+            let confirmations = self.confirmations(&outpoint.txid);
+            let safe =
+                confirmations > 0 || self.is_from_me(&self.transactions[&outpoint.txid], &name);
+            let address = Address::from_script(&output.script_pubkey, self.network)
+                .map_err(|e| RpcError::new(RPC_MISC_ERROR, e.to_string()))?
+                .to_string();
+            if self.is_immature(&outpoint.txid)
+                || !(min_conf..=max_conf).contains(&confirmations)
+                || !(addresses.is_empty() || addresses.contains(&address))
+                || !(safe || include_unsafe)
+            {
+                continue;
+            }
+            entries.push(json!({
+                "txid": outpoint.txid,
+                "vout": outpoint.vout,
+                "address": address,
+                "label": key.label,
+                "scriptPubKey": output.script_pubkey.to_hex_string(),
+                "amount": output.value.to_btc(),
+                "confirmations": confirmations,
+                "spendable": true,
+                "solvable": true,
+                "safe": safe,
+            }));
+        }
+        Ok(json!(entries))
+    }
+
+    fn wallet_tx_entry(
+        &self,
+        wallet: &str,
+        outpoint: OutPoint,
+        category: &str,
+        amount: SignedAmount,
+        fee: Option<Amount>,
+    ) -> Value {
+        let txid = &outpoint.txid;
+        let tx = &self.transactions[txid];
+        let output = &tx.output[outpoint.vout as usize];
+        let confirmations = self.confirmations(txid);
+        let address = Address::from_script(&output.script_pubkey, self.network)
+            .ok()
+            .map(|address| address.to_string());
+        let block = self
+            .confirmed_at
+            .get(txid)
+            .and_then(|&height| self.chain.at(height));
+        let time = block.map_or(self.chain.tip().time(), chain::Block::time);
+
+        let replaceable = confirmations == 0 && tx.is_explicitly_rbf();
+
+        let mut entry = json!({
+            "address": address,
+            "category": category,
+            "amount": amount.to_btc(),
+            "vout": outpoint.vout,
+            "confirmations": confirmations,
+            "txid": txid,
+            "time": time,
+            "timereceived": time,
+            "bip125-replaceable": if replaceable { "yes" } else { "no" },
+            "walletconflicts": [],
+        });
+        if let Some(key) = self.keys.get(&output.script_pubkey) {
+            entry["label"] = json!(key.label);
+        }
+        if let Some(fee) = fee {
+            entry["fee"] = json!(-fee.to_btc());
+            entry["abandoned"] = json!(false);
+        }
+        if let Some(block) = block {
+            let index = block.txids.iter().position(|id| id == txid);
+            entry["blockhash"] = json!(block.hash);
+            entry["blockheight"] = json!(block.height);
+            entry["blockindex"] = json!(index);
+            entry["blocktime"] = json!(block.time());
+        } else {
+            entry["trusted"] = json!(self.is_from_me(tx, wallet));
+        }
+        entry
+    }
+
+    /// sendtoaddress params: (address, amount, comment, comment_to, subtractfeefromamount, replaceable)
+    fn send_to_address(&mut self, wallet: Option<&str>, params: &[Value]) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        let address = self.address_param(params, 0)?;
+        let amount = amount_param(params, 1)?;
+        let subtract_fee: bool = optional(params, 4)?.unwrap_or(false);
+        let replaceable: bool = optional(params, 5)?.unwrap_or(true);
+
+        let wallet = &self.wallets[&name];
+        if !wallet.private_keys_enabled {
+            return Err(RpcError::new(
+                RPC_WALLET_ERROR,
+                "Error: Private keys are disabled for this wallet",
+            ));
+        }
+        if wallet.is_locked() {
+            return Err(RpcError::new(
+                RPC_WALLET_UNLOCK_NEEDED,
+                "Error: Please enter the wallet passphrase with walletpassphrase first.",
+            ));
+        }
+
+        let payment = TxOut {
+            value: amount,
+            script_pubkey: address.script_pubkey(),
+        };
+        let tx = self.fund_and_sign(&name, payment, subtract_fee, replaceable)?;
+        Ok(json!(self.accept_to_mempool(tx)?))
+    }
+
+    /// bumpfee params: (txid, options)
+    ///
+    /// Re-signs the same inputs and outputs, taking a higher fee out of the change: the
+    /// old fee plus the incremental relay fee (1 sat/vB) for the whole transaction, the
+    /// least BIP125 accepts. Core aims for its fee estimate instead, adds inputs when
+    /// there's no change to take it from, and reads `options`; the mock does neither.
+    fn bump_fee(&mut self, wallet: Option<&str>, params: &[Value]) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        let txid: Txid = required(params, 0, "txid")?;
+        let wallet_error = |message: &str| Err(RpcError::new(RPC_WALLET_ERROR, message));
+        if !self.wallet_txids(&name).contains(&txid) {
+            return Err(RpcError::new(
+                RPC_INVALID_ADDRESS_OR_KEY,
+                "Invalid or non-wallet transaction id",
+            ));
+        }
+        if !self.in_mempool(&txid) {
+            return wallet_error(
+                "Transaction has been mined, or is conflicted with a mined transaction",
+            );
+        }
+        let original = self.transactions[&txid].clone();
+        if !self.is_from_me(&original, &name) {
+            return wallet_error(
+                "Transaction contains inputs that don't belong to this wallet",
+            );
+        }
+        if !self.wallets[&name].private_keys_enabled {
+            return wallet_error(
+                "bumpfee is not available with wallets that have private keys disabled. Use psbtbumpfee instead.",
+            );
+        }
+        if !self.signals_rbf(&txid) {
+            return wallet_error("Transaction is not BIP 125 replaceable");
+        }
+        if !self.mempool_children(&txid).is_empty() {
+            return wallet_error("Transaction has descendants in the mempool");
+        }
+        if self.wallets[&name].is_locked() {
+            return Err(RpcError::new(
+                RPC_WALLET_UNLOCK_NEEDED,
+                "Error: Please enter the wallet passphrase with walletpassphrase first.",
+            ));
+        }
+        let Some(change) = original.output.iter().position(|output| {
+            self.keys
+                .get(&output.script_pubkey)
+                .is_some_and(|key| key.wallet == name && key.change)
+        }) else {
+            return wallet_error("Transaction has no change output to take a higher fee from");
+        };
+
+        // Sized with worst-case signatures, so the replacement can't come out bigger
+        // than what the extra fee covers
+        let inputs: Vec<TxIn> = original
+            .input
+            .iter()
+            .filter_map(|input| self.prevout(&input.previous_output))
+            .map(|prevout| self.dummy_input(&prevout.script_pubkey))
+            .collect();
+        let scripts: Vec<ScriptBuf> = original
+            .output
+            .iter()
+            .map(|output| output.script_pubkey.clone())
+            .collect();
+        let extra = fee_for(&inputs, &scripts);
+        let mut bumped = original.clone();
+        let change_value = bumped.output[change].value.checked_sub(extra);
+        // Change smaller than its script's dust limit goes into the fee instead
+        let dust = bumped.output[change].script_pubkey.dust_value();
+        match change_value.filter(|value| *value >= dust) {
+            Some(value) => bumped.output[change].value = value,
+            None => {
+                // Not worth keeping: the whole change goes to the fee
+                bumped.output.remove(change);
+            },
+        }
+        if bumped.output.is_empty() {
+            return wallet_error("Transaction has no change output to take a higher fee from");
+        }
+        let spent: Vec<(OutPoint, TxOut)> = bumped
+            .input
+            .iter_mut()
+            .map(|input| {
+                input.script_sig = ScriptBuf::new();
+                input.witness = Witness::new();
+                let prevout = self.prevout(&input.previous_output).cloned();
+                (
+                    input.previous_output,
+                    prevout.expect("mempool transactions spend known outputs"),
+                )
+            })
+            .collect();
+        let bumped = self.sign(bumped, &spent);
+        let fee = self.fee(&bumped);
+        let replacement = self.accept_to_mempool(bumped)?;
+        Ok(json!({
+            "txid": replacement,
+            "origfee": self.fee(&original).to_btc(),
+            "fee": fee.to_btc(),
+            "errors": [],
+        }))
+    }
+
+    /// Select coins for `payment`, add change, and sign every input
+    fn fund_and_sign(
+        &mut self,
+        wallet: &str,
+        payment: TxOut,
+        subtract_fee: bool,
+        replaceable: bool,
+    ) -> RpcResult<Transaction> {
+        let (unsigned, selected) = self.fund(wallet, payment, subtract_fee, replaceable)?;
+        Ok(self.sign(unsigned, &selected))
+    }
+
+    /// Select coins for `payment` and add change; returns the unsigned transaction and
+    /// the outputs its inputs spend
+    ///
+    /// Largest coins first; the change output (if any) goes last. Core randomizes its
+    /// position, so nothing downstream may rely on it.
+    fn fund(
+        &mut self,
+        wallet: &str,
+        mut payment: TxOut,
+        subtract_fee: bool,
+        replaceable: bool,
+    ) -> RpcResult<(Transaction, Vec<Coin>)> {
+        let sequence = if replaceable {
+            Sequence::ENABLE_RBF_NO_LOCKTIME
+        } else {
+            Sequence::ENABLE_LOCKTIME_NO_RBF
+        };
+        let insufficient =
+            || RpcError::new(RPC_WALLET_INSUFFICIENT_FUNDS, "Insufficient funds");
+
+        let mut selected: Vec<(OutPoint, TxOut)> = Vec::new();
+        let mut selected_value = Amount::ZERO;
+        let mut fee = Amount::ZERO;
+        let mut inputs = Vec::new();
+        let change_type = self.change_type(wallet, &payment.script_pubkey);
+        let scripts = [
+            payment.script_pubkey.clone(),
+            self.change_template(wallet, change_type),
+        ];
+        for coin in self.spendable(wallet) {
+            selected_value += coin.1.value;
+            inputs.push(self.dummy_input(&coin.1.script_pubkey));
+            selected.push(coin);
+            // Fee for these inputs, the payment and a change output
+            fee = fee_for(&inputs, &scripts);
+            let needed = if subtract_fee {
+                payment.value
+            } else {
+                payment.value + fee
+            };
+            if selected_value >= needed {
+                break;
+            }
+        }
+        let needed = if subtract_fee {
+            payment.value
+        } else {
+            payment.value + fee
+        };
+        if selected_value < needed {
+            return Err(insufficient());
+        }
+
+        if subtract_fee {
+            payment.value = payment
+                .value
+                .checked_sub(fee)
+                .filter(|value| *value >= payment.script_pubkey.dust_value())
+                .ok_or_else(|| {
+                    RpcError::new(
+                        RPC_TYPE_ERROR,
+                        "The transaction amount is too small to pay the fee",
+                    )
+                })?;
+        }
+        let change_value = selected_value - payment.value - fee;
+
+        let mut outputs = vec![payment];
+        // Change smaller than its script's dust limit goes into the fee instead
+        if change_value >= scripts[1].dust_value() {
+            let script_pubkey = self.derive_script(wallet, "", true, change_type)?;
+            outputs.push(TxOut {
+                value: change_value,
+                script_pubkey,
+            });
+        }
+
+        let unsigned = Transaction {
+            version: transaction::Version::TWO,
+            // Anti fee-sniping, like Core: only valid on top of the current tip
+            lock_time: absolute::LockTime::from_height(self.tip_height() as u32)
+                .unwrap_or(absolute::LockTime::ZERO),
+            input: selected
+                .iter()
+                .map(|(outpoint, _)| TxIn {
+                    previous_output: *outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs,
+        };
+        Ok((unsigned, selected))
+    }
+
+    /// An input as big as the one spending `script_pubkey` will be (scriptSig and
+    /// witness), for fee estimates
+    fn dummy_input(&self, script_pubkey: &ScriptBuf) -> TxIn {
+        let address_type = match self.keys.get(script_pubkey).map(|key| &key.spend) {
+            Some(Spend::Multisig(output)) => {
+                return TxIn {
+                    witness: output.dummy_witness(),
+                    ..TxIn::default()
+                };
+            },
+            Some(Spend::Single { address_type, .. }) => *address_type,
+            None => AddressType::Bech32,
+        };
+        let mut input = TxIn::default();
+        match address_type {
+            AddressType::Legacy => {
+                input.script_sig = Builder::new()
+                    .push_slice([0u8; 72])
+                    .push_slice([0u8; 33])
+                    .into_script();
+            },
+            AddressType::P2shSegwit | AddressType::Bech32 => {
+                if address_type == AddressType::P2shSegwit {
+                    input.script_sig = Builder::new().push_slice([0u8; 22]).into_script();
+                }
+                input.witness.push([0u8; 72]);
+                input.witness.push([0u8; 33]);
+            },
+            AddressType::Bech32m => input.witness.push([0u8; 64]),
+        }
+        input
+    }
+
+    /// The type of change `wallet` gets when paying to `payment`
+    ///
+    /// Like Core without `-changetype`: Taproot change for a Taproot payment (so the
+    /// change doesn't stand out), bech32 otherwise. A watch-only wallet only has the
+    /// type of its imported change descriptor.
+    fn change_type(&self, wallet: &str, payment: &ScriptBuf) -> AddressType {
+        let state = &self.wallets[wallet];
+        match state
+            .imported
+            .iter()
+            .find(|imported| imported.active && imported.internal)
+        {
+            Some(imported) if state.master.is_none() => descriptor_type(&imported.descriptor),
+            _ if payment.is_p2tr() => AddressType::Bech32m,
+            _ => AddressType::Bech32,
+        }
+    }
+
+    /// A script of the kind `wallet`'s next `change_type` change output will have
+    fn change_template(&self, wallet: &str, change_type: AddressType) -> ScriptBuf {
+        let imported = self.wallets[wallet]
+            .imported
+            .iter()
+            .find(|imported| imported.active && imported.internal)
+            .filter(|_| self.wallets[wallet].master.is_none());
+        if let Some(imported) = imported {
+            return imported
+                .descriptor
+                .derive(&self.secp, imported.next)
+                .script_pubkey;
+        }
+        match change_type {
+            AddressType::Legacy => ScriptBuf::new_p2pkh(&Hash::all_zeros()),
+            AddressType::P2shSegwit => ScriptBuf::new_p2sh(&Hash::all_zeros()),
+            AddressType::Bech32 => ScriptBuf::new_p2wpkh(&Hash::all_zeros()),
+            AddressType::Bech32m => Builder::new()
+                .push_opcode(opcodes::all::OP_PUSHNUM_1)
+                .push_slice([0u8; 32])
+                .into_script(),
+        }
+    }
+
+    /// walletcreatefundedpsbt params: (inputs, outputs, locktime, options, bip32derivs)
+    ///
+    /// Funds like sendtoaddress, minus the signing. The mock picks every input itself
+    /// (`inputs` must be empty), pays a single output, reads only `replaceable` from
+    /// `options` (default true) and fills in each input's spent output
+    /// (see [`Node::update_input`]).
+    fn wallet_create_funded_psbt(
+        &mut self,
+        wallet: Option<&str>,
+        params: &[Value],
+    ) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        let inputs: Vec<Value> = optional(params, 0)?.unwrap_or_default();
+        if !inputs.is_empty() {
+            return Err(RpcError::new(
+                RPC_INVALID_PARAMETER,
+                "the mock node only funds PSBTs from its own coin selection",
+            ));
+        }
+        // {"address": amount}, or Core's newer [{"address": amount}]
+        let outputs = match required::<Value>(params, 1, "outputs")? {
+            Value::Array(outputs) => outputs,
+            outputs => vec![outputs],
+        };
+        let (address, amount) = match outputs.as_slice() {
+            [Value::Object(output)] if output.len() == 1 => {
+                output.iter().next().expect("one entry")
+            },
+            _ => {
+                return Err(RpcError::new(
+                    RPC_INVALID_PARAMETER,
+                    "the mock node only funds PSBTs paying a single output",
+                ));
+            },
+        };
+        let address = self.address_param(&[json!(address)], 0)?;
+        let amount = amount_param(std::slice::from_ref(amount), 0)?;
+        let options: Value = optional(params, 3)?.unwrap_or_default();
+        let replaceable = options["replaceable"].as_bool().unwrap_or(true);
+
+        let payment = TxOut {
+            value: amount,
+            script_pubkey: address.script_pubkey(),
+        };
+        let (unsigned, selected) = self.fund(&name, payment, false, replaceable)?;
+        let changepos = if unsigned.output.len() > 1 { 1 } else { -1 };
+        let fee = selected
+            .iter()
+            .map(|(_, prevout)| prevout.value)
+            .sum::<Amount>()
+            - unsigned.output.iter().map(|output| output.value).sum();
+        let bip32derivs: bool = optional(params, 4)?.unwrap_or(true);
+        let mut psbt = Psbt::from_unsigned_tx(unsigned).expect("nothing is signed yet");
+        for (input, (outpoint, prevout)) in psbt.inputs.iter_mut().zip(selected) {
+            self.update_input(input, outpoint, prevout, bip32derivs);
+        }
+        Ok(json!({
+            "psbt": psbt::to_base64(&psbt),
+            "fee": fee.to_btc(),
+            "changepos": changepos,
+        }))
+    }
+
+    /// Updater: fill in what a signer needs to know about an input spending `prevout`
+    /// (at `outpoint`), one of our outputs
+    ///
+    /// The output itself, or for a legacy one the whole transaction it's in (pre-segwit
+    /// signatures don't commit to the amount); the redeem script of a P2SH-wrapped one,
+    /// the internal key of a Taproot one. For a multisig output the script too, and with
+    /// `bip32derivs` the origin of every key (that's how each cosigner finds the keys
+    /// it holds).
+    fn update_input(
+        &self,
+        input: &mut PsbtInput,
+        outpoint: OutPoint,
+        prevout: TxOut,
+        bip32derivs: bool,
+    ) {
+        let key = &self.keys[&prevout.script_pubkey];
+        match &key.spend {
+            Spend::Single {
+                address_type: AddressType::Legacy,
+                ..
+            } => input.non_witness_utxo = Some(self.transactions[&outpoint.txid].clone()),
+            _ => input.witness_utxo = Some(prevout),
+        }
+        match &key.spend {
+            Spend::Single {
+                public,
+                address_type,
+                ..
+            } => {
+                let master = self.wallets[&key.wallet].master.as_ref();
+                let origin = master.filter(|_| bip32derivs).map(|master| {
+                    let path = DerivationPath::from_str(&key.hd_keypath())
+                        .expect("hd_keypath() is a valid path");
+                    (master.fingerprint(&self.secp), path)
+                });
+                match address_type {
+                    AddressType::Bech32m => {
+                        let internal = public.inner.x_only_public_key().0;
+                        input.tap_internal_key = Some(internal);
+                        if let Some(origin) = origin {
+                            input.tap_key_origins.insert(internal, (Vec::new(), origin));
+                        }
+                    },
+                    _ => {
+                        if *address_type == AddressType::P2shSegwit {
+                            input.redeem_script = Some(p2wpkh(public));
+                        }
+                        if let Some(origin) = origin {
+                            input.bip32_derivation.insert(public.inner, origin);
+                        }
+                    },
+                }
+            },
+            Spend::Multisig(output) => match &output.taproot {
+                None => {
+                    input.witness_script = Some(output.script.clone());
+                    if bip32derivs {
+                        input.bip32_derivation.extend(output.keys.iter().cloned());
+                    }
+                },
+                Some(spend_info) => {
+                    let leaf = output.leaf();
+                    let leaf_hash = TapLeafHash::from_script(&leaf.0, leaf.1);
+                    input.tap_internal_key = Some(spend_info.internal_key());
+                    input.tap_merkle_root = spend_info.merkle_root();
+                    let control_block = spend_info
+                        .control_block(&leaf)
+                        .expect("the leaf is in the tree");
+                    input.tap_scripts.insert(control_block, leaf);
+                    if bip32derivs {
+                        for (public, origin) in &output.keys {
+                            input.tap_key_origins.insert(
+                                public.x_only_public_key().0,
+                                (vec![leaf_hash], origin.clone()),
+                            );
+                        }
+                    }
+                },
+            },
+        }
+    }
+
+    /// walletprocesspsbt params: (psbt, sign, sighashtype, bip32derivs, finalize)
+    ///
+    /// Fills in the spent output of every input that spends one of the wallet's coins
+    /// and, with `sign` (the default), signs those inputs. With `finalize` (also the
+    /// default) signed inputs get their final witness right away. Inputs of other
+    /// wallets are left alone; `complete` says whether every input is finalized.
+    fn wallet_process_psbt(&self, wallet: Option<&str>, params: &[Value]) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        let mut psbt = psbt_param(params, 0)?;
+        let sign: bool = optional(params, 1)?.unwrap_or(true);
+        let sighash: String = optional(params, 2)?.unwrap_or_else(|| "DEFAULT".to_owned());
+        let bip32derivs: bool = optional(params, 3)?.unwrap_or(true);
+        let finalize: bool = optional(params, 4)?.unwrap_or(true);
+        if !matches!(sighash.as_str(), "DEFAULT" | "ALL") {
+            return Err(RpcError::new(
+                RPC_INVALID_PARAMETER,
+                "the mock node only signs with SIGHASH_ALL",
+            ));
+        }
+        if sign && self.wallets[&name].is_locked() {
+            return Err(RpcError::new(
+                RPC_WALLET_UNLOCK_NEEDED,
+                "Error: Please enter the wallet passphrase with walletpassphrase first.",
+            ));
+        }
+
+        // Taproot signatures commit to every output being spent, not just their own
+        let prevouts: Option<Vec<TxOut>> = psbt
+            .inputs
+            .iter()
+            .zip(&psbt.unsigned_tx.input)
+            .map(|(input, txin)| {
+                input
+                    .witness_utxo
+                    .clone()
+                    .or_else(|| self.prevout(&txin.previous_output).cloned())
+            })
+            .collect();
+        let master = self.wallets[&name].master.as_ref().filter(|_| sign);
+        let mut cache = SighashCache::new(psbt.unsigned_tx.clone());
+        for (index, input) in psbt.inputs.iter_mut().enumerate() {
+            let outpoint = psbt.unsigned_tx.input[index].previous_output;
+            let own = self
+                .prevout(&outpoint)
+                .filter(|prevout| self.is_mine(&prevout.script_pubkey, &name));
+            let mut touched = false;
+            if let Some(prevout) = own {
+                self.update_input(input, outpoint, prevout.clone(), bip32derivs);
+                touched = true;
+                let single = matches!(
+                    self.keys[&prevout.script_pubkey].spend,
+                    Spend::Single { .. }
+                );
+                if sign && single && !is_finalized(input) {
+                    match self.signature(&mut cache, index, prevout, prevouts.as_deref()) {
+                        Some(SingleSignature::Ecdsa(public, signature)) => {
+                            input.partial_sigs.insert(public, signature);
+                        },
+                        Some(SingleSignature::Schnorr(signature)) => {
+                            input.tap_key_sig = Some(signature);
+                        },
+                        None => {},
+                    }
+                }
+            }
+            // Someone else's multisig input may still have a key of ours in it
+            if let Some(master) = master.filter(|_| !is_finalized(input)) {
+                touched |= self.sign_as_cosigner(
+                    master,
+                    &mut cache,
+                    index,
+                    input,
+                    prevouts.as_deref(),
+                );
+            }
+            if finalize && touched {
+                finalize_input(input, &psbt.unsigned_tx.input[index]);
+            }
+        }
+        let complete = psbt.inputs.iter().all(is_finalized);
+        Ok(json!({ "psbt": psbt::to_base64(&psbt), "complete": complete }))
+    }
+
+    /// Sign a multisig input with every key of ours its key origins name; whether
+    /// there was one
+    ///
+    /// The keys are found through the PSBT alone (the derivation paths the updater put
+    /// in), so a cosigner needs no copy of the multisig descriptor. Taproot inputs can
+    /// only be signed when every input's spent output is known (`prevouts`).
+    fn sign_as_cosigner(
+        &self,
+        master: &Xpriv,
+        cache: &mut SighashCache<Transaction>,
+        index: usize,
+        input: &mut PsbtInput,
+        prevouts: Option<&[TxOut]>,
+    ) -> bool {
+        let fingerprint = master.fingerprint(&self.secp);
+        let derive = |origin: &KeyOrigin| {
+            (origin.0 == fingerprint).then(|| {
+                master
+                    .derive_priv(&self.secp, &origin.1)
+                    .expect("derivation only fails with negligible probability")
+                    .private_key
+            })
+        };
+        let mut signed = false;
+
+        if let (Some(witness_script), Some(prevout)) =
+            (&input.witness_script, &input.witness_utxo)
+        {
+            for (public, origin) in &input.bip32_derivation {
+                let Some(secret) =
+                    derive(origin).filter(|s| s.public_key(&self.secp) == *public)
+                else {
+                    continue;
+                };
+                let sighash = cache
+                    .p2wsh_signature_hash(
+                        index,
+                        witness_script,
+                        prevout.value,
+                        EcdsaSighashType::All,
+                    )
+                    .expect("the input exists");
+                let message = Message::from_digest(sighash.to_byte_array());
+                let signature =
+                    ecdsa::Signature::sighash_all(self.secp.sign_ecdsa(&message, &secret));
+                input
+                    .partial_sigs
+                    .insert(PublicKey::new(*public), signature);
+                signed = true;
+            }
+        }
+
+        let Some(prevouts) = prevouts else {
+            return signed;
+        };
+        for (public, (leaf_hashes, origin)) in &input.tap_key_origins {
+            let Some(keypair) = derive(origin)
+                .map(|secret| Keypair::from_secret_key(&self.secp, &secret))
+                .filter(|keypair| keypair.x_only_public_key().0 == *public)
+            else {
+                continue;
+            };
+            for leaf_hash in leaf_hashes {
+                let sighash = cache
+                    .taproot_script_spend_signature_hash(
+                        index,
+                        &Prevouts::All(prevouts),
+                        *leaf_hash,
+                        TapSighashType::Default,
+                    )
+                    .expect("the input exists and every prevout is known");
+                let message = Message::from_digest(sighash.to_byte_array());
+                let signature = taproot::Signature {
+                    sig: self.secp.sign_schnorr_no_aux_rand(&message, &keypair),
+                    hash_ty: TapSighashType::Default,
+                };
+                input
+                    .tap_script_sigs
+                    .insert((*public, *leaf_hash), signature);
+                signed = true;
+            }
+        }
+        signed
+    }
+
+    /// combinepsbt params: (txs)
+    ///
+    /// Merges what several signers added to copies of the same PSBT.
+    fn combine_psbt(&self, params: &[Value]) -> RpcResult {
+        let encoded: Vec<String> = required(params, 0, "txs")?;
+        let mut psbts = encoded
+            .iter()
+            .map(|text| psbt_param(&[json!(text)], 0))
+            .collect::<RpcResult<Vec<Psbt>>>()?
+            .into_iter();
+        let mut combined = psbts.next().ok_or_else(|| {
+            RpcError::new(RPC_INVALID_PARAMETER, "Parameter 'txs' cannot be empty")
+        })?;
+        for psbt in psbts {
+            combined.combine(psbt).map_err(|_| {
+                RpcError::new(
+                    RPC_INVALID_PARAMETER,
+                    "PSBTs not compatible (different transactions)",
+                )
+            })?;
+        }
+        Ok(json!(psbt::to_base64(&combined)))
+    }
+
+    /// finalizepsbt params: (psbt, extract)
+    ///
+    /// Finalizes every single-key input that has its signature, and every multisig
+    /// input with enough of them (see [`finalize_input`]). Once all inputs are final,
+    /// `extract` (the default) returns the network transaction instead of the PSBT.
+    fn finalize_psbt(&self, params: &[Value]) -> RpcResult {
+        let mut psbt = psbt_param(params, 0)?;
+        let extract: bool = optional(params, 1)?.unwrap_or(true);
+        let mut complete = true;
+        for (input, txin) in psbt.inputs.iter_mut().zip(&psbt.unsigned_tx.input) {
+            complete &= finalize_input(input, txin);
+        }
+        if complete && extract {
+            let tx = psbt.extract_tx_unchecked_fee_rate();
+            return Ok(json!({
+                "hex": consensus::encode::serialize_hex(&tx),
+                "complete": true,
+            }));
+        }
+        Ok(json!({ "psbt": psbt::to_base64(&psbt), "complete": complete }))
+    }
+
+    /// signrawtransactionwithwallet params: (hexstring)
+    ///
+    /// Signs only if every input spends one of this wallet's outputs; otherwise the
+    /// transaction comes back unsigned, with an error for each input it can't sign.
+    fn sign_raw_transaction(&self, wallet: Option<&str>, params: &[Value]) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        let hex: String = required(params, 0, "hexstring")?;
+        let tx = decode_tx(&hex)?;
+        if self.wallets[&name].is_locked() {
+            return Err(RpcError::new(
+                RPC_WALLET_UNLOCK_NEEDED,
+                "Error: Please enter the wallet passphrase with walletpassphrase first.",
+            ));
+        }
+
+        let prevouts: Vec<Option<&TxOut>> = tx
+            .input
+            .iter()
+            .map(|input| self.prevout(&input.previous_output))
+            .collect();
+        let errors: Vec<Value> = tx
+            .input
+            .iter()
+            .zip(&prevouts)
+            .filter_map(|(input, prevout)| {
+                let error = match prevout {
+                    None => "Input not found or already spent",
+                    Some(prevout) if !self.can_sign(&prevout.script_pubkey, &name) => {
+                        "Unable to sign input, invalid stack size (possibly missing key)"
+                    },
+                    Some(_) => return None,
+                };
+                Some(json!({
+                    "txid": input.previous_output.txid,
+                    "vout": input.previous_output.vout,
+                    "witness": [],
+                    "scriptSig": "",
+                    "sequence": input.sequence.0,
+                    "error": error,
+                }))
+            })
+            .collect();
+        if !errors.is_empty() {
+            return Ok(json!({ "hex": hex, "complete": false, "errors": errors }));
+        }
+
+        let spent: Vec<(OutPoint, TxOut)> = tx
+            .input
+            .iter()
+            .zip(prevouts)
+            .map(|(input, prevout)| {
+                (
+                    input.previous_output,
+                    prevout.cloned().expect("checked above"),
+                )
+            })
+            .collect();
+        let signed = self.sign(tx, &spent);
+        Ok(json!({ "hex": consensus::encode::serialize_hex(&signed), "complete": true }))
+    }
+
+    /// Sign every input of `tx` (SIGHASH_ALL) with the wallet's single keys
+    fn sign(&self, mut tx: Transaction, spent: &[(OutPoint, TxOut)]) -> Transaction {
+        let prevouts: Vec<TxOut> = spent.iter().map(|(_, prevout)| prevout.clone()).collect();
+        let mut cache = SighashCache::new(tx.clone());
+        let unlocks: Vec<(ScriptBuf, Witness)> = prevouts
+            .iter()
+            .enumerate()
+            .map(|(index, prevout)| {
+                let signature = self
+                    .signature(&mut cache, index, prevout, Some(&prevouts))
+                    .expect("every prevout is known");
+                unlock(&signature, &prevout.script_pubkey)
+            })
+            .collect();
+        for (input, (script_sig, witness)) in tx.input.iter_mut().zip(unlocks) {
+            input.script_sig = script_sig;
+            input.witness = witness;
+        }
+        tx
+    }
+
+    /// Sign input `index`, which spends `prevout`, an output of one of our single keys
+    ///
+    /// ECDSA for legacy and segwit v0 outputs, each with its own sighash; Schnorr with
+    /// the tweaked key for Taproot ones, which needs every spent output (`prevouts`).
+    fn signature(
+        &self,
+        cache: &mut SighashCache<Transaction>,
+        index: usize,
+        prevout: &TxOut,
+        prevouts: Option<&[TxOut]>,
+    ) -> Option<SingleSignature> {
+        let Spend::Single {
+            secret,
+            public,
+            address_type,
+        } = &self.keys[&prevout.script_pubkey].spend
+        else {
+            unreachable!("only single-key outputs are signed by their wallet alone");
+        };
+        let sighash = match address_type {
+            AddressType::Legacy => cache
+                .legacy_signature_hash(
+                    index,
+                    &prevout.script_pubkey,
+                    EcdsaSighashType::All.to_u32(),
+                )
+                .expect("the input exists")
+                .to_byte_array(),
+            AddressType::P2shSegwit | AddressType::Bech32 => cache
+                .p2wpkh_signature_hash(
+                    index,
+                    &p2wpkh(public),
+                    prevout.value,
+                    EcdsaSighashType::All,
+                )
+                .expect("the input exists")
+                .to_byte_array(),
+            AddressType::Bech32m => {
+                let sighash = cache
+                    .taproot_key_spend_signature_hash(
+                        index,
+                        &Prevouts::All(prevouts?),
+                        TapSighashType::Default,
+                    )
+                    .expect("the input exists and every prevout is known");
+                let message = Message::from_digest(sighash.to_byte_array());
+                let keypair = Keypair::from_secret_key(&self.secp, secret)
+                    .tap_tweak(&self.secp, None)
+                    .to_inner();
+                return Some(SingleSignature::Schnorr(taproot::Signature {
+                    sig: self.secp.sign_schnorr_no_aux_rand(&message, &keypair),
+                    hash_ty: TapSighashType::Default,
+                }));
+            },
+        };
+        let message = Message::from_digest(sighash);
+        let signature = ecdsa::Signature::sighash_all(self.secp.sign_ecdsa(&message, secret));
+        Some(SingleSignature::Ecdsa(*public, signature))
+    }
+
+    fn wallet_passphrase(&mut self, wallet: Option<&str>, params: &[Value]) -> RpcResult {
+        let name = self.wallet_name(wallet)?;
+        let passphrase: String = required(params, 0, "passphrase")?;
+        let timeout: u64 = required(params, 1, "timeout")?;
+
+        let wallet = self.wallets.get_mut(&name).expect("resolved above");
+        match &wallet.passphrase {
+            None => Err(RpcError::new(
+                RPC_WALLET_WRONG_ENC_STATE,
+                "Error: running with an unencrypted wallet, but walletpassphrase was called.",
+            )),
+            Some(expected) if *expected != passphrase => Err(RpcError::new(
+                RPC_WALLET_PASSPHRASE_INCORRECT,
+                "Error: The wallet passphrase entered was incorrect.",
+            )),
+            Some(_) => {
+                wallet.unlocked_until = unix_now() + timeout;
+                Ok(Value::Null)
+            },
+        }
+    }
+}
+
+/// bitcoincore-rpc asks for the version before parsing `getblockchaininfo`
+fn network_info(connections: usize) -> Value {
+    json!({
+        "version": 280000,
+        "subversion": SUBVERSION,
+        "protocolversion": PROTOCOL_VERSION,
+        "localservices": "0000000000000c09",
+        "localrelay": true,
+        "timeoffset": 0,
+        "connections": connections,
+        "networkactive": true,
+        "networks": [],
+        "relayfee": 0.00001,
+        "incrementalfee": 0.00001,
+        "localaddresses": [],
+        "warnings": "",
+    })
+}
+
+/// Fee at [`FEE_RATE_SAT_PER_VB`] for a transaction with these (dummy) inputs and
+/// outputs paying these scripts
+///
+/// Sized with 72-byte signatures (the DER maximum plus sighash byte), like Core's
+/// estimate, so the real transaction is never bigger than what was paid for.
+fn fee_for(inputs: &[TxIn], scripts: &[ScriptBuf]) -> Amount {
+    let dummy = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: inputs.to_vec(),
+        output: scripts
+            .iter()
+            .map(|script_pubkey| TxOut {
+                value: Amount::ZERO,
+                script_pubkey: script_pubkey.clone(),
+            })
+            .collect(),
+    };
+    Amount::from_sat(dummy.vsize() as u64 * FEE_RATE_SAT_PER_VB)
+}
+
+/// Update `utxos` for `tx`: its inputs are now spent, its outputs unspent
+fn apply_to(utxos: &mut BTreeMap<OutPoint, TxOut>, tx: &Transaction) {
+    let txid = tx.txid();
+    for input in &tx.input {
+        utxos.remove(&input.previous_output);
+    }
+    for (vout, output) in tx.output.iter().enumerate() {
+        utxos.insert(OutPoint::new(txid, vout as u32), output.clone());
+    }
+}
+
+fn block_not_found() -> RpcError {
+    RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "Block not found")
+}
+
+/// A raw transaction parameter, as hex
+fn decode_tx(hex: &str) -> RpcResult<Transaction> {
+    Vec::<u8>::from_hex(hex)
+        .ok()
+        .and_then(|bytes| consensus::deserialize(&bytes).ok())
+        .ok_or_else(|| RpcError::new(RPC_DESERIALIZATION_ERROR, "TX decode failed"))
+}
+
+/// A base64 PSBT parameter
+fn psbt_param(params: &[Value], index: usize) -> RpcResult<Psbt> {
+    let text: String = required(params, index, "psbt")?;
+    psbt::from_base64(&text)
+        .map_err(|e| RpcError::new(RPC_DESERIALIZATION_ERROR, format!("TX decode failed {e}")))
+}
+
+/// Give a PSBT input its final witness if it has the signatures for it, and drop what
+/// only signers needed (BIP174's finalizer); whether it's final now
+///
+/// A single key (P2PKH, P2SH-P2WPKH, P2WPKH, Taproot key path) needs its one signature;
+/// multisig (P2WSH or a Taproot leaf) needs as many as the script's threshold, and uses
+/// exactly that many even if more cosigners signed. `txin` is the input in the
+/// unsigned transaction, for finding the spent output in `non_witness_utxo`.
+fn finalize_input(input: &mut PsbtInput, txin: &TxIn) -> bool {
+    if is_finalized(input) {
+        return true;
+    }
+    let vout = txin.previous_output.vout as usize;
+    let Some(prevout) = input.witness_utxo.clone().or_else(|| {
+        let tx = input.non_witness_utxo.as_ref()?;
+        tx.output.get(vout).cloned()
+    }) else {
+        return false;
+    };
+    let spent = &prevout.script_pubkey;
+    let signed_for = |script: &ScriptBuf, pay_to: fn(&PublicKey) -> ScriptBuf| {
+        input
+            .partial_sigs
+            .iter()
+            .find(|(public, _)| pay_to(public) == *script)
+            .map(|(public, signature)| SingleSignature::Ecdsa(*public, *signature))
+    };
+    let single = if spent.is_p2pkh() {
+        signed_for(spent, |public| ScriptBuf::new_p2pkh(&public.pubkey_hash()))
+    } else if spent.is_p2wpkh() {
+        signed_for(spent, p2wpkh)
+    } else if let Some(redeem_script) = input.redeem_script.as_ref().filter(|_| spent.is_p2sh())
+    {
+        signed_for(redeem_script, p2wpkh)
+    } else if spent.is_p2tr() {
+        input.tap_key_sig.map(SingleSignature::Schnorr)
+    } else {
+        None
+    };
+    let witness = if let Some(single) = single {
+        let (script_sig, witness) = unlock(&single, spent);
+        if !script_sig.is_empty() {
+            input.final_script_sig = Some(script_sig);
+        }
+        // A legacy input has nothing but its scriptSig
+        if witness.is_empty() {
+            clear_signer_fields(input);
+            return true;
+        }
+        Some(witness)
+    } else if spent.is_p2wsh() {
+        input.witness_script.as_ref().and_then(|script| {
+            let (threshold, keys) = multisig_keys(script)?;
+            let signatures: Vec<Vec<u8>> = keys
+                .iter()
+                .filter_map(|key| PublicKey::from_slice(key).ok())
+                .filter_map(|key| input.partial_sigs.get(&key))
+                .map(|signature| signature.to_vec())
+                .take(threshold)
+                .collect();
+            (signatures.len() == threshold).then(|| {
+                // CHECKMULTISIG pops one item too many, so an empty one goes first
+                let mut witness = Witness::new();
+                witness.push([]);
+                signatures
+                    .iter()
+                    .for_each(|signature| witness.push(signature));
+                witness.push(script.as_bytes());
+                witness
+            })
+        })
+    } else if prevout.script_pubkey.is_p2tr() {
+        input
+            .tap_scripts
+            .iter()
+            .find_map(|(control_block, (script, version))| {
+                let (threshold, keys) = multisig_keys(script)?;
+                let leaf_hash = TapLeafHash::from_script(script, *version);
+                let mut remaining = threshold;
+                let signatures: Vec<Vec<u8>> = keys
+                    .iter()
+                    .map(|key| {
+                        let signature = XOnlyPublicKey::from_slice(key)
+                            .ok()
+                            .and_then(|key| input.tap_script_sigs.get(&(key, leaf_hash)))
+                            .filter(|_| remaining > 0);
+                        match signature {
+                            Some(signature) => {
+                                remaining -= 1;
+                                signature.to_vec()
+                            },
+                            None => Vec::new(),
+                        }
+                    })
+                    .collect();
+                (remaining == 0).then(|| {
+                    // The first key's signature has to end up on top of the stack
+                    let mut witness = Witness::new();
+                    signatures
+                        .iter()
+                        .rev()
+                        .for_each(|signature| witness.push(signature));
+                    witness.push(script.as_bytes());
+                    witness.push(control_block.serialize());
+                    witness
+                })
+            })
+    } else {
+        None
+    };
+    let Some(witness) = witness else {
+        return false;
+    };
+    input.final_script_witness = Some(witness);
+    clear_signer_fields(input);
+    true
+}
+
+/// The scriptSig and witness that spend `spent`, one of our single-key outputs, with
+/// `signature`
+fn unlock(signature: &SingleSignature, spent: &ScriptBuf) -> (ScriptBuf, Witness) {
+    match signature {
+        SingleSignature::Ecdsa(public, signature) if spent.is_p2pkh() => {
+            let signature = PushBytesBuf::try_from(signature.to_vec())
+                .expect("a signature is a valid push");
+            let script_sig = Builder::new()
+                .push_slice(signature)
+                .push_key(public)
+                .into_script();
+            (script_sig, Witness::new())
+        },
+        SingleSignature::Ecdsa(public, signature) => {
+            let witness = Witness::p2wpkh(signature, &public.inner);
+            let script_sig = if spent.is_p2sh() {
+                let redeem_script = PushBytesBuf::try_from(p2wpkh(public).into_bytes())
+                    .expect("a P2WPKH script is a valid push");
+                Builder::new().push_slice(redeem_script).into_script()
+            } else {
+                ScriptBuf::new()
+            };
+            (script_sig, witness)
+        },
+        SingleSignature::Schnorr(signature) => {
+            let mut witness = Witness::new();
+            witness.push(signature.to_vec());
+            (ScriptBuf::new(), witness)
+        },
+    }
+}
+
+/// A finalized input has its scriptSig or witness (or both, for wrapped segwit)
+fn is_finalized(input: &PsbtInput) -> bool {
+    input.final_script_witness.is_some() || input.final_script_sig.is_some()
+}
+
+/// The P2WPKH script paying `public`
+fn p2wpkh(public: &PublicKey) -> ScriptBuf {
+    ScriptBuf::new_p2wpkh(
+        &public
+            .wpubkey_hash()
+            .expect("secp256k1 keys are compressed"),
+    )
+}
+
+/// What only signers needed: BIP174's finalizer drops it once the input is final
+fn clear_signer_fields(input: &mut PsbtInput) {
+    input.partial_sigs.clear();
+    input.sighash_type = None;
+    input.bip32_derivation.clear();
+    input.witness_script = None;
+    input.tap_script_sigs.clear();
+    input.tap_scripts.clear();
+    input.tap_key_origins.clear();
+    input.tap_internal_key = None;
+    input.tap_merkle_root = None;
+    input.tap_key_sig = None;
+    input.redeem_script = None;
+}
+
+/// Whether input `index` of `tx` satisfies every `<n> CHECKLOCKTIMEVERIFY` (BIP65) and
+/// `<n> CHECKSEQUENCEVERIFY` (BIP112) in `script`, as if every branch ran
+///
+/// CLTV compares `n` with the transaction's nLockTime, which the input has to enable;
+/// CSV compares it with the input's own sequence number, in a version 2 transaction.
+/// Either way it takes the same unit (height or time) and at least as much.
+fn timelocks_satisfied(script: &ScriptBuf, tx: &Transaction, index: usize) -> bool {
+    let Ok(instructions) = script.instructions().collect::<Result<Vec<_>, _>>() else {
+        return true;
+    };
+    let sequence = tx.input[index].sequence;
+    instructions.windows(2).all(|pair| {
+        let [number, Instruction::Op(op)] = pair else {
+            return true;
+        };
+        let Some(n) = number.script_num() else {
+            return true;
+        };
+        if *op == opcodes::all::OP_CLTV {
+            let Ok(n) = u32::try_from(n) else {
+                return false;
+            };
+            sequence.enables_absolute_lock_time()
+                && absolute::LockTime::from_consensus(n).is_implied_by(tx.lock_time)
+        } else if *op == opcodes::all::OP_CSV {
+            let Ok(n) = u32::try_from(n) else {
+                return false;
+            };
+            match Sequence::from_consensus(n).to_relative_lock_time() {
+                // With the disable flag set CSV does nothing
+                None => true,
+                Some(lock) => {
+                    tx.version >= transaction::Version::TWO
+                        && sequence
+                            .to_relative_lock_time()
+                            .is_some_and(|spent| lock.is_implied_by(spent))
+                },
+            }
+        } else {
+            true
+        }
+    })
+}
+
+/// A multisig script's threshold and serialized keys, in script order
+type MultisigKeys = (usize, Vec<Vec<u8>>);
+
+/// The threshold and keys of a `multi` witness script or a `multi_a` leaf; `None` for
+/// any other script
+fn multisig_keys(script: &ScriptBuf) -> Option<MultisigKeys> {
+    let instructions: Vec<Instruction> =
+        script.instructions().collect::<Result<_, _>>().ok()?;
+    let number = |instruction: &Instruction| {
+        instruction
+            .script_num()
+            .and_then(|number| usize::try_from(number).ok())
+    };
+    let keys = |instructions: &[Instruction], length: usize| -> Vec<Vec<u8>> {
+        instructions
+            .iter()
+            .filter_map(Instruction::push_bytes)
+            .filter(|bytes| bytes.len() == length)
+            .map(|bytes| bytes.as_bytes().to_vec())
+            .collect()
+    };
+    match instructions.as_slice() {
+        // k <key>... n CHECKMULTISIG
+        [
+            threshold,
+            middle @ ..,
+            _,
+            Instruction::Op(opcodes::all::OP_CHECKMULTISIG),
+        ] => Some((number(threshold)?, keys(middle, 33))),
+        // <key> CHECKSIG <key> CHECKSIGADD ... k NUMEQUAL
+        [
+            start @ ..,
+            threshold,
+            Instruction::Op(opcodes::all::OP_NUMEQUAL),
+        ] => Some((number(threshold)?, keys(start, 32))),
+        _ => None,
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Positional parameter `index`, `None` when missing or null
+fn optional<T: DeserializeOwned>(params: &[Value], index: usize) -> RpcResult<Option<T>> {
+    match params.get(index) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => serde_json::from_value(value.clone())
+            .map(Some)
+            .map_err(|e| RpcError::new(RPC_TYPE_ERROR, format!("parameter {index}: {e}"))),
+    }
+}
+
+fn required<T: DeserializeOwned>(params: &[Value], index: usize, name: &str) -> RpcResult<T> {
+    optional(params, index)?.ok_or_else(|| {
+        RpcError::new(
+            RPC_INVALID_PARAMETER,
+            format!("missing required parameter {name}"),
+        )
+    })
+}
+
+/// Amounts arrive as JSON numbers in BTC, e.g. `20.0`
+fn amount_param(params: &[Value], index: usize) -> RpcResult<Amount> {
+    let btc: f64 = required(params, index, "amount")?;
+    Amount::from_btc(btc)
+        .ok()
+        .filter(|amount| *amount > Amount::ZERO)
+        .ok_or_else(|| RpcError::new(RPC_TYPE_ERROR, "Invalid amount for send"))
+}
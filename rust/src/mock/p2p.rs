@@ -0,0 +1,147 @@
+//! Peer connections between mock nodes: `addnode` and block/transaction relay
+//!
+//! Mock nodes don't speak the P2P protocol. Instead every [`MockNode`](super::MockNode)
+//! in the process is registered under its address, and `addnode <address>` links two
+//! of them here. After each RPC call the node that handled it [`relay`]s: every linked
+//! pair in its part of the network swaps what the other is missing (blocks first,
+//! then mempool transactions) until nothing changes any more. News spreads hop by hop
+//! like over real connections, only without the delays, and always after the RPC
+//! response has gone out, so a caller still has to wait for it like on a real network.
+//!
+//! Locking order: the registry is never held while a node is locked, and relay only
+//! ever locks one node at a time, so nodes calling into the registry can't deadlock.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+
+use super::lock;
+use super::node::Node;
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    nodes: BTreeMap::new(),
+    links: BTreeSet::new(),
+});
+
+struct Registry {
+    nodes: BTreeMap<SocketAddr, Weak<Mutex<Node>>>,
+    /// Connections as `(from, to)`: `from` ran `addnode to`
+    links: BTreeSet<(SocketAddr, SocketAddr)>,
+}
+
+fn registry() -> MutexGuard<'static, Registry> {
+    REGISTRY.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub(super) fn register(addr: SocketAddr, node: &Arc<Mutex<Node>>) {
+    registry().nodes.insert(addr, Arc::downgrade(node));
+}
+
+/// Forget a node that shut down, along with all of its connections
+pub(super) fn unregister(addr: SocketAddr) {
+    let mut registry = registry();
+    registry.nodes.remove(&addr);
+    registry
+        .links
+        .retain(|&(from, to)| from != addr && to != addr);
+}
+
+/// Link `from` to the node listening on `to`, if there is one (and it isn't `from`)
+pub(super) fn connect(from: SocketAddr, to: &str) {
+    let Ok(to) = to.parse() else { return };
+    let mut registry = registry();
+    if to != from && registry.nodes.contains_key(&to) {
+        registry.links.insert((from, to));
+    }
+}
+
+/// Whether `from` has an outbound connection to `to`
+pub(super) fn is_connected(from: SocketAddr, to: &str) -> bool {
+    to.parse()
+        .is_ok_and(|to| registry().links.contains(&(from, to)))
+}
+
+pub(super) fn disconnect(from: SocketAddr, to: &str) {
+    if let Ok(to) = to.parse() {
+        registry().links.remove(&(from, to));
+    }
+}
+
+/// Drop the connection between `addr` and `peer`, whichever of them made it; `false`
+/// if they weren't connected
+pub(super) fn disconnect_peer(addr: SocketAddr, peer: &str) -> bool {
+    let Ok(peer) = peer.parse() else {
+        return false;
+    };
+    let mut registry = registry();
+    let outbound = registry.links.remove(&(addr, peer));
+    let inbound = registry.links.remove(&(peer, addr));
+    outbound || inbound
+}
+
+/// Everyone `addr` is connected to, with whether they connected to it (inbound)
+pub(super) fn peers(addr: SocketAddr) -> Vec<(SocketAddr, bool)> {
+    registry()
+        .links
+        .iter()
+        .filter_map(|&(from, to)| match (from == addr, to == addr) {
+            (true, _) => Some((to, false)),
+            (_, true) => Some((from, true)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Bring every node reachable from `origin` up to date with every other one
+pub(super) fn relay(origin: SocketAddr) {
+    let links = reachable_links(origin);
+    loop {
+        let mut changed = false;
+        for &(a, b) in &links {
+            let (Some(a), Some(b)) = (find(a), find(b)) else {
+                continue;
+            };
+            // Either side may be the one that's ahead
+            changed |= exchange(&a, &b);
+            changed |= exchange(&b, &a);
+        }
+        if !changed {
+            break;
+        }
+    }
+}
+
+/// Send `to` whatever `from` has that it doesn't; `true` if that changed `to`
+fn exchange(from: &Mutex<Node>, to: &Mutex<Node>) -> bool {
+    let inventory = lock(to).inventory();
+    let relay = lock(from).relay_to(&inventory);
+    !relay.is_empty() && lock(to).receive(relay)
+}
+
+fn find(addr: SocketAddr) -> Option<Arc<Mutex<Node>>> {
+    registry().nodes.get(&addr)?.upgrade()
+}
+
+/// Links of the connected part of the network `origin` belongs to
+fn reachable_links(origin: SocketAddr) -> Vec<(SocketAddr, SocketAddr)> {
+    let registry = registry();
+    let mut reached = BTreeSet::from([origin]);
+    let mut links = Vec::new();
+    let mut queue = vec![origin];
+    while let Some(addr) = queue.pop() {
+        for &(from, to) in &registry.links {
+            if from != addr && to != addr {
+                continue;
+            }
+            if !links.contains(&(from, to)) {
+                links.push((from, to));
+            }
+            for next in [from, to] {
+                if reached.insert(next) {
+                    queue.push(next);
+                }
+            }
+        }
+    }
+    links
+}
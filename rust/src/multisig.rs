@@ -0,0 +1,460 @@
+//! M-of-N multisig wallets shared between participants
+//!
+//! Coins locked by a multisig script can only be spent with signatures from M of its N
+//! keys. With descriptor wallets none of the cosigners has to give up control of a key:
+//! each one only shares an extended public key (xpub, from `listdescriptors`), and out
+//! of those we write a descriptor for every address the shared wallet will ever have:
+//! - P2WSH: `wsh(sortedmulti(M,KEY1/0/*,...))`, an OP_CHECKMULTISIG script that every
+//!   spend reveals in full, keys and threshold included
+//! - Taproot: `tr(NUMS,sortedmulti_a(M,KEY1/0/*,...))`, the same keys in a script leaf
+//!   (OP_CHECKSIGADD). The internal key is BIP341's unspendable "nothing up my sleeve"
+//!   point, so the key path is off and every spend goes through the leaf
+//!
+//! `sortedmulti` orders the keys per address (BIP67), so the order the cosigners are
+//! listed in doesn't matter. The descriptors (a receive and a change one) are imported
+//! into a watch-only wallet: it hands out the addresses and keeps track of the coins,
+//! but can't sign anything.
+//!
+//! Spending is a round of PSBTs (see psbt.rs): the watch-only wallet creates one,
+//! filling in the key origin (`[fingerprint/path]`) of every key in its inputs; each
+//! chosen cosigner signs its own copy, finding its keys by those origins;
+//! `combinepsbt` merges the signatures, and the finalizer builds the witness once M
+//! of them are there.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoincore_rpc::bitcoin::bip32::Fingerprint;
+use bitcoincore_rpc::bitcoin::psbt::Psbt;
+use bitcoincore_rpc::bitcoin::{Address, Amount, Txid};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::amount::{self, format_btc};
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::psbt::{self, PsbtInspection};
+use crate::wallets::{self, AddressType, WalletKind, WalletSpec};
+
+/// BIP341's provably unspendable internal key: the hash of the generator point, used
+/// as an x coordinate, so nobody knows its private key
+pub const NUMS_KEY: &str = "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+/// OP_CHECKMULTISIG takes at most this many keys
+const MAX_P2WSH_KEYS: usize = 20;
+
+/// Which script the multisig coins are locked with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MultisigKind {
+    /// `wsh(sortedmulti(...))`, built from the cosigners' BIP84 (`wpkh`) xpubs
+    P2wsh,
+    /// `tr(NUMS,sortedmulti_a(...))`, built from the cosigners' BIP86 (`tr`) xpubs
+    Taproot,
+}
+
+impl MultisigKind {
+    pub const ALL: [Self; 2] = [Self::P2wsh, Self::Taproot];
+
+    /// How the cosigner descriptor whose xpub is shared starts
+    fn cosigner_descriptor(self) -> &'static str {
+        match self {
+            Self::P2wsh => "wpkh(",
+            Self::Taproot => "tr(",
+        }
+    }
+    /// The addresses the multisig wallet hands out: Core only gives bech32m ones for
+    /// a `tr()` descriptor, so they have to be asked for by type
+    pub fn address_type(self) -> AddressType {
+        match self {
+            Self::P2wsh => AddressType::Bech32,
+            Self::Taproot => AddressType::Bech32m,
+        }
+    }
+}
+
+impl fmt::Display for MultisigKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::P2wsh => "p2wsh",
+            Self::Taproot => "taproot",
+        })
+    }
+}
+
+impl FromStr for MultisigKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                Error::Config(format!(
+                    "unknown multisig kind {s:?} (expected p2wsh or taproot)"
+                ))
+            })
+    }
+}
+
+/// M-of-N: how many signatures it takes, out of how many cosigners
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Threshold {
+    pub required: usize,
+    pub total: usize,
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-of-{}", self.required, self.total)
+    }
+}
+
+/// `M-of-N`, e.g. `2-of-3`
+impl FromStr for Threshold {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Config(format!("{s:?} isn't a threshold like 2-of-3"));
+        let (required, total) = s.split_once("-of-").ok_or_else(invalid)?;
+        Self::new(
+            required.trim().parse().map_err(|_| invalid())?,
+            total.trim().parse().map_err(|_| invalid())?,
+        )
+    }
+}
+
+impl Threshold {
+    /// `required` out of `total`, which has to be possible: at least one signature,
+    /// and no more than there are cosigners
+    pub fn new(required: usize, total: usize) -> Result<Self> {
+        let threshold = Self { required, total };
+        if required == 0 || required > total {
+            return Err(Error::Config(format!(
+                "a {threshold} multisig needs between 1 and {total} signatures"
+            )));
+        }
+        Ok(threshold)
+    }
+}
+
+/// A participant's share in a multisig wallet: its xpub, and where that came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Cosigner {
+    pub wallet: String,
+    /// The fingerprint of the wallet's master key, which signers recognize their keys by
+    pub fingerprint: Fingerprint,
+    /// `[fingerprint/origin/path]xpub`, without the chain and index steps
+    pub key: String,
+}
+
+/// A multisig wallet, as created by [`create`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MultisigWallet {
+    pub name: String,
+    pub kind: MultisigKind,
+    pub threshold: Threshold,
+    pub cosigners: Vec<Cosigner>,
+    /// With checksums, as imported
+    pub receive_descriptor: String,
+    pub change_descriptor: String,
+}
+
+impl fmt::Display for MultisigWallet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self
+            .cosigners
+            .iter()
+            .map(|cosigner| cosigner.wallet.as_str())
+            .collect();
+        write!(
+            f,
+            "{} {} multisig wallet {} shared by {}\n  receive: {}\n  change:  {}",
+            self.threshold,
+            self.kind,
+            self.name,
+            names.join(", "),
+            self.receive_descriptor,
+            self.change_descriptor
+        )
+    }
+}
+
+/// A payment out of a multisig wallet, made through [`pay`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MultisigSpend {
+    pub txid: Txid,
+    pub wallet: String,
+    pub kind: MultisigKind,
+    pub threshold: Threshold,
+    /// Every cosigner, in the order the wallet lists them
+    pub cosigners: Vec<String>,
+    /// The cosigners whose signatures the combined PSBT held
+    pub signers: Vec<String>,
+    #[serde(with = "amount::exact")]
+    pub fee: Amount,
+}
+
+impl MultisigSpend {
+    /// The cosigners who didn't sign
+    pub fn non_signers(&self) -> Vec<&str> {
+        self.cosigners
+            .iter()
+            .filter(|cosigner| !self.signers.contains(cosigner))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+impl fmt::Display for MultisigSpend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} payment {} from {}: signed by {}",
+            self.threshold,
+            self.kind,
+            self.txid,
+            self.wallet,
+            self.signers.join(", ")
+        )?;
+        let non_signers = self.non_signers();
+        if !non_signers.is_empty() {
+            write!(f, " (not {})", non_signers.join(", "))?;
+        }
+        write!(f, "; fee {}", format_btc(self.fee))
+    }
+}
+
+/// The parts of `listdescriptors` we read
+#[derive(Debug, Deserialize)]
+struct DescriptorList {
+    descriptors: Vec<DescriptorEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DescriptorEntry {
+    desc: String,
+    active: bool,
+    #[serde(default)]
+    internal: bool,
+}
+
+/// What `wallet` (named `name`) shares to take part in a `kind` multisig: the xpub of
+/// its active receive descriptor of the matching type
+pub fn cosigner(name: &str, wallet: &Client, kind: MultisigKind) -> Result<Cosigner> {
+    let list: DescriptorList = wallet.call("listdescriptors", &[])?;
+    let prefix = kind.cosigner_descriptor();
+    let key = list
+        .descriptors
+        .iter()
+        .filter(|entry| entry.active && !entry.internal)
+        .find_map(|entry| {
+            // e.g. wpkh([d34db33f/84h/1h/0h]tpub.../0/*)#checksum
+            let (descriptor, _) = entry.desc.split_once('#').unwrap_or((&entry.desc, ""));
+            descriptor
+                .strip_prefix(prefix)?
+                .strip_suffix(')')?
+                .strip_suffix("/0/*")
+                .map(str::to_owned)
+        })
+        .ok_or_else(|| {
+            Error::State(format!(
+                "wallet {name} has no active {prefix}...) descriptor to share a key from (is it watch-only?)"
+            ))
+        })?;
+    let fingerprint = key
+        .strip_prefix('[')
+        .and_then(|origin| origin.get(..8))
+        .and_then(|fingerprint| fingerprint.parse().ok())
+        .ok_or_else(|| Error::Invariant(format!("{name}'s key {key} has no key origin")))?;
+    Ok(Cosigner {
+        wallet: name.to_owned(),
+        fingerprint,
+        key,
+    })
+}
+
+/// The descriptor (without checksum) for the receive (`change: false`) or change
+/// chain of an M-of-N wallet over `cosigners`
+pub fn descriptor(
+    kind: MultisigKind,
+    required: usize,
+    cosigners: &[Cosigner],
+    change: bool,
+) -> String {
+    let chain = u8::from(change);
+    let keys: Vec<String> = cosigners
+        .iter()
+        .map(|cosigner| format!("{}/{chain}/*", cosigner.key))
+        .collect();
+    let keys = keys.join(",");
+    match kind {
+        MultisigKind::P2wsh => format!("wsh(sortedmulti({required},{keys}))"),
+        MultisigKind::Taproot => format!("tr({NUMS_KEY},sortedmulti_a({required},{keys}))"),
+    }
+}
+
+/// Add the checksum the node computes for `descriptor`
+fn with_checksum(rpc: &Client, descriptor: &str) -> Result<String> {
+    let info: Value = rpc.call("getdescriptorinfo", &[json!(descriptor)])?;
+    let checksum = info["checksum"]
+        .as_str()
+        .ok_or_else(|| Error::Invariant("getdescriptorinfo returned no checksum".to_owned()))?;
+    Ok(format!("{descriptor}#{checksum}"))
+}
+
+/// Set up a `required`-of-N `kind` multisig wallet called `name` over `cosigners`
+///
+/// The wallet is watch-only and blank; its only descriptors are the two imported here,
+/// both active, so `getnewaddress` and change come from them.
+pub fn create(
+    connection: &Connection,
+    name: &str,
+    kind: MultisigKind,
+    required: usize,
+    cosigners: &[Cosigner],
+) -> Result<MultisigWallet> {
+    let threshold = Threshold::new(required, cosigners.len())?;
+    if kind == MultisigKind::P2wsh && cosigners.len() > MAX_P2WSH_KEYS {
+        return Err(Error::Config(format!(
+            "P2WSH multisig takes at most {MAX_P2WSH_KEYS} keys, not {}",
+            cosigners.len()
+        )));
+    }
+
+    let rpc = connection.client(None)?;
+    let receive_descriptor =
+        with_checksum(&rpc, &descriptor(kind, required, cosigners, false))?;
+    let change_descriptor = with_checksum(&rpc, &descriptor(kind, required, cosigners, true))?;
+
+    wallets::provision(
+        connection,
+        &[WalletSpec {
+            name: name.to_owned(),
+            kind: Some(WalletKind::Descriptor),
+            blank: true,
+            disable_private_keys: true,
+            ..WalletSpec::default()
+        }],
+    )?;
+    let requests: Vec<Value> = [(&receive_descriptor, false), (&change_descriptor, true)]
+        .into_iter()
+        .map(|(descriptor, internal)| {
+            json!({
+                "desc": descriptor,
+                "active": true,
+                "internal": internal,
+                "timestamp": "now",
+            })
+        })
+        .collect();
+    let results: Vec<Value> = connection
+        .client(Some(name))?
+        .call("importdescriptors", &[json!(requests)])?;
+    if let Some(failed) = results.iter().find(|result| result["success"] != true) {
+        return Err(Error::State(format!(
+            "importing the multisig descriptors into {name} failed: {}",
+            failed["error"]["message"]
+        )));
+    }
+
+    Ok(MultisigWallet {
+        name: name.to_owned(),
+        kind,
+        threshold,
+        cosigners: cosigners.to_vec(),
+        receive_descriptor,
+        change_descriptor,
+    })
+}
+
+/// The cosigners with a signature in `psbt`, recognized by the key origins of the
+/// signing keys
+pub fn signers(psbt: &Psbt, cosigners: &[Cosigner]) -> Vec<String> {
+    let mut fingerprints = Vec::new();
+    for input in &psbt.inputs {
+        for public in input.partial_sigs.keys() {
+            if let Some((fingerprint, _)) = input.bip32_derivation.get(&public.inner) {
+                fingerprints.push(*fingerprint);
+            }
+        }
+        for (public, _) in input.tap_script_sigs.keys() {
+            if let Some((_, (fingerprint, _))) = input.tap_key_origins.get(public) {
+                fingerprints.push(*fingerprint);
+            }
+        }
+    }
+    cosigners
+        .iter()
+        .filter(|cosigner| fingerprints.contains(&cosigner.fingerprint))
+        .map(|cosigner| cosigner.wallet.clone())
+        .collect()
+}
+
+/// Pay `amount` to `recipient` out of the multisig `wallet` (whose RPC client is
+/// `rpc`), signed by `signers` (cosigner name and wallet client), and broadcast it
+///
+/// Each signer gets its own copy of the PSBT, as if it were sent to every cosigner at
+/// once; their signatures are combined afterwards. Fewer signers than the threshold is
+/// an error, before anything is created.
+pub fn pay(
+    wallet: &MultisigWallet,
+    rpc: &Client,
+    signers: &[(&str, &Client)],
+    recipient: &Address,
+    amount: Amount,
+) -> Result<MultisigSpend> {
+    if let Some((stranger, _)) = signers
+        .iter()
+        .find(|(name, _)| !wallet.cosigners.iter().any(|c| c.wallet == *name))
+    {
+        return Err(Error::Config(format!(
+            "{stranger} isn't a cosigner of {}",
+            wallet.name
+        )));
+    }
+    if signers.len() < wallet.threshold.required {
+        return Err(Error::Config(format!(
+            "{} needs {} signers, got {}",
+            wallet.threshold,
+            wallet.threshold.required,
+            signers.len()
+        )));
+    }
+
+    let created = psbt::create(rpc, recipient, amount)?;
+    let copies = signers
+        .iter()
+        .map(|(_, signer)| psbt::process(signer, &created, true))
+        .collect::<Result<Vec<Psbt>>>()?;
+    let combined = psbt::combine(rpc, &copies)?;
+    let signed_by = self::signers(&combined, &wallet.cosigners);
+
+    let (finalized, tx) = psbt::finalize(rpc, &combined)?;
+    let tx = tx.ok_or_else(|| {
+        Error::Invariant(format!(
+            "{} signatures from {} weren't enough to finalize: {}",
+            wallet.threshold,
+            signed_by.join(", "),
+            PsbtInspection::of(&finalized)
+        ))
+    })?;
+    let fee = PsbtInspection::of(&created).fee.ok_or_else(|| {
+        Error::Invariant("the multisig PSBT lacks its spent outputs".to_owned())
+    })?;
+    let txid = rpc.send_raw_transaction(&tx)?;
+    Ok(MultisigSpend {
+        txid,
+        wallet: wallet.name.clone(),
+        kind: wallet.kind,
+        threshold: wallet.threshold,
+        cosigners: wallet
+            .cosigners
+            .iter()
+            .map(|cosigner| cosigner.wallet.clone())
+            .collect(),
+        signers: signed_by,
+        fee,
+    })
+}
@@ -0,0 +1,12 @@
+//! Progress messages from long-running library calls
+//!
+//! Library functions never print. The ones that take a while (waiting for a node,
+//! running a scenario) report what they're doing through a [`Progress`] instead, and the
+//! caller decides where that goes: the binary prints it (to stderr while the report is
+//! on stdout), tests pass [`quiet`].
+
+/// Receives one progress message at a time, without a trailing newline
+pub type Progress = fn(&str);
+
+/// A [`Progress`] that drops everything
+pub fn quiet(_message: &str) {}
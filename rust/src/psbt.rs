@@ -0,0 +1,328 @@
+//! Paying through a PSBT, one role at a time
+//!
+//! `sendtoaddress` picks the coins, signs and broadcasts in one opaque call. A
+//! Partially Signed Bitcoin Transaction (BIP174) splits that up: it's the unsigned
+//! transaction plus, for every input, what a signer needs to know (the output it
+//! spends) and what signers have contributed so far. It's handed from role to role:
+//! - creator/updater: `walletcreatefundedpsbt` selects coins, adds change and fills in
+//!   the outputs being spent - nothing is signed yet,
+//! - signer: `walletprocesspsbt` signs whichever inputs the wallet holds keys for and
+//!   leaves the rest alone, so several wallets (or a hardware wallet) can take turns,
+//! - combiner: `combinepsbt` merges what several signers added to their own copies
+//!   (see multisig.rs),
+//! - finalizer: `finalizepsbt` turns the signatures into each input's final
+//!   scriptSig/witness, after which the network transaction can be extracted,
+//! - and `sendrawtransaction` broadcasts it like any other.
+//!
+//! Between roles a PSBT travels as base64 (what the RPCs take) or as raw bytes (BIP174's
+//! `.psbt` file format, what hardware wallet tools and Core's GUI read and write).
+//! [`write`] and [`read`] handle both, so any stage can be saved, inspected and picked
+//! up again by someone else.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bitcoincore_rpc::bitcoin::psbt::Psbt;
+use bitcoincore_rpc::bitcoin::{Address, Amount, Transaction, Txid};
+use bitcoincore_rpc::json::{WalletCreateFundedPsbtOptions, WalletProcessPsbtResult};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::amount::{self, format_btc};
+use crate::error::{Error, Result};
+
+/// BIP174: every serialized PSBT starts with these bytes ("psbt" and a separator)
+const MAGIC: &[u8] = b"psbt\xff";
+
+/// Which role last worked on a PSBT
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PsbtStage {
+    /// Funded by the sender's wallet, nothing signed
+    Created,
+    /// Passed through the recipient's wallet, which holds none of the keys
+    Reviewed,
+    /// Signed by the sender's wallet
+    Signed,
+    /// Signatures turned into final scriptSigs/witnesses
+    Finalized,
+}
+
+impl PsbtStage {
+    /// The file name (without extension) a stage is exported under; numbered so a
+    /// directory listing shows them in order
+    pub fn file_stem(self) -> &'static str {
+        match self {
+            Self::Created => "1-created",
+            Self::Reviewed => "2-reviewed",
+            Self::Signed => "3-signed",
+            Self::Finalized => "4-finalized",
+        }
+    }
+}
+
+impl fmt::Display for PsbtStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Created => "created by the sender",
+            Self::Reviewed => "reviewed by the recipient",
+            Self::Signed => "signed by the sender",
+            Self::Finalized => "finalized",
+        })
+    }
+}
+
+/// What a PSBT holds so far, read from the PSBT itself rather than from any wallet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PsbtInspection {
+    /// The txid the transaction will have: signatures don't change it
+    pub unsigned_txid: Txid,
+    pub inputs: usize,
+    pub outputs: usize,
+    /// Inputs with at least one signature that hasn't been finalized yet
+    pub signed_inputs: usize,
+    /// Inputs with a final scriptSig or witness
+    pub finalized_inputs: usize,
+    /// Inputs minus outputs; unknown unless every input says which output it spends
+    #[serde(with = "amount::exact_option")]
+    pub fee: Option<Amount>,
+}
+
+impl PsbtInspection {
+    pub fn of(psbt: &Psbt) -> Self {
+        let signed = psbt.inputs.iter().filter(|input| {
+            !input.partial_sigs.is_empty()
+                || input.tap_key_sig.is_some()
+                || !input.tap_script_sigs.is_empty()
+        });
+        let finalized = psbt.inputs.iter().filter(|input| {
+            input.final_script_sig.is_some() || input.final_script_witness.is_some()
+        });
+        Self {
+            unsigned_txid: psbt.unsigned_tx.txid(),
+            inputs: psbt.inputs.len(),
+            outputs: psbt.outputs.len(),
+            signed_inputs: signed.count(),
+            finalized_inputs: finalized.count(),
+            fee: psbt.fee().ok(),
+        }
+    }
+
+    /// Every input is finalized: the transaction can be extracted and broadcast
+    pub fn is_complete(&self) -> bool {
+        self.finalized_inputs == self.inputs
+    }
+}
+
+impl fmt::Display for PsbtInspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} input(s), {} signed, {} finalized; {} output(s); fee {}",
+            self.unsigned_txid,
+            self.inputs,
+            self.signed_inputs,
+            self.finalized_inputs,
+            self.outputs,
+            self.fee.map_or_else(|| "unknown".to_owned(), format_btc)
+        )
+    }
+}
+
+/// One stage of a PSBT payment, and where it was saved (if anywhere)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PsbtStep {
+    pub stage: PsbtStage,
+    pub base64: String,
+    pub inspection: PsbtInspection,
+    /// The binary and base64 exports of this stage
+    pub files: Vec<PathBuf>,
+}
+
+/// A payment made through [`pay`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PsbtPayment {
+    pub txid: Txid,
+    pub steps: Vec<PsbtStep>,
+}
+
+impl fmt::Display for PsbtPayment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PSBT payment {}", self.txid)?;
+        for step in &self.steps {
+            write!(f, "\n  {}: {}", step.stage, step.inspection)?;
+            for file in &step.files {
+                write!(f, "\n    saved to {}", file.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decode a base64 PSBT
+pub fn from_base64(text: &str) -> Result<Psbt> {
+    let bytes = base64::decode(text.trim())
+        .map_err(|e| Error::Config(format!("PSBT isn't valid base64: {e}")))?;
+    Psbt::deserialize(&bytes).map_err(|e| Error::Config(format!("invalid PSBT: {e}")))
+}
+
+/// Encode a PSBT as base64, the form the RPCs take
+pub fn to_base64(psbt: &Psbt) -> String {
+    base64::encode(psbt.serialize())
+}
+
+/// Load a PSBT from a file holding either its raw bytes or base64 text
+pub fn read(path: &Path) -> Result<Psbt> {
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(MAGIC) {
+        Psbt::deserialize(&bytes)
+            .map_err(|e| Error::Config(format!("{} isn't a valid PSBT: {e}", path.display())))
+    } else {
+        let text = String::from_utf8(bytes).map_err(|_| {
+            Error::Config(format!(
+                "{} is neither a binary nor a base64 PSBT",
+                path.display()
+            ))
+        })?;
+        from_base64(&text)
+    }
+}
+
+/// Save a PSBT: raw bytes if `path` ends in `.psbt`, base64 text otherwise
+pub fn write(path: &Path, psbt: &Psbt) -> Result<()> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "psbt")
+    {
+        fs::write(path, psbt.serialize())?;
+    } else {
+        fs::write(path, to_base64(psbt) + "\n")?;
+    }
+    Ok(())
+}
+
+/// Save `stage` of a PSBT to `dir` in both forms: `<stem>.psbt` and `<stem>.base64`
+pub fn export(dir: &Path, stage: PsbtStage, psbt: &Psbt) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let mut files = Vec::new();
+    for extension in ["psbt", "base64"] {
+        let path = dir.join(format!("{}.{extension}", stage.file_stem()));
+        write(&path, psbt)?;
+        files.push(path);
+    }
+    Ok(files)
+}
+
+/// Creator and updater: have the sender's `wallet` fund a payment of `amount` to
+/// `recipient`, without signing anything
+///
+/// The wallet picks the coins and adds change, just like `sendtoaddress`. The
+/// payment signals RBF, so it can still be replaced (see replacement.rs).
+pub fn create(wallet: &Client, recipient: &Address, amount: Amount) -> Result<Psbt> {
+    let outputs = [(recipient.to_string(), amount)].into();
+    let options = WalletCreateFundedPsbtOptions {
+        replaceable: Some(true),
+        ..WalletCreateFundedPsbtOptions::default()
+    };
+    let funded = wallet.wallet_create_funded_psbt(&[], &outputs, None, Some(options), None)?;
+    from_base64(&funded.psbt)
+}
+
+/// Signer: have `wallet` sign what it can of `psbt`, leaving finalizing to
+/// [`finalize`]
+///
+/// Inputs the wallet has no keys for are left as they are; that's not an error, another
+/// signer may still add them. With `sign: false` the wallet only fills in what it knows
+/// about the inputs and outputs (an updater).
+pub fn process(wallet: &Client, psbt: &Psbt, sign: bool) -> Result<Psbt> {
+    // wallet_process_psbt() can't turn off finalizing (the fifth parameter), and a
+    // wallet that finalizes its own signatures leaves nothing for the finalizer to do
+    let processed: WalletProcessPsbtResult = wallet.call(
+        "walletprocesspsbt",
+        &[
+            json!(to_base64(psbt)),
+            json!(sign),
+            json!("ALL"),
+            json!(true),
+            json!(false),
+        ],
+    )?;
+    from_base64(&processed.psbt)
+}
+
+/// Combiner: merge copies of the same PSBT that different signers worked on
+pub fn combine(rpc: &Client, psbts: &[Psbt]) -> Result<Psbt> {
+    let encoded: Vec<String> = psbts.iter().map(to_base64).collect();
+    let combined: String = rpc.call("combinepsbt", &[json!(encoded)])?;
+    from_base64(&combined)
+}
+
+/// Finalizer and extractor: turn the signatures of `psbt` into final scriptSigs and
+/// witnesses, and extract the transaction once every input has them
+///
+/// Needs no wallet: everything it takes is in the PSBT. The transaction is `None`
+/// while any input is still missing signatures.
+pub fn finalize(rpc: &Client, psbt: &Psbt) -> Result<(Psbt, Option<Transaction>)> {
+    // extract=false: keep the PSBT (for exporting) rather than only the transaction
+    let finalized = rpc.finalize_psbt(&to_base64(psbt), Some(false))?;
+    let psbt = from_base64(finalized.psbt.as_deref().ok_or_else(|| {
+        Error::Invariant("finalizepsbt returned no PSBT with extract=false".to_owned())
+    })?)?;
+    if !finalized.complete {
+        return Ok((psbt, None));
+    }
+    let tx = psbt
+        .clone()
+        .extract_tx()
+        .map_err(|e| Error::Invariant(format!("finalized PSBT doesn't extract: {e}")))?;
+    Ok((psbt, Some(tx)))
+}
+
+/// Pay `amount` from `sender` to `recipient` through a PSBT and broadcast it
+///
+/// The sender's wallet creates the PSBT, `recipient_wallet` (whose address is being
+/// paid) looks it over, the sender signs, and the node finalizes it. With `export_dir`
+/// every stage is saved there (see [`export`]).
+pub fn pay(
+    sender: &Client,
+    recipient_wallet: &Client,
+    recipient: &Address,
+    amount: Amount,
+    export_dir: Option<&Path>,
+) -> Result<PsbtPayment> {
+    let mut steps = Vec::new();
+    let mut record = |stage: PsbtStage, psbt: &Psbt| -> Result<()> {
+        let files = match export_dir {
+            Some(dir) => export(dir, stage, psbt)?,
+            None => Vec::new(),
+        };
+        steps.push(PsbtStep {
+            stage,
+            base64: to_base64(psbt),
+            inspection: PsbtInspection::of(psbt),
+            files,
+        });
+        Ok(())
+    };
+
+    let created = create(sender, recipient, amount)?;
+    record(PsbtStage::Created, &created)?;
+    // The recipient's wallet can sign none of it: the coins aren't its own
+    let reviewed = process(recipient_wallet, &created, true)?;
+    record(PsbtStage::Reviewed, &reviewed)?;
+    let signed = process(sender, &reviewed, true)?;
+    record(PsbtStage::Signed, &signed)?;
+    let (finalized, tx) = finalize(sender, &signed)?;
+    record(PsbtStage::Finalized, &finalized)?;
+
+    let tx = tx.ok_or_else(|| {
+        Error::Invariant(format!(
+            "the sender's wallet didn't sign every input: {}",
+            PsbtInspection::of(&finalized)
+        ))
+    })?;
+    let txid = sender.send_raw_transaction(&tx)?;
+    Ok(PsbtPayment { txid, steps })
+}
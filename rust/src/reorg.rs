@@ -0,0 +1,355 @@
+//! Chain reorganizations: what becomes of "confirmed" transactions when the chain changes
+//!
+//! A confirmation is only as final as the block it's in. When a node learns of a
+//! branch with more work that doesn't contain that block, it *reorganizes*: the blocks
+//! back to the fork point are disconnected, the other branch is connected, and every
+//! transaction of the disconnected blocks meets one of the fates in [`TxFate`].
+//!
+//! Two ways to make that happen on regtest:
+//! - [`fork`]: on a single node, `invalidateblock` the last `depth` blocks, mine a
+//!   longer competing branch with `generateblock`, then `reconsiderblock` the old one.
+//!   The old branch is valid again but now has less work, so the node stays where it
+//!   is: exactly as if a competing miner had won the race. [`conflicting_payment`]
+//!   builds a double-spend for the new branch to carry.
+//! - [`Cluster::partition`](crate::cluster::Cluster::partition): each side of a split
+//!   network mines its own branch, and once [`healed`](crate::cluster::Cluster::heal)
+//!   every node reorganizes onto the longer one.
+//!
+//! Either way, [`ChainMark::record`] the tip before and [`compare`] afterwards.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use bitcoincore_rpc::bitcoin::consensus::encode::serialize_hex;
+use bitcoincore_rpc::bitcoin::{
+    Address, Amount, Block, BlockHash, OutPoint, Transaction, Txid,
+};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::error::{Error, Result};
+use crate::transfer;
+
+/// The active tip at some moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ChainMark {
+    pub height: u64,
+    pub hash: BlockHash,
+}
+
+impl ChainMark {
+    pub fn record(rpc: &Client) -> Result<Self> {
+        let height = rpc.get_block_count()?;
+        // By height rather than getbestblockhash, so a block arriving in between can't
+        // make the two disagree
+        let hash = rpc.get_block_hash(height)?;
+        Ok(Self { height, hash })
+    }
+}
+
+/// What became of a transaction whose block was disconnected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "fate", rename_all = "snake_case")]
+pub enum TxFate {
+    /// The new branch confirms it too, at this height
+    Reconfirmed { height: u64 },
+    /// Back in the mempool, waiting to be mined again
+    InMempool,
+    /// Another transaction spends one of the same outputs, so this one can never
+    /// confirm: it was double-spent. `height` is where the replacement confirmed,
+    /// `None` if it's still in the mempool
+    DoubleSpent { by: Txid, height: Option<u64> },
+    /// Gone without a trace: the coinbase of a disconnected block (its reward only
+    /// ever existed in that block), or a transaction spending such an output or the
+    /// output of a double-spent transaction
+    Dropped,
+}
+
+impl fmt::Display for TxFate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reconfirmed { height } => write!(f, "re-confirmed at height {height}"),
+            Self::InMempool => write!(f, "back in the mempool"),
+            Self::DoubleSpent {
+                by,
+                height: Some(height),
+            } => write!(f, "double-spent by {by} (confirmed at height {height})"),
+            Self::DoubleSpent { by, height: None } => {
+                write!(f, "double-spent by {by} (in the mempool)")
+            },
+            Self::Dropped => write!(f, "dropped"),
+        }
+    }
+}
+
+/// One transaction of a disconnected block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DisconnectedTx {
+    pub txid: Txid,
+    /// Height of the block it was confirmed in before the reorg
+    pub block_height: u64,
+    pub coinbase: bool,
+    #[serde(flatten)]
+    pub fate: TxFate,
+}
+
+/// Everything a reorganization changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReorgReport {
+    pub old_tip: ChainMark,
+    pub new_tip: ChainMark,
+    /// The last block both branches share
+    pub fork_point: ChainMark,
+    /// Blocks that left the active chain, lowest first
+    pub disconnected_blocks: Vec<BlockHash>,
+    /// Blocks of the branch that replaced them, lowest first
+    pub connected_blocks: Vec<BlockHash>,
+    /// Every transaction of the disconnected blocks, in block order
+    pub transactions: Vec<DisconnectedTx>,
+}
+
+impl ReorgReport {
+    /// How many blocks were replaced; 0 if the old tip is still in the chain
+    pub fn depth(&self) -> usize {
+        self.disconnected_blocks.len()
+    }
+
+    pub fn fate(&self, txid: &Txid) -> Option<TxFate> {
+        self.transactions
+            .iter()
+            .find(|tx| tx.txid == *txid)
+            .map(|tx| tx.fate)
+    }
+}
+
+impl fmt::Display for ReorgReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.depth() == 0 {
+            return write!(
+                f,
+                "No reorganization: {} is still active",
+                self.old_tip.hash
+            );
+        }
+        writeln!(
+            f,
+            "Reorganization at height {}: {} block(s) disconnected, {} connected",
+            self.fork_point.height,
+            self.depth(),
+            self.connected_blocks.len()
+        )?;
+        writeln!(
+            f,
+            "  old tip {} ({})",
+            self.old_tip.height, self.old_tip.hash
+        )?;
+        write!(
+            f,
+            "  new tip {} ({})",
+            self.new_tip.height, self.new_tip.hash
+        )?;
+        for tx in &self.transactions {
+            let kind = if tx.coinbase { "coinbase" } else { "tx" };
+            write!(
+                f,
+                "\n  {kind} {} from block {}: {}",
+                tx.txid, tx.block_height, tx.fate
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the chain now with the tip recorded in `before`
+///
+/// Walks back from the old tip until it meets the active chain, then works out what
+/// happened to every transaction of the blocks on the way.
+pub fn compare(rpc: &Client, before: ChainMark) -> Result<ReorgReport> {
+    let after = ChainMark::record(rpc)?;
+
+    let mut fork_point = before;
+    let mut disconnected: Vec<(u64, Block)> = Vec::new();
+    while fork_point.height > after.height
+        || rpc.get_block_hash(fork_point.height)? != fork_point.hash
+    {
+        let block = rpc.get_block(&fork_point.hash)?;
+        fork_point = ChainMark {
+            height: fork_point.height - 1,
+            hash: block.header.prev_blockhash,
+        };
+        disconnected.push((fork_point.height + 1, block));
+    }
+    disconnected.reverse();
+
+    let mut connected: Vec<(u64, Block)> = Vec::new();
+    for height in fork_point.height + 1..=after.height {
+        let block = rpc.get_block(&rpc.get_block_hash(height)?)?;
+        connected.push((height, block));
+    }
+
+    let mut fates = Fates::new(rpc, &connected)?;
+    let mut transactions = Vec::new();
+    for (height, block) in &disconnected {
+        for tx in &block.txdata {
+            transactions.push(DisconnectedTx {
+                txid: tx.txid(),
+                block_height: *height,
+                coinbase: tx.is_coinbase(),
+                fate: fates.of(rpc, tx)?,
+            });
+        }
+    }
+
+    Ok(ReorgReport {
+        old_tip: before,
+        new_tip: after,
+        fork_point,
+        disconnected_blocks: disconnected
+            .iter()
+            .map(|(_, block)| block.block_hash())
+            .collect(),
+        connected_blocks: connected
+            .iter()
+            .map(|(_, block)| block.block_hash())
+            .collect(),
+        transactions,
+    })
+}
+
+/// Where the new branch and the mempool put each transaction and spent output
+struct Fates {
+    confirmed: HashMap<Txid, u64>,
+    /// Outputs the new branch spends, and by which transaction
+    spent_in_chain: HashMap<OutPoint, (Txid, u64)>,
+    mempool: HashSet<Txid>,
+    /// Outputs mempool transactions spend; only looked up if something needs it
+    spent_in_mempool: Option<HashMap<OutPoint, Txid>>,
+}
+
+impl Fates {
+    fn new(rpc: &Client, connected: &[(u64, Block)]) -> Result<Self> {
+        let mut confirmed = HashMap::new();
+        let mut spent_in_chain = HashMap::new();
+        for (height, block) in connected {
+            for tx in &block.txdata {
+                confirmed.insert(tx.txid(), *height);
+                for input in tx.input.iter().filter(|_| !tx.is_coinbase()) {
+                    spent_in_chain.insert(input.previous_output, (tx.txid(), *height));
+                }
+            }
+        }
+        Ok(Self {
+            confirmed,
+            spent_in_chain,
+            mempool: rpc.get_raw_mempool()?.into_iter().collect(),
+            spent_in_mempool: None,
+        })
+    }
+
+    fn of(&mut self, rpc: &Client, tx: &Transaction) -> Result<TxFate> {
+        let txid = tx.txid();
+        if let Some(&height) = self.confirmed.get(&txid) {
+            return Ok(TxFate::Reconfirmed { height });
+        }
+        if self.mempool.contains(&txid) {
+            return Ok(TxFate::InMempool);
+        }
+        if tx.is_coinbase() {
+            return Ok(TxFate::Dropped);
+        }
+
+        for input in &tx.input {
+            if let Some(&(by, height)) = self.spent_in_chain.get(&input.previous_output) {
+                return Ok(TxFate::DoubleSpent {
+                    by,
+                    height: Some(height),
+                });
+            }
+        }
+        let spent_in_mempool = match &self.spent_in_mempool {
+            Some(spent) => spent,
+            None => self
+                .spent_in_mempool
+                .insert(mempool_spends(rpc, &self.mempool)?),
+        };
+        for input in &tx.input {
+            if let Some(&by) = spent_in_mempool.get(&input.previous_output) {
+                return Ok(TxFate::DoubleSpent { by, height: None });
+            }
+        }
+        Ok(TxFate::Dropped)
+    }
+}
+
+fn mempool_spends(rpc: &Client, mempool: &HashSet<Txid>) -> Result<HashMap<OutPoint, Txid>> {
+    let mut spends = HashMap::new();
+    for txid in mempool {
+        for input in rpc.get_raw_transaction(txid, None)?.input {
+            spends.insert(input.previous_output, *txid);
+        }
+    }
+    Ok(spends)
+}
+
+/// Replace the last `depth` blocks of `rpc`'s node with a branch one block longer
+///
+/// The new branch's first block carries exactly `transactions` (say, a
+/// [`conflicting_payment`]); none of its blocks take anything from the mempool, so
+/// the old blocks' transactions that aren't conflicted stay unconfirmed. Its rewards
+/// go to `miner`: use an address that didn't mine the replaced blocks, or a block could
+/// come out identical to the one it replaces (same height, coinbase, transactions and,
+/// within the same second, timestamp).
+pub fn fork(
+    rpc: &Client,
+    miner: &Address,
+    depth: u64,
+    transactions: &[Transaction],
+) -> Result<ReorgReport> {
+    let before = ChainMark::record(rpc)?;
+    if depth == 0 || depth > before.height {
+        return Err(Error::Config(format!(
+            "can't replace {depth} block(s) of a chain at height {}",
+            before.height
+        )));
+    }
+
+    // Disconnects the block and everything after it; their transactions go back to
+    // the mempool
+    let first_replaced = rpc.get_block_hash(before.height - depth + 1)?;
+    rpc.invalidate_block(&first_replaced)?;
+
+    // One block more than was replaced, so the new branch has more work
+    let mut included: Vec<String> = transactions.iter().map(serialize_hex).collect();
+    for _ in 0..=depth {
+        generate_block(rpc, miner, &std::mem::take(&mut included))?;
+    }
+
+    // The old branch is valid again, but shorter, so it stays a side branch
+    rpc.reconsider_block(&first_replaced)?;
+    compare(rpc, before)
+}
+
+/// `generateblock`: mine exactly `transactions` (raw hex or mempool txids) on the tip
+fn generate_block(rpc: &Client, miner: &Address, transactions: &[String]) -> Result<BlockHash> {
+    #[derive(Deserialize)]
+    struct Generated {
+        hash: BlockHash,
+    }
+
+    let generated: Generated = rpc.call(
+        "generateblock",
+        &[json!(miner.to_string()), json!(transactions)],
+    )?;
+    Ok(generated.hash)
+}
+
+/// A transaction spending the same outputs as `txid`, paying all of it back to `to`
+///
+/// Signed by `wallet`, which must own every input of `txid` (it's the sender's
+/// wallet). It pays the same fee as the original and isn't broadcast: it's meant to be
+/// mined straight into a competing branch by [`fork`], the way a double-spending miner
+/// would. See [`transfer::respend`].
+pub fn conflicting_payment(wallet: &Client, txid: &Txid, to: &Address) -> Result<Transaction> {
+    transfer::respend(wallet, txid, to, Amount::ZERO)
+}
@@ -0,0 +1,202 @@
+//! Replace-by-fee: swapping an unconfirmed transaction for one that pays more
+//!
+//! Until a transaction is in a block, nothing stops its sender from signing another
+//! one that spends the same coins. A mempool can only hold one of the two, so nodes
+//! have to pick. Without further rules the first one seen wins; BIP125 lets the sender
+//! *opt in* to replacement instead, by giving an input a sequence number below
+//! 0xfffffffe. A signalling transaction may then be replaced by one that:
+//! - pays a higher fee rate than it,
+//! - pays at least as much fee as it and every transaction spending its outputs,
+//!   since those get evicted along with it,
+//! - and pays for its own relay on top (the incremental relay fee, 1 sat/vB).
+//!
+//! Two ways to do that, both in [`replace`]:
+//! - [`ReplaceMethod::BumpFee`]: the wallet's `bumpfee` re-signs the same payment with
+//!   less change, i.e. a higher fee. The recipient still gets paid, just sooner.
+//! - [`ReplaceMethod::Conflict`]: a different transaction spending the same inputs,
+//!   sending everything back to the sender. That's a double-spend, and why a payment
+//!   isn't worth much before it confirms.
+//!
+//! Either way exactly one of the two can ever confirm; [`Replacement::settle`] finds
+//! out which.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoincore_rpc::bitcoin::{Amount, BlockHash, Network, Txid};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::amount;
+use crate::error::{Error, Result};
+use crate::transfer;
+
+/// How to replace a payment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplaceMethod {
+    /// Same payment, higher fee (`bumpfee`)
+    BumpFee,
+    /// Same inputs, paying the sender instead (`sendrawtransaction`)
+    Conflict,
+}
+
+impl ReplaceMethod {
+    pub const ALL: [Self; 2] = [Self::BumpFee, Self::Conflict];
+}
+
+impl fmt::Display for ReplaceMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::BumpFee => "bump-fee",
+            Self::Conflict => "conflict",
+        })
+    }
+}
+
+impl FromStr for ReplaceMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|method| {
+                method.to_string().eq_ignore_ascii_case(s)
+                    || (s == "bumpfee" && *method == Self::BumpFee)
+            })
+            .ok_or_else(|| {
+                Error::Config(format!(
+                    "unknown replacement method {s:?} (expected bump-fee or conflict)"
+                ))
+            })
+    }
+}
+
+/// What a replacement did to the mempool, and which transaction won in the end
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Replacement {
+    pub method: ReplaceMethod,
+    pub original: Txid,
+    pub replacement: Txid,
+    #[serde(with = "amount::exact")]
+    pub original_fee: Amount,
+    #[serde(with = "amount::exact")]
+    pub replacement_fee: Amount,
+    /// The mempool right before the replacement was broadcast
+    pub mempool_before: Vec<Txid>,
+    /// ... and right after
+    pub mempool_after: Vec<Txid>,
+    /// What the replacement pushed out: the original and anything spending from it
+    pub evicted: Vec<Txid>,
+    /// Which of the two a block confirmed, once [`Replacement::settle`] saw one
+    pub confirmed: Option<Txid>,
+}
+
+impl Replacement {
+    /// Look for the original or the replacement in `block`
+    ///
+    /// Returns the one found there (also kept in `confirmed`), if either.
+    pub fn settle(&mut self, rpc: &Client, block: &BlockHash) -> Result<Option<Txid>> {
+        let block = rpc.get_block(block)?;
+        let confirmed = block
+            .txdata
+            .iter()
+            .map(|tx| tx.txid())
+            .find(|txid| *txid == self.original || *txid == self.replacement);
+        if confirmed.is_some() {
+            self.confirmed = confirmed;
+        }
+        Ok(confirmed)
+    }
+}
+
+impl fmt::Display for Replacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Replaced {} (fee {}) with {} (fee {}) by {}",
+            self.original,
+            self.original_fee,
+            self.replacement,
+            self.replacement_fee,
+            self.method
+        )?;
+        writeln!(
+            f,
+            "  mempool: {} transaction(s) before, {} after",
+            self.mempool_before.len(),
+            self.mempool_after.len()
+        )?;
+        for txid in &self.evicted {
+            writeln!(f, "  evicted {txid}")?;
+        }
+        match self.confirmed {
+            Some(txid) if txid == self.replacement => {
+                write!(f, "  confirmed: {txid}, the replacement")
+            },
+            Some(txid) => write!(f, "  confirmed: {txid}, the original"),
+            None => write!(f, "  neither is confirmed yet"),
+        }
+    }
+}
+
+/// Replace `txid`, a replaceable payment from `wallet` still in the mempool
+///
+/// For [`ReplaceMethod::Conflict`] the coins go to a fresh address of `wallet`, and the
+/// fee is the original's plus whatever it and its descendants paid: enough to evict
+/// all of them. That address must be on `network`.
+pub fn replace(
+    wallet: &Client,
+    network: Network,
+    txid: &Txid,
+    method: ReplaceMethod,
+) -> Result<Replacement> {
+    let entry = wallet.get_mempool_entry(txid)?;
+    if !entry.bip125_replaceable {
+        return Err(Error::State(format!(
+            "{txid} doesn't signal BIP125 replaceability"
+        )));
+    }
+    let mempool_before = wallet.get_raw_mempool()?;
+
+    let replacement = match method {
+        ReplaceMethod::BumpFee => bump_fee(wallet, txid)?,
+        ReplaceMethod::Conflict => {
+            let refund = wallet
+                .get_new_address(Some("Refund"), None)?
+                .require_network(network)?;
+            let conflict = transfer::respend(wallet, txid, &refund, entry.fees.descendant)?;
+            wallet.send_raw_transaction(&conflict)?
+        },
+    };
+
+    let mempool_after = wallet.get_raw_mempool()?;
+    let evicted = mempool_before
+        .iter()
+        .filter(|txid| !mempool_after.contains(txid))
+        .copied()
+        .collect();
+    Ok(Replacement {
+        method,
+        original: *txid,
+        replacement,
+        original_fee: entry.fees.base,
+        replacement_fee: wallet.get_mempool_entry(&replacement)?.fees.base,
+        mempool_before,
+        mempool_after,
+        evicted,
+        confirmed: None,
+    })
+}
+
+/// `bumpfee` with the wallet's defaults; returns the replacement's txid
+fn bump_fee(wallet: &Client, txid: &Txid) -> Result<Txid> {
+    #[derive(Deserialize)]
+    struct Bumped {
+        txid: Txid,
+    }
+
+    let bumped: Bumped = wallet.call("bumpfee", &[json!(txid)])?;
+    Ok(bumped.txid)
+}
@@ -0,0 +1,713 @@
+//! Report generation
+//!
+//! Writes a structured record of everything that happened, in one of several formats:
+//! - text: one piece of information per line, the legacy format the Jest grader parses
+//! - json: named fields, exact amounts, every input and output (for scripts and tools)
+//! - csv: one row per input, output and fee (for spreadsheets and reconciliation)
+//! - markdown: tables for pasting into issues and docs
+//!
+//! The comparison with the node's fee estimate, the stages of a PSBT payment
+//! (`--psbt`), the cosigners of a multisig payment (`--multisig`), the script tree of
+//! a Taproot payment (`--taproot-leaf`), a replacement (`--replace`), a CPFP child
+//! (`--cpfp-fee-rate`) or a reorganization (`--reorg-depth`) is only part of the json
+//! and markdown formats; the others describe the confirmed payment alone. So is what a
+//! Taproot script path input reveals: its leaf script and decoded control block.
+//!
+//! Everything goes through [`write_report`], so every format sees the same data.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bitcoincore_rpc::bitcoin::{Amount, Txid};
+use serde::Serialize;
+
+use crate::amount::{self, format_btc};
+use crate::analysis::{
+    FeeComparison, InputDetail, OutputDetail, OutputRole, TransactionAnalysis,
+};
+use crate::confirmation::Confirmation;
+use crate::cpfp::{CpfpBump, MempoolPackage};
+use crate::error::{Error, Result};
+use crate::multisig::MultisigSpend;
+use crate::psbt::PsbtPayment;
+use crate::reorg::ReorgReport;
+use crate::replacement::Replacement;
+use crate::taproot::TaprootPayment;
+
+/// How a [`TransactionReport`] is rendered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// The legacy ten-line format
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Markdown,
+}
+
+impl ReportFormat {
+    pub const ALL: [Self; 4] = [Self::Text, Self::Json, Self::Csv, Self::Markdown];
+
+    /// Conventional file extension, used to derive default output file names
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Text => "txt",
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Markdown => "md",
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Text => "text",
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Markdown => "markdown",
+        })
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|format| {
+                format.to_string().eq_ignore_ascii_case(s) || format.extension() == s
+            })
+            .ok_or_else(|| {
+                Error::Config(format!(
+                    "unknown report format {s:?} (expected text, json, csv or markdown)"
+                ))
+            })
+    }
+}
+
+/// Where a report goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputTarget {
+    Stdout,
+    File(PathBuf),
+}
+
+impl FromStr for OutputTarget {
+    type Err = std::convert::Infallible;
+
+    /// `-` means stdout, anything else is a file path
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "-" => Self::Stdout,
+            path => Self::File(PathBuf::from(path)),
+        })
+    }
+}
+
+impl fmt::Display for OutputTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stdout => f.write_str("stdout"),
+            Self::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Serializable summary of one confirmed payment
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TransactionReport {
+    pub txid: Txid,
+    /// Distinct funding addresses, in first-seen order
+    pub sender_addresses: Vec<String>,
+    pub inputs: Vec<InputDetail>,
+    pub outputs: Vec<OutputDetail>,
+    #[serde(with = "amount::exact")]
+    pub total_input: Amount,
+    #[serde(with = "amount::exact")]
+    pub total_output: Amount,
+    #[serde(with = "amount::exact")]
+    pub fee: Amount,
+    /// Fee divided by virtual size; a ratio, not an amount, so a plain number
+    pub fee_rate_sat_per_vb: f64,
+    /// Serialized bytes, weight units and virtual bytes (see analysis.rs)
+    pub size: u64,
+    pub weight: u64,
+    pub vsize: u64,
+    /// The fee rate next to the node's mempool minimum and fee estimate, if looked up
+    /// (json and markdown only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee_comparison: Option<FeeComparison>,
+    /// The block that confirmed the transaction: its hash, height, the transaction's
+    /// position in it and the Merkle proof of that (see confirmation.rs)
+    #[serde(flatten)]
+    pub confirmation: Confirmation,
+    /// How the payment went from PSBT to transaction, if it was paid through one (json
+    /// and markdown only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psbt: Option<PsbtPayment>,
+    /// Which cosigners signed, if the payment came out of a multisig wallet (json and
+    /// markdown only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multisig: Option<MultisigSpend>,
+    /// The script tree the payment spent from, if it came from one (json and markdown
+    /// only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taproot: Option<TaprootPayment>,
+    /// The replace-by-fee done before confirming, if any (json and markdown only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<Replacement>,
+    /// The child that paid for the payment before confirming, if any (json and
+    /// markdown only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpfp: Option<CpfpBump>,
+    /// The reorganization forced after confirming, if any (json and markdown only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reorg: Option<ReorgReport>,
+}
+
+impl TransactionReport {
+    pub fn new(analysis: &TransactionAnalysis, confirmation: Confirmation) -> Self {
+        Self {
+            txid: analysis.txid,
+            sender_addresses: analysis.sender_addresses.clone(),
+            inputs: analysis.inputs.clone(),
+            outputs: analysis.outputs.clone(),
+            total_input: analysis.total_input_amount,
+            total_output: analysis.total_output_amount,
+            fee: analysis.fee,
+            fee_rate_sat_per_vb: analysis.fee_rate_sat_per_vb,
+            size: analysis.size,
+            weight: analysis.weight,
+            vsize: analysis.vsize,
+            fee_comparison: None,
+            confirmation,
+            psbt: None,
+            multisig: None,
+            taproot: None,
+            replacement: None,
+            cpfp: None,
+            reorg: None,
+        }
+    }
+
+    /// First output with `role`, if any
+    fn first_output(&self, role: OutputRole) -> Option<&OutputDetail> {
+        self.outputs.iter().find(|output| output.role == role)
+    }
+}
+
+/// Render `report` as `format` into `target`
+///
+/// Files are created (or truncated) along with any missing parent directories.
+pub fn write_report(
+    report: &TransactionReport,
+    format: ReportFormat,
+    target: &OutputTarget,
+) -> Result<()> {
+    match target {
+        OutputTarget::Stdout => render(io::stdout().lock(), report, format),
+        OutputTarget::File(path) => {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut out = BufWriter::new(File::create(path)?);
+            render(&mut out, report, format)?;
+            out.flush()?;
+            Ok(())
+        },
+    }
+}
+
+/// Render `report` as `format` into any writer
+pub fn render<W: Write>(
+    out: W,
+    report: &TransactionReport,
+    format: ReportFormat,
+) -> Result<()> {
+    match format {
+        ReportFormat::Text => write_legacy(out, report),
+        ReportFormat::Json => write_json(out, report),
+        ReportFormat::Csv => write_csv(out, report),
+        ReportFormat::Markdown => write_markdown(out, report),
+    }
+}
+
+/// Files only found at the top of this repository (next to test/, which the Jest
+/// grader runs from)
+const REPO_ROOT_MARKERS: [&str; 2] = ["bitcoin.conf", "package.json"];
+
+/// Where reports go by default: the repository root, found from the working directory
+/// at run time, or the working directory itself when it isn't inside the repository
+pub fn default_dir() -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    repo_root(&cwd).unwrap_or(cwd)
+}
+
+/// The nearest of `start` and its ancestors that holds one of the repository's root files
+pub fn repo_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| {
+            REPO_ROOT_MARKERS
+                .iter()
+                .any(|file| dir.join(file).is_file())
+        })
+        .map(Path::to_path_buf)
+}
+
+/// `<dir>/out.<ext>`: the file name the legacy tooling expects for `format`
+pub fn default_file_name(dir: &Path, format: ReportFormat) -> PathBuf {
+    dir.join(format!("out.{}", format.extension()))
+}
+
+/// Write `report` as pretty-printed JSON
+pub fn write_json<W: Write>(mut out: W, report: &TransactionReport) -> Result<()> {
+    serde_json::to_writer_pretty(&mut out, report).map_err(std::io::Error::from)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Write the ten-line report the Jest grader (test/test.spec.ts) parses
+///
+/// Line-by-line breakdown:
+/// 1. Transaction ID (unique identifier for this transaction)
+/// 2. Sender address (where the money originally came from)
+/// 3. Total input amount (sum of every spent output)
+/// 4. Recipient address (where the intended payment went)
+/// 5. Recipient amount (how much the recipient got)
+/// 6. Change address (where the leftover money went back)
+/// 7. Change amount (how much went back as change)
+/// 8. Mining fees (how much miners got for including this transaction)
+/// 9. Height of the block that confirmed the transaction
+/// 10. Hash of that block (its fingerprint)
+///
+/// Amounts are exact decimal BTC (e.g. `0.0000141`), never floats or scientific notation.
+pub fn write_legacy<W: Write>(mut out: W, report: &TransactionReport) -> Result<()> {
+    let txid = report.txid;
+    let Confirmation {
+        block_height,
+        block_hash,
+        ..
+    } = &report.confirmation;
+
+    // The legacy format has a single line per party; extra inputs/outputs only show in JSON
+    let sender_address = report.sender_addresses.first().map_or("", String::as_str);
+    let (recipient_address, recipient_amount) =
+        legacy_output(report.first_output(OutputRole::Payment));
+    let (change_address, change_amount) =
+        legacy_output(report.first_output(OutputRole::Change));
+    let total_input_amount = format_btc(report.total_input);
+    let fee = format_btc(report.fee);
+
+    // The write! macro is like println! but writes to a file instead of stdout
+    // Each \n creates a new line in the output file
+    write!(
+        out,
+        "{txid}\n{sender_address}\n{total_input_amount}\n{recipient_address}\n{recipient_amount}\n{change_address}\n{change_amount}\n{fee}\n{block_height}\n{block_hash}\n"
+    )?;
+    Ok(())
+}
+
+/// Address and amount lines for an output; blank/zero when there is no such output
+fn legacy_output(output: Option<&OutputDetail>) -> (&str, String) {
+    match output {
+        Some(output) => (
+            output.address.as_deref().unwrap_or(""),
+            format_btc(output.amount),
+        ),
+        None => ("", format_btc(Amount::ZERO)),
+    }
+}
+
+/// Write one CSV row per input, output and fee
+///
+/// Every row repeats the txid and confirmation block so rows from several
+/// reports can be concatenated and still be told apart.
+pub fn write_csv<W: Write>(mut out: W, report: &TransactionReport) -> Result<()> {
+    writeln!(
+        out,
+        "txid,block_height,block_hash,kind,index,address,btc,sat,role,script_type,spend_path,asm"
+    )?;
+
+    let prefix = format!(
+        "{},{},{}",
+        report.txid, report.confirmation.block_height, report.confirmation.block_hash
+    );
+    for (index, input) in report.inputs.iter().enumerate() {
+        writeln!(
+            out,
+            "{prefix},input,{index},{},{},{},,{},{},{}",
+            csv_field(input.address.as_deref().unwrap_or("")),
+            format_btc(input.amount),
+            input.amount.to_sat(),
+            input.script_type,
+            input.spend_path,
+            csv_field(input.asm.as_deref().unwrap_or("")),
+        )?;
+    }
+    for output in &report.outputs {
+        writeln!(
+            out,
+            "{prefix},output,{},{},{},{},{},{},,{}",
+            output.vout,
+            csv_field(output.address.as_deref().unwrap_or("")),
+            format_btc(output.amount),
+            output.amount.to_sat(),
+            role_name(output.role),
+            output.script_type,
+            csv_field(output.asm.as_deref().unwrap_or("")),
+        )?;
+    }
+    writeln!(
+        out,
+        "{prefix},fee,,,{},{},,,,",
+        format_btc(report.fee),
+        report.fee.to_sat()
+    )?;
+    Ok(())
+}
+
+/// Write a human-readable summary with input/output tables
+pub fn write_markdown<W: Write>(mut out: W, report: &TransactionReport) -> Result<()> {
+    writeln!(out, "# Transaction `{}`\n", report.txid)?;
+    writeln!(out, "| Field | Value |\n| --- | --- |")?;
+    let confirmation = &report.confirmation;
+    writeln!(out, "| Block height | {} |", confirmation.block_height)?;
+    writeln!(out, "| Block hash | `{}` |", confirmation.block_hash)?;
+    writeln!(
+        out,
+        "| Position in block | {} of {} |",
+        confirmation.block_position, confirmation.block_tx_count
+    )?;
+    writeln!(out, "| Confirmations | {} |", confirmation.confirmations)?;
+    writeln!(
+        out,
+        "| Merkle proof | {} |",
+        if confirmation.merkle_proof.is_valid() {
+            "verified"
+        } else {
+            "**invalid**"
+        }
+    )?;
+    writeln!(
+        out,
+        "| Total input | {} BTC |",
+        format_btc(report.total_input)
+    )?;
+    writeln!(
+        out,
+        "| Total output | {} BTC |",
+        format_btc(report.total_output)
+    )?;
+    writeln!(
+        out,
+        "| Fee | {} BTC ({} sat) |",
+        format_btc(report.fee),
+        report.fee.to_sat()
+    )?;
+    writeln!(
+        out,
+        "| Fee rate | {:.2} sat/vB |",
+        report.fee_rate_sat_per_vb
+    )?;
+    writeln!(out, "| Virtual size | {} vB |", report.vsize)?;
+    writeln!(out, "| Weight | {} WU |", report.weight)?;
+    writeln!(out, "| Size | {} bytes |", report.size)?;
+    if let Some(comparison) = &report.fee_comparison {
+        writeln!(
+            out,
+            "| Mempool minimum | {:.2} sat/vB |",
+            comparison.mempool_min_fee_sat_per_vb
+        )?;
+        match comparison.estimate_sat_per_vb {
+            Some(estimate) => writeln!(
+                out,
+                "| Estimate ({} blocks) | {estimate:.2} sat/vB |",
+                comparison.estimate_target_blocks
+            )?,
+            None => writeln!(
+                out,
+                "| Estimate ({} blocks) | unavailable |",
+                comparison.estimate_target_blocks
+            )?,
+        }
+    }
+
+    writeln!(out, "\n## Inputs\n")?;
+    writeln!(
+        out,
+        "| # | Outpoint | Spends | Address | Amount (BTC) |\n| --- | --- | --- | --- | --- |"
+    )?;
+    for (index, input) in report.inputs.iter().enumerate() {
+        writeln!(
+            out,
+            "| {index} | `{}` | {} | {} | {} |",
+            input.outpoint,
+            input.spend_path,
+            markdown_script(input.address.as_deref(), input.asm.as_deref()),
+            format_btc(input.amount),
+        )?;
+    }
+
+    let reveals: Vec<_> = report
+        .inputs
+        .iter()
+        .enumerate()
+        .filter_map(|(index, input)| Some((index, input.tapscript.as_ref()?)))
+        .collect();
+    if !reveals.is_empty() {
+        writeln!(out, "\n## Script path\n")?;
+        writeln!(
+            out,
+            "| Input | Leaf script | Version | Internal key | Parity | Merkle path | Verified |\n| --- | --- | --- | --- | --- | --- | --- |"
+        )?;
+        for (index, reveal) in reveals {
+            let path: Vec<String> = reveal
+                .merkle_path
+                .iter()
+                .map(|hash| format!("`{hash}`"))
+                .collect();
+            writeln!(
+                out,
+                "| {index} | `{}` | 0x{:02x} | `{}` | {} | {} | {} |",
+                reveal.script.replace('|', "\\|"),
+                reveal.leaf_version,
+                reveal.internal_key,
+                if reveal.output_key_parity == 0 {
+                    "even"
+                } else {
+                    "odd"
+                },
+                if path.is_empty() {
+                    "-".to_owned()
+                } else {
+                    path.join("<br>")
+                },
+                if reveal.verified { "yes" } else { "no" },
+            )?;
+        }
+    }
+
+    writeln!(out, "\n## Outputs\n")?;
+    writeln!(
+        out,
+        "| vout | Type | Address | Amount (BTC) | Role | Sender's key |\n| --- | --- | --- | --- | --- | --- |"
+    )?;
+    for output in &report.outputs {
+        writeln!(
+            out,
+            "| {} | {} | {} | {} | {} | {} |",
+            output.vout,
+            output.script_type,
+            markdown_script(output.address.as_deref(), output.asm.as_deref()),
+            format_btc(output.amount),
+            role_name(output.role),
+            output
+                .ownership
+                .as_ref()
+                .and_then(|ownership| ownership.hd_keypath.as_deref())
+                .unwrap_or("-"),
+        )?;
+    }
+
+    if let Some(payment) = &report.psbt {
+        writeln!(out, "\n## PSBT\n")?;
+        writeln!(
+            out,
+            "| Stage | Inputs | Signed | Finalized | Fee | Files |\n| --- | --- | --- | --- | --- | --- |"
+        )?;
+        for step in &payment.steps {
+            let inspection = &step.inspection;
+            let files: Vec<String> = step
+                .files
+                .iter()
+                .map(|file| format!("`{}`", file.display()))
+                .collect();
+            writeln!(
+                out,
+                "| {} | {} | {} | {} | {} | {} |",
+                step.stage,
+                inspection.inputs,
+                inspection.signed_inputs,
+                inspection.finalized_inputs,
+                inspection.fee.map_or_else(|| "-".to_owned(), format_btc),
+                if files.is_empty() {
+                    "-".to_owned()
+                } else {
+                    files.join(", ")
+                },
+            )?;
+        }
+    }
+
+    if let Some(spend) = &report.multisig {
+        writeln!(out, "\n## Multisig\n")?;
+        writeln!(
+            out,
+            "{} {} wallet `{}`, signed by {} cosigner(s)\n",
+            spend.threshold,
+            spend.kind,
+            spend.wallet,
+            spend.signers.len()
+        )?;
+        writeln!(out, "| Cosigner | Signed |\n| --- | --- |")?;
+        for cosigner in &spend.cosigners {
+            let signed = if spend.signers.contains(cosigner) {
+                "yes"
+            } else {
+                "no"
+            };
+            writeln!(out, "| {cosigner} | {signed} |")?;
+        }
+    }
+
+    if let Some(payment) = &report.taproot {
+        writeln!(out, "\n## Taproot\n")?;
+        writeln!(
+            out,
+            "Output `{}` (output key `{}`), internal key `{}`, Merkle root `{}`; `{}` \
+             spent through the key path, `{}` through a leaf\n",
+            payment.address,
+            payment.output_key,
+            payment.internal_key,
+            payment.merkle_root,
+            payment.key_path,
+            payment.script_path,
+        )?;
+        writeln!(
+            out,
+            "| Leaf | Depth | Script | Spent |\n| --- | --- | --- | --- |"
+        )?;
+        for (index, leaf) in payment.leaves.iter().enumerate() {
+            writeln!(
+                out,
+                "| {index} | {} | `{}` | {} |",
+                leaf.depth,
+                leaf.script,
+                if leaf.spent { "yes" } else { "no" },
+            )?;
+        }
+    }
+
+    if let Some(replacement) = &report.replacement {
+        writeln!(out, "\n## Replacement\n")?;
+        writeln!(
+            out,
+            "Replaced by {}; mempool held {} transaction(s) before, {} after\n",
+            replacement.method,
+            replacement.mempool_before.len(),
+            replacement.mempool_after.len(),
+        )?;
+        writeln!(out, "| Transaction | Fee | Outcome |\n| --- | --- | --- |")?;
+        for (txid, fee) in [
+            (replacement.original, replacement.original_fee),
+            (replacement.replacement, replacement.replacement_fee),
+        ] {
+            let outcome = if replacement.confirmed == Some(txid) {
+                "confirmed"
+            } else if replacement.evicted.contains(&txid) {
+                "evicted"
+            } else {
+                "unconfirmed"
+            };
+            writeln!(out, "| `{txid}` | {} | {outcome} |", format_btc(fee))?;
+        }
+    }
+
+    if let Some(bump) = &report.cpfp {
+        writeln!(out, "\n## Child pays for parent\n")?;
+        writeln!(
+            out,
+            "Child `{}` spends `{}`, aiming for {} sat/vB\n",
+            bump.child, bump.spent, bump.target_sat_per_vb
+        )?;
+        writeln!(
+            out,
+            "| Package | Transactions | Fees | Size (vB) | Fee rate (sat/vB) |\n| --- | --- | --- | --- | --- |"
+        )?;
+        let rows: [(&str, &MempoolPackage, bool); 3] = [
+            ("Parent and ancestors, before", &bump.parent_before, true),
+            ("Parent and descendants, after", &bump.parent_after, false),
+            ("Child and ancestors", &bump.child_package, true),
+        ];
+        for (name, package, ancestors) in rows {
+            let (count, fees, size, rate) = if ancestors {
+                (
+                    package.ancestor_count,
+                    package.ancestor_fees,
+                    package.ancestor_size,
+                    package.ancestor_fee_rate(),
+                )
+            } else {
+                (
+                    package.descendant_count,
+                    package.descendant_fees,
+                    package.descendant_size,
+                    package.descendant_fee_rate(),
+                )
+            };
+            writeln!(
+                out,
+                "| {name} | {count} | {} | {size} | {rate:.2} |",
+                format_btc(fees)
+            )?;
+        }
+    }
+
+    if let Some(reorg) = &report.reorg {
+        writeln!(out, "\n## Reorganization\n")?;
+        writeln!(
+            out,
+            "{} block(s) after height {} replaced by {}; new tip {} (`{}`)\n",
+            reorg.depth(),
+            reorg.fork_point.height,
+            reorg.connected_blocks.len(),
+            reorg.new_tip.height,
+            reorg.new_tip.hash,
+        )?;
+        writeln!(
+            out,
+            "| Transaction | Old block | Fate |\n| --- | --- | --- |"
+        )?;
+        for tx in &reorg.transactions {
+            let kind = if tx.coinbase { " (coinbase)" } else { "" };
+            writeln!(
+                out,
+                "| `{}`{kind} | {} | {} |",
+                tx.txid, tx.block_height, tx.fate
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn role_name(role: OutputRole) -> &'static str {
+    match role {
+        OutputRole::Payment => "payment",
+        OutputRole::Change => "change",
+        OutputRole::Unknown => "unknown",
+    }
+}
+
+/// The address, or the disassembled script when there is none
+fn markdown_script(address: Option<&str>, asm: Option<&str>) -> String {
+    match (address, asm) {
+        (Some(address), _) => address.to_owned(),
+        (None, Some(asm)) => format!("`{}`", asm.replace('|', "\\|")),
+        (None, None) => "-".to_owned(),
+    }
+}
+
+/// Quote a CSV field if it contains a separator, quote or newline
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
@@ -0,0 +1,659 @@
+//! Scenario files: multi-party regtest simulations described in TOML or YAML
+//!
+//! A scenario lists:
+//! - `participants`: wallets to provision (same options as [`WalletSpec`])
+//! - `funding`: who gets coins before the story starts, either by mining rewards to
+//!   maturity (`kind = "mine"`) or by a (confirmed) payment from an already funded
+//!   participant (`kind = "transfer"`)
+//! - `steps`: payments, mining and balance assertions, executed in order
+//!
+//! With `nodes = N` the scenario runs on a network of N connected nodes (see
+//! [`cluster`]) and each participant's wallet lives on the node given by its `node`
+//! index (default 0). Nodes only see each other's blocks and transactions once those
+//! have propagated, so a `sync` step waits for that; leaving it out lets two miners
+//! race, or a payment reach some mempools but not others. `partition` splits the
+//! network so each half can mine its own branch, and `heal` joins it again, after
+//! which the shorter branch is reorganized away (see [`reorg`]); on any number of
+//! nodes, `reorg` replaces the last blocks with a competing branch directly.
+//!
+//! ```toml
+//! name = "Miner pays Alice"
+//!
+//! [[participants]]
+//! name = "Miner"
+//!
+//! [[participants]]
+//! name = "Alice"
+//!
+//! [[funding]]
+//! kind = "mine"
+//! participant = "Miner"
+//!
+//! [[steps]]
+//! action = "pay"
+//! from = "Miner"
+//! to = "Alice"
+//! amount = "20"
+//!
+//! [[steps]]
+//! action = "mine"
+//! by = "Miner"
+//!
+//! [[steps]]
+//! action = "assert_balance"
+//! participant = "Alice"
+//! equals = "20 BTC"
+//! ```
+//!
+//! Misspelled keys are errors rather than silently ignored.
+//!
+//! Amounts are strings (`"0.5"` BTC, `"1500 sat"`) or whole-BTC integers, never floats.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use bitcoincore_rpc::bitcoin::{Address, Amount, Txid};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Deserialize;
+
+use crate::amount::{self, format_btc};
+use crate::cluster::{self, Cluster, Topology};
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::mining::{self, WalletBalances};
+use crate::progress::Progress;
+use crate::reorg::{self, ChainMark, ReorgReport};
+use crate::transfer;
+use crate::wallets::{self, WalletKind, WalletSpec};
+
+/// How long a `sync` step waits for the nodes to agree
+const SYNC_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A whole simulation, as written in a scenario file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// How many connected nodes the scenario runs on
+    #[serde(default = "one_node")]
+    pub nodes: usize,
+    /// How those nodes are connected
+    #[serde(default)]
+    pub topology: Topology,
+    pub participants: Vec<Participant>,
+    #[serde(default)]
+    pub funding: Vec<Funding>,
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+fn one_node() -> usize {
+    1
+}
+
+/// A wallet taking part in the scenario, and the node it lives on
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "ParticipantEntry")]
+pub struct Participant {
+    pub wallet: WalletSpec,
+    /// Index of the node (0-based)
+    pub node: usize,
+}
+
+/// A participant as written in the file: the [`WalletSpec`] fields next to `node`
+///
+/// Spelled out rather than `#[serde(flatten)]`ed, which would let a misspelled wallet
+/// option through unnoticed.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ParticipantEntry {
+    name: String,
+    kind: Option<WalletKind>,
+    blank: bool,
+    #[serde(alias = "watch_only")]
+    disable_private_keys: bool,
+    passphrase: Option<String>,
+    avoid_reuse: bool,
+    node: usize,
+}
+
+impl From<ParticipantEntry> for Participant {
+    fn from(entry: ParticipantEntry) -> Self {
+        Self {
+            wallet: WalletSpec {
+                name: entry.name,
+                kind: entry.kind,
+                blank: entry.blank,
+                disable_private_keys: entry.disable_private_keys,
+                passphrase: entry.passphrase,
+                avoid_reuse: entry.avoid_reuse,
+            },
+            node: entry.node,
+        }
+    }
+}
+
+/// How a participant gets its starting coins
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Funding {
+    /// A payment from another participant, confirmed before the steps start
+    Transfer {
+        participant: String,
+        from: String,
+        #[serde(deserialize_with = "amount::human::deserialize")]
+        amount: Amount,
+    },
+    /// Coinbase rewards mined to the participant until they're spendable
+    Mine { participant: String },
+}
+
+/// One action in a scenario
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Step {
+    /// Send `amount` from one participant to a fresh address of another (left unconfirmed)
+    Pay {
+        from: String,
+        to: String,
+        #[serde(deserialize_with = "amount::human::deserialize")]
+        amount: Amount,
+    },
+    /// Mine `blocks` blocks (default 1) with the rewards going to `by`
+    Mine {
+        by: String,
+        #[serde(default = "one_block")]
+        blocks: u64,
+    },
+    /// Mine until `by` has a spendable balance (see [`mining::mine_to_maturity`])
+    MineToMaturity { by: String },
+    /// Wait until every node has the same tip and mempool (see [`cluster::wait_for_sync`])
+    Sync,
+    /// Replace the last `depth` blocks on `by`'s node with a longer branch mined by
+    /// `by` (see [`reorg::fork`])
+    Reorg { by: String, depth: u64 },
+    /// Cut every connection between `nodes` and the other nodes (see
+    /// [`Cluster::partition`])
+    Partition { nodes: Vec<usize> },
+    /// Reconnect a partitioned network and wait until it agrees on one chain again
+    Heal,
+    /// Check one of a participant's balances
+    AssertBalance(BalanceAssertion),
+}
+
+fn one_block() -> u64 {
+    1
+}
+
+/// Which part of a wallet's balance an assertion looks at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceKind {
+    #[default]
+    Trusted,
+    UntrustedPending,
+    Immature,
+}
+
+/// `participant`'s `kind` balance must be `equals`, or within `at_least`..=`at_most`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BalanceAssertion {
+    pub participant: String,
+    #[serde(default)]
+    pub kind: BalanceKind,
+    #[serde(default, deserialize_with = "optional_amount")]
+    pub equals: Option<Amount>,
+    #[serde(default, deserialize_with = "optional_amount")]
+    pub at_least: Option<Amount>,
+    #[serde(default, deserialize_with = "optional_amount")]
+    pub at_most: Option<Amount>,
+}
+
+fn optional_amount<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Amount>, D::Error> {
+    amount::human::deserialize(deserializer).map(Some)
+}
+
+impl BalanceAssertion {
+    /// Either `equals`, or a range with at least one end that isn't empty
+    fn validate(&self) -> Result<()> {
+        let ranged = self.at_least.is_some() || self.at_most.is_some();
+        let problem = match (self.equals, self.at_least, self.at_most) {
+            (None, None, None) => "checks nothing: give equals, at_least or at_most",
+            (Some(_), ..) if ranged => "gives both equals and a range",
+            (_, Some(min), Some(max)) if min > max => "has at_least above at_most",
+            _ => return Ok(()),
+        };
+        Err(Error::Config(format!(
+            "the balance assertion on {} {problem}",
+            self.participant
+        )))
+    }
+
+    /// `None` if the assertion holds, otherwise why not
+    fn check(&self, balances: &WalletBalances) -> Option<String> {
+        let actual = match self.kind {
+            BalanceKind::Trusted => balances.trusted,
+            BalanceKind::UntrustedPending => balances.untrusted_pending,
+            BalanceKind::Immature => balances.immature,
+        };
+
+        let failed = self.equals.is_some_and(|expected| actual != expected)
+            || self.at_least.is_some_and(|min| actual < min)
+            || self.at_most.is_some_and(|max| actual > max);
+        failed.then(|| {
+            format!(
+                "{} {:?} balance is {actual}, expected {self}",
+                self.participant, self.kind
+            )
+        })
+    }
+}
+
+impl fmt::Display for BalanceAssertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(expected) = self.equals {
+            parts.push(format!("= {} BTC", format_btc(expected)));
+        }
+        if let Some(min) = self.at_least {
+            parts.push(format!(">= {} BTC", format_btc(min)));
+        }
+        if let Some(max) = self.at_most {
+            parts.push(format!("<= {} BTC", format_btc(max)));
+        }
+        f.write_str(&parts.join(" and "))
+    }
+}
+
+impl Scenario {
+    /// Read a scenario, picking the parser from the extension (`.toml`, `.yaml`/`.yml`)
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&text),
+            Some("yaml" | "yml") => Self::from_yaml(&text),
+            _ => Err(Error::Config(format!(
+                "{}: scenario files must end in .toml, .yaml or .yml",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let scenario: Self = toml::from_str(text).map_err(|e| Error::Config(e.to_string()))?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        let scenario: Self =
+            serde_yaml::from_str(text).map_err(|e| Error::Config(e.to_string()))?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    /// Every name used in funding and steps must be a declared participant, every
+    /// participant must live on one of the scenario's nodes, and every balance
+    /// assertion must actually check something
+    fn validate(&self) -> Result<()> {
+        if self.nodes == 0 {
+            return Err(Error::Config(
+                "a scenario needs at least one node".to_owned(),
+            ));
+        }
+        if let Some(misplaced) = self.participants.iter().find(|p| p.node >= self.nodes) {
+            return Err(Error::Config(format!(
+                "participant {:?} is on node {}, but the scenario only has {} node(s)",
+                misplaced.wallet.name, misplaced.node, self.nodes
+            )));
+        }
+
+        for step in &self.steps {
+            match step {
+                Step::Reorg { depth: 0, .. } => {
+                    return Err(Error::Config(
+                        "a reorg step must replace at least one block".to_owned(),
+                    ));
+                },
+                Step::Partition { nodes } => self.validate_partition(nodes)?,
+                Step::AssertBalance(assertion) => assertion.validate()?,
+                _ => {},
+            }
+        }
+
+        let known = |name: &str| self.participants.iter().any(|p| p.wallet.name == name);
+
+        let mut referenced: Vec<&str> = Vec::new();
+        for funding in &self.funding {
+            match funding {
+                Funding::Transfer {
+                    participant, from, ..
+                } => {
+                    referenced.extend([participant.as_str(), from.as_str()]);
+                },
+                Funding::Mine { participant } => referenced.push(participant),
+            }
+        }
+        for step in &self.steps {
+            match step {
+                Step::Pay { from, to, .. } => referenced.extend([from.as_str(), to.as_str()]),
+                Step::Mine { by, .. }
+                | Step::MineToMaturity { by }
+                | Step::Reorg { by, .. } => referenced.push(by),
+                Step::AssertBalance(assertion) => referenced.push(&assertion.participant),
+                Step::Sync | Step::Partition { .. } | Step::Heal => {},
+            }
+        }
+
+        match referenced.into_iter().find(|name| !known(name)) {
+            Some(unknown) => Err(Error::Config(format!(
+                "scenario refers to {unknown:?}, which is not listed under participants"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// A partition must leave nodes on both sides
+    fn validate_partition(&self, side: &[usize]) -> Result<()> {
+        if let Some(node) = side.iter().find(|&&node| node >= self.nodes) {
+            return Err(Error::Config(format!(
+                "can't partition off node {node}: the scenario only has {} node(s)",
+                self.nodes
+            )));
+        }
+        if side.is_empty() || (0..self.nodes).all(|node| side.contains(&node)) {
+            return Err(Error::Config(
+                "a partition needs nodes on both sides".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What running a scenario produced
+#[derive(Debug, Clone, Default)]
+pub struct ScenarioOutcome {
+    /// Every payment sent (funding and steps), in order
+    pub payments: Vec<Txid>,
+    /// Assertions that didn't hold; the scenario keeps going after a failure
+    pub failures: Vec<String>,
+    /// Every reorganization a `reorg` or `heal` step caused, in order
+    pub reorgs: Vec<ReorgReport>,
+}
+
+/// Execute a single-node `scenario` against the node behind `connection`
+///
+/// Each step is reported to `progress` as it happens.
+pub fn run(
+    connection: &Connection,
+    scenario: &Scenario,
+    progress: Progress,
+) -> Result<ScenarioOutcome> {
+    execute(std::slice::from_ref(connection), None, scenario, progress)
+}
+
+/// Execute `scenario` on the nodes of `cluster`
+///
+/// RPC errors abort the run; failed balance assertions are collected in the outcome.
+pub fn run_on(
+    cluster: &Cluster,
+    scenario: &Scenario,
+    progress: Progress,
+) -> Result<ScenarioOutcome> {
+    execute(&cluster.connections(), Some(cluster), scenario, progress)
+}
+
+fn execute(
+    nodes: &[Connection],
+    cluster: Option<&Cluster>,
+    scenario: &Scenario,
+    progress: Progress,
+) -> Result<ScenarioOutcome> {
+    let nodes = nodes.get(..scenario.nodes).ok_or_else(|| {
+        Error::Config(format!(
+            "the scenario needs {} node(s), only {} given",
+            scenario.nodes,
+            nodes.len()
+        ))
+    })?;
+    let mut runner = Runner::new(nodes, cluster, &scenario.participants, progress);
+    let mut outcome = ScenarioOutcome::default();
+
+    for participant in &scenario.participants {
+        let spec = std::slice::from_ref(&participant.wallet);
+        let state = wallets::provision(&nodes[participant.node], spec)?[0];
+        match nodes.len() {
+            1 => progress(&format!("Participant {}: wallet {state}", spec[0].name)),
+            _ => progress(&format!(
+                "Participant {}: wallet {state} on node {}",
+                spec[0].name, participant.node
+            )),
+        }
+    }
+
+    // Coinbase funding first, so transfer funding can draw on it. Each miner waits for
+    // the previous one's blocks, so nobody's rewards end up on a losing branch
+    for funding in &scenario.funding {
+        if let Funding::Mine { participant } = funding {
+            runner.mine_to_maturity(participant)?;
+            runner.sync()?;
+        }
+    }
+    let mut funders = Vec::new();
+    let mut transfers = Vec::new();
+    for funding in &scenario.funding {
+        if let Funding::Transfer {
+            participant,
+            from,
+            amount,
+        } = funding
+        {
+            transfers.push(runner.pay(from, participant, *amount)?);
+            funders.push(from.as_str());
+        }
+    }
+    // Confirm the funding payments so every participant starts with trusted coins. Those
+    // sent on other nodes must have reached the miner's mempool first, or they'd be left
+    // out of the block
+    if let Some(first_funder) = funders.first() {
+        runner.wait_for_mempool(first_funder, &transfers)?;
+        runner.mine(first_funder, 1)?;
+        runner.sync()?;
+    }
+    outcome.payments.extend(transfers);
+
+    for step in &scenario.steps {
+        match step {
+            Step::Pay { from, to, amount } => {
+                outcome.payments.push(runner.pay(from, to, *amount)?)
+            },
+            Step::Mine { by, blocks } => runner.mine(by, *blocks)?,
+            Step::MineToMaturity { by } => runner.mine_to_maturity(by)?,
+            Step::Sync => runner.sync()?,
+            Step::Reorg { by, depth } => outcome.reorgs.push(runner.reorg(by, *depth)?),
+            Step::Partition { nodes } => runner.partition(nodes)?,
+            Step::Heal => outcome.reorgs.extend(runner.heal()?),
+            Step::AssertBalance(assertion) => {
+                let balances = mining::balances(runner.client(&assertion.participant)?)?;
+                match assertion.check(&balances) {
+                    None => {
+                        progress(&format!("✓ {} balance {assertion}", assertion.participant))
+                    },
+                    Some(failure) => {
+                        progress(&format!("✗ {failure}"));
+                        outcome.failures.push(failure);
+                    },
+                }
+            },
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// Per-participant RPC clients and mining addresses, created on first use
+struct Runner<'a> {
+    nodes: &'a [Connection],
+    /// What `partition` and `heal` act on; `None` for a single node
+    cluster: Option<&'a Cluster>,
+    /// Which node each participant's wallet is on
+    homes: HashMap<String, usize>,
+    clients: HashMap<String, Client>,
+    mining_addresses: HashMap<String, Address>,
+    /// Where each step is reported
+    progress: Progress,
+}
+
+impl<'a> Runner<'a> {
+    fn new(
+        nodes: &'a [Connection],
+        cluster: Option<&'a Cluster>,
+        participants: &[Participant],
+        progress: Progress,
+    ) -> Self {
+        Self {
+            nodes,
+            cluster,
+            homes: participants
+                .iter()
+                .map(|p| (p.wallet.name.clone(), p.node))
+                .collect(),
+            clients: HashMap::new(),
+            mining_addresses: HashMap::new(),
+            progress,
+        }
+    }
+
+    /// The node `participant`'s wallet lives on
+    fn home(&self, participant: &str) -> &'a Connection {
+        &self.nodes[self.homes[participant]]
+    }
+
+    fn client(&mut self, participant: &str) -> Result<&Client> {
+        if !self.clients.contains_key(participant) {
+            let client = self.home(participant).client(Some(participant))?;
+            self.clients.insert(participant.to_owned(), client);
+        }
+        Ok(&self.clients[participant])
+    }
+
+    fn new_address(&mut self, participant: &str, label: &str) -> Result<Address> {
+        let network = self.home(participant).network();
+        let address = self
+            .client(participant)?
+            .get_new_address(Some(label), None)?;
+        Ok(address.require_network(network)?)
+    }
+
+    /// One reward address per participant, like the simulator's "Mining Reward" address
+    fn mining_address(&mut self, participant: &str) -> Result<Address> {
+        if let Some(address) = self.mining_addresses.get(participant) {
+            return Ok(address.clone());
+        }
+        let address = self.new_address(participant, "Mining Reward")?;
+        self.mining_addresses
+            .insert(participant.to_owned(), address.clone());
+        Ok(address)
+    }
+
+    fn pay(&mut self, from: &str, to: &str, amount: Amount) -> Result<Txid> {
+        let recipient = self.new_address(to, "Received")?;
+        let txid = transfer::send_payment(self.client(from)?, &recipient, amount)?;
+        (self.progress)(&format!("{from} → {to}: {amount} in {txid}"));
+        Ok(txid)
+    }
+
+    fn mine(&mut self, by: &str, blocks: u64) -> Result<()> {
+        let address = self.mining_address(by)?;
+        mining::mine_blocks(self.client(by)?, &address, blocks)?;
+        (self.progress)(&format!("{by} mined {blocks} block(s)"));
+        Ok(())
+    }
+
+    /// Wait until `txids` are in the mempool of `participant`'s node
+    fn wait_for_mempool(&self, participant: &str, txids: &[Txid]) -> Result<()> {
+        let node = std::slice::from_ref(self.home(participant));
+        for txid in txids {
+            cluster::wait_for_mempools(node, txid, SYNC_TIMEOUT, self.progress)?;
+        }
+        Ok(())
+    }
+
+    /// Wait for every node to catch up; nothing to wait for with a single node
+    fn sync(&self) -> Result<()> {
+        if self.nodes.len() > 1 {
+            let tip = cluster::wait_for_sync(self.nodes, SYNC_TIMEOUT, self.progress)?;
+            (self.progress)(&format!("All {} nodes in sync at {tip}", self.nodes.len()));
+        }
+        Ok(())
+    }
+
+    fn reorg(&mut self, by: &str, depth: u64) -> Result<ReorgReport> {
+        // Not the usual reward address, or a block could come out identical to one it
+        // replaces
+        let address = self.new_address(by, "Fork")?;
+        let report = reorg::fork(self.client(by)?, &address, depth, &[])?;
+        (self.progress)(&format!(
+            "{by} replaced the last {depth} block(s)\n{report}"
+        ));
+        Ok(report)
+    }
+
+    fn cluster(&self) -> Result<&'a Cluster> {
+        self.cluster.ok_or_else(|| {
+            Error::Config("partitions need several nodes, run as a cluster".to_owned())
+        })
+    }
+
+    fn partition(&self, side: &[usize]) -> Result<()> {
+        self.cluster()?.partition(side)?;
+        (self.progress)(&format!(
+            "Node(s) {side:?} cut off from the rest of the network"
+        ));
+        Ok(())
+    }
+
+    /// Reconnect, wait for the network to settle, and report every node that had to
+    /// reorganize to get there
+    fn heal(&self) -> Result<Vec<ReorgReport>> {
+        let clients = self
+            .nodes
+            .iter()
+            .map(|node| node.client(None))
+            .collect::<bitcoincore_rpc::Result<Vec<_>>>()?;
+        let marks = clients
+            .iter()
+            .map(ChainMark::record)
+            .collect::<Result<Vec<_>>>()?;
+
+        self.cluster()?.heal()?;
+        (self.progress)("Network healed");
+        self.sync()?;
+
+        let mut reorgs = Vec::new();
+        for (i, (rpc, mark)) in clients.iter().zip(marks).enumerate() {
+            let report = reorg::compare(rpc, mark)?;
+            if report.depth() > 0 {
+                (self.progress)(&format!("Node {i}: {report}"));
+                reorgs.push(report);
+            }
+        }
+        Ok(reorgs)
+    }
+
+    fn mine_to_maturity(&mut self, by: &str) -> Result<()> {
+        let address = self.mining_address(by)?;
+        let outcome = mining::mine_to_maturity(self.client(by)?, &address)?;
+        (self.progress)(&format!(
+            "{by} mined {} block(s) to maturity: {}",
+            outcome.blocks_mined, outcome.balances
+        ));
+        Ok(())
+    }
+}
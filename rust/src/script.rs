@@ -0,0 +1,404 @@
+//! Script types: what locks an output, and how an input unlocks one
+//!
+//! Addresses are just an encoding of a handful of standard output scripts
+//! (scriptPubKeys). Plenty of valid outputs have none: OP_RETURN data carriers, bare
+//! multisig, and anything else a miner was willing to include. So the analysis
+//! classifies the script itself, and falls back to its disassembly (ASM) when there is
+//! no address to show.
+//!
+//! Inputs carry no script type of their own. What they spend is decided by the
+//! output they spend, and *how* they spend it shows in what they provide:
+//! - legacy spends put signatures and keys in the scriptSig,
+//! - segwit spends leave the scriptSig empty and use the witness instead,
+//! - P2SH-wrapped segwit does both: the scriptSig pushes the witness program as the
+//!   redeem script, and the witness satisfies it,
+//! - taproot spends either sign for the output key alone (key path, one witness
+//!   item) or reveal one script of the tree with a control block (script path).
+//!
+//! A script path spend gives away a little of the tree: [`TapscriptReveal`] decodes the
+//! leaf script and the control block (internal key, output key parity, and the hashes
+//! of the branches not taken), and checks they add up to the output key. The other
+//! leaves stay hidden.
+
+use std::fmt;
+
+use bitcoincore_rpc::bitcoin::key::XOnlyPublicKey;
+use bitcoincore_rpc::bitcoin::script::Instruction;
+use bitcoincore_rpc::bitcoin::secp256k1::Secp256k1;
+use bitcoincore_rpc::bitcoin::taproot::{ControlBlock, TapLeafHash};
+use bitcoincore_rpc::bitcoin::{Script, Witness};
+use serde::Serialize;
+
+/// BIP341: a last witness item starting with this byte is an annex, not a stack item
+const TAPROOT_ANNEX_PREFIX: u8 = 0x50;
+
+/// The standard output script templates (and what isn't one)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptType {
+    /// `<pubkey> OP_CHECKSIG`: the earliest outputs, coinbases mostly; no address
+    P2pk,
+    /// Pay to public key hash: legacy addresses starting with 1 (m/n on test networks)
+    P2pkh,
+    /// Pay to script hash: addresses starting with 3 (2 on test networks)
+    P2sh,
+    /// Segwit v0 key hash: bc1q... with a 20-byte program
+    P2wpkh,
+    /// Segwit v0 script hash: bc1q... with a 32-byte program
+    P2wsh,
+    /// Segwit v1 taproot: bc1p...
+    P2tr,
+    /// `OP_RETURN <data>`: provably unspendable, carries data rather than value
+    OpReturn,
+    /// `m <pubkey>... n OP_CHECKMULTISIG` right in the output; no address
+    BareMultisig,
+    /// Anything else, including witness versions nobody has defined yet
+    NonStandard,
+}
+
+impl ScriptType {
+    /// Classify a scriptPubKey
+    pub fn of(script: &Script) -> Self {
+        if script.is_p2pkh() {
+            Self::P2pkh
+        } else if script.is_p2sh() {
+            Self::P2sh
+        } else if script.is_p2wpkh() {
+            Self::P2wpkh
+        } else if script.is_p2wsh() {
+            Self::P2wsh
+        } else if script.is_p2tr() {
+            Self::P2tr
+        } else if script.is_op_return() {
+            Self::OpReturn
+        } else if script.is_multisig() {
+            Self::BareMultisig
+        } else if script.is_p2pk() {
+            Self::P2pk
+        } else {
+            Self::NonStandard
+        }
+    }
+}
+
+impl ScriptType {
+    /// The name Bitcoin Core gives the type (`scriptPubKey.type` in decoded transactions)
+    pub fn core_name(self) -> &'static str {
+        match self {
+            Self::P2pk => "pubkey",
+            Self::P2pkh => "pubkeyhash",
+            Self::P2sh => "scripthash",
+            Self::P2wpkh => "witness_v0_keyhash",
+            Self::P2wsh => "witness_v0_scripthash",
+            Self::P2tr => "witness_v1_taproot",
+            Self::OpReturn => "nulldata",
+            Self::BareMultisig => "multisig",
+            Self::NonStandard => "nonstandard",
+        }
+    }
+}
+
+impl fmt::Display for ScriptType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::P2pk => "P2PK",
+            Self::P2pkh => "P2PKH",
+            Self::P2sh => "P2SH",
+            Self::P2wpkh => "P2WPKH",
+            Self::P2wsh => "P2WSH",
+            Self::P2tr => "P2TR",
+            Self::OpReturn => "OP_RETURN",
+            Self::BareMultisig => "bare multisig",
+            Self::NonStandard => "non-standard",
+        })
+    }
+}
+
+/// How an input satisfied the script of the output it spends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpendPath {
+    /// A signature in the scriptSig
+    P2pk,
+    /// Signature and public key in the scriptSig
+    P2pkh,
+    /// Signatures in the scriptSig, one per required key
+    BareMultisig,
+    /// A legacy redeem script (and what satisfies it) in the scriptSig
+    P2sh,
+    /// Signature and public key in the witness
+    P2wpkh,
+    /// A witness script (and what satisfies it) as the last witness item
+    P2wsh,
+    /// P2WPKH wrapped in P2SH: the scriptSig only pushes the witness program
+    P2shP2wpkh,
+    /// P2WSH wrapped in P2SH
+    P2shP2wsh,
+    /// A single Schnorr signature for the taproot output key
+    P2trKeyPath,
+    /// One leaf script of the taproot tree, revealed with its control block
+    P2trScriptPath,
+    /// The spent script isn't a template we know how to unlock
+    Unknown,
+}
+
+impl SpendPath {
+    /// Identify the spend path from the output being spent (`spent`) and the input's
+    /// `script_sig` and `witness`
+    pub fn of(spent: &Script, script_sig: &Script, witness: &Witness) -> Self {
+        match ScriptType::of(spent) {
+            ScriptType::P2pk => Self::P2pk,
+            ScriptType::P2pkh => Self::P2pkh,
+            ScriptType::BareMultisig => Self::BareMultisig,
+            ScriptType::P2wpkh => Self::P2wpkh,
+            ScriptType::P2wsh => Self::P2wsh,
+            ScriptType::P2sh => match last_push(script_sig) {
+                // The redeem script is the last thing the scriptSig pushes
+                Some(redeem) if Script::from_bytes(redeem).is_p2wpkh() => Self::P2shP2wpkh,
+                Some(redeem) if Script::from_bytes(redeem).is_p2wsh() => Self::P2shP2wsh,
+                _ => Self::P2sh,
+            },
+            ScriptType::P2tr => {
+                let annexed = witness.len() >= 2
+                    && witness.last().and_then(<[u8]>::first) == Some(&TAPROOT_ANNEX_PREFIX);
+                let stack = witness.len() - usize::from(annexed);
+                if stack == 1 {
+                    Self::P2trKeyPath
+                } else {
+                    Self::P2trScriptPath
+                }
+            },
+            ScriptType::OpReturn | ScriptType::NonStandard => Self::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for SpendPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::P2pk => "P2PK",
+            Self::P2pkh => "P2PKH",
+            Self::BareMultisig => "bare multisig",
+            Self::P2sh => "P2SH",
+            Self::P2wpkh => "P2WPKH",
+            Self::P2wsh => "P2WSH",
+            Self::P2shP2wpkh => "P2SH-P2WPKH",
+            Self::P2shP2wsh => "P2SH-P2WSH",
+            Self::P2trKeyPath => "P2TR key path",
+            Self::P2trScriptPath => "P2TR script path",
+            Self::Unknown => "unknown",
+        })
+    }
+}
+
+/// What a taproot script path spend reveals: the leaf that ran, and the control block
+/// proving the output key commits to it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TapscriptReveal {
+    /// 0xc0 for tapscript, the only leaf version defined so far
+    pub leaf_version: u8,
+    /// The leaf script, as ASM
+    pub script: String,
+    /// Hash of the leaf version and script: what the tree commits to
+    pub leaf_hash: String,
+    /// The key the tree was added to (x-only)
+    pub internal_key: String,
+    /// Whether the output key's Y coordinate is odd; x-only keys leave it out
+    pub output_key_parity: u8,
+    /// Hashes of the sibling branches from the leaf up to the root; as many as the
+    /// leaf is deep
+    pub merkle_path: Vec<String>,
+    /// The leaf and control block hash up to the spent output key (BIP341)
+    pub verified: bool,
+}
+
+impl TapscriptReveal {
+    /// Decode the script path spend in `witness` of the taproot output `spent`; `None`
+    /// for key path spends, other scripts, and control blocks that don't parse
+    pub fn of(spent: &Script, witness: &Witness) -> Option<Self> {
+        if SpendPath::of(spent, Script::new(), witness) != SpendPath::P2trScriptPath {
+            return None;
+        }
+        let mut items: Vec<&[u8]> = witness.iter().collect();
+        if items.last()?.first() == Some(&TAPROOT_ANNEX_PREFIX) {
+            items.pop();
+        }
+        let control_block = ControlBlock::decode(items.pop()?).ok()?;
+        let script = Script::from_bytes(items.pop()?);
+        let output_key = XOnlyPublicKey::from_slice(&spent.as_bytes()[2..]).ok()?;
+        let verified = control_block.verify_taproot_commitment(
+            &Secp256k1::verification_only(),
+            output_key,
+            script,
+        );
+        Some(Self {
+            leaf_version: control_block.leaf_version.to_consensus(),
+            script: script.to_asm_string(),
+            leaf_hash: TapLeafHash::from_script(script, control_block.leaf_version).to_string(),
+            internal_key: control_block.internal_key.to_string(),
+            output_key_parity: control_block.output_key_parity.to_u8(),
+            merkle_path: control_block
+                .merkle_branch
+                .as_inner()
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            verified,
+        })
+    }
+
+    /// How deep the leaf is in the tree: 0 when it's the only one
+    pub fn depth(&self) -> usize {
+        self.merkle_path.len()
+    }
+}
+
+/// The data of the last push in `script`, if it ends with one
+fn last_push(script: &Script) -> Option<&[u8]> {
+    match script.instructions().last()? {
+        Ok(Instruction::PushBytes(bytes)) => Some(bytes.as_bytes()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoincore_rpc::bitcoin::blockdata::opcodes::all::{
+        OP_CHECKMULTISIG, OP_CHECKSIG, OP_PUSHNUM_1,
+    };
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::script::Builder;
+    use bitcoincore_rpc::bitcoin::secp256k1::SecretKey;
+    use bitcoincore_rpc::bitcoin::taproot::{LeafVersion, TaprootBuilder};
+    use bitcoincore_rpc::bitcoin::{PubkeyHash, ScriptBuf, WPubkeyHash, WScriptHash};
+
+    use super::*;
+
+    const PUBKEY: [u8; 33] = [2; 33];
+
+    #[test]
+    fn classifies_the_standard_templates() {
+        let p2wpkh = ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros());
+        let cases = [
+            (
+                ScriptBuf::new_p2pkh(&PubkeyHash::all_zeros()),
+                ScriptType::P2pkh,
+            ),
+            (ScriptBuf::new_p2sh(&p2wpkh.script_hash()), ScriptType::P2sh),
+            (p2wpkh.clone(), ScriptType::P2wpkh),
+            (
+                ScriptBuf::new_p2wsh(&WScriptHash::all_zeros()),
+                ScriptType::P2wsh,
+            ),
+            (ScriptBuf::new_op_return(b"hello"), ScriptType::OpReturn),
+            (
+                Builder::new()
+                    .push_opcode(OP_PUSHNUM_1)
+                    .push_slice(PUBKEY)
+                    .push_opcode(OP_PUSHNUM_1)
+                    .push_opcode(OP_CHECKMULTISIG)
+                    .into_script(),
+                ScriptType::BareMultisig,
+            ),
+            (ScriptBuf::from_bytes(vec![0x51]), ScriptType::NonStandard),
+        ];
+        for (script, expected) in cases {
+            assert_eq!(
+                ScriptType::of(&script),
+                expected,
+                "{}",
+                script.to_asm_string()
+            );
+        }
+    }
+
+    #[test]
+    fn wrapped_segwit_shows_in_the_redeem_script() {
+        let p2wpkh = ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros());
+        let p2sh = ScriptBuf::new_p2sh(&p2wpkh.script_hash());
+        let script_sig = Builder::new()
+            .push_slice(<&[u8; 22]>::try_from(p2wpkh.as_bytes()).unwrap())
+            .into_script();
+        let witness = Witness::from_slice(&[vec![0x30; 71], PUBKEY.to_vec()]);
+        assert_eq!(
+            SpendPath::of(&p2sh, &script_sig, &witness),
+            SpendPath::P2shP2wpkh
+        );
+        // Anything else in the scriptSig is a plain legacy redeem script
+        let legacy = Builder::new().push_slice([0x51]).into_script();
+        assert_eq!(
+            SpendPath::of(&p2sh, &legacy, &Witness::new()),
+            SpendPath::P2sh
+        );
+    }
+
+    #[test]
+    fn script_path_reveals_its_leaf_and_proves_it() {
+        let secp = Secp256k1::new();
+        let key = |byte: u8| {
+            let secret = SecretKey::from_slice(&[byte; 32]).unwrap();
+            secret.x_only_public_key(&secp).0
+        };
+        let leaf = |byte: u8| {
+            Builder::new()
+                .push_x_only_key(&key(byte))
+                .push_opcode(OP_CHECKSIG)
+                .into_script()
+        };
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(1, leaf(2))
+            .unwrap()
+            .add_leaf(1, leaf(3))
+            .unwrap()
+            .finalize(&secp, key(1))
+            .unwrap();
+        let p2tr = ScriptBuf::new_p2tr_tweaked(spend_info.output_key());
+        let revealed = (leaf(3), LeafVersion::TapScript);
+        let control_block = spend_info.control_block(&revealed).unwrap();
+        let witness = Witness::from_slice(&[
+            vec![0x01; 64],
+            revealed.0.to_bytes(),
+            control_block.serialize(),
+        ]);
+
+        let reveal = TapscriptReveal::of(&p2tr, &witness).unwrap();
+        assert!(reveal.verified);
+        assert_eq!(reveal.leaf_version, 0xc0);
+        assert_eq!(reveal.script, revealed.0.to_asm_string());
+        assert_eq!(reveal.internal_key, key(1).to_string());
+        assert_eq!(reveal.depth(), 1);
+        // A leaf the output key doesn't commit to doesn't verify
+        let forged = Witness::from_slice(&[
+            vec![0x01; 64],
+            leaf(4).to_bytes(),
+            control_block.serialize(),
+        ]);
+        assert!(!TapscriptReveal::of(&p2tr, &forged).unwrap().verified);
+        // Key path spends reveal nothing
+        let key_path = Witness::from_slice(&[vec![0x01; 64]]);
+        assert_eq!(TapscriptReveal::of(&p2tr, &key_path), None);
+    }
+
+    #[test]
+    fn taproot_key_path_is_one_signature_annex_aside() {
+        let p2tr = ScriptBuf::from_bytes([&[0x51, 0x20][..], &[7; 32]].concat());
+        let empty = ScriptBuf::new();
+        let signature = vec![0x01; 64];
+        let key_path = Witness::from_slice(std::slice::from_ref(&signature));
+        let annexed = Witness::from_slice(&[signature.clone(), vec![TAPROOT_ANNEX_PREFIX]]);
+        let script_path = Witness::from_slice(&[signature, vec![0x51], vec![0xc0; 33]]);
+
+        assert_eq!(
+            SpendPath::of(&p2tr, &empty, &key_path),
+            SpendPath::P2trKeyPath
+        );
+        assert_eq!(
+            SpendPath::of(&p2tr, &empty, &annexed),
+            SpendPath::P2trKeyPath
+        );
+        assert_eq!(
+            SpendPath::of(&p2tr, &empty, &script_path),
+            SpendPath::P2trScriptPath
+        );
+    }
+}
@@ -0,0 +1,343 @@
+//! Taproot: one output key, several ways to spend it
+//!
+//! A taproot output (BIP341) pays to a single x-only key, the *output key*. It is the
+//! *internal key* tweaked with the root of a tree of scripts, so it can be spent either
+//! - by the key path: a Schnorr signature for the output key (the internal key's owner
+//!   signs with the tweaked secret), which looks like any single-key payment and shows
+//!   nothing of the tree, or
+//! - by the script path: the witness reveals one leaf script, what satisfies it, and a
+//!   control block with the internal key and the hashes of the branches not taken. The
+//!   node hashes its way from the leaf to the root and checks that it tweaks the
+//!   internal key into the output key. The other leaves stay secret.
+//!
+//! [`pay`] builds a tree of three `<key> CHECKSIG` leaves, one at depth 1 and two at
+//! depth 2 (leaves closer to the root need a shorter proof, so they're cheaper to use),
+//! funds two outputs paying to it and spends both in one payment: the first through
+//! the key path, the second through the chosen leaf. Nobody's wallet can sign for keys
+//! it didn't make, so they're throwaway ones generated here, and the spend is signed
+//! here too, then checked with `testmempoolaccept` before it's broadcast.
+//!
+//! The forensics report decodes what the script path input revealed
+//! ([`crate::script::TapscriptReveal`]).
+
+use std::fmt;
+
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::key::{Keypair, TapTweak};
+use bitcoincore_rpc::bitcoin::script::Builder;
+use bitcoincore_rpc::bitcoin::secp256k1::{All, Message, Secp256k1, rand};
+use bitcoincore_rpc::bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+use bitcoincore_rpc::bitcoin::taproot::{
+    self, LeafVersion, TapLeafHash, TaprootBuilder, TaprootSpendInfo,
+};
+use bitcoincore_rpc::bitcoin::{
+    Address, Amount, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
+    Witness, absolute, opcodes, transaction,
+};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Serialize;
+
+use crate::amount::{self, format_btc};
+use crate::coin_control;
+use crate::error::{Error, Result};
+use crate::transfer;
+use crate::wallets::AddressType;
+
+/// Depth of each leaf in the tree: one next to the root, two a level further down
+const LEAF_DEPTHS: [u8; 3] = [1, 2, 2];
+
+/// Each funded output carries half the payment and this much more, for the fee; what
+/// isn't needed comes back as change
+const FEE_RESERVE: Amount = Amount::from_sat(10_000);
+
+/// One leaf script of the tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TapLeaf {
+    /// How many branches down from the root: the length of its Merkle proof
+    pub depth: u8,
+    /// The script, as ASM
+    pub script: String,
+    /// Whether the payment revealed and ran it
+    pub spent: bool,
+}
+
+/// What [`pay`] did
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TaprootPayment {
+    /// The tree's output, as an address (bech32m)
+    pub address: String,
+    pub internal_key: String,
+    /// The internal key tweaked with the Merkle root: all the output itself shows
+    pub output_key: String,
+    pub merkle_root: String,
+    pub leaves: Vec<TapLeaf>,
+    /// The funded output spent through the key path...
+    pub key_path: OutPoint,
+    /// ... and the one spent through a leaf
+    pub script_path: OutPoint,
+    pub txid: Txid,
+    #[serde(with = "amount::exact")]
+    pub fee: Amount,
+}
+
+impl TaprootPayment {
+    /// Position of the leaf the script path input revealed
+    pub fn spent_leaf(&self) -> Option<usize> {
+        self.leaves.iter().position(|leaf| leaf.spent)
+    }
+}
+
+impl fmt::Display for TaprootPayment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Taproot output {} (internal key {}, Merkle root {})",
+            self.address, self.internal_key, self.merkle_root
+        )?;
+        for (index, leaf) in self.leaves.iter().enumerate() {
+            let spent = if leaf.spent { ", spent" } else { "" };
+            write!(
+                f,
+                "\n  leaf {index} at depth {}: {}{spent}",
+                leaf.depth, leaf.script
+            )?;
+        }
+        write!(
+            f,
+            "\n  {} spent through the key path, {} through a leaf, in {} (fee {} BTC)",
+            self.key_path,
+            self.script_path,
+            self.txid,
+            format_btc(self.fee)
+        )
+    }
+}
+
+/// Build a tree, fund two outputs paying to it from `funder`, and pay `amount` to
+/// `to` with both: one through the key path, one through leaf `leaf`
+///
+/// `rpc` is any client of the node. The funding is confirmed in a block mined to a
+/// fresh address of `funder`, which also gets the change (as Taproot change, like a
+/// wallet would pick for a Taproot payment). The payment is broadcast, not confirmed.
+pub fn pay(
+    rpc: &Client,
+    funder: &Client,
+    network: Network,
+    to: &Address,
+    amount: Amount,
+    leaf: usize,
+) -> Result<TaprootPayment> {
+    if leaf >= LEAF_DEPTHS.len() {
+        return Err(Error::Config(format!(
+            "the tree has {} leaves (0 to {}), not {leaf}",
+            LEAF_DEPTHS.len(),
+            LEAF_DEPTHS.len() - 1
+        )));
+    }
+    let secp = Secp256k1::new();
+    let tree = Tree::new(&secp)?;
+    let address = Address::p2tr_tweaked(tree.spend_info.output_key(), network);
+
+    // Two ordinary payments to the same address; the sender can't tell it has a tree
+    let funded = amount / 2 + FEE_RESERVE;
+    let mut coins = Vec::with_capacity(2);
+    for _ in 0..2 {
+        let funding = transfer::send_payment(funder, &address, funded)?;
+        let vout = rpc
+            .get_raw_transaction(&funding, None)?
+            .output
+            .iter()
+            .position(|output| output.script_pubkey == address.script_pubkey())
+            .ok_or_else(|| {
+                Error::Invariant(format!("{funding} doesn't pay the tree at {address}"))
+            })?;
+        coins.push(OutPoint::new(funding, vout as u32));
+    }
+    let mine_to = funder
+        .get_new_address(Some("Taproot"), None)?
+        .require_network(network)?;
+    transfer::confirm(funder, &mine_to)?;
+
+    let change = funder
+        .get_raw_change_address(Some(AddressType::Bech32m.to_rpc()))?
+        .require_network(network)?;
+    let prevout = TxOut {
+        value: funded,
+        script_pubkey: address.script_pubkey(),
+    };
+    let payment = TxOut {
+        value: amount,
+        script_pubkey: to.script_pubkey(),
+    };
+    let (tx, fee) = tree
+        .spend(
+            &secp,
+            &coins,
+            &prevout,
+            leaf,
+            (payment, change.script_pubkey()),
+        )
+        .map_err(Error::Invariant)?;
+    let acceptance = coin_control::test_accept(rpc, &tx)?;
+    if let Some(reason) = acceptance.reject_reason {
+        return Err(Error::Invariant(format!(
+            "the node refuses the Taproot spend {}: {reason}",
+            tx.txid()
+        )));
+    }
+    let txid = rpc.send_raw_transaction(&tx)?;
+
+    Ok(TaprootPayment {
+        address: address.to_string(),
+        internal_key: tree.internal.x_only_public_key().0.to_string(),
+        output_key: tree.spend_info.output_key().to_string(),
+        merkle_root: tree
+            .spend_info
+            .merkle_root()
+            .map_or_else(String::new, |root| root.to_string()),
+        leaves: tree
+            .leaves
+            .iter()
+            .zip(LEAF_DEPTHS)
+            .enumerate()
+            .map(|(index, ((_, script), depth))| TapLeaf {
+                depth,
+                script: script.to_asm_string(),
+                spent: index == leaf,
+            })
+            .collect(),
+        key_path: coins[0],
+        script_path: coins[1],
+        txid,
+        fee,
+    })
+}
+
+/// The keys and scripts of the tree, and what BIP341 makes of them
+struct Tree {
+    internal: Keypair,
+    /// Each leaf's key, and its `<key> CHECKSIG` script
+    leaves: Vec<(Keypair, ScriptBuf)>,
+    spend_info: TaprootSpendInfo,
+}
+
+impl Tree {
+    fn new(secp: &Secp256k1<All>) -> Result<Self> {
+        let internal = Keypair::new(secp, &mut rand::thread_rng());
+        let leaves: Vec<(Keypair, ScriptBuf)> = LEAF_DEPTHS
+            .iter()
+            .map(|_| {
+                let keypair = Keypair::new(secp, &mut rand::thread_rng());
+                let script = Builder::new()
+                    .push_x_only_key(&keypair.x_only_public_key().0)
+                    .push_opcode(opcodes::all::OP_CHECKSIG)
+                    .into_script();
+                (keypair, script)
+            })
+            .collect();
+        let invalid = |e: taproot::TaprootBuilderError| {
+            Error::Invariant(format!("can't build the script tree: {e}"))
+        };
+        let mut builder = TaprootBuilder::new();
+        for ((_, script), depth) in leaves.iter().zip(LEAF_DEPTHS) {
+            builder = builder.add_leaf(depth, script.clone()).map_err(invalid)?;
+        }
+        let spend_info = builder
+            .finalize(secp, internal.x_only_public_key().0)
+            .map_err(|_| Error::Invariant("the script tree is incomplete".to_owned()))?;
+        Ok(Self {
+            internal,
+            leaves,
+            spend_info,
+        })
+    }
+
+    /// Make `payment` from `coins` (each one `prevout`), the first spent through the
+    /// key path and the second through leaf `leaf`, with the rest to the `change` script
+    ///
+    /// The fee is 1 sat/vB: Schnorr signatures are always 64 bytes, so the size is
+    /// known before signing.
+    fn spend(
+        &self,
+        secp: &Secp256k1<All>,
+        coins: &[OutPoint],
+        prevout: &TxOut,
+        leaf: usize,
+        (payment, change): (TxOut, ScriptBuf),
+    ) -> std::result::Result<(Transaction, Amount), String> {
+        let (leaf_key, script) = &self.leaves[leaf];
+        let control_block = self
+            .spend_info
+            .control_block(&(script.clone(), LeafVersion::TapScript))
+            .ok_or_else(|| format!("leaf {leaf} isn't in the tree"))?;
+        let script_path_witness = |signature: Vec<u8>| {
+            Witness::from_slice(&[signature, script.to_bytes(), control_block.serialize()])
+        };
+
+        let mut tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: coins
+                .iter()
+                .map(|coin| TxIn {
+                    previous_output: *coin,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![
+                payment,
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: change,
+                },
+            ],
+        };
+        tx.input[0].witness = Witness::from_slice(&[[0u8; 64]]);
+        tx.input[1].witness = script_path_witness(vec![0; 64]);
+        let fee = Amount::from_sat(tx.vsize() as u64);
+        let funded = prevout.value * coins.len() as u64;
+        let amount = tx.output[0].value;
+        tx.output[1].value = funded
+            .checked_sub(amount + fee)
+            .ok_or_else(|| format!("{funded} doesn't cover {amount} and a {fee} fee"))?;
+
+        // Taproot signatures commit to every output being spent
+        let prevouts = vec![prevout.clone(); coins.len()];
+        let mut cache = SighashCache::new(&tx);
+        let key_path = cache
+            .taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&prevouts),
+                TapSighashType::Default,
+            )
+            .map_err(|e| format!("can't hash {} for signing: {e}", coins[0]))?;
+        let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+        let script_path = cache
+            .taproot_script_spend_signature_hash(
+                1,
+                &Prevouts::All(&prevouts),
+                leaf_hash,
+                TapSighashType::Default,
+            )
+            .map_err(|e| format!("can't hash {} for signing: {e}", coins[1]))?;
+
+        // The key path signs for the output key: the internal key, tweaked
+        let tweaked = self
+            .internal
+            .tap_tweak(secp, self.spend_info.merkle_root())
+            .to_inner();
+        let sign = |sighash: [u8; 32], keypair: &Keypair| {
+            let message = Message::from_digest(sighash);
+            taproot::Signature {
+                sig: secp.sign_schnorr_no_aux_rand(&message, keypair),
+                hash_ty: TapSighashType::Default,
+            }
+            .to_vec()
+        };
+        tx.input[0].witness = Witness::from_slice(&[sign(key_path.to_byte_array(), &tweaked)]);
+        tx.input[1].witness = script_path_witness(sign(script_path.to_byte_array(), leaf_key));
+        Ok((tx, fee))
+    }
+}
@@ -0,0 +1,386 @@
+//! Timelocks: coins that can't move, and transactions that can't be mined, before a block
+//!
+//! Three ways to make time a spending condition, all in [`demonstrate`]:
+//! - [`TimelockKind::LockTime`]: the spending transaction's nLockTime. It may not go
+//!   into a block below that height, so nodes refuse it until then (`non-final`). It
+//!   locks nothing but that one transaction: whoever signed it can sign another one
+//!   without it at any time.
+//! - [`TimelockKind::Cltv`]: `OP_CHECKLOCKTIMEVERIFY` (BIP65) puts an absolute lock in
+//!   the coin's script, which then only accepts a spend whose nLockTime is at least as
+//!   high. Too high to be mined yet is `non-final` again; lower than the script's is a
+//!   script failure.
+//! - [`TimelockKind::Csv`]: `OP_CHECKSEQUENCEVERIFY` (BIP112) makes the lock relative:
+//!   the coin must be buried under so many blocks, counted from the one that confirms
+//!   it. The spending input's sequence number says how many (BIP68), and until they're
+//!   mined the spend is `non-BIP68-final`; a sequence number below the script's is a
+//!   script failure.
+//!
+//! Each attempt goes through `testmempoolaccept`, so the node names the rule that
+//! keeps a premature spend out, then exactly as many blocks as the lock needs are mined
+//! and the same transaction gets in.
+//!
+//! The script locks are P2WSH `<lock> CLTV|CSV DROP <key> CHECKSIG`. The wallet can't
+//! sign for a script it didn't make, so the key is a throwaway one generated here.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::key::Keypair;
+use bitcoincore_rpc::bitcoin::script::Builder;
+use bitcoincore_rpc::bitcoin::secp256k1::{Message, Secp256k1, rand};
+use bitcoincore_rpc::bitcoin::sighash::SighashCache;
+use bitcoincore_rpc::bitcoin::{
+    Address, Amount, EcdsaSighashType, Network, OutPoint, PublicKey, ScriptBuf, Sequence,
+    Transaction, TxIn, TxOut, Txid, Witness, absolute, ecdsa, opcodes, transaction,
+};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Serialize;
+
+use crate::coin_control::{self, ChosenInput, MempoolAcceptance, RawTransactionSpec};
+use crate::error::{Error, Result};
+use crate::{mining, transfer};
+
+/// How much is locked up
+pub const LOCKED_AMOUNT: Amount = Amount::ONE_BTC;
+
+/// CSV counts blocks in the low 16 bits of the sequence number
+const MAX_RELATIVE_BLOCKS: u32 = u16::MAX as u32;
+
+/// The biggest DER signature plus its sighash byte, to weigh a spend before signing it
+const MAX_SIGNATURE_SIZE: usize = 73;
+
+/// Which timelock to demonstrate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimelockKind {
+    /// nLockTime on the spending transaction
+    LockTime,
+    /// An absolute lock in the coin's script (`OP_CHECKLOCKTIMEVERIFY`)
+    Cltv,
+    /// A relative lock in the coin's script (`OP_CHECKSEQUENCEVERIFY`)
+    Csv,
+}
+
+impl TimelockKind {
+    pub const ALL: [Self; 3] = [Self::LockTime, Self::Cltv, Self::Csv];
+}
+
+impl fmt::Display for TimelockKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::LockTime => "locktime",
+            Self::Cltv => "cltv",
+            Self::Csv => "csv",
+        })
+    }
+}
+
+impl FromStr for TimelockKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| {
+                kind.to_string().eq_ignore_ascii_case(s)
+                    || (s.eq_ignore_ascii_case("nlocktime") && *kind == Self::LockTime)
+            })
+            .ok_or_else(|| {
+                Error::Config(format!(
+                    "unknown timelock {s:?} (expected locktime, cltv or csv)"
+                ))
+            })
+    }
+}
+
+/// One spend of the locked coin put to `testmempoolaccept`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpendAttempt {
+    pub txid: Txid,
+    /// The timelock fields it sets, e.g. `nLockTime 110`
+    pub sets: String,
+    /// The chain tip it was tried at
+    pub tip_height: u64,
+    pub acceptance: MempoolAcceptance,
+}
+
+impl fmt::Display for SpendAttempt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "at height {}, {} with {}: ",
+            self.tip_height, self.txid, self.sets
+        )?;
+        match &self.acceptance.reject_reason {
+            None => write!(f, "accepted"),
+            Some(reason) => write!(f, "rejected ({reason})"),
+        }
+    }
+}
+
+/// What [`demonstrate`] did
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TimelockDemo {
+    pub kind: TimelockKind,
+    /// How many blocks the lock lasts
+    pub blocks: u32,
+    /// The coin that gets spent: one of the wallet's (nLockTime), or the P2WSH output
+    /// paying to the lock script
+    pub locked: OutPoint,
+    /// The lock script, as ASM (CLTV and CSV only)
+    pub witness_script: Option<String>,
+    /// Spends tried before the lock was up, every one of them rejected
+    pub premature: Vec<SpendAttempt>,
+    /// Blocks mined between those and the spend that got in
+    pub blocks_mined: u64,
+    /// The spend accepted once the lock was up, and broadcast (left unconfirmed)
+    pub spend: SpendAttempt,
+}
+
+impl fmt::Display for TimelockDemo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} lock of {} block(s) on {}",
+            self.kind, self.blocks, self.locked
+        )?;
+        if let Some(script) = &self.witness_script {
+            write!(f, " ({script})")?;
+        }
+        for attempt in &self.premature {
+            write!(f, "\n  {attempt}")?;
+        }
+        write!(f, "\n  mined {} block(s)", self.blocks_mined)?;
+        write!(f, "\n  {}, broadcast", self.spend)
+    }
+}
+
+/// A spend of the locked coin, and the timelock fields it sets
+type Attempt = (Transaction, String);
+
+/// Lock up a coin of `wallet` with a `kind` timelock of `blocks` blocks, show the
+/// mempool turning down spends until the lock is up, mine the blocks and spend it
+///
+/// `rpc` is any client of the node; the mined blocks and the unlocked coins go to
+/// fresh addresses of `wallet`, which needs a spendable coin of over
+/// [`LOCKED_AMOUNT`]. Every premature attempt must be rejected, and the last one
+/// accepted, or it's an error.
+pub fn demonstrate(
+    rpc: &Client,
+    wallet: &Client,
+    network: Network,
+    kind: TimelockKind,
+    blocks: u32,
+) -> Result<TimelockDemo> {
+    if blocks == 0 {
+        return Err(Error::Config(
+            "a timelock lasts at least 1 block".to_owned(),
+        ));
+    }
+    if kind == TimelockKind::Csv && blocks > MAX_RELATIVE_BLOCKS {
+        return Err(Error::Config(format!(
+            "a relative lock lasts at most {MAX_RELATIVE_BLOCKS} blocks, not {blocks}"
+        )));
+    }
+    let mine_to = wallet
+        .get_new_address(Some("Timelock"), None)?
+        .require_network(network)?;
+    let unlocked = wallet
+        .get_new_address(Some("Unlocked"), None)?
+        .require_network(network)?;
+    let tip = rpc.get_block_count()?;
+    // The lock is up once `blocks` more blocks are mined: the last of them is at this
+    // height, and the first block the spend may go into is the one after it
+    let height = lock_height(tip + u64::from(blocks))?;
+
+    let (locked, witness_script, honest, cheat) = match kind {
+        TimelockKind::LockTime => {
+            let coin = coin_control::list_coins(wallet)?
+                .into_iter()
+                .find(|coin| coin.amount > LOCKED_AMOUNT)
+                .ok_or_else(|| {
+                    Error::State(format!("no coin of over {LOCKED_AMOUNT} to lock up"))
+                })?;
+            let locked = OutPoint::new(coin.txid, coin.vout);
+            let change = wallet.get_raw_change_address(None)?;
+            let spec = RawTransactionSpec {
+                inputs: vec![ChosenInput::new(locked)],
+                outputs: vec![(unlocked, LOCKED_AMOUNT)],
+                change_address: Some(change.require_network(network)?),
+                lock_time: height,
+                ..RawTransactionSpec::default()
+            };
+            let built = coin_control::build(wallet, &spec)?;
+            let honest = (built.tx, format!("nLockTime {height}"));
+            (locked, None, honest, None)
+        },
+        TimelockKind::Cltv | TimelockKind::Csv => {
+            let secp = Secp256k1::new();
+            let keypair = Keypair::new(&secp, &mut rand::thread_rng());
+            let key = PublicKey::new(keypair.public_key());
+            let script = match kind {
+                TimelockKind::Cltv => cltv_script(height, &key),
+                _ => csv_script(blocks, &key),
+            };
+
+            // Paying to the lock is an ordinary payment; the spend doesn't even wait
+            // for it to confirm, so the lock alone is what keeps it out
+            let address = Address::p2wsh(&script, network);
+            let funding = transfer::send_payment(wallet, &address, LOCKED_AMOUNT)?;
+            let vout = rpc
+                .get_raw_transaction(&funding, None)?
+                .output
+                .iter()
+                .position(|output| output.script_pubkey == address.script_pubkey())
+                .ok_or_else(|| {
+                    Error::Invariant(format!("{funding} doesn't pay the lock at {address}"))
+                })?;
+            let locked = OutPoint::new(funding, vout as u32);
+            let spend = |lock_time, sequence| {
+                let tx =
+                    spend_locked(&keypair, &script, locked, &unlocked, lock_time, sequence);
+                tx.map_err(Error::Invariant)
+            };
+
+            let (honest, cheat) = if kind == TimelockKind::Cltv {
+                // The input's sequence number has to enable nLockTime for CLTV to pass
+                let sequence = Sequence::ENABLE_RBF_NO_LOCKTIME;
+                let early = lock_height(tip)?;
+                (
+                    (spend(height, sequence)?, format!("nLockTime {height}")),
+                    (
+                        spend(early, sequence)?,
+                        format!("nLockTime {early}, below the script's {height}"),
+                    ),
+                )
+            } else {
+                let sequence = Sequence::from_height(blocks as u16);
+                (
+                    (
+                        spend(absolute::LockTime::ZERO, sequence)?,
+                        format!("sequence {blocks} blocks"),
+                    ),
+                    (
+                        spend(absolute::LockTime::ZERO, Sequence::ZERO)?,
+                        format!("sequence 0 blocks, below the script's {blocks}"),
+                    ),
+                )
+            };
+            let asm = script.to_asm_string();
+            (locked, Some(asm), honest, Some(cheat))
+        },
+    };
+
+    let attempt = |(tx, sets): &Attempt| -> Result<SpendAttempt> {
+        Ok(SpendAttempt {
+            txid: tx.txid(),
+            sets: sets.clone(),
+            tip_height: rpc.get_block_count()?,
+            acceptance: coin_control::test_accept(rpc, tx)?,
+        })
+    };
+    let premature = [Some(&honest), cheat.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(attempt)
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(accepted) = premature.iter().find(|a| a.acceptance.allowed) {
+        return Err(Error::Invariant(format!(
+            "the mempool accepted {} before its {kind} lock was up",
+            accepted.txid
+        )));
+    }
+
+    let blocks_mined = mining::mine_blocks(rpc, &mine_to, u64::from(blocks))?.len() as u64;
+    let spend = attempt(&honest)?;
+    if let Some(reason) = &spend.acceptance.reject_reason {
+        return Err(Error::Invariant(format!(
+            "{} is still rejected after {blocks_mined} block(s): {reason}",
+            spend.txid
+        )));
+    }
+    rpc.send_raw_transaction(&honest.0)?;
+
+    Ok(TimelockDemo {
+        kind,
+        blocks,
+        locked,
+        witness_script,
+        premature,
+        blocks_mined,
+        spend,
+    })
+}
+
+/// `height` as an nLockTime, which has to stay below the timestamps' range
+fn lock_height(height: u64) -> Result<absolute::LockTime> {
+    u32::try_from(height)
+        .ok()
+        .and_then(|height| absolute::LockTime::from_height(height).ok())
+        .ok_or_else(|| Error::Config(format!("height {height} is too high for a lock")))
+}
+
+/// `<height> CHECKLOCKTIMEVERIFY DROP <key> CHECKSIG`: `key` may spend it from
+/// block `height` on
+pub fn cltv_script(height: absolute::LockTime, key: &PublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_int(i64::from(height.to_consensus_u32()))
+        .push_opcode(opcodes::all::OP_CLTV)
+        .push_opcode(opcodes::all::OP_DROP)
+        .push_key(key)
+        .push_opcode(opcodes::all::OP_CHECKSIG)
+        .into_script()
+}
+
+/// `<blocks> CHECKSEQUENCEVERIFY DROP <key> CHECKSIG`: `key` may spend it once it has
+/// `blocks` confirmations
+pub fn csv_script(blocks: u32, key: &PublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_int(i64::from(blocks))
+        .push_opcode(opcodes::all::OP_CSV)
+        .push_opcode(opcodes::all::OP_DROP)
+        .push_key(key)
+        .push_opcode(opcodes::all::OP_CHECKSIG)
+        .into_script()
+}
+
+/// Spend the P2WSH output `locked` (worth [`LOCKED_AMOUNT`], paying to `script`) to
+/// `to`, signed with `keypair`
+///
+/// Version 2, so the sequence number's relative lock counts. The fee is 1 sat/vB of
+/// the largest the signed transaction can be.
+fn spend_locked(
+    keypair: &Keypair,
+    script: &ScriptBuf,
+    locked: OutPoint,
+    to: &Address,
+    lock_time: absolute::LockTime,
+    sequence: Sequence,
+) -> std::result::Result<Transaction, String> {
+    let mut tx = Transaction {
+        version: transaction::Version::TWO,
+        lock_time,
+        input: vec![TxIn {
+            previous_output: locked,
+            script_sig: ScriptBuf::new(),
+            sequence,
+            witness: Witness::from_slice(&[vec![0; MAX_SIGNATURE_SIZE], script.to_bytes()]),
+        }],
+        output: vec![TxOut {
+            value: LOCKED_AMOUNT,
+            script_pubkey: to.script_pubkey(),
+        }],
+    };
+    tx.output[0].value = LOCKED_AMOUNT - Amount::from_sat(tx.vsize() as u64);
+
+    let sighash = SighashCache::new(&tx)
+        .p2wsh_signature_hash(0, script, LOCKED_AMOUNT, EcdsaSighashType::All)
+        .map_err(|e| format!("can't hash {locked} for signing: {e}"))?;
+    let secp = Secp256k1::signing_only();
+    let message = Message::from_digest(sighash.to_byte_array());
+    let signature =
+        ecdsa::Signature::sighash_all(secp.sign_ecdsa(&message, &keypair.secret_key()));
+    tx.input[0].witness = Witness::from_slice(&[signature.to_vec(), script.to_bytes()]);
+    Ok(tx)
+}
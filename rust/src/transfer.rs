@@ -0,0 +1,132 @@
+//! Sending payments and confirming them
+//!
+//! This is where Bitcoin's UTXO model becomes apparent:
+//! we're not "transferring money" - we're consuming previous outputs and creating new ones.
+
+use bitcoincore_rpc::bitcoin::{
+    Address, Amount, BlockHash, ScriptBuf, Transaction, TxIn, TxOut, Txid, Witness,
+};
+use bitcoincore_rpc::{Client, RpcApi};
+
+use crate::error::{Error, Result};
+
+/// Pay `amount` to `recipient` from the wallet behind `rpc` and broadcast it
+pub fn send_payment(rpc: &Client, recipient: &Address, amount: Amount) -> Result<Txid> {
+    // send_to_address() is a high-level RPC call that:
+    // 1. Selects appropriate UTXOs (coins) from our wallet
+    // 2. Creates a transaction consuming those UTXOs as inputs
+    // 3. Creates two outputs: one to recipient, one back to us as "change"
+    // 4. Calculates and includes appropriate mining fees
+    // 5. Signs the transaction with our private keys
+    // 6. Broadcasts it to the network (mempool)
+    let txid = rpc.send_to_address(
+        recipient, // Destination address
+        amount,    // Amount to send
+        None,      // Comment (stored locally, not on blockchain)
+        None,      // Comment_to (stored locally, not on blockchain)
+        None,      // Subtract fee from amount? (false = add fee on top)
+        None,      // Replaceable? (RBF - Replace By Fee capability)
+        None,      // Confirmation target (affects fee calculation)
+        None,      // Estimate mode (affects fee calculation algorithm)
+    )?;
+    Ok(txid)
+}
+
+/// Like [`send_payment`], but explicitly signalling that the payment may be replaced
+///
+/// BIP125 "opt-in RBF": every input gets a sequence number below 0xfffffffe, which
+/// tells nodes that the sender may still swap this transaction for one paying a
+/// higher fee (see replacement.rs). Recent wallets do this by default anyway.
+pub fn send_replaceable_payment(
+    rpc: &Client,
+    recipient: &Address,
+    amount: Amount,
+) -> Result<Txid> {
+    let txid =
+        rpc.send_to_address(recipient, amount, None, None, None, Some(true), None, None)?;
+    Ok(txid)
+}
+
+/// A transaction spending the same outputs as `txid`, paying all of it back to `to`
+///
+/// It pays the original's fee plus `extra_fee`, keeps its inputs' sequence numbers
+/// (so it signals RBF exactly when the original did) and is signed by `wallet`, which
+/// must own every input of `txid` (it's the sender's wallet). Nothing is broadcast:
+/// that's up to the caller, which is what makes this a double-spend.
+pub fn respend(
+    wallet: &Client,
+    txid: &Txid,
+    to: &Address,
+    extra_fee: Amount,
+) -> Result<Transaction> {
+    let original = wallet.get_raw_transaction(txid, None)?;
+    let mut spent = Amount::ZERO;
+    for input in &original.input {
+        let outpoint = input.previous_output;
+        let parent = wallet.get_raw_transaction(&outpoint.txid, None)?;
+        let output = parent.output.get(outpoint.vout as usize).ok_or_else(|| {
+            Error::Invariant(format!("input spends {outpoint}, which does not exist"))
+        })?;
+        spent += output.value;
+    }
+    let paid: Amount = original.output.iter().map(|output| output.value).sum();
+    let fee = spent.checked_sub(paid).ok_or_else(|| {
+        Error::Invariant(format!("{txid} pays out more than its inputs hold"))
+    })?;
+    let value = spent
+        .checked_sub(fee + extra_fee)
+        .ok_or_else(|| Error::State(format!("{txid} can't pay {extra_fee} more in fees")))?;
+
+    let unsigned = Transaction {
+        version: original.version,
+        lock_time: original.lock_time,
+        input: original
+            .input
+            .iter()
+            .map(|input| TxIn {
+                previous_output: input.previous_output,
+                script_sig: ScriptBuf::new(),
+                sequence: input.sequence,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: vec![TxOut {
+            value,
+            script_pubkey: to.script_pubkey(),
+        }],
+    };
+
+    sign(wallet, &unsigned)
+}
+
+/// Have `wallet` sign every input of `unsigned`; an error unless it can sign them all
+pub fn sign(wallet: &Client, unsigned: &Transaction) -> Result<Transaction> {
+    let signed = wallet.sign_raw_transaction_with_wallet(unsigned, None, None)?;
+    if !signed.complete {
+        let errors: Vec<String> = signed
+            .errors
+            .unwrap_or_default()
+            .into_iter()
+            .map(|e| format!("{}:{}: {}", e.txid, e.vout, e.error))
+            .collect();
+        return Err(Error::State(format!(
+            "the wallet can't sign every input of {}: {}",
+            unsigned.txid(),
+            errors.join("; ")
+        )));
+    }
+    signed
+        .transaction()
+        .map_err(|e| Error::Invariant(format!("signed transaction doesn't decode: {e}")))
+}
+
+/// Mine one block to `address`, which pulls pending mempool transactions into the chain
+///
+/// This simulates what miners do: select transactions from mempool and include them in blocks.
+pub fn confirm(rpc: &Client, address: &Address) -> Result<BlockHash> {
+    let hashes = rpc.generate_to_address(1, address)?;
+    hashes
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::Invariant("generatetoaddress returned no block hash".to_owned()))
+}
@@ -0,0 +1,310 @@
+//! Wallet management
+//!
+//! Bitcoin Core can manage multiple wallets simultaneously.
+//! Each wallet has its own keys, addresses, and transaction history.
+//!
+//! A wallet can be in one of three states on a node:
+//! - loaded: in memory, usable right away
+//! - on disk: in the wallet directory (`listwalletdir`) but not loaded, e.g. after a restart
+//! - missing: never created
+//!
+//! [`provision`] takes a declarative list of [`WalletSpec`]s and brings every wallet to
+//! "loaded", creating it with the requested options when it doesn't exist yet.
+//! Running it twice is harmless.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoincore_rpc::{Client, RpcApi, json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+
+/// Which wallet database/key model to create
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WalletKind {
+    /// Output-descriptor wallet (Bitcoin Core's default since v23)
+    Descriptor,
+    /// Legacy BDB wallet with a keypool; deprecated and removed in newer Core releases
+    Legacy,
+}
+
+/// The kind of address a wallet hands out, by the names Core's `-addresstype` takes
+///
+/// Every one of them pays to a single key of the wallet; only the script (and so the
+/// cost of spending it) differs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AddressType {
+    /// P2PKH: signature and key in the scriptSig (`m`/`n` on test networks)
+    Legacy,
+    /// P2WPKH wrapped in P2SH, for senders that predate segwit (`2` on test networks)
+    P2shSegwit,
+    /// Native segwit v0 P2WPKH (`bcrt1q` on regtest)
+    Bech32,
+    /// Taproot (P2TR) spent through its key path (`bcrt1p` on regtest)
+    Bech32m,
+}
+
+impl AddressType {
+    pub const ALL: [Self; 4] = [Self::Legacy, Self::P2shSegwit, Self::Bech32, Self::Bech32m];
+
+    /// The same type, as the RPC client's `getnewaddress` takes it
+    pub fn to_rpc(self) -> json::AddressType {
+        match self {
+            Self::Legacy => json::AddressType::Legacy,
+            Self::P2shSegwit => json::AddressType::P2shSegwit,
+            Self::Bech32 => json::AddressType::Bech32,
+            Self::Bech32m => json::AddressType::Bech32m,
+        }
+    }
+}
+
+impl fmt::Display for AddressType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Legacy => "legacy",
+            Self::P2shSegwit => "p2sh-segwit",
+            Self::Bech32 => "bech32",
+            Self::Bech32m => "bech32m",
+        })
+    }
+}
+
+impl FromStr for AddressType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|address_type| address_type.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                Error::Config(format!(
+                    "unknown address type {s:?} (expected legacy, p2sh-segwit, bech32 or bech32m)"
+                ))
+            })
+    }
+}
+
+/// How a wallet should look once provisioned
+///
+/// Deserializable, with every field but `name` optional; scenario files take the same
+/// fields next to a participant's `node` (see scenario.rs).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalletSpec {
+    pub name: String,
+    /// `None` leaves the choice to the node (and accepts either kind when loading)
+    pub kind: Option<WalletKind>,
+    /// Create without keys or descriptors (to import some later)
+    pub blank: bool,
+    /// Watch-only: the wallet can track addresses but never sign
+    #[serde(alias = "watch_only")]
+    pub disable_private_keys: bool,
+    /// Encrypt the wallet; it must be unlocked (see [`unlock`]) before signing
+    pub passphrase: Option<String>,
+    /// Don't spend from addresses that were already spent from (privacy)
+    pub avoid_reuse: bool,
+}
+
+impl WalletSpec {
+    /// A plain wallet with the node's default options
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            ..Self::default()
+        }
+    }
+}
+
+/// `name[:option,option,...]`, e.g. `Cold:watch-only,blank` or `Vault:passphrase=hunter2`
+///
+/// Options: `descriptor`, `legacy`, `blank`, `watch-only`, `avoid-reuse`, `passphrase=<p>`.
+impl FromStr for WalletSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, options) = s.split_once(':').unwrap_or((s, ""));
+        if name.is_empty() {
+            return Err(Error::Config(format!("wallet spec {s:?} has no name")));
+        }
+
+        let mut spec = Self::new(name);
+        for option in options.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+            match option.split_once('=') {
+                Some(("passphrase", passphrase)) => {
+                    spec.passphrase = Some(passphrase.to_owned())
+                },
+                None if option == "descriptor" => spec.kind = Some(WalletKind::Descriptor),
+                None if option == "legacy" => spec.kind = Some(WalletKind::Legacy),
+                None if option == "blank" => spec.blank = true,
+                None if option == "watch-only" => spec.disable_private_keys = true,
+                None if option == "avoid-reuse" => spec.avoid_reuse = true,
+                _ => {
+                    return Err(Error::Config(format!(
+                        "unknown option {option:?} in wallet spec {s:?}"
+                    )));
+                },
+            }
+        }
+        Ok(spec)
+    }
+}
+
+/// What [`provision`] had to do for one wallet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalletState {
+    /// It was already loaded; nothing to do
+    AlreadyLoaded,
+    /// It existed on disk and was loaded
+    Loaded,
+    /// It didn't exist and was created (creating also loads it)
+    Created,
+}
+
+impl fmt::Display for WalletState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::AlreadyLoaded => "already loaded",
+            Self::Loaded => "loaded from disk",
+            Self::Created => "created",
+        })
+    }
+}
+
+/// The subset of `getwalletinfo` needed to check an existing wallet against its spec
+#[derive(Debug, Deserialize)]
+struct WalletFlags {
+    private_keys_enabled: bool,
+    avoid_reuse: Option<bool>,
+    descriptors: Option<bool>,
+}
+
+/// Make sure every wallet in `specs` exists and is loaded, in order
+///
+/// Existing wallets are checked against their spec: a wallet that was created with
+/// different key options (say, not watch-only) can't be converted, so that's an error
+/// rather than a silent surprise later on.
+pub fn provision(connection: &Connection, specs: &[WalletSpec]) -> Result<Vec<WalletState>> {
+    let rpc = connection.client(None)?;
+    let loaded = rpc.list_wallets()?;
+    let on_disk = rpc.list_wallet_dir()?;
+
+    let mut states = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let state = if loaded.contains(&spec.name) {
+            WalletState::AlreadyLoaded
+        } else if on_disk.contains(&spec.name) {
+            // If wallet already exists, we need to load it into memory
+            rpc.load_wallet(&spec.name)?;
+            WalletState::Loaded
+        } else {
+            create(&rpc, spec)?;
+            WalletState::Created
+        };
+
+        if state != WalletState::Created {
+            check_matches(&connection.client(Some(&spec.name))?, spec)?;
+        }
+        states.push(state);
+    }
+    Ok(states)
+}
+
+/// Provision plain wallets by name (the simulator's Miner and Trader)
+pub fn ensure_wallets(connection: &Connection, names: &[&str]) -> Result<Vec<WalletState>> {
+    let specs: Vec<WalletSpec> = names.iter().map(|name| WalletSpec::new(name)).collect();
+    provision(connection, &specs)
+}
+
+/// Unlock an encrypted wallet for `seconds` so it can sign
+pub fn unlock(wallet_rpc: &Client, passphrase: &str, seconds: u64) -> Result<()> {
+    wallet_rpc
+        .call::<serde_json::Value>("walletpassphrase", &[json!(passphrase), json!(seconds)])?;
+    Ok(())
+}
+
+fn create(rpc: &Client, spec: &WalletSpec) -> Result<()> {
+    // The bitcoincore-rpc helper predates the `descriptors` argument, so call it directly
+    // createwallet params: (name, disable_private_keys, blank, passphrase, avoid_reuse, descriptors)
+    rpc.call::<serde_json::Value>(
+        "createwallet",
+        &[
+            json!(spec.name),
+            json!(spec.disable_private_keys),
+            json!(spec.blank),
+            json!(spec.passphrase.as_deref().unwrap_or("")),
+            json!(spec.avoid_reuse),
+            json!(spec.kind.map(|kind| kind == WalletKind::Descriptor)),
+        ],
+    )?;
+    Ok(())
+}
+
+fn check_matches(wallet_rpc: &Client, spec: &WalletSpec) -> Result<()> {
+    let flags: WalletFlags = wallet_rpc.call("getwalletinfo", &[])?;
+
+    let mut mismatches = Vec::new();
+    if flags.private_keys_enabled == spec.disable_private_keys {
+        mismatches.push(format!(
+            "private keys enabled = {}",
+            flags.private_keys_enabled
+        ));
+    }
+    if flags
+        .avoid_reuse
+        .is_some_and(|avoid_reuse| avoid_reuse != spec.avoid_reuse)
+    {
+        mismatches.push(format!("avoid_reuse = {}", !spec.avoid_reuse));
+    }
+    if let (Some(descriptors), Some(kind)) = (flags.descriptors, spec.kind) {
+        if descriptors != (kind == WalletKind::Descriptor) {
+            mismatches.push(format!("descriptors = {descriptors}"));
+        }
+    }
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Config(format!(
+            "existing wallet {:?} doesn't match its spec: {}",
+            spec.name,
+            mismatches.join(", ")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bare_name_is_a_default_wallet() {
+        assert_eq!(
+            "Miner".parse::<WalletSpec>().unwrap(),
+            WalletSpec::new("Miner")
+        );
+    }
+
+    #[test]
+    fn options_after_the_colon() {
+        let spec: WalletSpec = "Vault:descriptor, avoid-reuse,passphrase=a=b"
+            .parse()
+            .unwrap();
+        assert_eq!(spec.kind, Some(WalletKind::Descriptor));
+        assert!(spec.avoid_reuse);
+        assert_eq!(spec.passphrase.as_deref(), Some("a=b"));
+
+        let cold: WalletSpec = "Cold:watch-only,blank".parse().unwrap();
+        assert!(cold.disable_private_keys && cold.blank);
+    }
+
+    #[test]
+    fn rejects_unknown_options_and_empty_names() {
+        assert!("Miner:turbo".parse::<WalletSpec>().is_err());
+        assert!(":blank".parse::<WalletSpec>().is_err());
+    }
+}
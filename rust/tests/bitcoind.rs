@@ -0,0 +1,187 @@
+//! Node readiness and the managed bitcoind lifecycle
+
+use std::time::Duration;
+
+use bitcoincore_rpc::RpcApi;
+use rust::Error;
+use rust::bitcoind::{self, Bitcoind, BitcoindOptions};
+use rust::mock::MockNode;
+use rust::progress;
+
+#[test]
+fn ready_node_passes_straight_away() {
+    let node = MockNode::start().unwrap();
+    bitcoind::wait_for_rpc(&node.connection(), Duration::ZERO, progress::quiet).unwrap();
+}
+
+#[test]
+fn warmup_errors_are_retried() {
+    let node = MockNode::start().unwrap();
+    node.warm_up(3);
+
+    bitcoind::wait_for_rpc(&node.connection(), Duration::from_secs(10), progress::quiet)
+        .unwrap();
+    assert_eq!(node.calls().len(), 4);
+}
+
+#[test]
+fn warmup_that_outlasts_the_timeout_is_an_error() {
+    let node = MockNode::start().unwrap();
+    node.warm_up(usize::MAX);
+
+    let error = bitcoind::wait_for_rpc(
+        &node.connection(),
+        Duration::from_millis(300),
+        progress::quiet,
+    )
+    .unwrap_err();
+    assert!(
+        matches!(error, Error::Node(ref message) if message.contains("Loading block index")),
+        "{error}"
+    );
+}
+
+#[test]
+fn missing_executable_is_reported() {
+    let options = BitcoindOptions {
+        executable: "/nonexistent/bitcoind".into(),
+        ..BitcoindOptions::default()
+    };
+    let error = Bitcoind::spawn(&options).unwrap_err();
+    assert!(
+        error.to_string().contains("/nonexistent/bitcoind"),
+        "{error}"
+    );
+}
+
+/// A stand-in executable that behaves like a bitcoind failing at startup
+#[cfg(unix)]
+fn fake_bitcoind(dir: &std::path::Path, script: &str) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.join("bitcoind");
+    std::fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[cfg(unix)]
+#[test]
+fn startup_errors_include_the_nodes_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let options = BitcoindOptions {
+        executable: fake_bitcoind(
+            dir.path(),
+            "echo 'Error: Cannot obtain a lock on data directory' >&2; exit 1",
+        ),
+        ..BitcoindOptions::default()
+    };
+
+    let error = Bitcoind::spawn(&options).unwrap_err();
+    assert!(
+        error.to_string().contains("Cannot obtain a lock"),
+        "{error}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn node_that_never_answers_times_out() {
+    let dir = tempfile::tempdir().unwrap();
+    let options = BitcoindOptions {
+        executable: fake_bitcoind(dir.path(), "exec sleep 30"),
+        startup_timeout: Duration::from_millis(300),
+        ..BitcoindOptions::default()
+    };
+
+    let error = Bitcoind::spawn(&options).unwrap_err();
+    assert!(error.to_string().contains("not ready"), "{error}");
+}
+
+/// A stand-in that records its datadir argument and pid in `started`, then hangs like
+/// a node stuck starting up
+#[cfg(unix)]
+fn hanging_bitcoind(dir: &std::path::Path, started: &std::path::Path) -> std::path::PathBuf {
+    let script = format!(
+        "echo \"$1 $$\" > {0}.tmp && mv {0}.tmp {0}; exec sleep 30",
+        started.display()
+    );
+    fake_bitcoind(dir, &script)
+}
+
+/// The datadir and pid [`hanging_bitcoind`] recorded
+#[cfg(unix)]
+fn started_node(started: &std::path::Path) -> (std::path::PathBuf, String) {
+    let started = std::fs::read_to_string(started).unwrap();
+    let (datadir, pid) = started.trim().split_once(' ').unwrap();
+    let datadir = datadir.strip_prefix("-datadir=").unwrap();
+    (datadir.into(), pid.to_owned())
+}
+
+#[cfg(unix)]
+fn kill(args: &[&str]) -> bool {
+    std::process::Command::new("kill")
+        .args(args)
+        .stderr(std::process::Stdio::null())
+        .status()
+        .unwrap()
+        .success()
+}
+
+#[cfg(unix)]
+#[test]
+fn failed_startup_kills_the_node_and_deletes_the_datadir() {
+    let dir = tempfile::tempdir().unwrap();
+    let started = dir.path().join("started");
+    let options = BitcoindOptions {
+        executable: hanging_bitcoind(dir.path(), &started),
+        startup_timeout: Duration::from_millis(300),
+        ..BitcoindOptions::default()
+    };
+
+    Bitcoind::spawn(&options).unwrap_err();
+    let (datadir, pid) = started_node(&started);
+    assert!(!datadir.exists());
+    assert!(!kill(&["-0", &pid]));
+}
+
+#[cfg(unix)]
+#[test]
+fn terminated_binary_kills_its_node_and_deletes_the_datadir() {
+    let dir = tempfile::tempdir().unwrap();
+    let started = dir.path().join("started");
+    let mut binary = std::process::Command::new(env!("CARGO_BIN_EXE_rust"))
+        .args(["--spawn-node", "--bitcoind"])
+        .arg(hanging_bitcoind(dir.path(), &started))
+        .arg("info")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    while !started.exists() {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    // Give the binary a moment to take note of the node it just started
+    std::thread::sleep(Duration::from_millis(200));
+    let (datadir, pid) = started_node(&started);
+    assert!(datadir.is_dir());
+
+    assert!(kill(&["-TERM", &binary.id().to_string()]));
+    assert!(!binary.wait().unwrap().success());
+    assert!(!datadir.exists());
+    // The node is gone too, not left running on its own
+    assert!(!kill(&["-0", &pid]));
+}
+
+/// Needs a real bitcoind on PATH: `cargo test -- --ignored`
+#[test]
+#[ignore]
+fn spawns_a_real_regtest_node() {
+    let node = Bitcoind::spawn(&BitcoindOptions::default()).unwrap();
+    let rpc = node.connection().client(None).unwrap();
+    assert_eq!(rpc.get_block_count().unwrap(), 0);
+
+    let datadir = node.datadir().to_path_buf();
+    node.stop().unwrap();
+    assert!(!datadir.exists());
+}
@@ -0,0 +1,218 @@
+//! Telling change from payments by asking the sender's wallet
+
+mod common;
+
+use bitcoincore_rpc::bitcoin::{
+    Address, Amount, Network, OutPoint, ScriptBuf, Sequence, SignedAmount, Transaction, TxIn,
+    TxOut, Txid, Witness, absolute, transaction,
+};
+use bitcoincore_rpc::{Client, RpcApi};
+use rust::analysis::{self, OutputRole};
+use rust::mock::MockNode;
+use rust::transfer;
+
+use common::Funded;
+
+/// Miner can spend; Trader and Shop each have an address to be paid at
+struct Payees {
+    _node: MockNode,
+    miner: Client,
+    miner_address: Address,
+    trader_address: Address,
+    shop_address: Address,
+}
+
+impl Payees {
+    fn new() -> Self {
+        let funded = Funded::with_wallets(&["Shop"]);
+        let shop = funded.node.connection().client(Some("Shop")).unwrap();
+        let shop_address = shop.get_new_address(None, None).unwrap().assume_checked();
+        Self {
+            _node: funded.node,
+            miner: funded.miner,
+            miner_address: funded.miner_address,
+            trader_address: funded.trader_address,
+            shop_address,
+        }
+    }
+
+    /// Spend one of Miner's coins into `outputs`, leaving `fee`, and confirm it
+    fn batch(&self, outputs: &[(&Address, Amount)], fee: Amount) -> Txid {
+        let coin = self
+            .miner
+            .list_unspent(None, None, None, None, None)
+            .unwrap()
+            .remove(0);
+        let paid: Amount = outputs.iter().map(|(_, amount)| *amount).sum();
+        let mut output: Vec<TxOut> = outputs
+            .iter()
+            .map(|(address, value)| TxOut {
+                value: *value,
+                script_pubkey: address.script_pubkey(),
+            })
+            .collect();
+        // Whatever is left goes to one more change address
+        let rest = self
+            .miner
+            .get_raw_change_address(None)
+            .unwrap()
+            .assume_checked();
+        output.push(TxOut {
+            value: coin.amount - paid - fee,
+            script_pubkey: rest.script_pubkey(),
+        });
+        let unsigned = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(coin.txid, coin.vout),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output,
+        };
+        let txid = self
+            .miner
+            .send_raw_transaction(&transfer::sign(&self.miner, &unsigned).unwrap())
+            .unwrap();
+        transfer::confirm(&self.miner, &self.miner_address).unwrap();
+        txid
+    }
+}
+
+#[test]
+fn change_comes_from_the_wallets_change_chain() {
+    let payees = Payees::new();
+    let txid = transfer::send_payment(
+        &payees.miner,
+        &payees.trader_address,
+        Amount::from_btc(20.0).unwrap(),
+    )
+    .unwrap();
+    transfer::confirm(&payees.miner, &payees.miner_address).unwrap();
+
+    let analysis =
+        analysis::analyze(&payees.miner, &payees.miner, &txid, Network::Regtest).unwrap();
+    assert_eq!(
+        analysis.recipient_addresses,
+        [payees.trader_address.to_string()]
+    );
+    assert_eq!(analysis.recipient_amount, Amount::from_btc(20.0).unwrap());
+    let change = analysis
+        .outputs
+        .iter()
+        .find(|output| output.role == OutputRole::Change)
+        .unwrap();
+    assert_eq!(analysis.change_addresses, [change.address.clone().unwrap()]);
+    let ownership = change.ownership.as_ref().unwrap();
+    assert!(ownership.is_mine && ownership.is_change, "{ownership:?}");
+    // The change chain of the wallet's BIP84 account
+    let keypath = ownership.hd_keypath.as_deref().unwrap();
+    assert!(keypath.starts_with("m/84h/1h/0h/1/"), "{keypath}");
+
+    // Nothing about the Trader's address is known to the Miner
+    let payment = analysis
+        .outputs
+        .iter()
+        .find(|output| output.role == OutputRole::Payment)
+        .unwrap();
+    let ownership = payment.ownership.as_ref().unwrap();
+    assert!(!ownership.is_mine && !ownership.is_change);
+    assert_eq!(ownership.hd_keypath, None);
+}
+
+#[test]
+fn batch_payment_has_several_recipients_and_change_outputs() {
+    let payees = Payees::new();
+    let split = payees
+        .miner
+        .get_raw_change_address(None)
+        .unwrap()
+        .assume_checked();
+    let (to_trader, to_shop, kept) = (
+        Amount::from_btc(1.0).unwrap(),
+        Amount::from_btc(2.0).unwrap(),
+        Amount::from_btc(3.0).unwrap(),
+    );
+    let fee = Amount::from_sat(500);
+    // Change first: its position gives nothing away
+    let txid = payees.batch(
+        &[
+            (&split, kept),
+            (&payees.trader_address, to_trader),
+            (&payees.shop_address, to_shop),
+        ],
+        fee,
+    );
+
+    let analysis =
+        analysis::analyze(&payees.miner, &payees.miner, &txid, Network::Regtest).unwrap();
+    let roles: Vec<OutputRole> = analysis.outputs.iter().map(|output| output.role).collect();
+    assert_eq!(
+        roles,
+        [
+            OutputRole::Change,
+            OutputRole::Payment,
+            OutputRole::Payment,
+            OutputRole::Change
+        ]
+    );
+    assert_eq!(
+        analysis.recipient_addresses,
+        [
+            payees.trader_address.to_string(),
+            payees.shop_address.to_string()
+        ]
+    );
+    assert_eq!(analysis.recipient_amount, to_trader + to_shop);
+    assert_eq!(analysis.change_addresses.len(), 2);
+    assert_eq!(
+        analysis.change_amount + analysis.recipient_amount + fee,
+        analysis.total_input_amount
+    );
+    // The sender is out exactly what the recipients got, plus the fee
+    assert_eq!(
+        analysis.sender_net,
+        SignedAmount::ZERO - (to_trader + to_shop + fee).to_signed().unwrap()
+    );
+}
+
+#[test]
+fn coins_sent_back_to_a_receive_address_are_a_self_payment() {
+    let payees = Payees::new();
+    let own = payees
+        .miner
+        .get_new_address(Some("Savings"), None)
+        .unwrap()
+        .assume_checked();
+    let (to_trader, to_self, fee) = (
+        Amount::from_btc(1.0).unwrap(),
+        Amount::from_btc(5.0).unwrap(),
+        Amount::from_sat(500),
+    );
+    let txid = payees.batch(&[(&payees.trader_address, to_trader), (&own, to_self)], fee);
+
+    let analysis =
+        analysis::analyze(&payees.miner, &payees.miner, &txid, Network::Regtest).unwrap();
+    let savings = &analysis.outputs[1];
+    assert_eq!(savings.role, OutputRole::Payment);
+    // The wallet owns it, but not on its change chain
+    let ownership = savings.ownership.as_ref().unwrap();
+    assert!(ownership.is_mine && !ownership.is_change);
+    let keypath = ownership.hd_keypath.as_deref().unwrap();
+    assert!(keypath.starts_with("m/84h/1h/0h/0/"), "{keypath}");
+
+    assert_eq!(
+        analysis.recipient_addresses,
+        [payees.trader_address.to_string(), own.to_string()]
+    );
+    assert_eq!(analysis.recipient_amount, to_trader + to_self);
+    // Only the rest of the coin went back as change...
+    assert_eq!(analysis.change_addresses.len(), 1);
+    // ... but paying itself cost the sender nothing beyond the fee
+    assert_eq!(
+        analysis.sender_net,
+        SignedAmount::ZERO - (to_trader + fee).to_signed().unwrap()
+    );
+}
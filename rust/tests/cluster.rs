@@ -0,0 +1,141 @@
+//! Several mock nodes peered into one network
+
+use std::time::Duration;
+
+use bitcoincore_rpc::RpcApi;
+use bitcoincore_rpc::bitcoin::{Address, Amount};
+use rust::Error;
+use rust::cluster::{self, Cluster, ClusterNode, Topology};
+use rust::mock::MockNode;
+use rust::{mining, progress, transfer, wallets};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `count` connected mock nodes; a cluster doesn't own nodes it didn't spawn, so they come back too
+fn mock_cluster(count: usize, topology: Topology) -> (Vec<MockNode>, Cluster) {
+    let nodes: Vec<MockNode> = (0..count).map(|_| MockNode::start().unwrap()).collect();
+    let members = nodes
+        .iter()
+        .map(|node| ClusterNode {
+            connection: node.connection(),
+            p2p_address: node.p2p_address(),
+        })
+        .collect();
+    let cluster = Cluster::new(members, topology, progress::quiet).unwrap();
+    (nodes, cluster)
+}
+
+fn wallet_address(node: &MockNode, wallet: &str) -> Address {
+    let connection = node.connection();
+    wallets::ensure_wallets(&connection, &[wallet]).unwrap();
+    let rpc = connection.client(Some(wallet)).unwrap();
+    rpc.get_new_address(None, None).unwrap().assume_checked()
+}
+
+#[test]
+fn blocks_reach_the_far_end_of_a_line() {
+    let (nodes, cluster) = mock_cluster(3, Topology::Line);
+    let peers = |i: usize| {
+        let rpc = nodes[i].connection().client(None).unwrap();
+        rpc.get_connection_count().unwrap()
+    };
+    assert_eq!((peers(0), peers(1), peers(2)), (1, 2, 1));
+
+    let address = wallet_address(&nodes[0], "Miner");
+    let rpc = nodes[0].connection().client(Some("Miner")).unwrap();
+    let mined = mining::mine_blocks(&rpc, &address, 5).unwrap();
+
+    let tip =
+        cluster::wait_for_same_tip(&cluster.connections(), TIMEOUT, progress::quiet).unwrap();
+    assert_eq!(tip, mined[4]);
+    let far_end = nodes[2].connection().client(None).unwrap();
+    assert_eq!(far_end.get_block_count().unwrap(), 5);
+}
+
+#[test]
+fn payments_propagate_and_confirm_everywhere() {
+    let (nodes, cluster) = mock_cluster(3, Topology::Mesh);
+    let connections = cluster.connections();
+
+    let miner_address = wallet_address(&nodes[0], "Miner");
+    let miner = nodes[0].connection().client(Some("Miner")).unwrap();
+    mining::mine_to_maturity(&miner, &miner_address).unwrap();
+    let bob_address = wallet_address(&nodes[2], "Bob");
+
+    let amount = Amount::from_btc(5.0).unwrap();
+    let txid = transfer::send_payment(&miner, &bob_address, amount).unwrap();
+    cluster::wait_for_mempools(&connections, &txid, TIMEOUT, progress::quiet).unwrap();
+
+    // Bob's node sees the payment as soon as it arrives, then a block from node 1 confirms it
+    let bob = nodes[2].connection().client(Some("Bob")).unwrap();
+    assert_eq!(mining::balances(&bob).unwrap().untrusted_pending, amount);
+    let other_address = wallet_address(&nodes[1], "Other");
+    let other = nodes[1].connection().client(Some("Other")).unwrap();
+    mining::mine_blocks(&other, &other_address, 1).unwrap();
+
+    cluster::wait_for_sync(&connections, TIMEOUT, progress::quiet).unwrap();
+    assert_eq!(mining::balances(&bob).unwrap().trusted, amount);
+    assert!(miner.get_raw_mempool().unwrap().is_empty());
+}
+
+#[test]
+fn nodes_that_mined_apart_converge_on_the_longer_chain() {
+    let alone: Vec<MockNode> = (0..2).map(|_| MockNode::start().unwrap()).collect();
+    let short_address = wallet_address(&alone[0], "Short");
+    let long_address = wallet_address(&alone[1], "Long");
+    let short = alone[0].connection().client(Some("Short")).unwrap();
+    let long = alone[1].connection().client(Some("Long")).unwrap();
+    mining::mine_blocks(&short, &short_address, 3).unwrap();
+    let long_chain = mining::mine_blocks(&long, &long_address, 5).unwrap();
+
+    let members = alone
+        .iter()
+        .map(|node| ClusterNode {
+            connection: node.connection(),
+            p2p_address: node.p2p_address(),
+        })
+        .collect();
+    let cluster = Cluster::new(members, Topology::Line, progress::quiet).unwrap();
+
+    let tip =
+        cluster::wait_for_same_tip(&cluster.connections(), TIMEOUT, progress::quiet).unwrap();
+    assert_eq!(tip, long_chain[4]);
+    // The short chain's rewards are gone with it
+    assert_eq!(mining::balances(&short).unwrap().immature, Amount::ZERO);
+}
+
+#[test]
+fn unconnected_nodes_never_agree() {
+    let nodes: Vec<MockNode> = (0..2).map(|_| MockNode::start().unwrap()).collect();
+    let address = wallet_address(&nodes[0], "Miner");
+    let rpc = nodes[0].connection().client(Some("Miner")).unwrap();
+    mining::mine_blocks(&rpc, &address, 1).unwrap();
+
+    let connections: Vec<_> = nodes.iter().map(MockNode::connection).collect();
+    let error =
+        cluster::wait_for_same_tip(&connections, Duration::from_millis(300), progress::quiet)
+            .unwrap_err();
+    assert!(
+        matches!(error, Error::Node(ref message) if message.contains("node 1 at 0")),
+        "{error}"
+    );
+}
+
+/// Needs a real bitcoind on PATH: `cargo test -- --ignored`
+#[test]
+#[ignore]
+fn spawned_nodes_relay_blocks() {
+    use rust::bitcoind::BitcoindOptions;
+
+    let cluster = Cluster::spawn(3, &BitcoindOptions::default(), Topology::Line).unwrap();
+    let connections = cluster.connections();
+    wallets::ensure_wallets(&connections[0], &["Miner"]).unwrap();
+    let rpc = connections[0].client(Some("Miner")).unwrap();
+    let address = rpc.get_new_address(None, None).unwrap().assume_checked();
+    let mined = mining::mine_blocks(&rpc, &address, 3).unwrap();
+
+    let tip =
+        cluster::wait_for_same_tip(&connections, Duration::from_secs(60), progress::quiet)
+            .unwrap();
+    assert_eq!(tip, mined[2]);
+}
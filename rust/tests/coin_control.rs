@@ -0,0 +1,122 @@
+//! Building a payment by hand from coins we choose
+
+mod common;
+
+use bitcoincore_rpc::bitcoin::{Address, Amount, Network, OutPoint, Sequence, absolute};
+use bitcoincore_rpc::{Client, RpcApi};
+use rust::coin_control::{self, ChosenInput, RawTransactionSpec};
+use rust::{Error, analysis, mining};
+
+use common::Funded;
+
+/// Miner has three mature coinbase coins; Trader has an address to be paid at
+fn coins() -> Funded {
+    let funded = Funded::new();
+    mining::mine_blocks(&funded.miner, &funded.miner_address, 2).unwrap();
+    funded
+}
+
+/// Pay the Trader 20 BTC out of `coin`, change back to the Miner
+fn payment(miner: &Client, trader_address: &Address, coin: OutPoint) -> RawTransactionSpec {
+    RawTransactionSpec {
+        inputs: vec![ChosenInput::new(coin)],
+        outputs: vec![(trader_address.clone(), Amount::from_btc(20.0).unwrap())],
+        change_address: Some(miner.get_raw_change_address(None).unwrap().assume_checked()),
+        ..RawTransactionSpec::default()
+    }
+}
+
+#[test]
+fn the_chosen_coin_is_the_one_spent() {
+    let Funded {
+        node: _node,
+        miner,
+        miner_address,
+        trader_address,
+        ..
+    } = coins();
+    let coins = coin_control::list_coins(&miner).unwrap();
+    assert_eq!(coins.len(), 3);
+    // Not the one the wallet would pick first
+    let chosen = OutPoint::new(coins[2].txid, coins[2].vout);
+
+    let built = coin_control::build(&miner, &payment(&miner, &trader_address, chosen)).unwrap();
+    assert!(built.acceptance.allowed, "{built}");
+    assert_eq!(built.change_output, Some(1));
+    let txid = coin_control::broadcast(&miner, &built).unwrap();
+    mining::mine_blocks(&miner, &miner_address, 1).unwrap();
+
+    let analysis = analysis::analyze(&miner, &miner, &txid, Network::Regtest).unwrap();
+    assert_eq!(analysis.inputs.len(), 1);
+    assert_eq!(analysis.inputs[0].outpoint, chosen);
+    assert_eq!(analysis.fee, built.fee);
+    assert!(analysis.fee_rate_sat_per_vb >= 1.0, "{analysis:?}");
+    assert!(analysis.change_addresses.len() == 1, "{analysis:?}");
+}
+
+#[test]
+fn a_future_lock_time_waits_for_its_block() {
+    let Funded {
+        node: _node,
+        miner,
+        miner_address,
+        trader_address,
+        ..
+    } = coins();
+    let coin = coin_control::list_coins(&miner).unwrap().remove(0);
+    let height = miner.get_block_count().unwrap();
+    let mut spec = payment(&miner, &trader_address, OutPoint::new(coin.txid, coin.vout));
+    // Minable two blocks from now, at the earliest
+    spec.lock_time = absolute::LockTime::from_height(height as u32 + 2).unwrap();
+
+    let built = coin_control::build(&miner, &spec).unwrap();
+    assert_eq!(built.acceptance.reject_reason.as_deref(), Some("non-final"));
+    let error = coin_control::broadcast(&miner, &built).unwrap_err();
+    assert!(error.to_string().contains("non-final"), "{error}");
+
+    // The next block is still too early, the one after it will do
+    mining::mine_blocks(&miner, &miner_address, 1).unwrap();
+    let acceptance = coin_control::test_accept(&miner, &built.tx).unwrap();
+    assert!(!acceptance.allowed);
+    mining::mine_blocks(&miner, &miner_address, 1).unwrap();
+    let acceptance = coin_control::test_accept(&miner, &built.tx).unwrap();
+    assert!(acceptance.allowed, "{acceptance:?}");
+
+    // Final sequence numbers switch the lock time off altogether
+    spec.inputs[0].sequence = Sequence::MAX;
+    spec.lock_time = absolute::LockTime::from_height(height as u32 + 100).unwrap();
+    let built = coin_control::build(&miner, &spec).unwrap();
+    assert!(built.acceptance.allowed, "{built}");
+}
+
+#[test]
+fn a_fee_below_the_relay_minimum_is_rejected() {
+    let Funded {
+        node: _node,
+        miner,
+        trader_address,
+        ..
+    } = coins();
+    let coin = coin_control::list_coins(&miner).unwrap().remove(0);
+    let mut spec = payment(&miner, &trader_address, OutPoint::new(coin.txid, coin.vout));
+    spec.fee_rate_sat_per_vb = 0;
+
+    let built = coin_control::build(&miner, &spec).unwrap();
+    assert_eq!(built.fee, Amount::ZERO);
+    let reason = built.acceptance.reject_reason.unwrap();
+    assert!(reason.starts_with("min relay fee not met"), "{reason}");
+}
+
+#[test]
+fn only_the_wallets_own_coins_can_be_chosen() {
+    let Funded {
+        node: _node,
+        miner,
+        trader_address,
+        ..
+    } = coins();
+    let missing = OutPoint::null();
+    let error =
+        coin_control::build(&miner, &payment(&miner, &trader_address, missing)).unwrap_err();
+    assert!(matches!(error, Error::Config(_)), "{error}");
+}
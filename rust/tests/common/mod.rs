@@ -0,0 +1,59 @@
+//! Fixtures shared by the integration tests
+//!
+//! Each test file pulls this in with `mod common;` and uses only part of it, so the
+//! rest would be dead code in that test binary.
+#![allow(dead_code)]
+
+use bitcoincore_rpc::bitcoin::Address;
+use bitcoincore_rpc::{Client, RpcApi};
+use rust::mining;
+use rust::mock::MockNode;
+use rust::wallets::{self, AddressType};
+
+/// A mock node where the Miner has mature coins and the Trader an address to be paid at
+pub struct Funded {
+    pub node: MockNode,
+    pub miner: Client,
+    /// Where the Miner's coinbase rewards went; mine further blocks here too
+    pub miner_address: Address,
+    pub trader: Client,
+    pub trader_address: Address,
+}
+
+impl Funded {
+    pub fn new() -> Self {
+        Self::start(&[], None)
+    }
+
+    /// The Miner's rewards paid to an `address_type` address instead of its default
+    pub fn with_address_type(address_type: AddressType) -> Self {
+        Self::start(&[], Some(address_type))
+    }
+
+    /// Also create the `others` wallets, empty
+    pub fn with_wallets(others: &[&str]) -> Self {
+        Self::start(others, None)
+    }
+
+    fn start(others: &[&str], address_type: Option<AddressType>) -> Self {
+        let node = MockNode::start().unwrap();
+        let connection = node.connection();
+        wallets::ensure_wallets(&connection, &["Miner", "Trader"]).unwrap();
+        wallets::ensure_wallets(&connection, others).unwrap();
+        let miner = connection.client(Some("Miner")).unwrap();
+        let miner_address = miner
+            .get_new_address(None, address_type.map(AddressType::to_rpc))
+            .unwrap()
+            .assume_checked();
+        mining::mine_to_maturity(&miner, &miner_address).unwrap();
+        let trader = connection.client(Some("Trader")).unwrap();
+        let trader_address = trader.get_new_address(None, None).unwrap().assume_checked();
+        Self {
+            node,
+            miner,
+            miner_address,
+            trader,
+            trader_address,
+        }
+    }
+}
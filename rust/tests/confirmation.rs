@@ -0,0 +1,71 @@
+//! Locating a payment's block and proving it's in there
+
+mod common;
+
+use bitcoincore_rpc::bitcoin::hex::DisplayHex;
+use bitcoincore_rpc::bitcoin::{Address, Amount, Txid};
+use bitcoincore_rpc::{Client, RpcApi};
+use rust::mock::MockNode;
+use rust::{Error, confirmation, mining, transfer};
+use serde_json::json;
+
+use common::Funded;
+
+/// Miner has paid Trader 20 BTC; nothing is confirmed yet
+fn paid() -> (MockNode, Client, Address, Txid) {
+    let Funded {
+        node,
+        miner,
+        miner_address,
+        trader_address,
+        ..
+    } = Funded::new();
+    let txid = transfer::send_payment(&miner, &trader_address, Amount::from_btc(20.0).unwrap())
+        .unwrap();
+    (node, miner, miner_address, txid)
+}
+
+#[test]
+fn the_containing_block_stays_put_as_the_tip_moves_on() {
+    let (_node, miner, miner_address, txid) = paid();
+    let block = transfer::confirm(&miner, &miner_address).unwrap();
+    let height = miner.get_block_count().unwrap();
+    mining::mine_blocks(&miner, &miner_address, 2).unwrap();
+
+    let confirmation = confirmation::locate(&miner, &txid).unwrap();
+    assert_eq!(confirmation.block_hash, block);
+    assert_eq!(confirmation.block_height, height);
+    assert_eq!(confirmation.confirmations, 3);
+    // Right after the coinbase, the only other transaction in the block
+    assert_eq!(confirmation.block_position, 1);
+    assert_eq!(confirmation.block_tx_count, 2);
+    let proof = &confirmation.merkle_proof;
+    assert!(
+        proof.verified_by_node && proof.verified_locally,
+        "{proof:?}"
+    );
+}
+
+#[test]
+fn unconfirmed_transactions_have_no_block() {
+    let (_node, miner, _, txid) = paid();
+    let error = confirmation::locate(&miner, &txid).unwrap_err();
+    assert!(matches!(error, Error::State(_)), "{error}");
+}
+
+#[test]
+fn a_tampered_proof_proves_nothing() {
+    let (_node, miner, miner_address, txid) = paid();
+    transfer::confirm(&miner, &miner_address).unwrap();
+    let mut proof = miner.get_tx_out_proof(&[txid], None).unwrap();
+
+    let verify = |proof: &[u8]| -> Vec<Txid> {
+        miner
+            .call("verifytxoutproof", &[json!(proof.to_lower_hex_string())])
+            .unwrap()
+    };
+    assert_eq!(verify(&proof), [txid]);
+    // Header (80 bytes), transaction count (4) and hash count (1), then the hashes
+    proof[85] ^= 1;
+    assert_eq!(verify(&proof), []);
+}
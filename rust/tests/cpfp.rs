@@ -0,0 +1,142 @@
+//! Child-pays-for-parent against the mock node's mempool
+
+mod common;
+
+use bitcoincore_rpc::bitcoin::{Address, Amount, Network, Txid};
+use bitcoincore_rpc::{Client, RpcApi};
+use rust::mock::MockNode;
+use rust::{Error, cpfp, mining, transfer};
+
+use common::Funded;
+
+/// Miner has paid Trader 20 BTC at the default 1 sat/vB; the payment is unconfirmed
+struct Stuck {
+    node: MockNode,
+    miner: Client,
+    miner_address: Address,
+    trader: Client,
+    payment: Txid,
+}
+
+impl Stuck {
+    fn new() -> Self {
+        let Funded {
+            node,
+            miner,
+            miner_address,
+            trader,
+            trader_address,
+        } = Funded::with_wallets(&["Bystander"]);
+        let payment =
+            transfer::send_payment(&miner, &trader_address, Amount::from_btc(20.0).unwrap())
+                .unwrap();
+        Self {
+            node,
+            miner,
+            miner_address,
+            trader,
+            payment,
+        }
+    }
+}
+
+#[test]
+fn recipient_lifts_the_package_to_the_target() {
+    let stuck = Stuck::new();
+    let bump = cpfp::bump(&stuck.trader, Network::Regtest, &stuck.payment, 10).unwrap();
+
+    assert_eq!(bump.spent.txid, stuck.payment);
+    assert!(bump.parent_before.ancestor_fee_rate() < 2.0);
+    let package = bump.child_package;
+    assert_eq!(package.ancestor_count, 2);
+    assert_eq!(
+        package.ancestor_fees,
+        bump.parent_before.fee + bump.child_fee
+    );
+    let rate = package.ancestor_fee_rate();
+    assert!((10.0..10.2).contains(&rate), "{rate} sat/vB");
+
+    // The parent now counts the child among its descendants
+    assert_eq!(bump.parent_after.descendant_count, 2);
+    assert_eq!(bump.parent_after.descendant_fees, package.ancestor_fees);
+    assert_eq!(
+        bump.parent_after.descendant_size,
+        bump.parent_after.vsize + package.vsize
+    );
+
+    transfer::confirm(&stuck.miner, &stuck.miner_address).unwrap();
+    assert!(stuck.miner.get_raw_mempool().unwrap().is_empty());
+    let kept = Amount::from_btc(20.0).unwrap() - bump.child_fee;
+    assert_eq!(mining::balances(&stuck.trader).unwrap().trusted, kept);
+}
+
+#[test]
+fn sender_can_bump_through_the_change() {
+    let stuck = Stuck::new();
+    let bump = cpfp::bump(&stuck.miner, Network::Regtest, &stuck.payment, 5).unwrap();
+
+    let change = stuck
+        .miner
+        .get_raw_transaction(&stuck.payment, None)
+        .unwrap();
+    let change_vout = change
+        .output
+        .iter()
+        .position(|output| output.value > Amount::from_btc(20.0).unwrap())
+        .unwrap();
+    assert_eq!(bump.spent.vout, change_vout as u32);
+    assert!(bump.child_package.ancestor_fee_rate() >= 5.0);
+}
+
+#[test]
+fn packages_already_at_the_target_are_left_alone() {
+    let stuck = Stuck::new();
+    let error = cpfp::bump(&stuck.trader, Network::Regtest, &stuck.payment, 1).unwrap_err();
+    assert!(matches!(error, Error::State(_)), "{error}");
+    assert_eq!(stuck.miner.get_raw_mempool().unwrap(), [stuck.payment]);
+}
+
+#[test]
+fn change_address_on_another_network_is_refused() {
+    let stuck = Stuck::new();
+    let error = cpfp::bump(&stuck.trader, Network::Bitcoin, &stuck.payment, 10).unwrap_err();
+    assert!(matches!(error, Error::Address(_)), "{error}");
+    assert_eq!(stuck.miner.get_raw_mempool().unwrap(), [stuck.payment]);
+}
+
+#[test]
+fn only_owners_of_an_output_can_bump() {
+    let stuck = Stuck::new();
+    let bystander = stuck.node.connection().client(Some("Bystander")).unwrap();
+    let error = cpfp::bump(&bystander, Network::Regtest, &stuck.payment, 10).unwrap_err();
+    assert!(
+        matches!(error, Error::State(ref message) if message.contains("owns no output")),
+        "{error}"
+    );
+}
+
+#[test]
+fn absurd_fee_rates_are_an_error_not_an_overflow() {
+    let stuck = Stuck::new();
+    let error = cpfp::bump(
+        &stuck.trader,
+        Network::Regtest,
+        &stuck.payment,
+        u64::MAX / 2,
+    )
+    .unwrap_err();
+    assert!(matches!(error, Error::Config(_)), "{error}");
+    // More than the output holds, but no overflow
+    let error = cpfp::bump(
+        &stuck.trader,
+        Network::Regtest,
+        &stuck.payment,
+        1_000_000_000,
+    )
+    .unwrap_err();
+    assert!(
+        matches!(error, Error::State(ref message) if message.contains("too little")),
+        "{error}"
+    );
+    assert_eq!(stuck.miner.get_raw_mempool().unwrap(), [stuck.payment]);
+}
@@ -0,0 +1,88 @@
+//! Fee rate, weight and virtual size of a payment, and the node's view of fees
+
+mod common;
+
+use bitcoincore_rpc::RpcApi;
+use bitcoincore_rpc::bitcoin::{Amount, Network};
+use rust::analysis::{self, FEE_ESTIMATE_TARGET_BLOCKS};
+use rust::transfer;
+
+use common::Funded;
+
+#[test]
+fn sizes_come_from_the_decoded_transaction() {
+    let Funded {
+        node: _node,
+        miner,
+        miner_address,
+        trader_address,
+        ..
+    } = Funded::new();
+    let txid = transfer::send_payment(&miner, &trader_address, Amount::from_btc(20.0).unwrap())
+        .unwrap();
+    transfer::confirm(&miner, &miner_address).unwrap();
+
+    let analysis = analysis::analyze(&miner, &miner, &txid, Network::Regtest).unwrap();
+    let tx = miner.get_raw_transaction(&txid, None).unwrap();
+    assert_eq!(analysis.weight, tx.weight().to_wu());
+    assert_eq!(analysis.size, tx.total_size() as u64);
+    // A virtual byte is four weight units, rounded up
+    assert_eq!(analysis.vsize, analysis.weight.div_ceil(4));
+    // Witness bytes are discounted, so a segwit spend is smaller virtually than on disk
+    assert!(analysis.vsize < analysis.size);
+
+    let expected = analysis.fee.to_sat() as f64 / analysis.vsize as f64;
+    assert_eq!(analysis.fee_rate_sat_per_vb, expected);
+    // The mock's wallets pay 1 sat/vB
+    assert!((1.0..1.1).contains(&expected), "{expected} sat/vB");
+}
+
+#[test]
+fn no_estimate_before_any_payment_confirms() {
+    let Funded {
+        node: _node, miner, ..
+    } = Funded::new();
+    let comparison =
+        analysis::compare_fee_rate(&miner, 1.0, FEE_ESTIMATE_TARGET_BLOCKS).unwrap();
+
+    assert_eq!(comparison.mempool_min_fee_sat_per_vb, 1.0);
+    assert!(comparison.meets_mempool_min());
+    assert_eq!(comparison.estimate_sat_per_vb, None);
+    assert_eq!(comparison.estimate_ratio(), None);
+    assert!(!comparison.estimate_errors.is_empty());
+}
+
+#[test]
+fn estimate_follows_confirmed_fee_rates() {
+    let Funded {
+        node: _node,
+        miner,
+        miner_address,
+        trader_address,
+        ..
+    } = Funded::new();
+    let txid = transfer::send_payment(&miner, &trader_address, Amount::from_btc(20.0).unwrap())
+        .unwrap();
+    transfer::confirm(&miner, &miner_address).unwrap();
+    let analysis = analysis::analyze(&miner, &miner, &txid, Network::Regtest).unwrap();
+
+    let comparison = analysis::compare_fee_rate(
+        &miner,
+        analysis.fee_rate_sat_per_vb,
+        FEE_ESTIMATE_TARGET_BLOCKS,
+    )
+    .unwrap();
+    assert_eq!(
+        comparison.estimate_target_blocks,
+        FEE_ESTIMATE_TARGET_BLOCKS
+    );
+    let estimate = comparison.estimate_sat_per_vb.unwrap();
+    assert!((1.0..1.1).contains(&estimate), "{estimate} sat/vB");
+    let ratio = comparison.estimate_ratio().unwrap();
+    assert!((0.95..1.05).contains(&ratio), "{ratio}");
+
+    // Half the going rate falls short of both
+    let low = analysis::compare_fee_rate(&miner, 0.5, FEE_ESTIMATE_TARGET_BLOCKS).unwrap();
+    assert!(!low.meets_mempool_min());
+    assert!(low.estimate_ratio().unwrap() < 1.0);
+}
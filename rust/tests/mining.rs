@@ -0,0 +1,48 @@
+//! Coinbase maturity against the mock node
+
+use bitcoincore_rpc::RpcApi;
+use bitcoincore_rpc::bitcoin::Amount;
+use rust::mining::{self, COINBASE_MATURITY};
+use rust::mock::MockNode;
+use rust::wallets;
+
+#[test]
+fn mine_to_maturity_mines_exactly_enough_in_one_call() {
+    let node = MockNode::start().unwrap();
+    let connection = node.connection();
+    wallets::ensure_wallets(&connection, &["Miner"]).unwrap();
+    let rpc = connection.client(Some("Miner")).unwrap();
+    let address = rpc.get_new_address(None, None).unwrap().assume_checked();
+
+    let outcome = mining::mine_to_maturity(&rpc, &address).unwrap();
+    assert_eq!(outcome.blocks_mined, COINBASE_MATURITY + 1);
+    assert_eq!(outcome.balances.trusted, Amount::from_btc(50.0).unwrap());
+    assert_eq!(outcome.balances.immature, Amount::from_btc(5000.0).unwrap());
+    let generate_calls = node
+        .calls()
+        .iter()
+        .filter(|c| *c == "generatetoaddress")
+        .count();
+    assert_eq!(generate_calls, 1);
+
+    // Already spendable: nothing more to mine
+    let again = mining::mine_to_maturity(&rpc, &address).unwrap();
+    assert_eq!(again.blocks_mined, 0);
+}
+
+#[test]
+fn partially_matured_rewards_only_need_the_remaining_blocks() {
+    let node = MockNode::start().unwrap();
+    let connection = node.connection();
+    wallets::ensure_wallets(&connection, &["Miner"]).unwrap();
+    let rpc = connection.client(Some("Miner")).unwrap();
+    let address = rpc.get_new_address(None, None).unwrap().assume_checked();
+
+    mining::mine_blocks(&rpc, &address, 40).unwrap();
+    let balances = mining::balances(&rpc).unwrap();
+    assert_eq!(mining::blocks_until_spendable(&rpc, &balances).unwrap(), 61);
+
+    let outcome = mining::mine_to_maturity(&rpc, &address).unwrap();
+    assert_eq!(outcome.blocks_mined, 61);
+    assert_eq!(rpc.get_block_count().unwrap(), 101);
+}
@@ -0,0 +1,228 @@
+//! Multisig wallets shared between cosigners, spent by combining their signatures
+
+mod common;
+
+use bitcoincore_rpc::bitcoin::{Address, Amount};
+use bitcoincore_rpc::{Client, RpcApi};
+use rust::connection::Connection;
+use rust::mock::MockNode;
+use rust::multisig::{self, MultisigKind, MultisigWallet};
+use rust::{Error, transfer};
+
+use common::Funded;
+
+const COSIGNERS: [&str; 3] = ["Alice", "Bob", "Carol"];
+
+/// A 2-of-3 `kind` wallet over Alice, Bob and Carol, holding 10 BTC from the Miner
+struct Shared {
+    _node: MockNode,
+    connection: Connection,
+    miner: Client,
+    miner_address: Address,
+    wallet: MultisigWallet,
+    vault: Client,
+    trader_address: Address,
+}
+
+fn shared(kind: MultisigKind) -> Shared {
+    let Funded {
+        node,
+        miner,
+        miner_address,
+        trader_address,
+        ..
+    } = Funded::with_wallets(&COSIGNERS);
+    let connection = node.connection();
+
+    let cosigners: Vec<_> = COSIGNERS
+        .iter()
+        .map(|name| {
+            let client = connection.client(Some(name)).unwrap();
+            multisig::cosigner(name, &client, kind).unwrap()
+        })
+        .collect();
+    let wallet = multisig::create(&connection, "Vault", kind, 2, &cosigners).unwrap();
+    let vault = connection.client(Some("Vault")).unwrap();
+    let vault_address = vault
+        .get_new_address(None, Some(kind.address_type().to_rpc()))
+        .unwrap()
+        .assume_checked();
+    transfer::send_payment(&miner, &vault_address, Amount::from_int_btc(10)).unwrap();
+    transfer::confirm(&miner, &miner_address).unwrap();
+
+    Shared {
+        _node: node,
+        connection,
+        miner,
+        miner_address,
+        wallet,
+        vault,
+        trader_address,
+    }
+}
+
+fn signers<'a>(shared: &Shared, names: &[&'a str]) -> Vec<(&'a str, Client)> {
+    names
+        .iter()
+        .map(|name| (*name, shared.connection.client(Some(name)).unwrap()))
+        .collect()
+}
+
+#[test]
+fn two_of_three_p2wsh_is_spent_with_two_signatures() {
+    let shared = shared(MultisigKind::P2wsh);
+    assert!(
+        shared
+            .wallet
+            .receive_descriptor
+            .starts_with("wsh(sortedmulti(2,")
+    );
+    for cosigner in &shared.wallet.cosigners {
+        assert!(
+            shared.wallet.receive_descriptor.contains(&cosigner.key),
+            "{}",
+            shared.wallet
+        );
+    }
+    assert_eq!(
+        shared.vault.get_balance(None, None).unwrap(),
+        Amount::from_int_btc(10)
+    );
+
+    let signers = signers(&shared, &["Alice", "Carol"]);
+    let signers: Vec<(&str, &Client)> = signers.iter().map(|(n, c)| (*n, c)).collect();
+    let amount = Amount::from_int_btc(4);
+    let spend = multisig::pay(
+        &shared.wallet,
+        &shared.vault,
+        &signers,
+        &shared.trader_address,
+        amount,
+    )
+    .unwrap();
+    assert_eq!(spend.signers, ["Alice", "Carol"]);
+    assert_eq!(spend.non_signers(), ["Bob"]);
+
+    let tx = shared.miner.get_raw_transaction(&spend.txid, None).unwrap();
+    // The dummy CHECKMULTISIG pops, two signatures and the 2-of-3 script itself
+    let witness = &tx.input[0].witness;
+    assert_eq!(witness.len(), 4);
+    assert!(witness.nth(0).unwrap().is_empty());
+    assert_eq!(witness.last().unwrap().len(), 1 + 3 * 34 + 2);
+    assert_eq!(
+        shared
+            .miner
+            .get_mempool_entry(&spend.txid)
+            .unwrap()
+            .fees
+            .base,
+        spend.fee
+    );
+
+    transfer::confirm(&shared.miner, &shared.miner_address).unwrap();
+    assert_eq!(
+        shared.vault.get_balance(None, None).unwrap(),
+        Amount::from_int_btc(6) - spend.fee
+    );
+}
+
+#[test]
+fn two_of_three_taproot_is_spent_through_its_leaf() {
+    let shared = shared(MultisigKind::Taproot);
+    assert!(
+        shared
+            .wallet
+            .receive_descriptor
+            .starts_with(&format!("tr({},sortedmulti_a(2,", multisig::NUMS_KEY))
+    );
+    // A tr() descriptor only hands out bech32m addresses, not the default type
+    let error = shared.vault.get_new_address(None, None).unwrap_err();
+    assert!(
+        error.to_string().contains("No bech32 addresses available"),
+        "{error}"
+    );
+
+    let signers = signers(&shared, &["Bob", "Carol"]);
+    let signers: Vec<(&str, &Client)> = signers.iter().map(|(n, c)| (*n, c)).collect();
+    let spend = multisig::pay(
+        &shared.wallet,
+        &shared.vault,
+        &signers,
+        &shared.trader_address,
+        Amount::from_int_btc(4),
+    )
+    .unwrap();
+    assert_eq!(spend.signers, ["Bob", "Carol"]);
+
+    // One slot per key (Alice's empty), the leaf script and the control block
+    let tx = shared.miner.get_raw_transaction(&spend.txid, None).unwrap();
+    let witness = &tx.input[0].witness;
+    assert_eq!(witness.len(), 5);
+    let signatures: Vec<usize> = witness.iter().take(3).map(<[u8]>::len).collect();
+    assert_eq!(signatures.iter().filter(|len| **len == 64).count(), 2);
+    assert_eq!(signatures.iter().filter(|len| **len == 0).count(), 1);
+    // Leaf version and internal key, and no siblings in a one-leaf tree
+    assert_eq!(witness.last().unwrap().len(), 33);
+    assert!(shared.miner.get_mempool_entry(&spend.txid).is_ok());
+}
+
+#[test]
+fn more_signers_than_needed_all_show_up() {
+    let shared = shared(MultisigKind::P2wsh);
+    let signers = signers(&shared, &COSIGNERS);
+    let signers: Vec<(&str, &Client)> = signers.iter().map(|(n, c)| (*n, c)).collect();
+    let spend = multisig::pay(
+        &shared.wallet,
+        &shared.vault,
+        &signers,
+        &shared.trader_address,
+        Amount::ONE_BTC,
+    )
+    .unwrap();
+    assert_eq!(spend.signers, COSIGNERS);
+    // ... but the witness only takes as many as the threshold
+    let tx = shared.miner.get_raw_transaction(&spend.txid, None).unwrap();
+    assert_eq!(tx.input[0].witness.len(), 4);
+}
+
+#[test]
+fn too_few_or_foreign_signers_are_refused() {
+    let shared = shared(MultisigKind::P2wsh);
+    let amount = Amount::ONE_BTC;
+
+    let alone = signers(&shared, &["Alice"]);
+    let alone: Vec<(&str, &Client)> = alone.iter().map(|(n, c)| (*n, c)).collect();
+    let error = multisig::pay(
+        &shared.wallet,
+        &shared.vault,
+        &alone,
+        &shared.trader_address,
+        amount,
+    )
+    .unwrap_err();
+    assert!(matches!(error, Error::Config(_)), "{error}");
+
+    let with_miner = signers(&shared, &["Alice", "Miner"]);
+    let with_miner: Vec<(&str, &Client)> = with_miner.iter().map(|(n, c)| (*n, c)).collect();
+    let error = multisig::pay(
+        &shared.wallet,
+        &shared.vault,
+        &with_miner,
+        &shared.trader_address,
+        amount,
+    )
+    .unwrap_err();
+    assert!(
+        error.to_string().contains("Miner isn't a cosigner"),
+        "{error}"
+    );
+    assert!(shared.miner.get_raw_mempool().unwrap().is_empty());
+
+    // The watch-only wallet can't sign on its own either
+    let error =
+        transfer::send_payment(&shared.vault, &shared.trader_address, amount).unwrap_err();
+    assert!(
+        error.to_string().contains("Private keys are disabled"),
+        "{error}"
+    );
+}
@@ -0,0 +1,107 @@
+//! Paying through a PSBT, one role at a time
+
+mod common;
+
+use bitcoincore_rpc::RpcApi;
+use bitcoincore_rpc::bitcoin::Amount;
+use rust::psbt::{self, PsbtInspection, PsbtStage};
+
+use common::Funded;
+
+#[test]
+fn each_role_adds_its_part() {
+    let Funded {
+        node: _node,
+        miner,
+        trader,
+        trader_address,
+        ..
+    } = Funded::new();
+    let amount = Amount::from_btc(20.0).unwrap();
+
+    let created = psbt::create(&miner, &trader_address, amount).unwrap();
+    let inspection = PsbtInspection::of(&created);
+    assert_eq!(
+        (inspection.signed_inputs, inspection.finalized_inputs),
+        (0, 0)
+    );
+    // The creator filled in what every input spends, so the fee is known up front
+    let fee = inspection.fee.unwrap();
+    assert!(fee > Amount::ZERO);
+
+    // None of the coins are the Trader's: nothing for it to sign
+    let reviewed = psbt::process(&trader, &created, true).unwrap();
+    assert_eq!(reviewed, created);
+
+    let signed = psbt::process(&miner, &reviewed, true).unwrap();
+    let inspection = PsbtInspection::of(&signed);
+    assert_eq!(inspection.signed_inputs, inspection.inputs);
+    assert_eq!(inspection.finalized_inputs, 0);
+
+    let (finalized, tx) = psbt::finalize(&miner, &signed).unwrap();
+    assert!(PsbtInspection::of(&finalized).is_complete());
+    let tx = tx.unwrap();
+    // Signatures don't change the txid: it was fixed when the PSBT was created
+    assert_eq!(tx.txid(), created.unsigned_tx.txid());
+    let txid = miner.send_raw_transaction(&tx).unwrap();
+    assert_eq!(miner.get_mempool_entry(&txid).unwrap().fees.base, fee);
+}
+
+#[test]
+fn unsigned_inputs_keep_the_transaction_in() {
+    let Funded {
+        node: _node,
+        miner,
+        trader,
+        trader_address,
+        ..
+    } = Funded::new();
+    let created = psbt::create(&miner, &trader_address, Amount::ONE_BTC).unwrap();
+    let reviewed = psbt::process(&trader, &created, true).unwrap();
+
+    let (finalized, tx) = psbt::finalize(&miner, &reviewed).unwrap();
+    assert_eq!(tx, None);
+    assert_eq!(PsbtInspection::of(&finalized).finalized_inputs, 0);
+}
+
+#[test]
+fn every_stage_is_saved_and_read_back_in_both_forms() {
+    let Funded {
+        node: _node,
+        miner,
+        trader,
+        trader_address,
+        ..
+    } = Funded::new();
+    let dir = tempfile::tempdir().unwrap();
+    let payment = psbt::pay(
+        &miner,
+        &trader,
+        &trader_address,
+        Amount::from_btc(20.0).unwrap(),
+        Some(dir.path()),
+    )
+    .unwrap();
+
+    let stages: Vec<PsbtStage> = payment.steps.iter().map(|step| step.stage).collect();
+    assert_eq!(
+        stages,
+        [
+            PsbtStage::Created,
+            PsbtStage::Reviewed,
+            PsbtStage::Signed,
+            PsbtStage::Finalized
+        ]
+    );
+    for step in &payment.steps {
+        let [binary, base64] = step.files.as_slice() else {
+            panic!("{step:?}");
+        };
+        assert!(std::fs::read(binary).unwrap().starts_with(b"psbt\xff"));
+        assert_eq!(std::fs::read_to_string(base64).unwrap().trim(), step.base64);
+        let expected = psbt::from_base64(&step.base64).unwrap();
+        assert_eq!(psbt::read(binary).unwrap(), expected);
+        assert_eq!(psbt::read(base64).unwrap(), expected);
+    }
+    assert!(miner.get_mempool_entry(&payment.txid).is_ok());
+}
@@ -0,0 +1,168 @@
+//! Forks, double-spends and partitions on the mock node's block tree
+
+mod common;
+
+use std::time::Duration;
+
+use common::Funded;
+
+use bitcoincore_rpc::Client;
+use bitcoincore_rpc::RpcApi;
+use bitcoincore_rpc::bitcoin::{Address, Amount, Txid};
+use bitcoincore_rpc::bitcoincore_rpc_json::GetChainTipsResultStatus;
+use rust::Error;
+use rust::cluster::{self, Cluster, ClusterNode, Topology};
+use rust::mock::MockNode;
+use rust::reorg::{self, ChainMark, TxFate};
+use rust::{mining, progress, transfer, wallets};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A miner with spendable coins who has paid Bob 5 BTC in the tip block
+struct Paid {
+    node: MockNode,
+    payment: Txid,
+}
+
+impl Paid {
+    fn new() -> Self {
+        let Funded {
+            node,
+            miner,
+            miner_address: address,
+            ..
+        } = Funded::with_wallets(&["Bob"]);
+
+        let bob = new_address(&node, "Bob");
+        let payment =
+            transfer::send_payment(&miner, &bob, Amount::from_btc(5.0).unwrap()).unwrap();
+        transfer::confirm(&miner, &address).unwrap();
+        Self { node, payment }
+    }
+
+    fn wallet(&self, name: &str) -> Client {
+        self.node.connection().client(Some(name)).unwrap()
+    }
+}
+
+fn new_address(node: &MockNode, wallet: &str) -> Address {
+    let rpc = node.connection().client(Some(wallet)).unwrap();
+    rpc.get_new_address(None, None).unwrap().assume_checked()
+}
+
+#[test]
+fn replaced_payments_go_back_to_the_mempool() {
+    let paid = Paid::new();
+    let miner = paid.wallet("Miner");
+    let fork_address = new_address(&paid.node, "Miner");
+
+    let report = reorg::fork(&miner, &fork_address, 1, &[]).unwrap();
+    assert_eq!(report.depth(), 1);
+    assert_eq!(report.connected_blocks.len(), 2);
+    assert_eq!(report.fork_point.height, report.old_tip.height - 1);
+    assert_eq!(report.fate(&paid.payment), Some(TxFate::InMempool));
+    assert!(miner.get_raw_mempool().unwrap().contains(&paid.payment));
+
+    // The replaced block's reward is gone with it
+    let coinbase = report.transactions.iter().find(|tx| tx.coinbase).unwrap();
+    assert_eq!(coinbase.fate, TxFate::Dropped);
+}
+
+#[test]
+fn a_conflicting_branch_double_spends_the_payment() {
+    let paid = Paid::new();
+    let miner = paid.wallet("Miner");
+    let bob = paid.wallet("Bob");
+    assert_eq!(
+        mining::balances(&bob).unwrap().trusted,
+        Amount::from_btc(5.0).unwrap()
+    );
+
+    let refund = new_address(&paid.node, "Miner");
+    let conflict = reorg::conflicting_payment(&miner, &paid.payment, &refund).unwrap();
+    let fork_address = new_address(&paid.node, "Miner");
+    let report =
+        reorg::fork(&miner, &fork_address, 1, std::slice::from_ref(&conflict)).unwrap();
+
+    assert_eq!(
+        report.fate(&paid.payment),
+        Some(TxFate::DoubleSpent {
+            by: conflict.txid(),
+            height: Some(report.fork_point.height + 1),
+        })
+    );
+    assert!(miner.get_raw_mempool().unwrap().is_empty());
+    assert_eq!(mining::balances(&bob).unwrap().trusted, Amount::ZERO);
+}
+
+#[test]
+fn the_replaced_branch_stays_a_valid_fork() {
+    let paid = Paid::new();
+    let miner = paid.wallet("Miner");
+    let old_tip = miner.get_best_block_hash().unwrap();
+    let fork_address = new_address(&paid.node, "Miner");
+    reorg::fork(&miner, &fork_address, 1, &[]).unwrap();
+
+    let tips = miner.get_chain_tips().unwrap();
+    assert_eq!(tips.len(), 2, "{tips:?}");
+    assert_eq!(tips[0].status, GetChainTipsResultStatus::Active);
+    let fork = tips.iter().find(|tip| tip.hash == old_tip).unwrap();
+    assert_eq!(fork.status, GetChainTipsResultStatus::ValidFork);
+    assert_eq!(fork.branch_length, 1);
+}
+
+#[test]
+fn forks_must_replace_blocks_that_exist() {
+    let node = MockNode::start().unwrap();
+    wallets::ensure_wallets(&node.connection(), &["Miner"]).unwrap();
+    let address = new_address(&node, "Miner");
+    let miner = node.connection().client(Some("Miner")).unwrap();
+    mining::mine_blocks(&miner, &address, 2).unwrap();
+
+    for depth in [0, 3] {
+        let error = reorg::fork(&miner, &address, depth, &[]).unwrap_err();
+        assert!(matches!(error, Error::Config(_)), "{error}");
+    }
+    assert_eq!(miner.get_block_count().unwrap(), 2);
+}
+
+#[test]
+fn healing_a_partition_reorgs_the_shorter_side() {
+    let nodes: Vec<MockNode> = (0..3).map(|_| MockNode::start().unwrap()).collect();
+    let members = nodes
+        .iter()
+        .map(|node| ClusterNode {
+            connection: node.connection(),
+            p2p_address: node.p2p_address(),
+        })
+        .collect();
+    let cluster = Cluster::new(members, Topology::Line, progress::quiet).unwrap();
+    let miner = |i: usize| {
+        let connection = nodes[i].connection();
+        wallets::ensure_wallets(&connection, &["Miner"]).unwrap();
+        (
+            connection.client(Some("Miner")).unwrap(),
+            new_address(&nodes[i], "Miner"),
+        )
+    };
+
+    // 0 – 1 | 2: the pair outmines the node on its own
+    cluster.partition(&[2]).unwrap();
+    let (lone, lone_address) = miner(2);
+    mining::mine_blocks(&lone, &lone_address, 2).unwrap();
+    let (pair, pair_address) = miner(0);
+    let winners = mining::mine_blocks(&pair, &pair_address, 3).unwrap();
+    cluster::wait_for_same_tip(&cluster.connections()[..2], TIMEOUT, progress::quiet).unwrap();
+    assert_eq!(lone.get_block_count().unwrap(), 2);
+
+    let before = ChainMark::record(&lone).unwrap();
+    cluster.heal().unwrap();
+    let tip =
+        cluster::wait_for_same_tip(&cluster.connections(), TIMEOUT, progress::quiet).unwrap();
+    assert_eq!(tip, winners[2]);
+
+    let report = reorg::compare(&lone, before).unwrap();
+    assert_eq!(report.depth(), 2);
+    assert_eq!(report.fork_point.height, 0);
+    assert_eq!(report.connected_blocks, winners);
+}
@@ -0,0 +1,233 @@
+//! Replace-by-fee against the mock node's BIP125 rules
+
+mod common;
+
+use std::time::Duration;
+
+use bitcoincore_rpc::RpcApi;
+use bitcoincore_rpc::bitcoin::{Amount, Network, Txid};
+use rust::Error;
+use rust::cluster::{self, Cluster, ClusterNode, Topology};
+use rust::mock::MockNode;
+use rust::replacement::{self, ReplaceMethod};
+use rust::{mining, progress, transfer};
+
+use common::Funded;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Miner pays Trader `btc`, replaceably
+fn pay(setup: &Funded, btc: f64) -> Txid {
+    let amount = Amount::from_btc(btc).unwrap();
+    transfer::send_replaceable_payment(&setup.miner, &setup.trader_address, amount).unwrap()
+}
+
+#[test]
+fn bumped_payment_evicts_the_original_and_still_pays() {
+    let setup = Funded::new();
+    let original = pay(&setup, 20.0);
+
+    let mut replaced = replacement::replace(
+        &setup.miner,
+        Network::Regtest,
+        &original,
+        ReplaceMethod::BumpFee,
+    )
+    .unwrap();
+    assert_eq!(replaced.evicted, [original]);
+    assert_eq!(replaced.mempool_before, [original]);
+    assert_eq!(replaced.mempool_after, [replaced.replacement]);
+    assert!(replaced.replacement_fee > replaced.original_fee);
+
+    let block = transfer::confirm(&setup.miner, &setup.miner_address).unwrap();
+    assert_eq!(
+        replaced.settle(&setup.miner, &block).unwrap(),
+        Some(replaced.replacement)
+    );
+    assert_eq!(
+        mining::balances(&setup.trader).unwrap().trusted,
+        Amount::from_btc(20.0).unwrap()
+    );
+}
+
+#[test]
+fn conflicting_replacement_double_spends_the_payment() {
+    let setup = Funded::new();
+    let original = pay(&setup, 20.0);
+    assert_eq!(
+        mining::balances(&setup.trader).unwrap().untrusted_pending,
+        Amount::from_btc(20.0).unwrap()
+    );
+
+    let mut replaced = replacement::replace(
+        &setup.miner,
+        Network::Regtest,
+        &original,
+        ReplaceMethod::Conflict,
+    )
+    .unwrap();
+    assert_eq!(replaced.evicted, [original]);
+    // Paying the original's fee again on top is what BIP125 asks for here
+    assert_eq!(replaced.replacement_fee, replaced.original_fee * 2);
+
+    let block = transfer::confirm(&setup.miner, &setup.miner_address).unwrap();
+    assert_eq!(
+        replaced.settle(&setup.miner, &block).unwrap(),
+        Some(replaced.replacement)
+    );
+    let balances = mining::balances(&setup.trader).unwrap();
+    assert_eq!(balances.trusted + balances.untrusted_pending, Amount::ZERO);
+}
+
+#[test]
+fn descendants_are_evicted_with_the_original() {
+    let setup = Funded::new();
+    let original = pay(&setup, 20.0);
+    // Spends the original's change, so it can't outlive it
+    let child = pay(&setup, 5.0);
+
+    let error = replacement::replace(
+        &setup.miner,
+        Network::Regtest,
+        &original,
+        ReplaceMethod::BumpFee,
+    );
+    assert!(
+        matches!(error, Err(Error::Rpc(ref e)) if e.to_string().contains("descendants")),
+        "{error:?}"
+    );
+
+    let replaced = replacement::replace(
+        &setup.miner,
+        Network::Regtest,
+        &original,
+        ReplaceMethod::Conflict,
+    )
+    .unwrap();
+    assert_eq!(replaced.evicted, [original, child]);
+    assert_eq!(
+        setup.miner.get_raw_mempool().unwrap(),
+        [replaced.replacement]
+    );
+}
+
+#[test]
+fn refund_address_on_another_network_is_refused() {
+    let setup = Funded::new();
+    let original = pay(&setup, 20.0);
+
+    let error = replacement::replace(
+        &setup.miner,
+        Network::Bitcoin,
+        &original,
+        ReplaceMethod::Conflict,
+    );
+    assert!(matches!(error, Err(Error::Address(_))), "{error:?}");
+    // Nothing was sent
+    assert_eq!(setup.miner.get_raw_mempool().unwrap(), [original]);
+}
+
+#[test]
+fn non_signalling_payments_keep_their_place() {
+    let setup = Funded::new();
+    let amount = Amount::from_btc(20.0).unwrap();
+    let final_payment = setup
+        .miner
+        .send_to_address(
+            &setup.trader_address,
+            amount,
+            None,
+            None,
+            None,
+            Some(false),
+            None,
+            None,
+        )
+        .unwrap();
+
+    let error = replacement::replace(
+        &setup.miner,
+        Network::Regtest,
+        &final_payment,
+        ReplaceMethod::BumpFee,
+    );
+    assert!(matches!(error, Err(Error::State(_))), "{error:?}");
+
+    // Broadcasting a double-spend anyway doesn't get it in either: first seen wins
+    let refund = setup
+        .miner
+        .get_new_address(None, None)
+        .unwrap()
+        .assume_checked();
+    let conflict = transfer::respend(
+        &setup.miner,
+        &final_payment,
+        &refund,
+        Amount::from_sat(1000),
+    )
+    .unwrap();
+    let error = setup.miner.send_raw_transaction(&conflict).unwrap_err();
+    assert!(
+        error.to_string().contains("txn-mempool-conflict"),
+        "{error}"
+    );
+    assert_eq!(setup.miner.get_raw_mempool().unwrap(), [final_payment]);
+}
+
+#[test]
+fn replacements_must_pay_for_their_own_relay() {
+    let setup = Funded::new();
+    let original = pay(&setup, 20.0);
+
+    // Same fee, smaller transaction: a higher fee rate, but nothing extra to relay it
+    let refund = setup
+        .miner
+        .get_new_address(None, None)
+        .unwrap()
+        .assume_checked();
+    let conflict = transfer::respend(&setup.miner, &original, &refund, Amount::ZERO).unwrap();
+    let error = setup.miner.send_raw_transaction(&conflict).unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("not enough additional fees to relay"),
+        "{error}"
+    );
+    assert!(setup.miner.get_raw_mempool().unwrap().contains(&original));
+}
+
+#[test]
+fn replacements_propagate_to_peers() {
+    let setup = Funded::new();
+    let peer = MockNode::start().unwrap();
+    let members = [&setup.node, &peer]
+        .iter()
+        .map(|node| ClusterNode {
+            connection: node.connection(),
+            p2p_address: node.p2p_address(),
+        })
+        .collect();
+    let cluster = Cluster::new(members, Topology::Line, progress::quiet).unwrap();
+    cluster::wait_for_same_tip(&cluster.connections(), TIMEOUT, progress::quiet).unwrap();
+
+    let original = pay(&setup, 20.0);
+    cluster::wait_for_mempools(&cluster.connections(), &original, TIMEOUT, progress::quiet)
+        .unwrap();
+    let replaced = replacement::replace(
+        &setup.miner,
+        Network::Regtest,
+        &original,
+        ReplaceMethod::BumpFee,
+    )
+    .unwrap();
+
+    cluster::wait_for_mempools(
+        &cluster.connections(),
+        &replaced.replacement,
+        TIMEOUT,
+        progress::quiet,
+    )
+    .unwrap();
+    let peer_rpc = peer.connection().client(None).unwrap();
+    assert_eq!(peer_rpc.get_raw_mempool().unwrap(), [replaced.replacement]);
+}
@@ -0,0 +1,293 @@
+//! The example scenarios in scenarios/, run against the mock node
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use rust::Error;
+use rust::cluster::{Cluster, ClusterNode};
+use rust::mock::MockNode;
+use rust::progress;
+use rust::reorg::TxFate;
+use rust::scenario::{self, Funding, Scenario, Step};
+
+/// Run an example on as many connected mock nodes as it asks for
+fn run_example(file: &str) -> scenario::ScenarioOutcome {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("scenarios")
+        .join(file);
+    let scenario = Scenario::from_file(&path).unwrap();
+    let nodes: Vec<MockNode> = (0..scenario.nodes)
+        .map(|_| MockNode::start().unwrap())
+        .collect();
+    run_on(&nodes, &scenario)
+}
+
+/// Run `scenario` on `nodes`, connected as it asks for
+fn run_on(nodes: &[MockNode], scenario: &Scenario) -> scenario::ScenarioOutcome {
+    let members = nodes
+        .iter()
+        .map(|node| ClusterNode {
+            connection: node.connection(),
+            p2p_address: node.p2p_address(),
+        })
+        .collect();
+    let cluster = Cluster::new(members, scenario.topology, progress::quiet).unwrap();
+    scenario::run_on(&cluster, scenario, progress::quiet).unwrap()
+}
+
+#[test]
+fn miner_pays_trader_example_passes() {
+    let outcome = run_example("miner-pays-trader.toml");
+    assert_eq!(outcome.payments.len(), 1);
+    assert!(outcome.failures.is_empty(), "{:?}", outcome.failures);
+}
+
+#[test]
+fn three_party_example_passes() {
+    let outcome = run_example("three-party.yaml");
+    assert_eq!(outcome.payments.len(), 3);
+    assert!(outcome.failures.is_empty(), "{:?}", outcome.failures);
+}
+
+#[test]
+fn three_node_line_example_passes() {
+    let outcome = run_example("three-node-line.toml");
+    assert_eq!(outcome.payments.len(), 1);
+    assert!(outcome.failures.is_empty(), "{:?}", outcome.failures);
+}
+
+#[test]
+fn funding_sent_on_another_node_is_confirmed_too() {
+    let scenario = Scenario::from_toml(
+        r#"
+        nodes = 2
+        participants = [
+            { name = "Miner" },
+            { name = "Alice" },
+            { name = "Bob", node = 1 },
+            { name = "Carol", node = 1 },
+        ]
+        funding = [
+            { kind = "mine", participant = "Miner" },
+            { kind = "mine", participant = "Bob" },
+            { kind = "transfer", participant = "Alice", from = "Miner", amount = 1 },
+            { kind = "transfer", participant = "Carol", from = "Bob", amount = 2 },
+        ]
+
+        [[steps]]
+        action = "assert_balance"
+        participant = "Carol"
+        equals = 2
+        "#,
+    )
+    .unwrap();
+
+    let nodes = [MockNode::start().unwrap(), MockNode::start().unwrap()];
+    let outcome = run_on(&nodes, &scenario);
+    assert_eq!(outcome.payments.len(), 2);
+    assert!(outcome.failures.is_empty(), "{:?}", outcome.failures);
+
+    // Mock nodes relay too fast to lose the race, so check the miner's node looked for
+    // the payments before mining them in
+    let calls = nodes[0].calls();
+    let paid = calls.iter().rposition(|c| c == "sendtoaddress").unwrap();
+    let mined = paid
+        + calls[paid..]
+            .iter()
+            .position(|c| c == "generatetoaddress")
+            .unwrap();
+    assert!(
+        calls[paid..mined].iter().any(|c| c == "getrawmempool"),
+        "{calls:?}"
+    );
+}
+
+#[test]
+fn partition_example_reorgs_the_losing_side() {
+    let outcome = run_example("partition.toml");
+    assert!(outcome.failures.is_empty(), "{:?}", outcome.failures);
+    // Only Alice's node switched branches, dropping her one block
+    assert_eq!(outcome.reorgs.len(), 1);
+    let reorg = &outcome.reorgs[0];
+    assert_eq!(reorg.depth(), 1);
+    assert_eq!(reorg.fate(&outcome.payments[0]), Some(TxFate::InMempool));
+}
+
+#[test]
+fn failed_assertions_are_collected_not_fatal() {
+    let scenario = Scenario::from_toml(
+        r#"
+        [[participants]]
+        name = "Miner"
+
+        [[steps]]
+        action = "mine_to_maturity"
+        by = "Miner"
+
+        [[steps]]
+        action = "assert_balance"
+        participant = "Miner"
+        equals = "49 BTC"
+
+        [[steps]]
+        action = "assert_balance"
+        participant = "Miner"
+        kind = "immature"
+        at_least = 5000
+        "#,
+    )
+    .unwrap();
+    let node = MockNode::start().unwrap();
+
+    let outcome = scenario::run(&node.connection(), &scenario, progress::quiet).unwrap();
+    assert_eq!(outcome.failures.len(), 1, "{:?}", outcome.failures);
+    assert!(outcome.failures[0].contains("Miner"));
+}
+
+#[test]
+fn steps_are_reported_through_progress_not_printed() {
+    // A plain fn can't capture anything, so the messages land in a static
+    static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    fn record(message: &str) {
+        MESSAGES.lock().unwrap().push(message.to_owned());
+    }
+
+    let scenario = Scenario::from_yaml(
+        "participants: [{name: Miner}]\n\
+         steps: [{action: mine, by: Miner, blocks: 2}, \
+                 {action: assert_balance, participant: Miner, at_least: 0}]\n",
+    )
+    .unwrap();
+    let node = MockNode::start().unwrap();
+
+    scenario::run(&node.connection(), &scenario, record).unwrap();
+    let messages = MESSAGES.lock().unwrap();
+    assert!(
+        messages.iter().any(|m| m == "Miner mined 2 block(s)"),
+        "{messages:?}"
+    );
+    assert!(
+        messages.iter().any(|m| m.starts_with("✓ Miner balance")),
+        "{messages:?}"
+    );
+}
+
+#[test]
+fn unknown_participants_are_rejected_when_loading() {
+    let error = Scenario::from_yaml(
+        "participants: [{name: Alice}]\nsteps: [{action: mine, by: Bob}]\n",
+    )
+    .unwrap_err();
+    assert!(
+        matches!(error, Error::Config(ref message) if message.contains("Bob")),
+        "{error}"
+    );
+}
+
+#[test]
+fn fractional_numbers_are_not_amounts() {
+    let error = Scenario::from_toml(
+        "[[participants]]\nname = \"A\"\n[[participants]]\nname = \"B\"\n\
+         [[steps]]\naction = \"pay\"\nfrom = \"A\"\nto = \"B\"\namount = 0.1\n",
+    );
+    assert!(error.is_err());
+}
+
+#[test]
+fn misspelled_funding_is_an_error_not_mining() {
+    let participants = "participants: [{name: Miner}, {name: Alice}]\n";
+    for funding in [
+        "funding: [{kind: transfer, participant: Alice, form: Miner, amount: 1}]\n",
+        "funding: [{kind: transfer, participant: Alice, from: Miner, amout: 1}]\n",
+        "funding: [{participant: Alice}]\n",
+    ] {
+        let error = Scenario::from_yaml(&format!("{participants}{funding}")).unwrap_err();
+        assert!(matches!(error, Error::Config(_)), "{funding}: {error}");
+    }
+
+    let scenario = Scenario::from_yaml(&format!(
+        "{participants}funding: [{{kind: mine, participant: Miner}}, \
+         {{kind: transfer, participant: Alice, from: Miner, amount: 1}}]\n"
+    ))
+    .unwrap();
+    assert!(matches!(scenario.funding[0], Funding::Mine { .. }));
+    assert!(matches!(scenario.funding[1], Funding::Transfer { .. }));
+}
+
+#[test]
+fn misspelled_wallet_options_are_rejected() {
+    let error =
+        Scenario::from_toml("[[participants]]\nname = \"A\"\nwatch_onyl = true\n").unwrap_err();
+    assert!(
+        matches!(error, Error::Config(ref message) if message.contains("watch_onyl")),
+        "{error}"
+    );
+
+    let scenario =
+        Scenario::from_toml("[[participants]]\nname = \"A\"\nwatch_only = true\nnode = 0\n")
+            .unwrap();
+    assert!(scenario.participants[0].wallet.disable_private_keys);
+}
+
+#[test]
+fn misspelled_step_keys_are_rejected() {
+    let participants = "participants: [{name: Miner}]\n";
+    let error = Scenario::from_yaml(&format!(
+        "{participants}steps: [{{action: mine, by: Miner, blokcs: 6}}]\n"
+    ))
+    .unwrap_err();
+    assert!(
+        matches!(error, Error::Config(ref message) if message.contains("blokcs")),
+        "{error}"
+    );
+
+    let scenario = Scenario::from_yaml(&format!(
+        "{participants}steps: [{{action: mine, by: Miner, blocks: 6}}]\n"
+    ))
+    .unwrap();
+    assert!(matches!(scenario.steps[0], Step::Mine { blocks: 6, .. }));
+}
+
+#[test]
+fn balance_assertions_must_check_something_consistent() {
+    let participants = "participants: [{name: Miner}]\n";
+    for (bounds, problem) in [
+        ("", "checks nothing"),
+        ("equals: 1, at_least: 1, ", "both equals and a range"),
+        ("at_least: 2, at_most: 1, ", "at_least above at_most"),
+    ] {
+        let error = Scenario::from_yaml(&format!(
+            "{participants}steps: [{{action: assert_balance, {bounds}participant: Miner}}]\n"
+        ))
+        .unwrap_err();
+        assert!(
+            matches!(error, Error::Config(ref message) if message.contains(problem)),
+            "{bounds}: {error}"
+        );
+    }
+
+    let scenario = Scenario::from_yaml(&format!(
+        "{participants}steps: [{{action: assert_balance, participant: Miner, \
+         at_least: 1, at_most: 1}}]\n"
+    ))
+    .unwrap();
+    assert_eq!(scenario.steps.len(), 1);
+}
+
+#[test]
+fn participants_must_live_on_a_declared_node() {
+    let error = Scenario::from_toml("nodes = 2\n[[participants]]\nname = \"A\"\nnode = 2\n")
+        .unwrap_err();
+    assert!(
+        matches!(error, Error::Config(ref message) if message.contains("node 2")),
+        "{error}"
+    );
+}
+
+#[test]
+fn multi_node_scenarios_need_enough_nodes() {
+    let scenario = Scenario::from_toml("nodes = 2\n[[participants]]\nname = \"A\"\n").unwrap();
+    let node = MockNode::start().unwrap();
+    let error = scenario::run(&node.connection(), &scenario, progress::quiet).unwrap_err();
+    assert!(matches!(error, Error::Config(_)), "{error}");
+}
@@ -0,0 +1,124 @@
+//! Script types of a payment with outputs no address can describe
+
+mod common;
+
+use bitcoincore_rpc::RpcApi;
+use bitcoincore_rpc::bitcoin::blockdata::opcodes::all::{OP_CHECKMULTISIG, OP_PUSHNUM_1};
+use bitcoincore_rpc::bitcoin::script::Builder;
+use bitcoincore_rpc::bitcoin::{
+    Amount, Network, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+    Witness, absolute, transaction,
+};
+use rust::analysis::{self, OutputRole};
+use rust::report::{self, TransactionReport};
+use rust::script::{ScriptType, SpendPath};
+use rust::{confirmation, transfer};
+
+use common::Funded;
+
+#[test]
+fn undecodable_outputs_are_classified_and_disassembled() {
+    let Funded {
+        node: _node,
+        miner,
+        miner_address,
+        trader_address,
+        ..
+    } = Funded::new();
+
+    // One mature coinbase, split into a payment, a data carrier, a bare 1-of-1
+    // multisig and change
+    let coin = miner
+        .list_unspent(None, None, None, None, None)
+        .unwrap()
+        .remove(0);
+    // The secp256k1 generator point: a valid key nobody here has the private key for
+    let pubkey: PublicKey =
+        "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+            .parse()
+            .unwrap();
+    let multisig = Builder::new()
+        .push_opcode(OP_PUSHNUM_1)
+        .push_key(&pubkey)
+        .push_opcode(OP_PUSHNUM_1)
+        .push_opcode(OP_CHECKMULTISIG)
+        .into_script();
+    let payment = Amount::from_btc(20.0).unwrap();
+    let locked = Amount::from_sat(10_000);
+    let fee = Amount::from_sat(1_000);
+    let unsigned = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(coin.txid, coin.vout),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: vec![
+            TxOut {
+                value: payment,
+                script_pubkey: trader_address.script_pubkey(),
+            },
+            TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new_op_return(b"forensics"),
+            },
+            TxOut {
+                value: locked,
+                script_pubkey: multisig,
+            },
+            TxOut {
+                value: coin.amount - payment - locked - fee,
+                script_pubkey: miner_address.script_pubkey(),
+            },
+        ],
+    };
+    let signed = transfer::sign(&miner, &unsigned).unwrap();
+    let txid = miner.send_raw_transaction(&signed).unwrap();
+    transfer::confirm(&miner, &miner_address).unwrap();
+
+    let analysis = analysis::analyze(&miner, &miner, &txid, Network::Regtest).unwrap();
+    assert_eq!(analysis.fee, fee);
+    let input = &analysis.inputs[0];
+    assert_eq!(input.script_type, ScriptType::P2wpkh);
+    assert_eq!(input.spend_path, SpendPath::P2wpkh);
+    assert_eq!(input.asm, None);
+
+    // Nothing is dropped: every output is there, typed, with ASM where there's no address
+    let types: Vec<ScriptType> = analysis.outputs.iter().map(|o| o.script_type).collect();
+    assert_eq!(
+        types,
+        [
+            ScriptType::P2wpkh,
+            ScriptType::OpReturn,
+            ScriptType::BareMultisig,
+            ScriptType::P2wpkh
+        ]
+    );
+    let data = &analysis.outputs[1];
+    assert_eq!(data.role, OutputRole::Unknown);
+    assert_eq!(data.address, None);
+    assert!(
+        data.asm.as_deref().unwrap().starts_with("OP_RETURN"),
+        "{data:?}"
+    );
+    let bare = &analysis.outputs[2];
+    assert_eq!(bare.amount, locked);
+    assert!(
+        bare.asm.as_deref().unwrap().ends_with("OP_CHECKMULTISIG"),
+        "{bare:?}"
+    );
+    assert_eq!(analysis.outputs[0].asm, None);
+
+    let confirmation = confirmation::locate(&miner, &txid).unwrap();
+    let report = TransactionReport::new(&analysis, confirmation);
+    let mut markdown = Vec::new();
+    report::write_markdown(&mut markdown, &report).unwrap();
+    let markdown = String::from_utf8(markdown).unwrap();
+    assert!(
+        markdown.contains("| 1 | OP_RETURN | `OP_RETURN"),
+        "{markdown}"
+    );
+    assert!(markdown.contains("bare multisig"), "{markdown}");
+}
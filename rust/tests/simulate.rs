@@ -0,0 +1,664 @@
+//! End-to-end: run the `rust` binary against the mock node and check its reports
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use bitcoincore_rpc::RpcApi;
+use bitcoincore_rpc::bitcoin::Amount;
+use rust::amount::parse_amount;
+use rust::mock::MockNode;
+
+/// Run the binary against `node` with explicit credentials (no bitcoin.conf lookup)
+fn run(node: &MockNode, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rust"))
+        .args([
+            "--rpc-url",
+            &node.url(),
+            "--rpc-user",
+            "alice",
+            "--rpc-password",
+            "password",
+        ])
+        .args(args)
+        .output()
+        .expect("the binary runs")
+}
+
+fn simulate(node: &MockNode, dir: &Path, format: &str) -> String {
+    let path = dir.join(format!("report.{format}"));
+    let output = run(
+        node,
+        &[
+            "simulate",
+            "--format",
+            format,
+            "--output",
+            path.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        output.status.success(),
+        "simulate failed:\n{}\n{}\n{:?}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr),
+        node.calls()
+    );
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn legacy_report_has_ten_consistent_lines() {
+    let node = MockNode::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let report = simulate(&node, dir.path(), "text");
+
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines.len(), 10, "{report}");
+    let amount = |line: usize| parse_amount(lines[line]).unwrap();
+
+    // Same checks as test/test.spec.ts: the amounts add up exactly
+    assert_eq!(amount(4), Amount::from_btc(20.0).unwrap());
+    assert_eq!(amount(2), amount(4) + amount(6) + amount(7));
+    assert!(amount(7) > Amount::ZERO);
+
+    // 101 blocks to mature the first coinbase, plus one to confirm the payment
+    let rpc = node.connection().client(None).unwrap();
+    assert_eq!(lines[8], "102");
+    assert_eq!(lines[9], rpc.get_best_block_hash().unwrap().to_string());
+}
+
+#[test]
+fn json_report_matches_the_node() {
+    let node = MockNode::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let report: serde_json::Value =
+        serde_json::from_str(&simulate(&node, dir.path(), "json")).unwrap();
+
+    let rpc = node.connection().client(None).unwrap();
+    let txid = report["txid"].as_str().unwrap().parse().unwrap();
+    let tx = rpc.get_raw_transaction(&txid, None).unwrap();
+    assert_eq!(report["vsize"], tx.vsize());
+    assert_eq!(report["weight"], tx.weight().to_wu());
+    assert_eq!(report["size"], tx.total_size());
+    assert_eq!(report["inputs"].as_array().unwrap().len(), tx.input.len());
+    assert_eq!(report["outputs"].as_array().unwrap().len(), tx.output.len());
+    // The block the payment is in, proven to hold it
+    assert_eq!(report["block_position"], 1);
+    assert_eq!(report["confirmations"], 1);
+    assert_eq!(report["merkle_proof"]["verified_by_node"], true);
+    assert_eq!(report["merkle_proof"]["verified_locally"], true);
+    // The Miner's wallet recognised its change key
+    let change = report["outputs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|output| output["role"] == "change")
+        .unwrap();
+    assert_eq!(change["ownership"]["is_change"], true, "{change}");
+    // The mock charges 1 sat/vB, so the fee in sats is (about) the vsize
+    let fee = report["fee"]["sat"].as_u64().unwrap();
+    assert!(fee >= report["vsize"].as_u64().unwrap(), "{fee} sat");
+
+    // By the time it's analyzed the payment itself is history the estimate draws on
+    let comparison = &report["fee_comparison"];
+    assert_eq!(
+        comparison["fee_rate_sat_per_vb"],
+        report["fee_rate_sat_per_vb"]
+    );
+    assert_eq!(comparison["mempool_min_fee_sat_per_vb"], 1.0);
+    assert!(
+        comparison["estimate_sat_per_vb"].as_f64().is_some(),
+        "{comparison}"
+    );
+}
+
+#[test]
+fn second_run_reuses_wallets_and_mines_nothing_extra() {
+    let node = MockNode::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    simulate(&node, dir.path(), "text");
+    let report = simulate(&node, dir.path(), "text");
+
+    // The Miner's balance is already spendable: only the confirmation block is mined
+    assert_eq!(report.lines().nth(8), Some("103"));
+    let creates = node
+        .calls()
+        .iter()
+        .filter(|call| *call == "createwallet")
+        .count();
+    assert_eq!(creates, 2);
+}
+
+#[test]
+fn output_with_several_formats_is_rejected_before_touching_the_node() {
+    let node = MockNode::start().unwrap();
+    let output = run(
+        &node,
+        &["simulate", "--format", "text,json", "--output", "-"],
+    );
+
+    assert!(!output.status.success());
+    // Only the read-only readiness check got through
+    assert_eq!(node.calls(), ["getblockcount"]);
+}
+
+#[test]
+fn json_report_on_stdout_is_all_stdout_holds() {
+    let node = MockNode::start().unwrap();
+    let output = run(&node, &["simulate", "--format", "json", "--output", "-"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["block_height"], 102);
+    // The progress messages are still there, on stderr
+    assert!(stderr.contains("Blockchain Info"), "{stderr}");
+    assert!(stderr.contains("Summary: Sent"), "{stderr}");
+}
+
+#[test]
+fn default_reports_go_to_the_repo_root_found_at_run_time() {
+    let node = MockNode::start().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    std::fs::write(repo.path().join("package.json"), "{}").unwrap();
+    let workdir = repo.path().join("rust/src");
+    std::fs::create_dir_all(&workdir).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rust"))
+        .current_dir(&workdir)
+        .args([
+            "--rpc-url",
+            &node.url(),
+            "--rpc-user",
+            "alice",
+            "--rpc-password",
+            "password",
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(repo.path().join("out.txt").is_file());
+    assert!(repo.path().join("out.json").is_file());
+    assert!(!workdir.join("out.txt").exists());
+}
+
+#[test]
+fn unreachable_node_is_reported_by_url() {
+    let node = MockNode::start().unwrap();
+    let url = node.url();
+    drop(node);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rust"))
+        .args([
+            "--rpc-url",
+            &url,
+            "--rpc-user",
+            "alice",
+            "--rpc-password",
+            "password",
+            "info",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("{url} not ready")), "{stderr}");
+}
+
+#[test]
+fn double_spend_reorg_is_reported() {
+    let node = MockNode::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("report.json");
+    let output = run(
+        &node,
+        &[
+            "simulate",
+            "--reorg-depth",
+            "1",
+            "--double-spend",
+            "--format",
+            "json",
+            "--output",
+            path.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let reorg = &report["reorg"];
+    assert_eq!(reorg["disconnected_blocks"].as_array().unwrap().len(), 1);
+    let payment = reorg["transactions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|tx| tx["fate"] == "double_spent")
+        .unwrap();
+    // What the report describes is the double-spend, which the active chain holds now
+    assert_eq!(payment["by"], report["txid"]);
+    assert_reported_block_is_active(&node, &report);
+}
+
+#[test]
+fn reorged_payment_is_mined_again_and_reported_in_its_new_block() {
+    let node = MockNode::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("report.json");
+    let output = run(
+        &node,
+        &[
+            "simulate",
+            "--reorg-depth",
+            "1",
+            "--format",
+            "json",
+            "--output",
+            path.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let payment = report["reorg"]["transactions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|tx| tx["txid"] == report["txid"])
+        .unwrap();
+    assert_eq!(payment["fate"], "in_mempool");
+    assert_reported_block_is_active(&node, &report);
+    // Mined again on top of the longer branch: 102 + 1 new block + the confirmation
+    assert_eq!(report["block_height"], 104);
+}
+
+/// The block the report puts the transaction in is on the active chain, and holds it
+fn assert_reported_block_is_active(node: &MockNode, report: &serde_json::Value) {
+    let rpc = node.connection().client(None).unwrap();
+    let height = report["block_height"].as_u64().unwrap();
+    let active = rpc.get_block_hash(height).unwrap();
+    assert_eq!(report["block_hash"], active.to_string());
+    let block = rpc.get_block(&active).unwrap();
+    let txid = report["txid"].as_str().unwrap();
+    assert!(
+        block.txdata.iter().any(|tx| tx.txid().to_string() == txid),
+        "{txid} isn't in block {active}"
+    );
+}
+
+#[test]
+fn bumped_payment_is_the_one_reported() {
+    let node = MockNode::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("report.json");
+    let output = run(
+        &node,
+        &[
+            "simulate",
+            "--replace",
+            "bump-fee",
+            "--format",
+            "json",
+            "--output",
+            path.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let replacement = &report["replacement"];
+    assert_eq!(replacement["method"], "bump_fee");
+    assert_eq!(replacement["confirmed"], report["txid"]);
+    assert_eq!(replacement["evicted"][0], replacement["original"]);
+    // Still a payment to the Trader, just with less change
+    let payment = report["outputs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|output| output["role"] == "payment")
+        .unwrap();
+    assert_eq!(payment["amount"]["sat"], 2_000_000_000u64);
+}
+
+#[test]
+fn cpfp_child_is_reported() {
+    let node = MockNode::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("report.md");
+    let output = run(
+        &node,
+        &[
+            "simulate",
+            "--cpfp-fee-rate",
+            "8",
+            "--format",
+            "markdown",
+            "--output",
+            path.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let report = std::fs::read_to_string(path).unwrap();
+    assert!(report.contains("## Child pays for parent"), "{report}");
+    assert!(report.contains("aiming for 8 sat/vB"), "{report}");
+}
+
+#[test]
+fn psbt_stages_are_exported_and_reported() {
+    let node = MockNode::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("report.json");
+    let psbt_dir = dir.path().join("psbt");
+    let output = run(
+        &node,
+        &[
+            "simulate",
+            "--psbt",
+            "--psbt-dir",
+            psbt_dir.to_str().unwrap(),
+            "--format",
+            "json",
+            "--output",
+            path.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let psbt = &report["psbt"];
+    assert_eq!(psbt["txid"], report["txid"]);
+    let steps = psbt["steps"].as_array().unwrap();
+    assert_eq!(steps.len(), 4);
+    assert_eq!(steps[3]["stage"], "finalized");
+    assert_eq!(steps[3]["inspection"]["fee"], report["fee"]);
+    for step in steps {
+        for file in step["files"].as_array().unwrap() {
+            assert!(Path::new(file.as_str().unwrap()).exists(), "{file}");
+        }
+    }
+}
+
+#[test]
+fn exported_psbt_can_be_signed_and_finalized_from_the_command_line() {
+    let node = MockNode::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    // Any simulation leaves the Miner with coins to spare; pay the Trader again by hand
+    simulate(&node, dir.path(), "text");
+    let connection = node.connection();
+    let miner = connection.client(Some("Miner")).unwrap();
+    let trader = connection.client(Some("Trader")).unwrap();
+    let address = trader.get_new_address(None, None).unwrap().assume_checked();
+    let created = rust::psbt::create(&miner, &address, Amount::ONE_BTC).unwrap();
+    let unsigned = dir.path().join("unsigned.base64");
+    rust::psbt::write(&unsigned, &created).unwrap();
+
+    let succeeded = |output: Output| {
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        assert!(
+            output.status.success(),
+            "{stdout}\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        stdout
+    };
+    let signed = dir.path().join("signed.psbt");
+    let stdout = succeeded(run(
+        &node,
+        &[
+            "--wallet",
+            "Miner",
+            "psbt",
+            "sign",
+            unsigned.to_str().unwrap(),
+            "--output",
+            signed.to_str().unwrap(),
+        ],
+    ));
+    assert!(stdout.contains("1 signed, 0 finalized"), "{stdout}");
+    let stdout = succeeded(run(&node, &["psbt", "decode", signed.to_str().unwrap()]));
+    assert!(stdout.contains("1 signed"), "{stdout}");
+    // Everything decode shows is in the file: it works with no node to talk to
+    let offline = Command::new(env!("CARGO_BIN_EXE_rust"))
+        .args(["--rpc-url", "http://127.0.0.1:1", "psbt", "decode"])
+        .arg(&signed)
+        .output()
+        .unwrap();
+    assert_eq!(succeeded(offline), stdout);
+
+    let stdout = succeeded(run(
+        &node,
+        &["psbt", "finalize", signed.to_str().unwrap(), "--broadcast"],
+    ));
+    let txid = created.unsigned_tx.txid();
+    assert!(
+        stdout.contains(&format!("Broadcast transaction {txid}")),
+        "{stdout}"
+    );
+    assert!(miner.get_mempool_entry(&txid).is_ok());
+}
+
+#[test]
+fn coin_listed_by_the_coins_command_is_the_one_spent() {
+    let node = MockNode::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    // The first run leaves the Miner with change and a few mature coinbases
+    simulate(&node, dir.path(), "text");
+    let output = run(&node, &["--wallet", "Miner", "coins"]);
+    assert!(output.status.success());
+    let listing = String::from_utf8(output.stdout).unwrap();
+    // Not the largest: the wallet wouldn't have picked it by itself
+    let coin = listing
+        .lines()
+        .rfind(|line| line.contains(" BTC ("))
+        .and_then(|line| line.split_whitespace().next())
+        .unwrap()
+        .to_owned();
+
+    let path = dir.path().join("report.json");
+    let output = run(
+        &node,
+        &[
+            "simulate",
+            "--coin",
+            &coin,
+            "--format",
+            "json",
+            "--output",
+            path.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let inputs = report["inputs"].as_array().unwrap();
+    assert_eq!(inputs.len(), 1);
+    assert_eq!(inputs[0]["outpoint"], coin.as_str(), "{listing}");
+}
+
+#[test]
+fn multisig_signers_are_reported() {
+    let node = MockNode::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("report.json");
+    let output = run(
+        &node,
+        &[
+            "simulate",
+            "--multisig",
+            "2-of-3",
+            "--multisig-kind",
+            "taproot",
+            "--signer",
+            "Cosigner1",
+            "--signer",
+            "Cosigner3",
+            "--format",
+            "json",
+            "--output",
+            path.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let multisig = &report["multisig"];
+    assert_eq!(multisig["txid"], report["txid"]);
+    assert_eq!(multisig["kind"], "taproot");
+    assert_eq!(
+        multisig["signers"],
+        serde_json::json!(["Cosigner1", "Cosigner3"])
+    );
+    assert_eq!(multisig["fee"], report["fee"]);
+    // The multisig wallet recognizes its own change
+    let roles: Vec<&str> = report["outputs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|output| output["role"].as_str().unwrap())
+        .collect();
+    assert!(roles.contains(&"change"), "{roles:?}");
+}
+
+#[test]
+fn multisig_needs_enough_signers() {
+    let node = MockNode::start().unwrap();
+    let output = run(
+        &node,
+        &["simulate", "--multisig", "2-of-3", "--signer", "Cosigner2"],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("needs 2 signers"), "{stderr}");
+}
+
+#[test]
+fn timelock_command_spends_once_the_lock_is_up() {
+    let node = MockNode::start().unwrap();
+    let output = run(&node, &["timelock", "csv", "--blocks", "3"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("rejected (non-BIP68-final)"), "{stdout}");
+    assert!(stdout.contains("mined 3 block(s)"), "{stdout}");
+    assert!(stdout.contains("accepted, broadcast"), "{stdout}");
+    assert!(stdout.contains("Confirmed"), "{stdout}");
+}
+
+#[test]
+fn taproot_leaf_is_decoded_in_the_report() {
+    let node = MockNode::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("report.json");
+    let output = run(
+        &node,
+        &[
+            "simulate",
+            "--taproot-leaf",
+            "1",
+            "--format",
+            "json",
+            "--output",
+            path.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Input 1 revealed a leaf at depth 2"),
+        "{stdout}"
+    );
+
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    assert_eq!(report["taproot"]["txid"], report["txid"]);
+    let inputs = report["inputs"].as_array().unwrap();
+    assert_eq!(inputs[0]["spend_path"], "p2tr_key_path");
+    assert_eq!(inputs[1]["spend_path"], "p2tr_script_path");
+    let tapscript = &inputs[1]["tapscript"];
+    assert_eq!(tapscript["verified"], true);
+    assert_eq!(
+        tapscript["script"],
+        report["taproot"]["leaves"][1]["script"]
+    );
+    assert_eq!(tapscript["internal_key"], report["taproot"]["internal_key"]);
+}
+
+#[test]
+fn address_type_picks_the_miner_and_trader_addresses() {
+    let node = MockNode::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("report.json");
+    let output = run(
+        &node,
+        &[
+            "simulate",
+            "--address-type",
+            "p2sh-segwit",
+            "--format",
+            "json",
+            "--output",
+            path.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    for input in report["inputs"].as_array().unwrap() {
+        assert_eq!(input["spend_path"], "p2sh_p2wpkh", "{input}");
+    }
+    let payment = &report["outputs"][0];
+    assert_eq!(payment["role"], "payment");
+    assert_eq!(payment["script_type"], "p2sh");
+
+    let output = run(&node, &["simulate", "--address-type", "segwit"]);
+    assert!(!output.status.success());
+}
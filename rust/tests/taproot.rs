@@ -0,0 +1,160 @@
+//! Taproot: one payment spending a script tree through its key path and through a leaf
+
+mod common;
+
+use bitcoincore_rpc::RpcApi;
+use bitcoincore_rpc::bitcoin::{Amount, Network};
+use rust::analysis::{self, OutputRole};
+use rust::report::{self, TransactionReport};
+use rust::script::{ScriptType, SpendPath};
+use rust::taproot;
+use rust::{Error, confirmation, transfer};
+
+use common::Funded;
+
+#[test]
+fn key_path_and_leaf_spend_one_payment() {
+    let Funded {
+        node: _node,
+        miner,
+        miner_address,
+        trader_address,
+        ..
+    } = Funded::new();
+    let amount = Amount::from_int_btc(5);
+    let payment =
+        taproot::pay(&miner, &miner, Network::Regtest, &trader_address, amount, 0).unwrap();
+    assert_eq!(payment.spent_leaf(), Some(0));
+    let depths: Vec<u8> = payment.leaves.iter().map(|leaf| leaf.depth).collect();
+    assert_eq!(depths, [1, 2, 2]);
+    assert!(payment.address.starts_with("bcrt1p"), "{payment}");
+    assert_eq!(
+        miner.get_mempool_entry(&payment.txid).unwrap().fees.base,
+        payment.fee
+    );
+    transfer::confirm(&miner, &miner_address).unwrap();
+
+    let analysis = analysis::analyze(&miner, &miner, &payment.txid, Network::Regtest).unwrap();
+    assert_eq!(analysis.fee, payment.fee);
+    let [key_path, script_path] = analysis.inputs.as_slice() else {
+        panic!("{:?}", analysis.inputs);
+    };
+    assert_eq!(key_path.outpoint, payment.key_path);
+    assert_eq!(key_path.spend_path, SpendPath::P2trKeyPath);
+    // The key path shows nothing of the tree
+    assert_eq!(key_path.tapscript, None);
+    assert_eq!(script_path.outpoint, payment.script_path);
+    assert_eq!(script_path.spend_path, SpendPath::P2trScriptPath);
+    let reveal = script_path.tapscript.as_ref().unwrap();
+    assert!(reveal.verified);
+    assert_eq!(reveal.leaf_version, 0xc0);
+    assert_eq!(reveal.script, payment.leaves[0].script);
+    assert!(reveal.script.ends_with("OP_CHECKSIG"), "{reveal:?}");
+    assert_eq!(reveal.internal_key, payment.internal_key);
+    // The leaf next to the root only needs the hash of the other branch
+    assert_eq!(reveal.depth(), 1);
+
+    // A Taproot payment gets Taproot change, which the Miner recognizes as its own
+    let change = &analysis.outputs[1];
+    assert_eq!(change.role, OutputRole::Change);
+    assert_eq!(change.script_type, ScriptType::P2tr);
+    assert_eq!(analysis.outputs[0].amount, amount);
+}
+
+#[test]
+fn deeper_leaf_needs_a_longer_proof() {
+    let Funded {
+        node: _node,
+        miner,
+        trader_address,
+        ..
+    } = Funded::new();
+    let payment = taproot::pay(
+        &miner,
+        &miner,
+        Network::Regtest,
+        &trader_address,
+        Amount::ONE_BTC,
+        2,
+    )
+    .unwrap();
+
+    let tx = miner.get_raw_transaction(&payment.txid, None).unwrap();
+    // Signature, leaf script, and a control block with two sibling hashes
+    let witness = &tx.input[1].witness;
+    assert_eq!(witness.len(), 3);
+    assert_eq!(witness.last().unwrap().len(), 33 + 2 * 32);
+    assert_eq!(tx.input[0].witness.len(), 1);
+
+    let analysis = analysis::analyze(&miner, &miner, &payment.txid, Network::Regtest).unwrap();
+    let reveal = analysis.inputs[1].tapscript.as_ref().unwrap();
+    assert_eq!(reveal.depth(), 2);
+    assert_eq!(reveal.script, payment.leaves[2].script);
+    assert!(reveal.verified);
+}
+
+#[test]
+fn report_decodes_the_control_block() {
+    let Funded {
+        node: _node,
+        miner,
+        miner_address,
+        trader_address,
+        ..
+    } = Funded::new();
+    let payment = taproot::pay(
+        &miner,
+        &miner,
+        Network::Regtest,
+        &trader_address,
+        Amount::ONE_BTC,
+        1,
+    )
+    .unwrap();
+    transfer::confirm(&miner, &miner_address).unwrap();
+    let analysis = analysis::analyze(&miner, &miner, &payment.txid, Network::Regtest).unwrap();
+    let confirmation = confirmation::locate(&miner, &payment.txid).unwrap();
+    let mut report = TransactionReport::new(&analysis, confirmation);
+    report.taproot = Some(payment.clone());
+
+    let mut markdown = Vec::new();
+    report::write_markdown(&mut markdown, &report).unwrap();
+    let markdown = String::from_utf8(markdown).unwrap();
+    assert!(markdown.contains("## Script path"), "{markdown}");
+    assert!(
+        markdown.contains(&format!("| 1 | `{}` | 0xc0 |", payment.leaves[1].script)),
+        "{markdown}"
+    );
+    assert!(markdown.contains("## Taproot"), "{markdown}");
+    assert!(markdown.contains(&payment.merkle_root), "{markdown}");
+
+    let mut json = Vec::new();
+    report::write_json(&mut json, &report).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    let tapscript = &json["inputs"][1]["tapscript"];
+    assert_eq!(tapscript["verified"], true);
+    assert_eq!(tapscript["merkle_path"].as_array().unwrap().len(), 2);
+    assert!(json["inputs"][0].get("tapscript").is_none());
+    assert_eq!(json["taproot"]["leaves"][1]["spent"], true);
+}
+
+#[test]
+fn leaf_outside_the_tree_is_refused() {
+    let Funded {
+        node: _node,
+        miner,
+        trader_address,
+        ..
+    } = Funded::new();
+    let error = taproot::pay(
+        &miner,
+        &miner,
+        Network::Regtest,
+        &trader_address,
+        Amount::ONE_BTC,
+        3,
+    )
+    .unwrap_err();
+    assert!(matches!(error, Error::Config(_)), "{error}");
+    assert!(miner.get_raw_mempool().unwrap().is_empty());
+}
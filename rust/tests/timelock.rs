@@ -0,0 +1,128 @@
+//! Timelocks: the mempool refuses early spends with the rule they break, then takes them
+
+mod common;
+
+use bitcoincore_rpc::bitcoin::{Address, Amount, Network, OutPoint, absolute};
+use bitcoincore_rpc::{Client, RpcApi};
+use rust::coin_control::{self, ChosenInput, RawTransactionSpec};
+use rust::mock::MockNode;
+use rust::timelock::{self, TimelockKind};
+use rust::{Error, mining, transfer};
+
+use common::Funded;
+
+const SCRIPT_FAILURE: &str =
+    "mandatory-script-verify-flag-failed (Locktime requirement not satisfied)";
+
+/// A mock node with a Miner holding mature coins
+fn funded_miner() -> (MockNode, Client, Client, Address) {
+    let Funded {
+        node,
+        miner,
+        miner_address,
+        ..
+    } = Funded::new();
+    let rpc = node.connection().client(None).unwrap();
+    (node, rpc, miner, miner_address)
+}
+
+fn reasons(demo: &timelock::TimelockDemo) -> Vec<&str> {
+    demo.premature
+        .iter()
+        .map(|attempt| attempt.acceptance.reject_reason.as_deref().unwrap())
+        .collect()
+}
+
+#[test]
+fn locktime_spend_is_non_final_until_its_height() {
+    let (_node, rpc, miner, _) = funded_miner();
+    let tip = rpc.get_block_count().unwrap();
+    let demo = timelock::demonstrate(&rpc, &miner, Network::Regtest, TimelockKind::LockTime, 5)
+        .unwrap();
+
+    assert_eq!(reasons(&demo), ["non-final"]);
+    assert_eq!(demo.premature[0].tip_height, tip);
+    assert!(demo.witness_script.is_none());
+    assert_eq!(demo.blocks_mined, 5);
+    assert_eq!(demo.spend.tip_height, tip + 5);
+    assert_eq!(demo.spend.txid, demo.premature[0].txid);
+    let tx = rpc.get_raw_transaction(&demo.spend.txid, None).unwrap();
+    assert_eq!(
+        tx.lock_time,
+        absolute::LockTime::from_height(tip as u32 + 5).unwrap()
+    );
+    assert!(rpc.get_mempool_entry(&demo.spend.txid).is_ok());
+}
+
+#[test]
+fn cltv_rejects_both_a_future_and_a_too_low_locktime() {
+    let (_node, rpc, miner, address) = funded_miner();
+    let tip = rpc.get_block_count().unwrap();
+    let demo =
+        timelock::demonstrate(&rpc, &miner, Network::Regtest, TimelockKind::Cltv, 3).unwrap();
+
+    assert_eq!(reasons(&demo), ["non-final", SCRIPT_FAILURE]);
+    let script = demo.witness_script.as_deref().unwrap();
+    assert!(script.contains("OP_CLTV"), "{script}");
+    assert!(demo.premature[1].sets.contains(&format!("nLockTime {tip}")));
+    assert_eq!(demo.spend.txid, demo.premature[0].txid);
+    assert!(demo.spend.acceptance.allowed);
+
+    // Mined in the next block, spending the coin the script locked
+    transfer::confirm(&miner, &address).unwrap();
+    let info = rpc
+        .get_raw_transaction_info(&demo.spend.txid, None)
+        .unwrap();
+    assert_eq!(info.confirmations, Some(1));
+    assert_eq!(info.vin[0].txid, Some(demo.locked.txid));
+}
+
+#[test]
+fn csv_waits_for_confirmations_of_the_locked_coin() {
+    let (_node, rpc, miner, _) = funded_miner();
+    let demo =
+        timelock::demonstrate(&rpc, &miner, Network::Regtest, TimelockKind::Csv, 4).unwrap();
+
+    assert_eq!(reasons(&demo), ["non-BIP68-final", SCRIPT_FAILURE]);
+    assert!(demo.witness_script.as_deref().unwrap().contains("OP_CSV"));
+    // Mined in the first of the four blocks, so it has exactly four confirmations
+    let funding = rpc
+        .get_raw_transaction_info(&demo.locked.txid, None)
+        .unwrap();
+    assert_eq!(funding.confirmations, Some(4));
+    let tx = rpc.get_raw_transaction(&demo.spend.txid, None).unwrap();
+    assert_eq!(tx.input[0].sequence.0, 4);
+    assert!(rpc.get_mempool_entry(&demo.spend.txid).is_ok());
+}
+
+#[test]
+fn locktime_one_block_short_is_still_non_final() {
+    let (_node, rpc, miner, address) = funded_miner();
+    let coin = &coin_control::list_coins(&miner).unwrap()[0];
+    let tip = rpc.get_block_count().unwrap();
+    let spec = RawTransactionSpec {
+        inputs: vec![ChosenInput::new(OutPoint::new(coin.txid, coin.vout))],
+        outputs: vec![(address.clone(), Amount::ONE_BTC)],
+        change_address: Some(address.clone()),
+        lock_time: absolute::LockTime::from_height(tip as u32 + 2).unwrap(),
+        ..RawTransactionSpec::default()
+    };
+    let built = coin_control::build(&miner, &spec).unwrap();
+    assert_eq!(built.acceptance.reject_reason.as_deref(), Some("non-final"));
+
+    mining::mine_blocks(&rpc, &address, 1).unwrap();
+    let acceptance = coin_control::test_accept(&rpc, &built.tx).unwrap();
+    assert_eq!(acceptance.reject_reason.as_deref(), Some("non-final"));
+    mining::mine_blocks(&rpc, &address, 1).unwrap();
+    assert!(coin_control::test_accept(&rpc, &built.tx).unwrap().allowed);
+}
+
+#[test]
+fn lock_needs_at_least_a_block() {
+    let (_node, rpc, miner, _) = funded_miner();
+    let error = timelock::demonstrate(&rpc, &miner, Network::Regtest, TimelockKind::Csv, 0)
+        .unwrap_err();
+    assert!(matches!(error, Error::Config(_)), "{error}");
+    assert!("nlocktime".parse::<TimelockKind>().is_ok());
+    assert!("cltv-ish".parse::<TimelockKind>().is_err());
+}
@@ -0,0 +1,161 @@
+//! Wallet provisioning against the mock node
+
+mod common;
+
+use bitcoincore_rpc::RpcApi;
+use bitcoincore_rpc::bitcoin::{Amount, Network};
+use rust::analysis;
+use rust::mock::MockNode;
+use rust::script::{ScriptType, SpendPath};
+use rust::wallets::{self, AddressType, WalletSpec, WalletState};
+use rust::{psbt, transfer};
+
+use common::Funded;
+
+#[test]
+fn provisioning_twice_is_harmless() {
+    let node = MockNode::start().unwrap();
+    let connection = node.connection();
+
+    let first = wallets::ensure_wallets(&connection, &["Miner", "Trader"]).unwrap();
+    assert_eq!(first, [WalletState::Created, WalletState::Created]);
+    let second = wallets::ensure_wallets(&connection, &["Miner", "Trader"]).unwrap();
+    assert_eq!(
+        second,
+        [WalletState::AlreadyLoaded, WalletState::AlreadyLoaded]
+    );
+}
+
+#[test]
+fn unloaded_wallets_are_loaded_from_disk() {
+    let node = MockNode::start().unwrap();
+    let connection = node.connection();
+    wallets::ensure_wallets(&connection, &["Miner"]).unwrap();
+    connection
+        .client(None)
+        .unwrap()
+        .unload_wallet(Some("Miner"))
+        .unwrap();
+
+    let states = wallets::ensure_wallets(&connection, &["Miner"]).unwrap();
+    assert_eq!(states, [WalletState::Loaded]);
+}
+
+#[test]
+fn existing_wallet_with_other_key_options_is_an_error() {
+    let node = MockNode::start().unwrap();
+    let connection = node.connection();
+    wallets::ensure_wallets(&connection, &["Cold"]).unwrap();
+
+    let watch_only: WalletSpec = "Cold:watch-only".parse().unwrap();
+    let error = wallets::provision(&connection, &[watch_only]).unwrap_err();
+    assert!(
+        error.to_string().contains("doesn't match its spec"),
+        "{error}"
+    );
+}
+
+#[test]
+fn encrypted_wallet_must_be_unlocked_with_the_right_passphrase() {
+    let node = MockNode::start().unwrap();
+    let connection = node.connection();
+    let vault: WalletSpec = "Vault:passphrase=hunter2".parse().unwrap();
+    wallets::provision(&connection, &[vault]).unwrap();
+
+    let rpc = connection.client(Some("Vault")).unwrap();
+    assert!(wallets::unlock(&rpc, "wrong", 60).is_err());
+    wallets::unlock(&rpc, "hunter2", 60).unwrap();
+}
+
+#[test]
+fn every_address_type_is_spent_the_way_it_locks() {
+    for (address_type, script_type, spend_path, purpose) in [
+        (AddressType::Legacy, ScriptType::P2pkh, SpendPath::P2pkh, 44),
+        (
+            AddressType::P2shSegwit,
+            ScriptType::P2sh,
+            SpendPath::P2shP2wpkh,
+            49,
+        ),
+        (
+            AddressType::Bech32,
+            ScriptType::P2wpkh,
+            SpendPath::P2wpkh,
+            84,
+        ),
+        (
+            AddressType::Bech32m,
+            ScriptType::P2tr,
+            SpendPath::P2trKeyPath,
+            86,
+        ),
+    ] {
+        let Funded {
+            node: _node,
+            miner,
+            miner_address,
+            trader_address,
+            ..
+        } = Funded::with_address_type(address_type);
+        assert_eq!(ScriptType::of(&miner_address.script_pubkey()), script_type);
+        let info = miner.get_address_info(&miner_address).unwrap();
+        let keypath = info.hd_key_path.unwrap().to_string();
+        assert!(keypath.starts_with(&format!("m/{purpose}'")), "{keypath}");
+
+        let txid = transfer::send_payment(&miner, &trader_address, Amount::ONE_BTC).unwrap();
+        transfer::confirm(&miner, &miner_address).unwrap();
+        let analysis = analysis::analyze(&miner, &miner, &txid, Network::Regtest).unwrap();
+        let input = &analysis.inputs[0];
+        assert_eq!(
+            (input.script_type, input.spend_path),
+            (script_type, spend_path),
+            "{address_type}"
+        );
+        assert_eq!(
+            input.address.as_deref(),
+            Some(miner_address.to_string().as_str())
+        );
+    }
+}
+
+#[test]
+fn every_address_type_is_signed_through_a_psbt() {
+    for address_type in AddressType::ALL {
+        let Funded {
+            node: _node,
+            miner,
+            trader,
+            trader_address,
+            ..
+        } = Funded::with_address_type(address_type);
+        let payment =
+            psbt::pay(&miner, &trader, &trader_address, Amount::ONE_BTC, None).unwrap();
+        let tx = miner.get_raw_transaction(&payment.txid, None).unwrap();
+        let input = &tx.input[0];
+        // Legacy puts everything in the scriptSig, native segwit nothing at all
+        let (script_sig, witness) = (!input.script_sig.is_empty(), input.witness.len());
+        let expected = match address_type {
+            AddressType::Legacy => (true, 0),
+            AddressType::P2shSegwit => (true, 2),
+            AddressType::Bech32 => (false, 2),
+            AddressType::Bech32m => (false, 1),
+        };
+        assert_eq!((script_sig, witness), expected, "{address_type}");
+        assert!(miner.get_mempool_entry(&payment.txid).is_ok());
+    }
+}
+
+#[test]
+fn address_types_parse_by_core_names() {
+    for address_type in AddressType::ALL {
+        assert_eq!(
+            address_type.to_string().parse::<AddressType>().unwrap(),
+            address_type
+        );
+    }
+    assert_eq!(
+        "P2SH-SEGWIT".parse::<AddressType>().unwrap(),
+        AddressType::P2shSegwit
+    );
+    assert!("taproot".parse::<AddressType>().is_err());
+}